@@ -0,0 +1,1329 @@
+pub mod audit;
+pub mod budget;
+pub mod checkpoint;
+mod clone;
+pub mod hooks;
+pub mod journal;
+pub mod materialize;
+mod metadata;
+pub mod on_error;
+pub mod parallel;
+pub mod plan;
+pub mod saved_plan;
+pub mod script;
+pub mod summary;
+pub mod throttle;
+pub mod trash;
+pub mod verify;
+#[cfg(windows)]
+mod windows;
+
+use crate::hashing;
+use crate::matching::directories::DirectoryMatch;
+use crate::matching::{FileState, MatchingFile};
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os;
+use std::path::{Path, PathBuf};
+
+/// How a target file gets replaced by its matching source.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    /// Hardlinks only work within a single filesystem
+    Hardlink,
+    /// An independent copy sharing storage with the source, which the consumer can modify.
+    /// Needs a filesystem with reflinks such as btrfs, XFS or APFS
+    Reflink,
+    /// Keep both files but have the filesystem share their blocks, on Linux btrfs or XFS
+    Dedupe,
+}
+
+impl LinkMode {
+    fn verb(&self) -> &'static str {
+        match self {
+            LinkMode::Symlink => "Symlinking",
+            LinkMode::Hardlink => "Hardlinking",
+            LinkMode::Reflink => "Reflinking",
+            LinkMode::Dedupe => "Deduplicating",
+        }
+    }
+}
+
+/// Refuses any change under the source paths. Every action checks the paths it is about to
+/// create, rename or delete against it, so a bug can't damage seeded data.
+#[derive(Debug, Default)]
+pub struct SourceGuard {
+    roots: Vec<PathBuf>,
+}
+
+impl SourceGuard {
+    pub fn new(roots: &[PathBuf]) -> io::Result<Self> {
+        let roots = roots
+            .iter()
+            .map(fs::canonicalize)
+            .collect::<io::Result<_>>()?;
+        Ok(Self { roots })
+    }
+
+    /// Fails if `path` itself (not whatever it links to) lies under a source path.
+    fn check(&self, path: &Path) -> io::Result<()> {
+        if self.roots.is_empty() {
+            return Ok(());
+        }
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+                fs::canonicalize(parent)?.join(name)
+            }
+            _ => fs::canonicalize(path)?,
+        };
+        match self.roots.iter().find(|root| resolved.starts_with(root)) {
+            Some(root) => {
+                tracing::error!("Refusing to modify {path:?} under source path {root:?}");
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("Refusing to modify {path:?} under source path {root:?}"),
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// What happened to a single match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applied {
+    Replaced {
+        saved_bytes: u64,
+        /// The mode that worked, which may be a fallback
+        mode: LinkMode,
+    },
+    /// Left alone as it changed since being scanned
+    Skipped,
+}
+
+/// Options controlling how matches are applied.
+#[derive(Debug, Default)]
+pub struct LinkOptions {
+    pub mode: LinkMode,
+    /// Modes tried in order when `mode` isn't supported by the filesystem or can't cross its
+    /// device boundary
+    pub fallbacks: Vec<LinkMode>,
+    /// Point symlinks at their source relative to the directory they're in, so the tree still
+    /// works when moved or mounted elsewhere
+    pub relative: bool,
+    pub guard: SourceGuard,
+    /// Keep replaced files here instead of deleting them
+    pub trash: Option<trash::Trash>,
+    /// Hash the source and target again right before acting, on top of checking their size and
+    /// modification time
+    pub rehash: bool,
+    /// Record every replaced file here so it can be undone. Directory symlinks aren't recorded
+    pub journal: Option<journal::Journal>,
+    /// Limit how quickly changes are made
+    pub throttle: Option<throttle::Throttle>,
+    /// Leave targets modified after their source alone, as they may have diverged since their
+    /// hash was cached
+    pub skip_newer_targets: bool,
+    pub hooks: hooks::Hooks,
+    /// Log every attempted replacement and its result here
+    pub audit: Option<audit::AuditLog>,
+    /// Don't print each replacement, e.g. as stdout is kept for JSON
+    pub quiet: bool,
+    /// Record every target dealt with here, so an interrupted run can resume after it
+    pub checkpoint: Option<checkpoint::Checkpoint>,
+    /// Where to report each attempted replacement as it happens
+    pub progress: crate::matching::progress::Progress,
+    /// Subtrees left alone or replaced with other link modes
+    pub dir_configs: crate::matching::dir_config::DirConfigs,
+}
+
+impl LinkOptions {
+    /// Whether `dest` is a regular file modified after `src`, when guarding against that.
+    fn target_is_newer(&self, src: &Path, dest: &Path) -> io::Result<bool> {
+        if !self.skip_newer_targets {
+            return Ok(false);
+        }
+        let dest_metadata = fs::symlink_metadata(dest)?;
+        Ok(dest_metadata.is_file() && dest_metadata.modified()? > fs::metadata(src)?.modified()?)
+    }
+
+    /// Prints the replacement of `matching_file` unless quiet.
+    fn announce(&self, matching_file: &MatchingFile, link_mode: LinkMode) {
+        if !self.quiet {
+            println!(
+                "{0} {1:?} with {2:?}",
+                link_mode.verb(),
+                matching_file.dest_path(),
+                matching_file.src_path()
+            );
+        }
+    }
+
+    /// Waits for the throttle, if any, before changing the filesystem.
+    fn pace(&self) {
+        if let Some(throttle) = &self.throttle {
+            throttle.wait();
+        }
+    }
+
+    /// Whether the source and destination of `matching_file` are still as they were when
+    /// matched.
+    fn unchanged(&self, matching_file: &MatchingFile) -> io::Result<bool> {
+        let (src, dest) = (matching_file.src_path(), matching_file.dest_path());
+        if let Some((src_state, dest_state)) = matching_file.states() {
+            let (Ok(src_metadata), Ok(dest_metadata)) =
+                (fs::metadata(src), fs::symlink_metadata(dest))
+            else {
+                return Ok(false);
+            };
+            if FileState::of(&src_metadata)? != src_state
+                || FileState::of(&dest_metadata)? != dest_state
+            {
+                return Ok(false);
+            }
+        }
+        if self.rehash && !matching_file.hash().is_empty() {
+            for path in [src, dest] {
+                // Broken symlinks being repaired have nothing to hash
+                if path == dest && !fs::exists(dest)? {
+                    continue;
+                }
+                if hashing::compute_file_hash(path)? != matching_file.hash() {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Reports the result of an attempted replacement and appends it to the audit log, if there
+    /// is one. Failing to write the log is only logged, as the replacement already happened and
+    /// is journalled.
+    fn audit(&self, entry: &audit::AuditEntry, result: &io::Result<Applied>) {
+        let result = match result {
+            Ok(Applied::Replaced { .. }) => Ok("replaced"),
+            Ok(Applied::Skipped) => Ok("skipped"),
+            Err(e) => Err(e),
+        };
+        self.progress.applied(entry, result);
+        if let Some(audit) = &self.audit
+            && let Err(e) = audit.record(entry, result)
+        {
+            tracing::error!(
+                "Couldn't add {target:?} to the audit log: {e}",
+                target = entry.target
+            );
+        }
+    }
+
+    /// Records the target of `matching_file` in the journal, if there is one, before it is
+    /// replaced. Only regular files are recorded, as those are all undo can restore.
+    fn record(&self, matching_file: &MatchingFile, mode: LinkMode) -> io::Result<()> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        if !fs::symlink_metadata(matching_file.dest_path())?.is_file() {
+            return Ok(());
+        }
+        journal.record(&journal::JournalEntry::capture(
+            matching_file.dest_path(),
+            matching_file.src_path(),
+            matching_file.hash(),
+            mode,
+        )?)
+    }
+}
+
+/// What a symlink placed at `link` should contain to reach `src`, relative to the directory
+/// it's in when `relative` is set.
+fn symlink_target(src: &Path, link: &Path, relative: bool) -> io::Result<PathBuf> {
+    if !relative {
+        return Ok(src.to_path_buf());
+    }
+    let link_dir = fs::canonicalize(link.parent().unwrap_or(Path::new(".")))?;
+    // Resolve only the parent so a source that is itself a link isn't followed
+    let src = match (src.parent(), src.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            fs::canonicalize(parent)?.join(name)
+        }
+        _ => fs::canonicalize(src)?,
+    };
+    // Nothing leads from one Windows drive to another
+    if link_dir.components().next() != src.components().next() {
+        return Ok(src);
+    }
+    Ok(relative_path(&link_dir, &src))
+}
+
+/// The path leading from the directory `from` to `to`. Both must be absolute and free of `.`
+/// and `..` components.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let ups = from.components().count() - common;
+    std::iter::repeat_n(Path::new(".."), ups)
+        .chain(
+            to.components()
+                .skip(common)
+                .map(|c| Path::new(c.as_os_str())),
+        )
+        .collect()
+}
+
+/// Longest file name most filesystems allow, in bytes
+const NAME_MAX: usize = 255;
+
+/// A hidden name next to `path` for staging its replacement. Being in the same directory keeps
+/// the final `rename()` on one filesystem and therefore atomic. Names too long to take the
+/// suffix are shortened, with a hash of the whole name to keep them apart.
+fn temp_sibling(path: &Path, suffix: &str) -> PathBuf {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let file_name = path.file_name().unwrap_or_default();
+    let tail = format!(".atorrlinker-{}.{suffix}", std::process::id());
+    let mut name = std::ffi::OsString::from(".");
+    if 1 + file_name.len() + tail.len() <= NAME_MAX {
+        name.push(file_name);
+    } else {
+        let mut hasher = DefaultHasher::new();
+        file_name.hash(&mut hasher);
+        let hash = format!("~{:016x}", hasher.finish());
+        let file_name = file_name.to_string_lossy();
+        let mut end = NAME_MAX - 1 - hash.len() - tail.len();
+        while !file_name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.push(&file_name[..end]);
+        name.push(hash);
+    }
+    name.push(tail);
+    path.with_file_name(name)
+}
+
+/// Atomically replaces `dest` with `staged`, flushing the directory entry to disk so a crash
+/// leaves either the old or the new file in place, never neither.
+fn replace_with(staged: &Path, dest: &Path) -> io::Result<()> {
+    if let Err(e) = fs::rename(staged, dest) {
+        let _ = remove_link(staged);
+        return Err(e);
+    }
+    #[cfg(unix)]
+    if let Some(parent) = dest.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Removes a staged link. Windows, unlike unix, removes links to directories as directories.
+fn remove_link(path: &Path) -> io::Result<()> {
+    fs::remove_file(path).or_else(|e| {
+        if cfg!(windows) {
+            fs::remove_dir(path)
+        } else {
+            Err(e)
+        }
+    })
+}
+
+/// Gives the link staged at `staged` the owner of the `dest` it replaces. Symlinks get their own
+/// owner where permitted, usually when running as root. Hardlinks share the owner of their
+/// source, so a change of owner is only reported.
+#[cfg(unix)]
+fn keep_owner(staged: &Path, dest: &Path, link_mode: LinkMode) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let (dest_metadata, staged_metadata) =
+        (fs::symlink_metadata(dest)?, fs::symlink_metadata(staged)?);
+    let owner = (dest_metadata.uid(), dest_metadata.gid());
+    let new_owner = (staged_metadata.uid(), staged_metadata.gid());
+    if owner == new_owner {
+        return Ok(());
+    }
+    if link_mode == LinkMode::Symlink {
+        match os::unix::fs::lchown(staged, Some(owner.0), Some(owner.1)) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            Err(e) => return Err(e),
+        }
+    }
+    tracing::warn!(
+        "Replacing {dest:?} changes its owner from {}:{} to {}:{}",
+        owner.0,
+        owner.1,
+        new_owner.0,
+        new_owner.1
+    );
+    Ok(())
+}
+
+/// Warns when replacing the file `dest` with a link loses its ACLs or security labels.
+fn warn_lost_security_xattrs(dest: &Path, link_mode: LinkMode, journaled: bool) -> io::Result<()> {
+    let link = match link_mode {
+        LinkMode::Symlink => "symlink",
+        LinkMode::Hardlink => "hardlink",
+        // New or unchanged inodes that keep them
+        LinkMode::Reflink | LinkMode::Dedupe => return Ok(()),
+    };
+    if !fs::symlink_metadata(dest)?.is_file() {
+        return Ok(());
+    }
+    let names = metadata::security_xattrs(dest)?;
+    if !names.is_empty() {
+        tracing::warn!(
+            "{dest:?} has {} which its {link} can't keep{}",
+            names.join(", "),
+            if journaled {
+                ", they're recorded in the journal"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Space freed by replacing `dest` with a link to `src`.
+pub fn saved_bytes(src: &Path, dest: &Path, size: u64) -> u64 {
+    let Ok(dest_metadata) = fs::symlink_metadata(dest) else {
+        return 0;
+    };
+    if !dest_metadata.is_file() {
+        return 0;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Already hardlinked together
+        if fs::metadata(src)
+            .is_ok_and(|m| (m.dev(), m.ino()) == (dest_metadata.dev(), dest_metadata.ino()))
+        {
+            return 0;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = src;
+    size
+}
+
+/// Reads the start of the file staged at `staged` through its link, so a link to a source that
+/// is unreadable or on a missing mount never replaces the only copy.
+fn check_readable(staged: &Path, size: u64) -> io::Result<()> {
+    let mut buf = [0; 64];
+    let read = io::Read::read(&mut fs::File::open(staged)?, &mut buf)?;
+    if read == 0 && size > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Nothing could be read through {staged:?}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `e` is the filesystem refusing a change for lack of permissions or being read-only,
+/// as opposed to something going wrong.
+pub fn needs_privileges(e: &io::Error) -> bool {
+    e.raw_os_error().is_some()
+        && matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+        )
+}
+
+/// Checks `mode` can link `src` into `dir`, by making such a link there, reading through it and
+/// removing it again. Dedupe is tried on a reflinked copy, as it needs a file with the same
+/// content.
+pub fn probe_link(mode: LinkMode, src: &Path, dir: &Path) -> io::Result<()> {
+    let probe = temp_sibling(&dir.join("doctor"), "probe");
+    let result = (|| {
+        match mode {
+            LinkMode::Symlink => {
+                #[cfg(unix)]
+                os::unix::fs::symlink(src, &probe)?;
+                #[cfg(windows)]
+                windows::symlink_file(src, src, &probe)?;
+            }
+            LinkMode::Hardlink => fs::hard_link(src, &probe)?,
+            LinkMode::Reflink => clone::reflink(src, &probe)?,
+            LinkMode::Dedupe => {
+                clone::reflink(src, &probe)?;
+                clone::dedupe(src, &probe)?;
+            }
+        }
+        check_readable(&probe, fs::metadata(src)?.len())
+    })();
+    if let Err(e) = fs::remove_file(&probe)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        tracing::warn!("Couldn't remove {probe:?}: {e}");
+    }
+    result
+}
+
+/// Replace the destination of a single match with a link to its source.
+pub fn link_file(matching_file: &MatchingFile, options: &LinkOptions) -> io::Result<Applied> {
+    let result = try_link_file(matching_file, options);
+    // The mode that worked, or the one that was tried first
+    let mode = match result {
+        Ok(Applied::Replaced { mode, .. }) => mode,
+        _ => options.mode,
+    };
+    options.audit(
+        &audit::AuditEntry {
+            action: &format!("{mode:?}").to_lowercase(),
+            target: matching_file.dest_path(),
+            source: Some(matching_file.src_path()),
+            hash: Some(matching_file.hash()),
+        },
+        &result,
+    );
+    if result.is_ok()
+        && let Some(checkpoint) = &options.checkpoint
+    {
+        checkpoint.done(matching_file.dest_path())?;
+    }
+    result
+}
+
+fn try_link_file(matching_file: &MatchingFile, options: &LinkOptions) -> io::Result<Applied> {
+    if !options.unchanged(matching_file)? {
+        tracing::warn!(
+            "Skipping {:?}: it or {:?} changed since being scanned",
+            matching_file.dest_path(),
+            matching_file.src_path()
+        );
+        return Ok(Applied::Skipped);
+    }
+    let dest = matching_file.dest_path();
+    if options.dir_configs.skipped(dest.parent().unwrap_or(dest))? {
+        tracing::warn!(
+            "Skipping {dest:?}: it's in a subtree configured to be left alone in {}",
+            crate::matching::dir_config::FILE_NAME
+        );
+        return Ok(Applied::Skipped);
+    }
+    if options.target_is_newer(matching_file.src_path(), matching_file.dest_path())? {
+        tracing::warn!(
+            "Skipping {:?}: it was modified after {:?}, use --force to replace it anyway",
+            matching_file.dest_path(),
+            matching_file.src_path()
+        );
+        return Ok(Applied::Skipped);
+    }
+    let modes = match options.dir_configs.link_modes(dest)? {
+        Some(modes) => modes,
+        None => std::iter::once(options.mode)
+            .chain(options.fallbacks.iter().copied())
+            .collect(),
+    };
+    let event = hooks::HookEvent {
+        target: matching_file.dest_path(),
+        source: matching_file.src_path(),
+        hash: matching_file.hash(),
+        size: matching_file.size(),
+        mode: modes[0],
+    };
+    if !options.hooks.pre(&event)? {
+        return Ok(Applied::Skipped);
+    }
+    options.pace();
+    let saved_bytes = saved_bytes(
+        matching_file.src_path(),
+        matching_file.dest_path(),
+        matching_file.size(),
+    );
+
+    let mut modes = modes.into_iter().peekable();
+    while let Some(mode) = modes.next() {
+        match link_file_as(matching_file, options, mode) {
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::Unsupported | io::ErrorKind::CrossesDevices
+                ) && modes.peek().is_some() =>
+            {
+                tracing::info!(
+                    "Can't use {mode:?} for {:?}, falling back: {e}",
+                    matching_file.dest_path()
+                );
+            }
+            result => {
+                let mode = result?;
+                options.hooks.post(&hooks::HookEvent { mode, ..event });
+                return Ok(Applied::Replaced { saved_bytes, mode });
+            }
+        }
+    }
+    unreachable!("There is always at least one mode")
+}
+
+/// Replaces the destination of `matching_file` using `link_mode`, returning the mode used. That
+/// is `link_mode` except for symlinks on Windows, which may fall back to hard links.
+fn link_file_as(
+    matching_file: &MatchingFile,
+    options: &LinkOptions,
+    link_mode: LinkMode,
+) -> io::Result<LinkMode> {
+    let guard = &options.guard;
+    if link_mode == LinkMode::Dedupe {
+        guard.check(matching_file.dest_path())?;
+        // Deduplication leaves the content alone, so it only needs recording once it worked
+        clone::dedupe(matching_file.src_path(), matching_file.dest_path())?;
+        options.record(matching_file, link_mode)?;
+        options.announce(matching_file, link_mode);
+        return Ok(link_mode);
+    }
+
+    // Make temporary link
+    let tmp_path = &temp_sibling(matching_file.dest_path(), "tmp");
+    guard.check(tmp_path)?;
+    guard.check(matching_file.dest_path())?;
+    warn_lost_security_xattrs(
+        matching_file.dest_path(),
+        link_mode,
+        options.journal.is_some(),
+    )?;
+    let link_mode = match link_mode {
+        LinkMode::Symlink => {
+            let link_target = symlink_target(
+                matching_file.src_path(),
+                matching_file.dest_path(),
+                options.relative,
+            )?;
+            #[cfg(unix)]
+            os::unix::fs::symlink(link_target, tmp_path)?;
+            #[cfg(windows)]
+            let link_mode =
+                windows::symlink_file(&link_target, matching_file.src_path(), tmp_path)?;
+            link_mode
+        }
+        LinkMode::Hardlink => {
+            fs::hard_link(matching_file.src_path(), tmp_path)?;
+            link_mode
+        }
+        LinkMode::Reflink => {
+            // A new inode that can carry the replaced file's own metadata
+            clone::reflink(matching_file.src_path(), tmp_path)?;
+            let kept = metadata::FileMetadata::capture(matching_file.dest_path())
+                .and_then(|metadata| metadata.apply(tmp_path));
+            if let Err(e) = kept {
+                let _ = fs::remove_file(tmp_path);
+                return Err(e);
+            }
+            link_mode
+        }
+        LinkMode::Dedupe => unreachable!("Deduplication happens in place"),
+    };
+    #[cfg(unix)]
+    if let Err(e) = keep_owner(tmp_path, matching_file.dest_path(), link_mode) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
+    if let Err(e) = check_readable(tmp_path, matching_file.size()) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
+
+    options.announce(matching_file, link_mode);
+
+    if let Some(trash) = &options.trash
+        && let Err(e) = trash.keep_file(matching_file.dest_path())
+    {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
+    if let Err(e) = options.record(matching_file, link_mode) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
+    replace_with(tmp_path, matching_file.dest_path())?;
+    Ok(link_mode)
+}
+
+pub fn dry_run_directory(directory: &DirectoryMatch) {
+    println!(
+        "Symlinking directory {0:?} with {1:?}",
+        &directory.dest_dir, &directory.src_dir
+    );
+}
+
+/// Replace a whole target directory with a symlink to its matching source directory.
+pub fn link_directory(directory: &DirectoryMatch, options: &LinkOptions) -> io::Result<Applied> {
+    let result = try_link_directory(directory, options);
+    options.audit(
+        &audit::AuditEntry {
+            action: "symlink_directory",
+            target: &directory.dest_dir,
+            source: Some(&directory.src_dir),
+            hash: None,
+        },
+        &result,
+    );
+    result
+}
+
+fn try_link_directory(directory: &DirectoryMatch, options: &LinkOptions) -> io::Result<Applied> {
+    let guard = &options.guard;
+    let tmp_path = &temp_sibling(&directory.dest_dir, "tmp");
+    let old_path = &temp_sibling(&directory.dest_dir, "old");
+    for path in [tmp_path, old_path, &directory.dest_dir] {
+        guard.check(path)?;
+    }
+    let event = hooks::HookEvent {
+        target: &directory.dest_dir,
+        source: &directory.src_dir,
+        hash: "",
+        size: directory.size,
+        mode: LinkMode::Symlink,
+    };
+    if !options.hooks.pre(&event)? {
+        return Ok(Applied::Skipped);
+    }
+    options.pace();
+
+    // Make temporary symlink
+    let link_target = symlink_target(&directory.src_dir, &directory.dest_dir, options.relative)?;
+    #[cfg(unix)]
+    os::unix::fs::symlink(link_target, tmp_path)?;
+    #[cfg(windows)]
+    windows::symlink_dir(&link_target, &directory.src_dir, tmp_path)?;
+    // Make sure the source is there to be listed before giving up the only copy
+    if let Err(e) = fs::read_dir(tmp_path).and_then(|mut entries| entries.next().transpose()) {
+        let _ = remove_link(tmp_path);
+        return Err(e);
+    }
+
+    if !options.quiet {
+        dry_run_directory(directory);
+    }
+
+    // Move the directory out of the way before swapping the link in
+    if let Err(e) = fs::rename(&directory.dest_dir, old_path) {
+        let _ = remove_link(tmp_path);
+        return Err(e);
+    }
+    if let Err(e) = replace_with(tmp_path, &directory.dest_dir) {
+        fs::rename(old_path, &directory.dest_dir)?;
+        return Err(e);
+    }
+    match &options.trash {
+        Some(trash) => {
+            trash.keep_directory(old_path, &directory.dest_dir)?;
+        }
+        None => fs::remove_dir_all(old_path)?,
+    }
+    options.hooks.post(&event);
+    Ok(Applied::Replaced {
+        saved_bytes: directory.size,
+        mode: LinkMode::Symlink,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::matching::MatchReason;
+
+    #[test]
+    fn replace_file() {
+        const FILE_CONTENT: &str = "hello test test";
+
+        let src_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+
+        let src_file_path = src_dir.path().join("original_file.txt");
+        let target_file_path = target_dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, FILE_CONTENT).unwrap();
+        fs::write(&target_file_path, FILE_CONTENT).unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            FILE_CONTENT.len() as u64,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+
+        // TEST
+        let applied = link_file(&matching, &LinkOptions::default()).unwrap();
+
+        // CONFIRM
+        assert!(
+            fs::symlink_metadata(&target_file_path)
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), FILE_CONTENT);
+        assert_eq!(
+            applied,
+            Applied::Replaced {
+                saved_bytes: FILE_CONTENT.len() as u64,
+                mode: LinkMode::Symlink,
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_file_hardlink() {
+        use std::os::unix::fs::MetadataExt as _;
+        const FILE_CONTENT: &str = "hello test test";
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, FILE_CONTENT).unwrap();
+        fs::write(&target_file_path, FILE_CONTENT).unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path.clone(),
+            target_file_path.clone(),
+            FILE_CONTENT.len() as u64,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+
+        // TEST
+        link_file(
+            &matching,
+            &LinkOptions {
+                mode: LinkMode::Hardlink,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // CONFIRM
+        let target_metadata = fs::symlink_metadata(&target_file_path).unwrap();
+        assert!(!target_metadata.is_symlink());
+        assert_eq!(
+            target_metadata.ino(),
+            fs::metadata(&src_file_path).unwrap().ino()
+        );
+    }
+
+    #[test]
+    fn replace_directory() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+
+        let src_path = src_dir.path().join("show");
+        let dest_path = target_dir.path().join("show");
+        for dir in [&src_path, &dest_path] {
+            fs::create_dir(dir).unwrap();
+            fs::write(dir.join("episode.mkv"), "episode").unwrap();
+        }
+
+        // TEST
+        link_directory(
+            &DirectoryMatch {
+                src_dir: src_path,
+                dest_dir: dest_path.clone(),
+                size: 7,
+            },
+            &LinkOptions::default(),
+        )
+        .unwrap();
+
+        // CONFIRM
+        assert!(fs::symlink_metadata(&dest_path).unwrap().is_symlink());
+        assert_eq!(
+            fs::read_to_string(dest_path.join("episode.mkv")).unwrap(),
+            "episode"
+        );
+        assert_eq!(fs::read_dir(target_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn guard_refuses_source_paths() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let src_file_path = src_dir.path().join("original_file.txt");
+        let other_path = src_dir.path().join("other_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&other_path, "content").unwrap();
+
+        // A match pointing back into the source paths must never be applied
+        let matching = MatchingFile::new(
+            src_file_path,
+            other_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let options = LinkOptions {
+            guard: SourceGuard::new(&[src_dir.path().to_path_buf()]).unwrap(),
+            ..Default::default()
+        };
+
+        let err = link_file(&matching, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!fs::symlink_metadata(&other_path).unwrap().is_symlink());
+    }
+
+    #[test]
+    fn replace_file_reflink() {
+        const FILE_CONTENT: &str = "hello test test";
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, FILE_CONTENT).unwrap();
+        fs::write(&target_file_path, FILE_CONTENT).unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path.clone(),
+            target_file_path.clone(),
+            FILE_CONTENT.len() as u64,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+
+        match link_file(
+            &matching,
+            &LinkOptions {
+                mode: LinkMode::Reflink,
+                ..Default::default()
+            },
+        ) {
+            Ok(_) => {
+                // An independent file: changing it leaves the source alone
+                fs::write(&target_file_path, "changed").unwrap();
+                assert_eq!(fs::read_to_string(&src_file_path).unwrap(), FILE_CONTENT);
+            }
+            // Filesystems without reflinks leave the target untouched
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+                assert_eq!(fs::read_to_string(&target_file_path).unwrap(), FILE_CONTENT);
+                assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn replace_file_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let options = LinkOptions {
+            mode: LinkMode::Reflink,
+            fallbacks: vec![LinkMode::Symlink],
+            ..Default::default()
+        };
+        let Applied::Replaced { mode, .. } = link_file(&matching, &options).unwrap() else {
+            panic!("Should have been replaced");
+        };
+        // Whichever mode this filesystem supports
+        let is_symlink = fs::symlink_metadata(&target_file_path)
+            .unwrap()
+            .is_symlink();
+        assert_eq!(is_symlink, mode == LinkMode::Symlink);
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), "content");
+    }
+
+    #[test]
+    fn dedupe_file() {
+        const FILE_CONTENT: &str = "hello test test";
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, FILE_CONTENT).unwrap();
+        fs::write(&target_file_path, FILE_CONTENT).unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            FILE_CONTENT.len() as u64,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+
+        // Filesystems without block sharing report it as unsupported
+        if let Err(e) = link_file(
+            &matching,
+            &LinkOptions {
+                mode: LinkMode::Dedupe,
+                ..Default::default()
+            },
+        ) {
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        }
+        let target_metadata = fs::symlink_metadata(&target_file_path).unwrap();
+        assert!(target_metadata.is_file());
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), FILE_CONTENT);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn replace_file_audit_failure() {
+        const FILE_CONTENT: &str = "hello test test";
+
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, FILE_CONTENT).unwrap();
+        fs::write(&target_file_path, FILE_CONTENT).unwrap();
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            FILE_CONTENT.len() as u64,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+
+        // Every write to the audit log fails, but the file was replaced all the same
+        let options = LinkOptions {
+            audit: Some(audit::AuditLog::open(Path::new("/dev/full")).unwrap()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            link_file(&matching, &options).unwrap(),
+            Applied::Replaced { .. }
+        ));
+        assert!(
+            fs::symlink_metadata(&target_file_path)
+                .unwrap()
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    fn test_temp_sibling() {
+        let dir = Path::new("/target");
+        let staged = temp_sibling(&dir.join("episode.mkv"), "tmp");
+        assert_eq!(
+            staged,
+            dir.join(format!(
+                ".episode.mkv.atorrlinker-{}.tmp",
+                std::process::id()
+            ))
+        );
+
+        // The longest names a filesystem allows still fit with the suffix, and stay apart
+        let long = "é".repeat(120) + "1.mkv";
+        let other = "é".repeat(120) + "2.mkv";
+        let staged = temp_sibling(&dir.join(&long), "tmp");
+        let name = staged.file_name().unwrap();
+        assert!(name.len() <= NAME_MAX);
+        assert!(name.to_str().unwrap().starts_with(".éé"));
+        assert!(name.to_str().unwrap().ends_with(".tmp"));
+        assert_ne!(staged, temp_sibling(&dir.join(&other), "tmp"));
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(
+                Path::new("/data/tv/show"),
+                Path::new("/data/seed/show/ep.mkv")
+            ),
+            Path::new("../../seed/show/ep.mkv")
+        );
+        assert_eq!(
+            relative_path(Path::new("/data"), Path::new("/data/ep.mkv")),
+            Path::new("ep.mkv")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_file_relative() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("source/episode.mkv");
+        let target_file_path = dir.path().join("target/show/episode.mkv");
+        for path in [&src_file_path, &target_file_path] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "episode").unwrap();
+        }
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let options = LinkOptions {
+            relative: true,
+            ..Default::default()
+        };
+        link_file(&matching, &options).unwrap();
+
+        assert_eq!(
+            fs::read_link(&target_file_path).unwrap(),
+            Path::new("../../source/episode.mkv")
+        );
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), "episode");
+    }
+
+    #[test]
+    fn replace_file_keeps_siblings() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        // Would have been the staging name when it was derived from the extension
+        let sibling_path = dir.path().join("copied_file.tmp");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+        fs::write(&sibling_path, "unrelated").unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        link_file(&matching, &LinkOptions::default()).unwrap();
+
+        assert!(
+            fs::symlink_metadata(&target_file_path)
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&sibling_path).unwrap(), "unrelated");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn replace_file_into_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("target/copied_file.txt");
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let trash_dir = dir.path().join("trash");
+        let options = LinkOptions {
+            trash: Some(trash::Trash::new(&trash_dir).unwrap()),
+            ..Default::default()
+        };
+        link_file(&matching, &options).unwrap();
+
+        assert!(
+            fs::symlink_metadata(&target_file_path)
+                .unwrap()
+                .is_symlink()
+        );
+        let run = fs::read_dir(&trash_dir).unwrap().next().unwrap().unwrap();
+        let trashed = run.path().join(target_file_path.strip_prefix("/").unwrap());
+        assert!(!fs::symlink_metadata(&trashed).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(trashed).unwrap(), "content");
+    }
+
+    #[test]
+    fn skip_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        )
+        .with_states()
+        .unwrap();
+        // Modified between matching and acting
+        fs::write(&target_file_path, "changed content").unwrap();
+
+        assert_eq!(
+            link_file(&matching, &LinkOptions::default()).unwrap(),
+            Applied::Skipped
+        );
+
+        assert!(fs::symlink_metadata(&target_file_path).unwrap().is_file());
+        assert_eq!(
+            fs::read_to_string(&target_file_path).unwrap(),
+            "changed content"
+        );
+    }
+
+    #[test]
+    fn skip_rehashed_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "CONTENT").unwrap();
+
+        // Neither file changed since being matched, so only hashing again catches that their
+        // content differs
+        let matching = MatchingFile::new(
+            src_file_path.clone(),
+            target_file_path.clone(),
+            7,
+            hashing::compute_file_hash(&src_file_path).unwrap(),
+            MatchReason::SourceScan,
+        )
+        .with_states()
+        .unwrap();
+        let options = LinkOptions {
+            rehash: true,
+            ..Default::default()
+        };
+        assert_eq!(link_file(&matching, &options).unwrap(), Applied::Skipped);
+
+        assert!(fs::symlink_metadata(&target_file_path).unwrap().is_file());
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), "CONTENT");
+        // Nothing staged is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn keep_target_with_unreadable_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("unmounted/original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&target_file_path, "content").unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let err = link_file(&matching, &LinkOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), "content");
+        // Only the target is left, no staged link
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn skip_newer_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+        let now = std::time::SystemTime::now();
+        let set_modified = |path: &Path, modified| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap()
+        };
+        set_modified(&src_file_path, now - std::time::Duration::from_secs(60));
+        set_modified(&target_file_path, now);
+
+        let matching = MatchingFile::new(
+            src_file_path.clone(),
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let options = LinkOptions {
+            skip_newer_targets: true,
+            ..Default::default()
+        };
+        assert_eq!(link_file(&matching, &options).unwrap(), Applied::Skipped);
+        assert!(fs::symlink_metadata(&target_file_path).unwrap().is_file());
+
+        // Fine once the source is the newer one
+        set_modified(&src_file_path, now + std::time::Duration::from_secs(60));
+        assert!(matches!(
+            link_file(&matching, &options).unwrap(),
+            Applied::Replaced { .. }
+        ));
+        assert!(
+            fs::symlink_metadata(&target_file_path)
+                .unwrap()
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replace_file_keeps_owner() {
+        use std::os::unix::fs::MetadataExt as _;
+        // Only root can hand files to someone else
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+        os::unix::fs::chown(&target_file_path, Some(1234), Some(5678)).unwrap();
+
+        let matching = MatchingFile::new(
+            src_file_path,
+            target_file_path.clone(),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        link_file(&matching, &LinkOptions::default()).unwrap();
+
+        let metadata = fs::symlink_metadata(&target_file_path).unwrap();
+        assert!(metadata.is_symlink());
+        assert_eq!((metadata.uid(), metadata.gid()), (1234, 5678));
+    }
+
+    #[test]
+    fn test_needs_privileges() {
+        #[cfg(unix)]
+        for errno in [libc::EACCES, libc::EPERM, libc::EROFS] {
+            assert!(needs_privileges(&io::Error::from_raw_os_error(errno)));
+        }
+        // Refusals by the source guard are deliberate, not a missing privilege
+        assert!(!needs_privileges(&io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Refusing to modify source path"
+        )));
+        assert!(!needs_privileges(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A change made, or attempted, on a target.
+#[derive(Debug, serde::Serialize)]
+pub struct AuditEntry<'a> {
+    /// What was done, e.g. `symlink`, `hardlink`, `symlink_directory` or `undo`
+    pub action: &'a str,
+    pub target: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+struct AuditLine<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    entry: &'a AuditEntry<'a>,
+    result: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Append-only log of every change made to the targets, one JSON object per line. Unlike the
+/// journal nothing is ever removed from it, so it answers what happened to a file and when
+/// long after the change was undone.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends `entry` with its result: `Ok` with e.g. `replaced` or `skipped`, or the error it
+    /// failed with.
+    pub fn record(&self, entry: &AuditEntry, result: Result<&str, &io::Error>) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&AuditLine {
+            timestamp: rfc3339(SystemTime::now()),
+            entry,
+            result: result.unwrap_or("failed"),
+            error: result.err().map(ToString::to_string),
+        })?;
+        line.push(b'\n');
+        let mut file = self.file.lock().expect("Audit log lock poisoned");
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp, e.g. `2024-05-14T09:30:00Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(1_715_679_000)),
+            "2024-05-14T09:30:00Z"
+        );
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let entry = AuditEntry {
+            action: "symlink",
+            target: Path::new("/target/episode.mkv"),
+            source: Some(Path::new("/source/episode.mkv")),
+            hash: Some("ABC"),
+        };
+        AuditLog::open(&path)
+            .unwrap()
+            .record(&entry, Ok("replaced"))
+            .unwrap();
+        // Reopening appends
+        AuditLog::open(&path)
+            .unwrap()
+            .record(&entry, Err(&io::Error::other("disk full")))
+            .unwrap();
+
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "symlink");
+        assert_eq!(lines[0]["source"], "/source/episode.mkv");
+        assert_eq!(lines[0]["result"], "replaced");
+        assert!(lines[0].get("error").is_none());
+        assert_eq!(lines[1]["result"], "failed");
+        assert_eq!(lines[1]["error"], "disk full");
+    }
+}
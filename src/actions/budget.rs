@@ -0,0 +1,61 @@
+/// Caps how much a single run may replace. Once a match doesn't fit, every later one is refused
+/// too so a following run can pick up exactly where this one stopped.
+#[derive(Debug, Default)]
+pub struct Budget {
+    max_actions: Option<u64>,
+    max_bytes: Option<u64>,
+    actions: u64,
+    bytes: u64,
+    exhausted: bool,
+}
+
+impl Budget {
+    pub fn new(max_actions: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_actions,
+            max_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Whether another action over `size` bytes fits, counting it if it does.
+    pub fn take(&mut self, size: u64) -> bool {
+        self.exhausted = self.exhausted
+            || self.max_actions.is_some_and(|max| self.actions >= max)
+            || self
+                .max_bytes
+                .is_some_and(|max| self.bytes.saturating_add(size) > max);
+        if self.exhausted {
+            return false;
+        }
+        self.actions += 1;
+        self.bytes = self.bytes.saturating_add(size);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let mut unlimited = Budget::new(None, None);
+        assert!((0..100).all(|_| unlimited.take(u64::MAX / 2)));
+
+        let mut actions = Budget::new(Some(2), None);
+        assert!(actions.take(10));
+        assert!(actions.take(10));
+        assert!(!actions.take(0));
+
+        let mut bytes = Budget::new(None, Some(100));
+        assert!(bytes.take(60));
+        assert!(!bytes.take(50));
+        // Stays exhausted even though this would fit
+        assert!(!bytes.take(10));
+
+        let mut huge = Budget::new(None, Some(u64::MAX - 1));
+        assert!(huge.take(u64::MAX - 10));
+        assert!(!huge.take(u64::MAX));
+    }
+}
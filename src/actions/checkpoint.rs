@@ -0,0 +1,211 @@
+use sha2::Digest as _;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::matching::{FileState, MatchingFile};
+
+/// A line of the checkpoint: the paths of the run, a match to apply, or a target dealt with.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum CheckpointLine {
+    Paths {
+        sources: Vec<PathBuf>,
+        targets: Vec<PathBuf>,
+    },
+    Planned {
+        planned: MatchingFile,
+        states: Option<(FileState, FileState)>,
+    },
+    Done {
+        done: PathBuf,
+    },
+}
+
+/// The matches of a run still to be applied, kept on disk so an interrupted run can be resumed
+/// without scanning again. Starts out with the whole plan, then each target replaced or skipped
+/// is appended as done. Removed once the run is over.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Starts a checkpoint for a run over `sources` and `targets` that will apply `planned`.
+    pub fn create(
+        path: &Path,
+        sources: &[PathBuf],
+        targets: &[PathBuf],
+        planned: &[MatchingFile],
+    ) -> io::Result<Self> {
+        let mut out = io::BufWriter::new(File::create(path)?);
+        write_line(
+            &mut out,
+            &CheckpointLine::Paths {
+                sources: sources.to_vec(),
+                targets: targets.to_vec(),
+            },
+        )?;
+        for matching in planned {
+            write_line(
+                &mut out,
+                &CheckpointLine::Planned {
+                    planned: matching.clone(),
+                    states: matching.states(),
+                },
+            )?;
+        }
+        let file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        file.sync_data()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records that `target` no longer needs applying.
+    pub fn done(&self, target: &Path) -> io::Result<()> {
+        let mut file = self.file.lock().expect("Checkpoint lock poisoned");
+        write_line(
+            &mut *file,
+            &CheckpointLine::Done {
+                done: target.to_path_buf(),
+            },
+        )?;
+        file.sync_data()
+    }
+
+    /// Removes the checkpoint, as the run finished.
+    pub fn finish(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+fn write_line(out: &mut impl Write, line: &CheckpointLine) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, line)?;
+    out.write_all(b"\n")
+}
+
+/// Where the checkpoint of a run over `sources` and `targets` is kept in `dir`, so runs over
+/// different paths keep separate checkpoints.
+pub fn path_for(dir: &Path, sources: &[PathBuf], targets: &[PathBuf]) -> PathBuf {
+    let mut digest = sha2::Sha256::new();
+    for path in sources.iter().chain([&PathBuf::new()]).chain(targets) {
+        digest.update(path.as_os_str().as_encoded_bytes());
+        digest.update([0]);
+    }
+    dir.join(format!("{}.jsonl", crate::lock::hex(&digest.finalize())))
+}
+
+/// Reopens the checkpoint at `path` to resume the run it was made for, returning the matches
+/// still to apply. `None` when there is no checkpoint to resume.
+pub fn resume(
+    path: &Path,
+    sources: &[PathBuf],
+    targets: &[PathBuf],
+) -> io::Result<Option<(Checkpoint, Vec<MatchingFile>)>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut planned = Vec::new();
+    let mut done = HashSet::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        // A line cut short by the interruption
+        let Ok(line) = serde_json::from_str::<CheckpointLine>(&line) else {
+            tracing::warn!("Ignoring unreadable line {} of checkpoint {path:?}", i + 1);
+            continue;
+        };
+        match line {
+            CheckpointLine::Paths {
+                sources: ref checkpoint_sources,
+                targets: ref checkpoint_targets,
+            } => {
+                if checkpoint_sources != sources || checkpoint_targets != targets {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Checkpoint {path:?} is for a run over other paths: sources \
+                             {checkpoint_sources:?}, targets {checkpoint_targets:?}"
+                        ),
+                    ));
+                }
+            }
+            CheckpointLine::Planned { planned: m, states } => {
+                planned.push(m.with_recorded_states(states))
+            }
+            CheckpointLine::Done { done: target } => {
+                done.insert(target);
+            }
+        }
+    }
+    planned.retain(|matching| !done.contains(matching.dest_path()));
+    let file = fs::OpenOptions::new().append(true).open(path)?;
+    Ok(Some((
+        Checkpoint {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        },
+        planned,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+
+    #[test]
+    fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.jsonl");
+        let sources = [PathBuf::from("/source")];
+        let targets = [PathBuf::from("/target")];
+        let planned: Vec<_> = ["a.mkv", "b.mkv"]
+            .into_iter()
+            .map(|name| {
+                MatchingFile::new(
+                    sources[0].join(name),
+                    targets[0].join(name),
+                    7,
+                    "ABC".to_owned(),
+                    MatchReason::SourceScan,
+                )
+            })
+            .collect();
+
+        assert!(resume(&path, &sources, &targets).unwrap().is_none());
+        let checkpoint = Checkpoint::create(&path, &sources, &targets, &planned).unwrap();
+        checkpoint.done(&targets[0].join("a.mkv")).unwrap();
+        drop(checkpoint);
+        // Interrupted while writing a line
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"done\":")
+            .unwrap();
+
+        let (checkpoint, remaining) = resume(&path, &sources, &targets).unwrap().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].dest_path(), targets[0].join("b.mkv"));
+        assert_eq!(remaining[0].hash(), "ABC");
+        checkpoint.finish().unwrap();
+        assert!(!path.exists());
+
+        // Resuming a run over other paths
+        Checkpoint::create(&path, &sources, &targets, &planned).unwrap();
+        assert!(resume(&path, &targets, &sources).is_err());
+        assert_ne!(
+            path_for(dir.path(), &sources, &targets),
+            path_for(dir.path(), &targets, &sources)
+        );
+    }
+}
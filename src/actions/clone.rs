@@ -0,0 +1,138 @@
+use std::{fs, io, path::Path};
+
+/// Creates `dest` as a copy of `src` sharing its extents (a reflink), so it has its own inode
+/// but takes no extra space until one of them is modified.
+#[cfg(target_os = "linux")]
+pub(super) fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src_file = fs::File::open(src)?;
+    let dest_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call
+    let result = unsafe { libc::ioctl(dest_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+    if result == -1 {
+        let err = io::Error::last_os_error();
+        drop(dest_file);
+        fs::remove_file(dest)?;
+        return Err(unsupported_or(err));
+    }
+    Ok(())
+}
+
+/// `struct file_dedupe_range` followed by a single `struct file_dedupe_range_info`
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// `_IOWR(0x94, 54, struct file_dedupe_range)`
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: libc::Ioctl = 0xC018_9436_u32 as libc::Ioctl;
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+/// Asks the filesystem to share the extents of `src` with the identical file `dest`, leaving
+/// both as regular files. The kernel checks the contents match before sharing anything.
+#[cfg(target_os = "linux")]
+pub(super) fn dedupe(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src_file = fs::File::open(src)?;
+    let dest_file = fs::OpenOptions::new().write(true).open(dest)?;
+    let len = src_file.metadata()?.len();
+
+    let mut offset = 0;
+    while offset < len {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: len - offset,
+            dest_count: 1,
+            dest_fd: i64::from(dest_file.as_raw_fd()),
+            dest_offset: offset,
+            ..Default::default()
+        };
+        // SAFETY: `range` is a valid file_dedupe_range with room for one destination
+        let result = unsafe { libc::ioctl(src_file.as_raw_fd(), FIDEDUPERANGE, &mut range) };
+        if result == -1 {
+            return Err(unsupported_or(io::Error::last_os_error()));
+        }
+        match range.status {
+            FILE_DEDUPE_RANGE_DIFFERS => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{dest:?} differs from {src:?}"),
+                ));
+            }
+            status if status < 0 => {
+                return Err(unsupported_or(io::Error::from_raw_os_error(-status)));
+            }
+            _ => {}
+        }
+        // Filesystems cap how much is deduplicated per call
+        if range.bytes_deduped == 0 {
+            return Err(io::Error::other(format!(
+                "No progress deduplicating {dest:?}"
+            )));
+        }
+        offset += range.bytes_deduped;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn dedupe(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Block-level deduplication is only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "macos")]
+pub(super) fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let to_cstring = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (src, dest) = (to_cstring(src)?, to_cstring(dest)?);
+    // SAFETY: both paths are valid NUL terminated strings
+    if unsafe { libc::clonefile(src.as_ptr(), dest.as_ptr(), 0) } == -1 {
+        return Err(unsupported_or(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(super) fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reflinks are only supported on Linux and macOS",
+    ))
+}
+
+/// Makes errors from filesystems without reflink or dedupe support recognisable.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn unsupported_or(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL | libc::EXDEV) => io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Filesystem does not support sharing extents: {err}"),
+        ),
+        _ => err,
+    }
+}
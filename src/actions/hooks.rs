@@ -0,0 +1,172 @@
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+
+use super::LinkMode;
+
+/// Shell commands run around each replacement, e.g. to notify a media server. They're given the
+/// details of the replacement in `ATORRLINKER_*` environment variables.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    /// Runs before replacing. The target is left alone when it fails
+    pub pre: Option<String>,
+    /// Runs after replacing
+    pub post: Option<String>,
+}
+
+/// The replacement a hook is run for.
+#[derive(Clone, Copy)]
+pub struct HookEvent<'a> {
+    pub target: &'a Path,
+    pub source: &'a Path,
+    /// Empty for directories
+    pub hash: &'a str,
+    pub size: u64,
+    pub mode: LinkMode,
+}
+
+impl Hooks {
+    /// Runs the pre hook, if any, returning whether to go ahead with the replacement.
+    pub(super) fn pre(&self, event: &HookEvent) -> io::Result<bool> {
+        let Some(command) = &self.pre else {
+            return Ok(true);
+        };
+        let status = run(command, event, "pre")?;
+        if !status.success() {
+            tracing::warn!(
+                "Skipping {:?}: the pre hook exited with {status}",
+                event.target
+            );
+        }
+        Ok(status.success())
+    }
+
+    /// Runs the post hook, if any. Failures are only logged as the replacement already happened.
+    pub(super) fn post(&self, event: &HookEvent) {
+        let Some(command) = &self.post else {
+            return;
+        };
+        match run(command, event, "post") {
+            Ok(status) if status.success() => {}
+            Ok(status) => tracing::warn!("Post hook for {:?} exited with {status}", event.target),
+            Err(e) => tracing::warn!("Post hook for {:?} failed to run: {e}", event.target),
+        }
+    }
+}
+
+/// `command` run by the platform's shell.
+pub fn shell(command: &str) -> Command {
+    #[cfg(unix)]
+    let shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    };
+    #[cfg(windows)]
+    let shell = {
+        use std::os::windows::process::CommandExt;
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").raw_arg(command);
+        shell
+    };
+    shell
+}
+
+/// Runs a hook, logging what it prints rather than letting it onto stdout, where it would
+/// corrupt the JSON report.
+fn run(command: &str, event: &HookEvent, stage: &str) -> io::Result<ExitStatus> {
+    let output = shell(command)
+        .env("ATORRLINKER_HOOK", stage)
+        .env("ATORRLINKER_TARGET", event.target)
+        .env("ATORRLINKER_SOURCE", event.source)
+        .env("ATORRLINKER_HASH", event.hash)
+        .env("ATORRLINKER_SIZE", event.size.to_string())
+        .env(
+            "ATORRLINKER_MODE",
+            format!("{:?}", event.mode).to_lowercase(),
+        )
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        tracing::info!("{stage} hook for {:?}: {line}", event.target);
+    }
+    Ok(output.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let hooks = Hooks {
+            pre: Some("test \"$ATORRLINKER_SIZE\" -lt 100".to_owned()),
+            post: Some(format!(
+                "echo \"$ATORRLINKER_HOOK $ATORRLINKER_TARGET $ATORRLINKER_HASH $ATORRLINKER_MODE\" \
+                 >> {:?}",
+                log
+            )),
+        };
+        let event = |size| HookEvent {
+            target: Path::new("/target/file"),
+            source: Path::new("/source/file"),
+            hash: "abc",
+            size,
+            mode: LinkMode::Hardlink,
+        };
+
+        assert!(hooks.pre(&event(10)).unwrap());
+        assert!(!hooks.pre(&event(1000)).unwrap());
+        hooks.post(&event(10));
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "post /target/file abc hardlink\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_hook_stdout() {
+        // A hook writes to the stdout of the process, so the report is checked from a child
+        if std::env::var_os("ATORRLINKER_HOOK_STDOUT").is_some() {
+            let hooks = Hooks {
+                pre: Some("echo from the pre hook".to_owned()),
+                post: Some("echo from the post hook".to_owned()),
+            };
+            let event = HookEvent {
+                target: Path::new("/target/file"),
+                source: Path::new("/source/file"),
+                hash: "abc",
+                size: 10,
+                mode: LinkMode::Hardlink,
+            };
+            assert!(hooks.pre(&event).unwrap());
+            hooks.post(&event);
+            println!("{}", serde_json::json!({ "replaced": 1 }));
+            return;
+        }
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "actions::hooks::tests::test_hook_stdout",
+                "--nocapture",
+                "--quiet",
+            ])
+            .env("ATORRLINKER_HOOK_STDOUT", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!stdout.contains("hook"), "{stdout}");
+        let report = stdout.lines().find(|line| line.starts_with('{')).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(report).unwrap(),
+            serde_json::json!({ "replaced": 1 })
+        );
+    }
+}
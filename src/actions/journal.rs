@@ -0,0 +1,378 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use super::{LinkMode, metadata::FileMetadata, replace_with, summary::RunSummary, temp_sibling};
+use crate::hashing::{self, Hash};
+
+/// A target file as it was just before being replaced.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub source: PathBuf,
+    pub hash: Hash,
+    pub mode: LinkMode,
+    #[serde(flatten)]
+    pub metadata: FileMetadata,
+    pub replaced_at: SystemTime,
+}
+
+impl JournalEntry {
+    /// Captures the state of `path` before it gets replaced by `source`.
+    pub(super) fn capture(
+        path: &Path,
+        source: &Path,
+        hash: &str,
+        mode: LinkMode,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            source: source.to_path_buf(),
+            hash: hash.to_string(),
+            mode,
+            metadata: FileMetadata::capture(path)?,
+            replaced_at: SystemTime::now(),
+        })
+    }
+}
+
+/// A dangling symlink removed by `prune`, kept so it can be recreated by hand. Undo leaves these
+/// alone.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PrunedLink {
+    pub pruned: PathBuf,
+    /// What the link pointed at
+    pub link_target: PathBuf,
+    pub pruned_at: SystemTime,
+}
+
+/// A line of the journal: a replaced file, a pruned link or the totals of a finished run.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum JournalLine {
+    Summary { summary: RunSummary },
+    Pruned(PrunedLink),
+    Entry(JournalEntry),
+}
+
+/// Append-only record of every replacement, one JSON object per line, so it can be undone.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    /// Entries recorded through this journal, i.e. during the current run. Also keeps appends
+    /// from several threads apart
+    recorded: Mutex<Vec<JournalEntry>>,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            recorded: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn append(&self, line: &JournalLine) -> io::Result<()> {
+        let mut line = serde_json::to_vec(line)?;
+        line.push(b'\n');
+        (&self.file).write_all(&line)?;
+        self.file.sync_data()
+    }
+
+    /// Appends `entry`, flushing it to disk before the replacement goes ahead.
+    pub(super) fn record(&self, entry: &JournalEntry) -> io::Result<()> {
+        let mut recorded = self.recorded.lock().expect("Journal lock poisoned");
+        self.append(&JournalLine::Entry(entry.clone()))?;
+        recorded.push(entry.clone());
+        Ok(())
+    }
+
+    /// Removes the dangling symlink `link`, appending it first.
+    pub fn prune(&self, link: &Path) -> io::Result<()> {
+        self.append(&JournalLine::Pruned(PrunedLink {
+            pruned: link.to_path_buf(),
+            link_target: fs::read_link(link)?,
+            pruned_at: SystemTime::now(),
+        }))?;
+        fs::remove_file(link)
+    }
+
+    /// Appends the totals of a finished run.
+    pub fn record_summary(&self, summary: &RunSummary) -> io::Result<()> {
+        self.append(&JournalLine::Summary {
+            summary: summary.clone(),
+        })
+    }
+
+    /// The totals of every finished run recorded, oldest first.
+    pub fn summaries(&self) -> io::Result<Vec<RunSummary>> {
+        Ok(read_lines(&self.path)?
+            .into_iter()
+            .filter_map(|line| match line {
+                JournalLine::Summary { summary } => Some(summary),
+                JournalLine::Pruned(_) | JournalLine::Entry(_) => None,
+            })
+            .collect())
+    }
+
+    #[cfg(test)]
+    pub fn entries(&self) -> io::Result<Vec<JournalEntry>> {
+        Ok(read_lines(&self.path)?
+            .into_iter()
+            .filter_map(|line| match line {
+                JournalLine::Entry(entry) => Some(entry),
+                JournalLine::Summary { .. } | JournalLine::Pruned(_) => None,
+            })
+            .collect())
+    }
+
+    #[cfg(test)]
+    pub fn pruned(&self) -> io::Result<Vec<PrunedLink>> {
+        Ok(read_lines(&self.path)?
+            .into_iter()
+            .filter_map(|line| match line {
+                JournalLine::Pruned(link) => Some(link),
+                JournalLine::Summary { .. } | JournalLine::Entry(_) => None,
+            })
+            .collect())
+    }
+
+    /// Restores every journalled file under one of `paths` (or all of them when empty) by
+    /// copying the content back from what it links to. Undone entries are dropped from the
+    /// journal; returns the restored paths.
+    pub fn undo(self, paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+        self.undo_where(|entry| paths.is_empty() || paths.iter().any(|p| entry.path.starts_with(p)))
+    }
+
+    /// Undoes every replacement recorded during this run.
+    pub fn rollback(self) -> io::Result<Vec<PathBuf>> {
+        let recorded = std::mem::take(&mut *self.recorded.lock().expect("Journal lock poisoned"));
+        self.undo_where(|entry| recorded.contains(entry))
+    }
+
+    fn undo_where(self, selected: impl Fn(&JournalEntry) -> bool) -> io::Result<Vec<PathBuf>> {
+        let mut remaining = Vec::new();
+        let mut restored = Vec::new();
+        // Newest first, in case a path was replaced more than once
+        for line in read_lines(&self.path)?.into_iter().rev() {
+            let entry = match line {
+                JournalLine::Entry(entry) if selected(&entry) => entry,
+                line => {
+                    remaining.push(line);
+                    continue;
+                }
+            };
+            match restore(&entry) {
+                Ok(()) => restored.push(entry.path),
+                Err(e) => {
+                    tracing::error!("Could not restore {:?}: {e}", entry.path);
+                    remaining.push(JournalLine::Entry(entry));
+                }
+            }
+        }
+        remaining.reverse();
+        self.rewrite(&remaining)?;
+        Ok(restored)
+    }
+
+    fn rewrite(&self, lines: &[JournalLine]) -> io::Result<()> {
+        let tmp_path = temp_sibling(&self.path, "tmp");
+        let mut contents = Vec::new();
+        for line in lines {
+            contents.extend(serde_json::to_vec(line)?);
+            contents.push(b'\n');
+        }
+        fs::write(&tmp_path, contents)?;
+        replace_with(&tmp_path, &self.path)
+    }
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<JournalLine>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Puts an independent copy of the original file back at `entry.path`.
+fn restore(entry: &JournalEntry) -> io::Result<()> {
+    let tmp_path = temp_sibling(&entry.path, "tmp");
+    fs::copy(&entry.path, &tmp_path)?;
+    if !entry.hash.is_empty() && hashing::compute_file_hash(&tmp_path)? != entry.hash {
+        fs::remove_file(&tmp_path)?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} no longer has its original content", entry.path),
+        ));
+    }
+
+    entry.metadata.apply(&tmp_path)?;
+
+    replace_with(&tmp_path, &entry.path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{LinkOptions, link_file};
+    use crate::matching::{MatchReason, MatchingFile};
+
+    #[test]
+    #[cfg(unix)]
+    fn test_undo() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let target_file_path = dir.path().join("copied_file.txt");
+        fs::write(&src_file_path, "content").unwrap();
+        fs::write(&target_file_path, "content").unwrap();
+        fs::set_permissions(&target_file_path, fs::Permissions::from_mode(0o640)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&target_file_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let journal_path = dir.path().join("journal.jsonl");
+        let options = LinkOptions {
+            journal: Some(Journal::open(&journal_path).unwrap()),
+            ..Default::default()
+        };
+        let matching = MatchingFile::new(
+            src_file_path.clone(),
+            target_file_path.clone(),
+            7,
+            hashing::compute_file_hash(&src_file_path).unwrap(),
+            MatchReason::SourceScan,
+        );
+        link_file(&matching, &options).unwrap();
+
+        let journal = options.journal.unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 1);
+
+        let restored = journal.undo(&[]).unwrap();
+        let journal = Journal::open(&journal_path).unwrap();
+
+        assert_eq!(restored, vec![target_file_path.clone()]);
+        let metadata = fs::symlink_metadata(&target_file_path).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(fs::read_to_string(&target_file_path).unwrap(), "content");
+        assert!(journal.entries().unwrap().is_empty());
+        // The source is left alone
+        assert!(fs::symlink_metadata(&src_file_path).unwrap().is_file());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let src_file_path = dir.path().join("original_file.txt");
+        let earlier_path = dir.path().join("earlier.txt");
+        let current_path = dir.path().join("current.txt");
+        for path in [&src_file_path, &earlier_path, &current_path] {
+            fs::write(path, "content").unwrap();
+        }
+        let journal_path = dir.path().join("journal.jsonl");
+        let link = |target: &Path| {
+            let options = LinkOptions {
+                journal: Some(Journal::open(&journal_path).unwrap()),
+                ..Default::default()
+            };
+            let matching = MatchingFile::new(
+                src_file_path.clone(),
+                target.to_path_buf(),
+                7,
+                String::new(),
+                MatchReason::SourceScan,
+            );
+            link_file(&matching, &options).unwrap();
+            options.journal.unwrap()
+        };
+
+        // A previous run is left alone
+        link(&earlier_path)
+            .record_summary(&crate::actions::summary::RunSummary::new())
+            .unwrap();
+        let restored = link(&current_path).rollback().unwrap();
+
+        assert_eq!(restored, vec![current_path.clone()]);
+        assert!(fs::symlink_metadata(&current_path).unwrap().is_file());
+        assert!(fs::symlink_metadata(&earlier_path).unwrap().is_symlink());
+        let entries = Journal::open(&journal_path).unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, earlier_path);
+        // Run summaries are kept too
+        let lines = read_lines(&journal_path).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(matches!(lines[1], JournalLine::Summary { .. }));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_prune() {
+        use crate::matching::links::find_dangling_links;
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = fs::canonicalize(dir.path()).unwrap();
+        let (source_dir, target_dir) = (dir.join("source"), dir.join("target"));
+        let unmounted = dir.join("unmounted");
+        for path in [&source_dir, &target_dir, &unmounted] {
+            fs::create_dir(path).unwrap();
+        }
+        fs::write(source_dir.join("e01.mkv"), "episode").unwrap();
+        fs::write(target_dir.join("poster.jpg"), "poster").unwrap();
+        let link = |destination: &Path, name: &str| {
+            symlink(destination, target_dir.join(name)).unwrap();
+            target_dir.join(name)
+        };
+        let alive = link(&source_dir.join("e01.mkv"), "e01.mkv");
+        let removed = link(&source_dir.join("e02.mkv"), "e02.mkv");
+        let removed_dir = link(&source_dir.join("Season 2/e01.mkv"), "s02e01.mkv");
+        let outside = link(&dir.join("elsewhere/e01.mkv"), "elsewhere.mkv");
+        let into_unmounted = link(&unmounted.join("e01.mkv"), "unmounted.mkv");
+
+        let journal_path = dir.join("journal.jsonl");
+        let journal = Journal::open(&journal_path).unwrap();
+        let mut dangling = find_dangling_links(&[&source_dir, &unmounted], &[&target_dir]).unwrap();
+        dangling.sort();
+        assert_eq!(dangling, [removed.clone(), removed_dir.clone()]);
+        for link in &dangling {
+            journal.prune(link).unwrap();
+        }
+
+        for path in [&removed, &removed_dir] {
+            assert!(fs::symlink_metadata(path).is_err());
+        }
+        // Links still working, pointing outside the source paths or into what looks unmounted
+        // are kept, as are files
+        for path in [&alive, &outside, &into_unmounted] {
+            assert!(fs::symlink_metadata(path).unwrap().is_symlink());
+        }
+        assert!(target_dir.join("poster.jpg").is_file());
+        let pruned = journal.pruned().unwrap();
+        assert_eq!(pruned.len(), 2);
+        assert_eq!(pruned[0].pruned, removed);
+        assert_eq!(pruned[0].link_target, source_dir.join("e02.mkv"));
+
+        // Undo leaves pruned links alone
+        assert!(journal.undo(&[]).unwrap().is_empty());
+        assert!(fs::symlink_metadata(&removed).is_err());
+        assert_eq!(
+            Journal::open(&journal_path).unwrap().pruned().unwrap(),
+            pruned
+        );
+    }
+}
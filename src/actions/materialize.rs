@@ -0,0 +1,114 @@
+use std::{fs, io, path::Path};
+
+use super::{remove_link, replace_with, temp_sibling};
+
+/// Replaces the symlink `link` with a copy of `resolved`, the file or directory it points at, so
+/// it no longer depends on it. Returns the number of bytes copied.
+pub fn materialize(link: &Path, resolved: &Path) -> io::Result<u64> {
+    let tmp_path = &temp_sibling(link, "tmp");
+    if fs::metadata(resolved)?.is_dir() {
+        let target = fs::read_link(link)?;
+        let copied = match copy_tree(resolved, tmp_path) {
+            Ok(copied) => copied,
+            Err(e) => {
+                let _ = fs::remove_dir_all(tmp_path);
+                return Err(e);
+            }
+        };
+        // A directory can't be renamed over a symlink, so the link goes first, and is put back
+        // if the copy can't take its place
+        remove_link(link)?;
+        if let Err(e) = fs::rename(tmp_path, link) {
+            let _ = fs::remove_dir_all(tmp_path);
+            #[cfg(unix)]
+            let restored = std::os::unix::fs::symlink(&target, link);
+            #[cfg(windows)]
+            let restored = super::windows::symlink_dir(&target, resolved, link);
+            if let Err(restore) = restored {
+                tracing::error!("Couldn't restore the symlink {link:?} to {target:?}: {restore}");
+            }
+            return Err(e);
+        }
+        Ok(copied)
+    } else {
+        let copied = copy_file(resolved, tmp_path).inspect_err(|_| {
+            let _ = fs::remove_file(tmp_path);
+        })?;
+        replace_with(tmp_path, link)?;
+        Ok(copied)
+    }
+}
+
+/// Copies a file along with its permissions and modification time.
+fn copy_file(src: &Path, dest: &Path) -> io::Result<u64> {
+    let copied = fs::copy(src, dest)?;
+    fs::File::options()
+        .write(true)
+        .open(dest)?
+        .set_modified(fs::metadata(src)?.modified()?)?;
+    Ok(copied)
+}
+
+/// Copies the directory `src` to `dest`. Symlinks inside it are copied as symlinks.
+fn copy_tree(src: &Path, dest: &Path) -> io::Result<u64> {
+    fs::create_dir(dest)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dest = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copied += copy_tree(&entry.path(), &dest)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &dest)?;
+            #[cfg(not(unix))]
+            {
+                copied += copy_file(&entry.path(), &dest)?;
+            }
+        } else {
+            copied += copy_file(&entry.path(), &dest)?;
+        }
+    }
+    fs::set_permissions(dest, fs::metadata(src)?.permissions())?;
+    Ok(copied)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_materialize() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.mkv");
+        let source_dir = dir.path().join("season");
+        fs::write(&source, "episode").unwrap();
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("e01.mkv"), "e01").unwrap();
+        symlink("e01.mkv", source_dir.join("latest.mkv")).unwrap();
+        let file_link = dir.path().join("file_link");
+        let dir_link = dir.path().join("dir_link");
+        symlink(&source, &file_link).unwrap();
+        symlink(&source_dir, &dir_link).unwrap();
+
+        assert_eq!(materialize(&file_link, &source).unwrap(), 7);
+        assert!(fs::symlink_metadata(&file_link).unwrap().is_file());
+        assert_eq!(fs::read_to_string(&file_link).unwrap(), "episode");
+        assert_eq!(
+            fs::metadata(&file_link).unwrap().modified().unwrap(),
+            fs::metadata(&source).unwrap().modified().unwrap()
+        );
+
+        assert_eq!(materialize(&dir_link, &source_dir).unwrap(), 3);
+        assert!(fs::symlink_metadata(&dir_link).unwrap().is_dir());
+        assert_eq!(
+            fs::read_to_string(dir_link.join("latest.mkv")).unwrap(),
+            "e01"
+        );
+        // The originals are untouched
+        assert_eq!(fs::read_to_string(&source).unwrap(), "episode");
+        assert!(source_dir.join("e01.mkv").exists());
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+    time::SystemTime,
+};
+
+/// Metadata of a replaced file worth keeping for auditing and restoring.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FileMetadata {
+    pub modified: SystemTime,
+    pub accessed: SystemTime,
+    /// Unix permission bits, or just the read-only flag elsewhere
+    pub permissions: u32,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// Extended attributes as name and value
+    #[serde(default)]
+    pub xattrs: Vec<(String, Vec<u8>)>,
+}
+
+impl FileMetadata {
+    pub(super) fn capture(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        #[cfg(unix)]
+        let (permissions, uid, gid) = {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+            (
+                metadata.permissions().mode(),
+                Some(metadata.uid()),
+                Some(metadata.gid()),
+            )
+        };
+        #[cfg(not(unix))]
+        let (permissions, uid, gid) = (u32::from(metadata.permissions().readonly()), None, None);
+        Ok(Self {
+            modified: metadata.modified()?,
+            accessed: metadata.accessed()?,
+            permissions,
+            uid,
+            gid,
+            xattrs: xattr::list(path)?,
+        })
+    }
+
+    /// Applies the recorded metadata to the regular file at `path`. Ownership can only be given
+    /// away by root and is skipped when not permitted.
+    pub(super) fn apply(&self, path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Err(e) = std::os::unix::fs::chown(path, self.uid, self.gid) {
+            if e.kind() != io::ErrorKind::PermissionDenied {
+                return Err(e);
+            }
+            tracing::debug!("Not permitted to restore the owner of {path:?}");
+        }
+        for (name, value) in &self.xattrs {
+            match xattr::set(path, name, value) {
+                Err(e)
+                    if is_security_xattr(name) && e.kind() == io::ErrorKind::PermissionDenied =>
+                {
+                    tracing::warn!("Not permitted to restore {name} on {path:?}");
+                }
+                result => result?,
+            }
+        }
+
+        let file = File::options().write(true).open(path)?;
+        let mut permissions = file.metadata()?.permissions();
+        #[cfg(unix)]
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, self.permissions);
+        #[cfg(not(unix))]
+        permissions.set_readonly(self.permissions != 0);
+        file.set_times(
+            fs::FileTimes::new()
+                .set_modified(self.modified)
+                .set_accessed(self.accessed),
+        )?;
+        drop(file);
+        fs::set_permissions(path, permissions)
+    }
+}
+
+/// Whether the extended attribute `name` holds access control, like a POSIX ACL or SELinux label.
+fn is_security_xattr(name: &str) -> bool {
+    name.starts_with("security.") || name.starts_with("system.posix_acl_")
+}
+
+/// Names of the extended attributes of `path` holding access control. Links can't carry these
+/// for the file they replace.
+pub(super) fn security_xattrs(path: &Path) -> io::Result<Vec<String>> {
+    Ok(xattr::list(path)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| is_security_xattr(name))
+        .collect())
+}
+
+#[cfg(target_os = "linux")]
+mod xattr {
+    use std::{
+        ffi::{CStr, CString},
+        io,
+        os::unix::ffi::OsStrExt,
+        path::Path,
+    };
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Calls `f` with a buffer, first with none to learn the size needed.
+    fn read_sized(f: impl Fn(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let len = f(std::ptr::null_mut(), 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buf = vec![0u8; len as usize];
+            let read = f(buf.as_mut_ptr().cast(), buf.len());
+            if read >= 0 {
+                buf.truncate(read as usize);
+                return Ok(buf);
+            }
+            // The attribute grew between both calls, try again
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ERANGE) {
+                return Err(err);
+            }
+        }
+    }
+
+    pub(super) fn list(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let path = c_path(path)?;
+        // SAFETY: `path` is NUL terminated and `buf` is valid for `size` bytes
+        let names = match read_sized(|buf, size| unsafe {
+            libc::listxattr(path.as_ptr(), buf.cast(), size)
+        }) {
+            Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+            names => names?,
+        };
+
+        let mut xattrs = Vec::new();
+        for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+            let name = CString::new(name).expect("Split on NUL bytes");
+            // SAFETY: as above
+            let value = read_sized(|buf, size| unsafe {
+                libc::getxattr(path.as_ptr(), name.as_ptr(), buf, size)
+            })?;
+            xattrs.push((CStr::to_string_lossy(&name).into_owned(), value));
+        }
+        Ok(xattrs)
+    }
+
+    pub(super) fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = c_path(path)?;
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: both strings are NUL terminated and `value` is valid for its length
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod xattr {
+    use std::{io, path::Path};
+
+    pub(super) fn list(_path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+        Ok(Vec::new())
+    }
+
+    pub(super) fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_capture_apply() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("original");
+        let copy = dir.path().join("copy");
+        fs::write(&original, "content").unwrap();
+        fs::write(&copy, "content").unwrap();
+        fs::set_permissions(&original, fs::Permissions::from_mode(0o600)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&original)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        #[cfg(target_os = "linux")]
+        let has_xattr = xattr::set(&original, "user.origin", b"tracker").is_ok();
+
+        let metadata = FileMetadata::capture(&original).unwrap();
+        metadata.apply(&copy).unwrap();
+
+        let copied = fs::metadata(&copy).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o777, 0o600);
+        assert_eq!(copied.modified().unwrap(), modified);
+        assert_eq!(
+            FileMetadata::capture(&copy).unwrap(),
+            FileMetadata {
+                accessed: copied.accessed().unwrap(),
+                ..metadata.clone()
+            }
+        );
+        #[cfg(target_os = "linux")]
+        if has_xattr {
+            assert_eq!(
+                metadata.xattrs,
+                vec![("user.origin".to_string(), b"tracker".to_vec())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_security_xattrs() {
+        assert!(is_security_xattr("security.selinux"));
+        assert!(is_security_xattr("system.posix_acl_access"));
+        assert!(!is_security_xattr("user.origin"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "content").unwrap();
+        assert!(security_xattrs(&path).unwrap().is_empty());
+        // Only possible with privileges on a filesystem supporting them
+        #[cfg(target_os = "linux")]
+        if xattr::set(&path, "security.atorrlinker", b"label").is_ok() {
+            let _ = xattr::set(&path, "user.origin", b"tracker");
+            assert_eq!(security_xattrs(&path).unwrap(), ["security.atorrlinker"]);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::{io, str::FromStr, thread, time::Duration};
+
+/// Retries when `retry` is given without a count
+const DEFAULT_RETRIES: u32 = 3;
+/// Wait before the first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Doublings of the wait before it stops growing, at 6.4s
+const MAX_BACKOFF: u32 = 6;
+
+/// What to do when replacing a single target fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// End the run
+    #[default]
+    Abort,
+    /// Report the target and carry on with the rest
+    Skip,
+    /// Try again up to this many times before ending the run
+    Retry(u32),
+}
+
+impl FromStr for OnError {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "abort" => Ok(Self::Abort),
+            None if s == "skip" => Ok(Self::Skip),
+            None if s == "retry" => Ok(Self::Retry(DEFAULT_RETRIES)),
+            Some(("retry", count)) => count
+                .parse()
+                .map(Self::Retry)
+                .map_err(|_| format!("Invalid retry count {count:?}")),
+            _ => Err(format!(
+                "Invalid error policy {s:?}: expected abort, skip or retry[:N]"
+            )),
+        }
+    }
+}
+
+impl OnError {
+    /// Calls `f` until it succeeds or runs out of retries. Missing privileges won't go away by
+    /// trying again, so those are never retried.
+    pub fn retry<T>(self, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let retries = match self {
+            Self::Retry(retries) => retries,
+            Self::Abort | Self::Skip => 0,
+        };
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < retries && !super::needs_privileges(&e) => {
+                    tracing::warn!("Retrying ({}/{retries}) after: {e}", attempt + 1);
+                    thread::sleep(retry_delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// How long to wait before retrying after `attempt` retries.
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_DELAY.saturating_mul(1 << attempt.min(MAX_BACKOFF))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("abort".parse(), Ok(OnError::Abort));
+        assert_eq!("skip".parse(), Ok(OnError::Skip));
+        assert_eq!("retry".parse(), Ok(OnError::Retry(DEFAULT_RETRIES)));
+        assert_eq!("retry:5".parse(), Ok(OnError::Retry(5)));
+        assert!("retry:many".parse::<OnError>().is_err());
+        assert!("ignore".parse::<OnError>().is_err());
+    }
+
+    #[test]
+    fn test_retry() {
+        let mut calls = 0;
+        let result = OnError::Retry(2).retry(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::other("flaky"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = OnError::Abort.retry(|| {
+            calls += 1;
+            Err(io::Error::other("broken"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        assert_eq!(retry_delay(0), RETRY_DELAY);
+        assert_eq!(retry_delay(3), RETRY_DELAY * 8);
+        // Long runs of retries wait the longest delay without overflowing
+        assert_eq!(retry_delay(40), Duration::from_millis(6400));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_millis(6400));
+    }
+}
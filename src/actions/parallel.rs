@@ -0,0 +1,143 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    num::NonZeroUsize,
+    path::Path,
+    sync::mpsc,
+    thread,
+};
+
+/// How many items each worker may have queued before the producer waits for it
+const QUEUE_LEN: usize = 64;
+
+/// Runs `work` for each of `items` on `jobs` threads, handing the results to `done` on the
+/// calling thread as they come in. Items sharing a `key` go to the same thread in order, so
+/// changes within one directory never race each other.
+///
+/// Stops taking new items after the first error from `items` or `done`, but still hands the
+/// result of every item already started to `done` before returning that error.
+pub fn run_keyed<T: Send, R: Send>(
+    jobs: NonZeroUsize,
+    items: impl Iterator<Item = io::Result<T>>,
+    key: impl Fn(&T) -> &Path,
+    work: impl Fn(&T) -> R + Sync,
+    mut done: impl FnMut(T, R) -> io::Result<()>,
+) -> io::Result<()> {
+    thread::scope(|scope| {
+        let work = &work;
+        let (result_tx, result_rx) = mpsc::channel();
+        let senders: Vec<_> = (0..jobs.get())
+            .map(|_| {
+                let (tx, rx) = mpsc::sync_channel::<T>(QUEUE_LEN);
+                let result_tx = result_tx.clone();
+                scope.spawn(move || {
+                    for item in rx {
+                        let result = work(&item);
+                        if result_tx.send((item, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                tx
+            })
+            .collect();
+        drop(result_tx);
+
+        let mut first_error = None;
+        let mut finish = |(item, result), first_error: &mut Option<io::Error>| {
+            if let Err(e) = done(item, result) {
+                first_error.get_or_insert(e);
+            }
+        };
+        for item in items {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    first_error = Some(e);
+                    break;
+                }
+            };
+            let mut hasher = DefaultHasher::new();
+            key(&item).hash(&mut hasher);
+            let worker = (hasher.finish() % jobs.get() as u64) as usize;
+            senders[worker]
+                .send(item)
+                .expect("Workers run until their queue is closed");
+            for finished in result_rx.try_iter() {
+                finish(finished, &mut first_error);
+            }
+            if first_error.is_some() {
+                break;
+            }
+        }
+        // Let the workers finish their queues and wait for the last results
+        drop(senders);
+        for finished in result_rx {
+            finish(finished, &mut first_error);
+        }
+        first_error.map_or(Ok(()), Err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_run_keyed() {
+        let jobs = NonZeroUsize::new(4).unwrap();
+        let items = (0..200).map(|i| Ok((PathBuf::from(format!("dir{}", i % 7)), i)));
+        let started = Mutex::new(HashMap::<PathBuf, Vec<i32>>::new());
+        let mut finished = 0;
+        run_keyed(
+            jobs,
+            items,
+            |(dir, _)| dir,
+            |(dir, i)| {
+                let mut started = started.lock().unwrap();
+                started.entry(dir.clone()).or_default().push(*i);
+                i * 2
+            },
+            |(_, i), doubled| {
+                assert_eq!(doubled, i * 2);
+                finished += 1;
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(finished, 200);
+        // Each directory's items ran in their original order
+        for order in started.into_inner().unwrap().values() {
+            assert!(order.is_sorted());
+        }
+    }
+
+    #[test]
+    fn test_run_keyed_error() {
+        let jobs = NonZeroUsize::new(2).unwrap();
+        let items = (0..1000).map(|i| Ok((PathBuf::from(format!("dir{}", i % 3)), i)));
+        let mut finished = 0;
+        let err = run_keyed(
+            jobs,
+            items,
+            |(dir, _)| dir,
+            |_| (),
+            |(_, i), ()| {
+                finished += 1;
+                if i == 10 {
+                    return Err(io::Error::other("failed"));
+                }
+                Ok(())
+            },
+        )
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "failed");
+        // No new items after the error, though some may have been queued already
+        assert!(finished < 1000);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, Write},
+    path::Path,
+};
+
+use ratatui::crossterm::style::{Color, Stylize};
+
+use super::{Applied, LinkMode, saved_bytes, script::Script};
+use crate::matching::MatchingFile;
+use crate::matching::directories::DirectoryMatch;
+use crate::template::Template;
+
+/// The fields a `--format` template can use for each planned action.
+pub const TEMPLATE_FIELDS: &[&str] = &["dest", "src", "size", "saved", "hash", "mode", "kind"];
+
+/// How a dry run reports its planned actions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// A listing of the actions grouped by target directory
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+/// A single replacement a dry run would have made, or a real run attempted.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlannedAction {
+    pub action: LinkMode,
+    pub directory: bool,
+    pub target: String,
+    pub source: String,
+    pub size: u64,
+    /// Bytes freed by the replacement, zero when the target is already a link
+    pub saved_bytes: u64,
+    pub hash: String,
+    /// What happened to it in a real run: replaced, skipped or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PlannedAction {
+    /// Records the outcome of attempting the action.
+    fn with_result(self, result: &io::Result<Applied>) -> Self {
+        match result {
+            Ok(Applied::Replaced { saved_bytes, mode }) => Self {
+                action: *mode,
+                saved_bytes: *saved_bytes,
+                result: Some("replaced"),
+                ..self
+            },
+            Ok(Applied::Skipped) => Self {
+                saved_bytes: 0,
+                result: Some("skipped"),
+                ..self
+            },
+            Err(e) => Self {
+                saved_bytes: 0,
+                result: Some("failed"),
+                error: Some(e.to_string()),
+                ..self
+            },
+        }
+    }
+
+    /// The value of one of [`TEMPLATE_FIELDS`].
+    fn field(&self, field: &str) -> String {
+        match field {
+            "dest" => self.target.clone(),
+            "src" => self.source.clone(),
+            "size" => self.size.to_string(),
+            "saved" => self.saved_bytes.to_string(),
+            "hash" => self.hash.clone(),
+            "mode" => format!("{:?}", self.action).to_lowercase(),
+            "kind" if self.directory => "directory".to_owned(),
+            "kind" => "file".to_owned(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Collects the actions of a dry run along with the space they would save.
+#[derive(Debug)]
+pub struct Plan {
+    format: OutputFormat,
+    mode: LinkMode,
+    actions: Vec<PlannedAction>,
+    saved_bytes: u64,
+    /// Also writes the actions out as a shell script
+    script: Option<Script>,
+    /// Color the text listing
+    color: bool,
+    /// Writes a line per action from this instead of the text listing
+    template: Option<Template>,
+}
+
+impl Plan {
+    pub fn new(format: OutputFormat, mode: LinkMode) -> Self {
+        Self {
+            format,
+            mode,
+            actions: Vec::new(),
+            saved_bytes: 0,
+            script: None,
+            color: false,
+            template: None,
+        }
+    }
+
+    pub fn with_template(self, template: Option<Template>) -> Self {
+        Self { template, ..self }
+    }
+
+    pub fn with_color(self, color: bool) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_script(self, script: Script) -> Self {
+        Self {
+            script: Some(script),
+            ..self
+        }
+    }
+
+    pub fn file(&mut self, matching_file: &MatchingFile) -> io::Result<()> {
+        if let Some(script) = &mut self.script {
+            script.file(matching_file)?;
+        }
+        let saved = saved_bytes(
+            matching_file.src_path(),
+            matching_file.dest_path(),
+            matching_file.size(),
+        );
+        self.saved_bytes += saved;
+        tracing::debug!(
+            "Match {:?}: hash {}, {} bytes, decided by {:?}",
+            matching_file.dest_path(),
+            matching_file.hash(),
+            matching_file.size(),
+            matching_file.reason()
+        );
+        self.actions.push(self.file_action(matching_file, saved));
+        Ok(())
+    }
+
+    fn file_action(&self, matching_file: &MatchingFile, saved_bytes: u64) -> PlannedAction {
+        PlannedAction {
+            action: self.mode,
+            directory: false,
+            target: matching_file.dest_path().to_string_lossy().into_owned(),
+            source: matching_file.src_path().to_string_lossy().into_owned(),
+            size: matching_file.size(),
+            saved_bytes,
+            hash: matching_file.hash().to_string(),
+            result: None,
+            error: None,
+        }
+    }
+
+    pub fn directory(&mut self, directory: &DirectoryMatch) -> io::Result<()> {
+        if let Some(script) = &mut self.script {
+            script.directory(directory)?;
+        }
+        self.saved_bytes += directory.size;
+        self.actions.push(directory_action(directory));
+        Ok(())
+    }
+
+    /// Keeps the result of actually replacing `matching_file`, when reporting as JSON.
+    pub fn applied_file(&mut self, matching_file: &MatchingFile, result: &io::Result<Applied>) {
+        if self.format == OutputFormat::Json {
+            let action = self.file_action(matching_file, matching_file.size());
+            self.actions.push(action.with_result(result));
+        }
+    }
+
+    /// Keeps the result of actually replacing `directory`, when reporting as JSON.
+    pub fn applied_directory(&mut self, directory: &DirectoryMatch, result: &io::Result<Applied>) {
+        if self.format == OutputFormat::Json {
+            self.actions
+                .push(directory_action(directory).with_result(result));
+        }
+    }
+
+    /// Writes the script, if any, and returns the actions collected along with the projected
+    /// saving of a dry run.
+    pub fn into_actions(self) -> io::Result<(Vec<PlannedAction>, u64)> {
+        if let Some(script) = self.script {
+            script.finish(self.saved_bytes)?;
+        }
+        Ok((self.actions, self.saved_bytes))
+    }
+
+    /// Writes out everything collected along with the projected saving.
+    pub fn finish(self, mut out: impl Write) -> io::Result<()> {
+        let format = self.format;
+        let color = self.color;
+        let template = self.template.clone();
+        let (actions, saved_bytes) = self.into_actions()?;
+        match format {
+            OutputFormat::Text if let Some(template) = template => {
+                for action in &actions {
+                    writeln!(out, "{}", template.render(|field| action.field(field)))?;
+                }
+            }
+            OutputFormat::Text => {
+                write_listing(&mut out, &actions, color)?;
+                writeln!(out, "Projected saving: {saved_bytes} bytes")?;
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(
+                    &mut out,
+                    &serde_json::json!({
+                        "actions": actions,
+                        "projected_saved_bytes": saved_bytes,
+                    }),
+                )?;
+                writeln!(out)?;
+            }
+            OutputFormat::Csv => {
+                writeln!(out, "action,directory,target,source,size,saved_bytes,hash")?;
+                for action in &actions {
+                    writeln!(
+                        out,
+                        "{},{},{},{},{},{},{}",
+                        serde_json::to_value(action.action)?
+                            .as_str()
+                            .unwrap_or_default(),
+                        action.directory,
+                        csv_field(&action.target),
+                        csv_field(&action.source),
+                        action.size,
+                        action.saved_bytes,
+                        action.hash
+                    )?;
+                }
+                writeln!(out, "total,,,,,{saved_bytes},")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `actions` grouped by the directory of their target, diff style: each target is
+/// marked `-` when its data goes away, or `~` when it is already a link that only changes.
+fn write_listing(out: &mut impl Write, actions: &[PlannedAction], color: bool) -> io::Result<()> {
+    let paint = |text: &dyn Display, fg: Color| {
+        if color {
+            text.to_string().with(fg).to_string()
+        } else {
+            text.to_string()
+        }
+    };
+    let mut by_dir: BTreeMap<&Path, Vec<&PlannedAction>> = BTreeMap::new();
+    for action in actions {
+        let target = Path::new(&action.target);
+        by_dir
+            .entry(target.parent().unwrap_or(target))
+            .or_default()
+            .push(action);
+    }
+    for (dir, actions) in by_dir {
+        let header = format!("{}/", dir.display());
+        if color {
+            writeln!(out, "{}", header.bold())?;
+        } else {
+            writeln!(out, "{header}")?;
+        }
+        for action in actions {
+            let name = Path::new(&action.target).file_name().map_or_else(
+                || action.target.clone(),
+                |name| name.to_string_lossy().into(),
+            );
+            let (marker, fg) = if action.saved_bytes == 0 {
+                ("~", Color::Yellow)
+            } else {
+                ("-", Color::Red)
+            };
+            let (slash, kind) = if action.directory {
+                ("/", "directory ")
+            } else {
+                ("", "")
+            };
+            let mode = format!("{:?}", action.action).to_lowercase();
+            writeln!(
+                out,
+                "  {} {} {}  {}",
+                paint(&format_args!("{marker} {name}{slash}"), fg),
+                paint(&"→", Color::DarkGrey),
+                paint(&action.source, Color::Green),
+                paint(
+                    &format_args!("{} bytes, {kind}{mode}", action.size),
+                    Color::DarkGrey
+                )
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn directory_action(directory: &DirectoryMatch) -> PlannedAction {
+    PlannedAction {
+        action: LinkMode::Symlink,
+        directory: true,
+        target: directory.dest_dir.to_string_lossy().into_owned(),
+        source: directory.src_dir.to_string_lossy().into_owned(),
+        size: directory.size,
+        saved_bytes: directory.size,
+        hash: String::new(),
+        result: None,
+        error: None,
+    }
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+    use std::fs;
+
+    fn plan_for(format: OutputFormat) -> (tempfile::TempDir, Plan) {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source.mkv");
+        let copy = dir.path().join("copy, 1.mkv");
+        let link = dir.path().join("link.mkv");
+        fs::write(&src, "episode").unwrap();
+        fs::write(&copy, "episode").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&src, &link).unwrap();
+
+        let mut plan = Plan::new(format, LinkMode::Symlink);
+        for dest in [copy, link] {
+            plan.file(&MatchingFile::new(
+                src.clone(),
+                dest,
+                7,
+                "ABC".to_string(),
+                MatchReason::SourceScan,
+            ))
+            .unwrap();
+        }
+        (dir, plan)
+    }
+
+    #[test]
+    fn test_plan_json() {
+        let (_dir, plan) = plan_for(OutputFormat::Json);
+        let mut out = Vec::new();
+        plan.finish(&mut out).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["actions"].as_array().unwrap().len(), 2);
+        assert_eq!(json["actions"][0]["action"], "symlink");
+        assert_eq!(json["actions"][0]["saved_bytes"], 7);
+        // Replacing an existing link saves nothing
+        #[cfg(unix)]
+        assert_eq!(json["actions"][1]["saved_bytes"], 0);
+        assert_eq!(json["projected_saved_bytes"], 7);
+    }
+
+    #[test]
+    fn test_applied_json() {
+        let matching = MatchingFile::new(
+            "/source.mkv".into(),
+            "/copy.mkv".into(),
+            7,
+            "ABC".to_string(),
+            MatchReason::SourceScan,
+        );
+        let mut plan = Plan::new(OutputFormat::Json, LinkMode::Reflink);
+        plan.applied_file(
+            &matching,
+            &Ok(Applied::Replaced {
+                saved_bytes: 7,
+                mode: LinkMode::Hardlink,
+            }),
+        );
+        plan.applied_file(&matching, &Ok(Applied::Skipped));
+        plan.applied_file(&matching, &Err(io::Error::other("disk full")));
+
+        let json = serde_json::to_value(plan.into_actions().unwrap().0).unwrap();
+        assert_eq!(json[0]["action"], "hardlink");
+        assert_eq!(json[0]["result"], "replaced");
+        assert_eq!(json[0]["saved_bytes"], 7);
+        assert_eq!(json[1]["result"], "skipped");
+        assert_eq!(json[1]["saved_bytes"], 0);
+        assert_eq!(json[2]["action"], "reflink");
+        assert_eq!(json[2]["result"], "failed");
+        assert_eq!(json[2]["error"], "disk full");
+
+        // Text output prints as it goes instead
+        let mut plan = Plan::new(OutputFormat::Text, LinkMode::Symlink);
+        plan.applied_file(&matching, &Ok(Applied::Skipped));
+        assert!(plan.into_actions().unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_plan_text() {
+        let (dir, plan) = plan_for(OutputFormat::Text);
+        let mut out = Vec::new();
+        plan.finish(&mut out).unwrap();
+
+        let source = dir.path().join("source.mkv");
+        let mut expected = vec![
+            format!("{}/", dir.path().display()),
+            format!("  - copy, 1.mkv → {}  7 bytes, symlink", source.display()),
+        ];
+        // Replacing an existing link is marked apart
+        #[cfg(unix)]
+        expected.push(format!(
+            "  ~ link.mkv → {}  7 bytes, symlink",
+            source.display()
+        ));
+        expected.push("Projected saving: 7 bytes".to_owned());
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().collect::<Vec<_>>(),
+            expected
+        );
+
+        let (_dir, plan) = plan_for(OutputFormat::Text);
+        let mut out = Vec::new();
+        plan.with_color(true).finish(&mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\x1b["));
+
+        let (dir, plan) = plan_for(OutputFormat::Text);
+        let template = crate::template::parse_template(r"{dest}\t{saved}").unwrap();
+        let mut out = Vec::new();
+        plan.with_template(Some(template)).finish(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().next().unwrap(),
+            format!("{}\t7", dir.path().join("copy, 1.mkv").display())
+        );
+    }
+
+    #[test]
+    fn test_plan_csv() {
+        let (dir, plan) = plan_for(OutputFormat::Csv);
+        let mut out = Vec::new();
+        plan.finish(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "action,directory,target,source,size,saved_bytes,hash"
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "symlink,false,\"{}\",{},7,7,ABC",
+                dir.path().join("copy, 1.mkv").display(),
+                dir.path().join("source.mkv").display()
+            )
+        );
+        assert_eq!(lines.last().unwrap(), &"total,,,,,7,");
+    }
+}
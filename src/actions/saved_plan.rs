@@ -0,0 +1,173 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::matching::{FileState, MatchingFile};
+
+/// A match along with the state of its files when it was planned.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct SavedMatch {
+    #[serde(flatten)]
+    matching: MatchingFile,
+    states: Option<(FileState, FileState)>,
+}
+
+/// The matches of a run saved for review, to be applied later by the `apply` command.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedPlan {
+    pub sources: Vec<PathBuf>,
+    pub targets: Vec<PathBuf>,
+    matches: Vec<SavedMatch>,
+}
+
+impl SavedPlan {
+    pub fn new(sources: &[PathBuf], targets: &[PathBuf], matches: &[MatchingFile]) -> Self {
+        Self {
+            sources: sources.to_vec(),
+            targets: targets.to_vec(),
+            matches: matches
+                .iter()
+                .map(|matching| SavedMatch {
+                    matching: matching.clone(),
+                    states: matching.states(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid plan {path:?}: {e}"),
+            )
+        })
+    }
+
+    pub fn matches(&self) -> impl Iterator<Item = &MatchingFile> {
+        self.matches.iter().map(|saved| &saved.matching)
+    }
+
+    /// Keeps only the matches at `entries`, indices into [`SavedPlan::matches`], to apply part
+    /// of the plan. Fails on an index past the end.
+    pub fn select(mut self, entries: &[usize]) -> io::Result<Self> {
+        if let Some(entry) = entries.iter().find(|&&entry| entry >= self.matches.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The plan has no entry {entry}"),
+            ));
+        }
+        let mut index = 0;
+        self.matches.retain(|_| {
+            index += 1;
+            entries.contains(&(index - 1))
+        });
+        Ok(self)
+    }
+
+    /// The planned matches, each still carrying the state its files were in, so any changed
+    /// since are skipped when applying.
+    pub fn into_matches(self) -> Vec<MatchingFile> {
+        self.matches
+            .into_iter()
+            .map(|saved| saved.matching.with_recorded_states(saved.states))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+    use std::fs;
+
+    #[test]
+    fn test_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("source.mkv"), dir.path().join("copy.mkv"));
+        fs::write(&src, "episode").unwrap();
+        fs::write(&dest, "episode").unwrap();
+        let matching = MatchingFile::new(
+            src.clone(),
+            dest.clone(),
+            7,
+            "ABC".to_owned(),
+            MatchReason::SourceScan,
+        )
+        .with_states()
+        .unwrap();
+
+        let path = dir.path().join("plan.json");
+        let states = matching.states();
+        SavedPlan::new(
+            &[dir.path().to_path_buf()],
+            &[dir.path().to_path_buf()],
+            &[matching],
+        )
+        .save(&path)
+        .unwrap();
+        let plan = SavedPlan::load(&path).unwrap();
+        assert_eq!(plan.sources, [dir.path()]);
+        assert_eq!(plan.targets, [dir.path()]);
+        assert_eq!(plan.matches().count(), 1);
+        let plan = plan.select(&[0]).unwrap();
+        let matches = plan.into_matches();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].dest_path(), dest);
+        assert_eq!(matches[0].hash(), "ABC");
+        assert_eq!(matches[0].states(), states);
+
+        let plan = SavedPlan::load(&path).unwrap();
+        assert!(plan.select(&[1]).is_err());
+        let plan = SavedPlan::load(&path).unwrap().select(&[]).unwrap();
+        assert_eq!(plan.matches().count(), 0);
+
+        fs::write(&path, "not a plan").unwrap();
+        assert!(SavedPlan::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let matches: Vec<_> = ["e01.mkv", "e02.mkv", "e03.mkv"]
+            .map(|name| {
+                MatchingFile::new(
+                    Path::new("/source").join(name),
+                    Path::new("/target").join(name),
+                    7,
+                    String::new(),
+                    MatchReason::SourceScan,
+                )
+            })
+            .into();
+        let plan = || SavedPlan::new(&[], &[], &matches);
+        let picked = |entries: &[usize]| -> Vec<PathBuf> {
+            plan()
+                .select(entries)
+                .unwrap()
+                .matches()
+                .map(|matching| matching.dest_path().to_path_buf())
+                .collect()
+        };
+
+        // Entries keep their order in the plan, however they're given
+        assert_eq!(
+            picked(&[2, 0]),
+            [Path::new("/target/e01.mkv"), Path::new("/target/e03.mkv")]
+        );
+        assert_eq!(picked(&[1, 1]), [Path::new("/target/e02.mkv")]);
+        assert_eq!(picked(&[0, 1, 2]).len(), 3);
+        let e = plan().select(&[0, 3]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(e.to_string(), "The plan has no entry 3");
+        assert!(plan().select(&[usize::MAX]).is_err());
+    }
+}
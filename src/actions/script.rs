@@ -0,0 +1,277 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{LinkMode, symlink_target, temp_sibling};
+use crate::matching::MatchingFile;
+use crate::matching::directories::DirectoryMatch;
+
+/// A shell script of the exact commands a run would execute, for review before running it.
+#[derive(Debug)]
+pub struct Script {
+    path: PathBuf,
+    mode: LinkMode,
+    relative: bool,
+    body: Vec<u8>,
+    replacements: usize,
+}
+
+impl Script {
+    pub fn new(path: &Path, mode: LinkMode, relative: bool) -> io::Result<Self> {
+        if mode == LinkMode::Dedupe {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Deduplication has no shell command to put in a script",
+            ));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode,
+            relative,
+            body: Vec::new(),
+            replacements: 0,
+        })
+    }
+
+    pub fn file(&mut self, matching_file: &MatchingFile) -> io::Result<()> {
+        let (src, dest) = (matching_file.src_path(), matching_file.dest_path());
+        let tmp_path = temp_sibling(dest, "tmp");
+        writeln!(
+            self.body,
+            "\n# Duplicate of {} ({} bytes, hash {})",
+            src.display(),
+            matching_file.size(),
+            matching_file.hash()
+        )?;
+        self.guard(&["cmp", "-s", "--"], src, dest)?;
+        match self.mode {
+            LinkMode::Symlink => {
+                self.command(
+                    &["ln", "-s", "--"],
+                    &[&symlink_target(src, dest, self.relative)?, &tmp_path],
+                )?;
+            }
+            LinkMode::Hardlink => self.command(&["ln", "--"], &[src, &tmp_path])?,
+            // GNU coreutils only. The clone keeps the target's own metadata, not the source's
+            LinkMode::Reflink => {
+                self.command(&["cp", "--reflink=always", "--"], &[src, &tmp_path])?;
+                self.command_or_carry_on(&["chown", "--reference"], &[dest, &tmp_path])?;
+                self.command(&["chmod", "--reference"], &[dest, &tmp_path])?;
+                self.command(&["touch", "-r"], &[dest, &tmp_path])?;
+            }
+            LinkMode::Dedupe => unreachable!("Refused when creating the script"),
+        }
+        self.command(&["mv", "-f", "--"], &[&tmp_path, dest])?;
+        self.end_guard(dest)?;
+        self.replacements += 1;
+        Ok(())
+    }
+
+    pub fn directory(&mut self, directory: &DirectoryMatch) -> io::Result<()> {
+        let (src, dest) = (&directory.src_dir, &directory.dest_dir);
+        let old_path = temp_sibling(dest, "old");
+        writeln!(
+            self.body,
+            "\n# Directory duplicate of {} ({} bytes)",
+            src.display(),
+            directory.size
+        )?;
+        self.guard(&["diff", "-r", "--"], src, dest)?;
+        self.command(&["mv", "--"], &[dest, &old_path])?;
+        self.command(
+            &["ln", "-s", "--"],
+            &[&symlink_target(src, dest, self.relative)?, dest],
+        )?;
+        self.command(&["rm", "-rf", "--"], &[&old_path])?;
+        self.end_guard(dest)?;
+        self.replacements += 1;
+        Ok(())
+    }
+
+    /// Opens the block of commands replacing `dest`, run only if `compare` finds it still has the
+    /// content of `src`. Files may have changed between the review and running the script.
+    fn guard(&mut self, compare: &[&str], src: &Path, dest: &Path) -> io::Result<()> {
+        self.body.write_all(b"if ")?;
+        self.body.write_all(compare.join(" ").as_bytes())?;
+        for path in [src, dest] {
+            self.body.push(b' ');
+            quote(path, &mut self.body);
+        }
+        self.body.write_all(b" >/dev/null 2>&1; then\n")?;
+        Ok(())
+    }
+
+    fn end_guard(&mut self, dest: &Path) -> io::Result<()> {
+        self.body.write_all(b"else\n  echo \"Skipping \"")?;
+        quote(dest, &mut self.body);
+        self.body
+            .write_all(b"\", it no longer matches its source\" >&2\nfi\n")?;
+        Ok(())
+    }
+
+    fn command(&mut self, command: &[&str], paths: &[&Path]) -> io::Result<()> {
+        self.body.write_all(b"  ")?;
+        self.body.write_all(command.join(" ").as_bytes())?;
+        for path in paths {
+            self.body.push(b' ');
+            quote(path, &mut self.body);
+        }
+        self.body.push(b'\n');
+        Ok(())
+    }
+
+    /// Like `command`, but carries on when it fails, as for what only root may do.
+    fn command_or_carry_on(&mut self, command: &[&str], paths: &[&Path]) -> io::Result<()> {
+        self.command(command, paths)?;
+        self.body.pop();
+        self.body.write_all(b" 2>/dev/null || true\n")?;
+        Ok(())
+    }
+
+    /// Writes the script out, executable, with a header explaining it.
+    pub fn finish(self, saved_bytes: u64) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(&self.path)?);
+        writeln!(out, "#!/bin/sh")?;
+        writeln!(
+            out,
+            "# {} replacements planned by atorrlinker-undup, reclaiming {saved_bytes} bytes.",
+            self.replacements
+        )?;
+        writeln!(
+            out,
+            "# Review them before running. Targets whose content no longer matches their source \
+             are\n# skipped. Each file link is made under a temporary name and then renamed over \
+             its target\n# so the file is never missing, but a directory is moved aside before \
+             its symlink takes its\n# place and is briefly missing. Stops at the first failing \
+             command."
+        )?;
+        writeln!(out, "set -eu")?;
+        out.write_all(&self.body)?;
+        out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends `path` single quoted for the shell, which keeps every byte but `'` literal.
+fn quote(path: &Path, out: &mut Vec<u8>) {
+    out.push(b'\'');
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte == b'\'' {
+            out.extend_from_slice(b"'\\''");
+        } else {
+            out.push(byte);
+        }
+    }
+    out.push(b'\'');
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+    use std::process::Command;
+
+    #[test]
+    fn test_quote() {
+        let mut out = Vec::new();
+        quote(Path::new("/media/it's here"), &mut out);
+        assert_eq!(out, b"'/media/it'\\''s here'");
+    }
+
+    #[test]
+    fn test_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source's.mkv");
+        let dest = dir.path().join("copy $(touch pwned).mkv");
+        fs::write(&src, "episode").unwrap();
+        fs::write(&dest, "episode").unwrap();
+        let script_path = dir.path().join("plan.sh");
+
+        let mut script = Script::new(&script_path, LinkMode::Symlink, false).unwrap();
+        script
+            .file(&MatchingFile::new(
+                src.clone(),
+                dest.clone(),
+                7,
+                "ABC".to_string(),
+                MatchReason::SourceScan,
+            ))
+            .unwrap();
+        // Changed after the plan was made, so left alone
+        let changed = dir.path().join("changed.mkv");
+        fs::write(&changed, "episode").unwrap();
+        script
+            .file(&MatchingFile::new(
+                src.clone(),
+                changed.clone(),
+                7,
+                "ABC".to_string(),
+                MatchReason::SourceScan,
+            ))
+            .unwrap();
+        fs::write(&changed, "edited").unwrap();
+        script.finish(14).unwrap();
+
+        let output = Command::new(&script_path).output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(fs::read_link(&dest).unwrap(), src);
+        assert!(!dir.path().join("pwned").exists());
+        assert_eq!(fs::read_to_string(&changed).unwrap(), "edited");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping"));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn test_reflink_script() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source.mkv");
+        let dest = dir.path().join("copy.mkv");
+        fs::write(&src, "episode").unwrap();
+        if crate::actions::clone::reflink(&src, &dir.path().join("probe")).is_err() {
+            eprintln!("Skipping, reflinks aren't supported here");
+            return;
+        }
+        fs::write(&dest, "episode").unwrap();
+        fs::set_permissions(&dest, fs::Permissions::from_mode(0o640)).unwrap();
+        let modified =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&dest)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let script_path = dir.path().join("plan.sh");
+
+        let mut script = Script::new(&script_path, LinkMode::Reflink, false).unwrap();
+        script
+            .file(&MatchingFile::new(
+                src.clone(),
+                dest.clone(),
+                7,
+                "ABC".to_string(),
+                MatchReason::SourceScan,
+            ))
+            .unwrap();
+        script.finish(7).unwrap();
+
+        let output = Command::new(&script_path).output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        let metadata = fs::symlink_metadata(&dest).unwrap();
+        assert!(metadata.is_file());
+        assert_ne!(metadata.ino(), fs::metadata(&src).unwrap().ino());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "episode");
+        // The target's metadata, not the source's
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        assert_eq!(metadata.modified().unwrap(), modified);
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::SystemTime;
+
+use super::Applied;
+
+/// Totals for a run that actually replaced files.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RunSummary {
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+    pub files_linked: u64,
+    pub directories_linked: u64,
+    pub bytes_reclaimed: u64,
+    /// Matches left alone, either declined or changed since being scanned
+    pub skipped: u64,
+    pub failures: u64,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        let now = SystemTime::now();
+        Self {
+            started_at: now,
+            finished_at: now,
+            files_linked: 0,
+            directories_linked: 0,
+            bytes_reclaimed: 0,
+            skipped: 0,
+            failures: 0,
+        }
+    }
+
+    pub fn file(&mut self, applied: Applied) {
+        match applied {
+            Applied::Replaced { saved_bytes, .. } => {
+                self.files_linked += 1;
+                self.bytes_reclaimed += saved_bytes;
+            }
+            Applied::Skipped => self.skipped += 1,
+        }
+    }
+
+    pub fn directory(&mut self, applied: Applied) {
+        match applied {
+            Applied::Replaced { saved_bytes, .. } => {
+                self.directories_linked += 1;
+                self.bytes_reclaimed += saved_bytes;
+            }
+            Applied::Skipped => self.skipped += 1,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished_at = SystemTime::now();
+    }
+
+    /// Adds the totals of `other`, a later part of the same run.
+    pub fn add(&mut self, other: &RunSummary) {
+        self.finished_at = other.finished_at;
+        self.files_linked += other.files_linked;
+        self.directories_linked += other.directories_linked;
+        self.bytes_reclaimed += other.bytes_reclaimed;
+        self.skipped += other.skipped;
+        self.failures += other.failures;
+    }
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Linked {0} files and {1} directories, reclaiming {2} bytes; {3} skipped, {4} failed",
+            self.files_linked,
+            self.directories_linked,
+            self.bytes_reclaimed,
+            self.skipped,
+            self.failures
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::LinkMode;
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::new();
+        summary.file(Applied::Replaced {
+            saved_bytes: 10,
+            mode: LinkMode::Symlink,
+        });
+        summary.file(Applied::Replaced {
+            saved_bytes: 0,
+            mode: LinkMode::Symlink,
+        });
+        summary.file(Applied::Skipped);
+        summary.directory(Applied::Replaced {
+            saved_bytes: 100,
+            mode: LinkMode::Symlink,
+        });
+
+        assert_eq!(summary.files_linked, 2);
+        assert_eq!(summary.directories_linked, 1);
+        assert_eq!(summary.bytes_reclaimed, 110);
+        assert_eq!(
+            summary.to_string(),
+            "Linked 2 files and 1 directories, reclaiming 110 bytes; 1 skipped, 0 failed"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Spaces filesystem changes out so slow drives and network filesystems aren't flooded with
+/// thousands of unlinks and links at once.
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Duration,
+    /// Held while waiting, so operations on several threads are spaced out too
+    last: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Allows at most `ops_per_second` operations each second.
+    pub fn per_second(ops_per_second: f64) -> io::Result<Self> {
+        if !(ops_per_second.is_finite() && ops_per_second > 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Operations per second must be positive, got {ops_per_second}"),
+            ));
+        }
+        Ok(Self {
+            interval: Duration::from_secs_f64(1.0 / ops_per_second),
+            last: Mutex::new(None),
+        })
+    }
+
+    /// Blocks until the next operation is allowed.
+    pub fn wait(&self) {
+        let mut last = self.last.lock().expect("Throttle lock poisoned");
+        if let Some(last) = *last {
+            let elapsed = last.elapsed();
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        assert!(Throttle::per_second(0.0).is_err());
+        assert!(Throttle::per_second(f64::NAN).is_err());
+
+        let throttle = Throttle::per_second(20.0).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            throttle.wait();
+        }
+        // The first operation goes straight through, the other two wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
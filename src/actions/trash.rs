@@ -0,0 +1,133 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Where replaced target files are kept instead of being deleted. Each run trashes into its own
+/// subdirectory named after its start time, under which the original absolute paths are mirrored.
+#[derive(Debug)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(trash_dir: &Path) -> io::Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        let trash_dir = std::path::absolute(trash_dir)?;
+        Ok(Self {
+            dir: trash_dir.join(now.as_secs().to_string()),
+        })
+    }
+
+    /// Where `path` ends up in the trash.
+    fn path_for(&self, path: &Path) -> io::Result<PathBuf> {
+        let relative: PathBuf = std::path::absolute(path)?
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        Ok(self.dir.join(relative))
+    }
+
+    /// Keeps a copy of the file at `path` before it is replaced, hardlinking it where possible
+    /// so the replacement itself can still happen atomically.
+    pub(super) fn keep_file(&self, path: &Path) -> io::Result<PathBuf> {
+        let trash_path = self.path_for(path)?;
+        if let Some(parent) = trash_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::hard_link(path, &trash_path) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(path, &trash_path)?;
+            }
+            result => result?,
+        }
+        Ok(trash_path)
+    }
+
+    /// Moves a directory that has already been swapped out of `original` into the trash.
+    pub(super) fn keep_directory(&self, dir: &Path, original: &Path) -> io::Result<PathBuf> {
+        let trash_path = self.path_for(original)?;
+        if let Some(parent) = trash_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(dir, &trash_path).inspect_err(|e| {
+            tracing::error!("Could not move {dir:?} into the trash, it was left in place: {e}")
+        })?;
+        Ok(trash_path)
+    }
+}
+
+/// Deletes every run in `trash_dir` that was trashed at least `older_than` ago, returning the
+/// removed run directories.
+pub fn purge(trash_dir: &Path, older_than: Duration) -> io::Result<Vec<PathBuf>> {
+    let cutoff = SystemTime::now()
+        .checked_sub(older_than)
+        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut removed = Vec::new();
+    for entry in fs::read_dir(trash_dir)? {
+        let entry = entry?;
+        let Some(trashed_at) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u64>().ok())
+        else {
+            tracing::warn!("Ignoring unexpected entry {:?} in the trash", entry.path());
+            continue;
+        };
+        if trashed_at <= cutoff {
+            fs::remove_dir_all(entry.path())?;
+            removed.push(entry.path());
+        }
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("target/show/episode.mkv");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "episode").unwrap();
+
+        let trash = Trash::new(&dir.path().join("trash")).unwrap();
+        let trash_path = trash.keep_file(&file).unwrap();
+
+        assert!(trash_path.starts_with(&trash.dir));
+        assert!(trash_path.ends_with("target/show/episode.mkv"));
+        assert_eq!(fs::read_to_string(trash_path).unwrap(), "episode");
+    }
+
+    #[test]
+    fn test_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_run = dir.path().join("1000");
+        let new_run = dir.path().join(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .to_string(),
+        );
+        for run in [&old_run, &new_run] {
+            fs::create_dir_all(run.join("target")).unwrap();
+            fs::write(run.join("target/file"), "content").unwrap();
+        }
+
+        let removed = purge(dir.path(), Duration::from_secs(60 * 60)).unwrap();
+
+        assert_eq!(removed, vec![old_run.clone()]);
+        assert!(!old_run.exists());
+        assert!(new_run.exists());
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Applied, LinkMode};
+use crate::hashing;
+use crate::matching::MatchingFile;
+use crate::matching::directories::DirectoryMatch;
+
+/// A replaced target and what it should now be.
+#[derive(Debug)]
+struct Linked {
+    dest: PathBuf,
+    src: PathBuf,
+    size: u64,
+    /// Empty for directories
+    hash: String,
+    mode: LinkMode,
+}
+
+/// A target that isn't what it should be after being replaced.
+#[derive(Debug, PartialEq)]
+pub struct Discrepancy {
+    pub path: PathBuf,
+    pub problem: String,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.problem)
+    }
+}
+
+/// Collects the targets replaced during a run to check them once it's done.
+#[derive(Debug, Default)]
+pub struct Verifier {
+    linked: Vec<Linked>,
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(&mut self, matching_file: &MatchingFile, applied: Applied) {
+        if let Applied::Replaced { mode, .. } = applied {
+            self.linked.push(Linked {
+                dest: matching_file.dest_path().to_path_buf(),
+                src: matching_file.src_path().to_path_buf(),
+                size: matching_file.size(),
+                hash: matching_file.hash().to_owned(),
+                mode,
+            });
+        }
+    }
+
+    pub fn directory(&mut self, directory: &DirectoryMatch, applied: Applied) {
+        if let Applied::Replaced { mode, .. } = applied {
+            self.linked.push(Linked {
+                dest: directory.dest_dir.clone(),
+                src: directory.src_dir.clone(),
+                size: directory.size,
+                hash: String::new(),
+                mode,
+            });
+        }
+    }
+
+    /// Number of replaced targets collected so far.
+    pub fn count(&self) -> usize {
+        self.linked.len()
+    }
+
+    /// Checks every replaced target still resolves to its source, and reads `sample` of the
+    /// files, spread evenly across the run, back through their link to compare their hash.
+    pub fn run(&self, sample: usize) -> Vec<Discrepancy> {
+        let files = self.linked.iter().filter(|l| !l.hash.is_empty()).count();
+        let every = files.div_ceil(sample.max(1)).max(1);
+        let mut discrepancies = Vec::new();
+        let mut file_index = 0;
+        for linked in &self.linked {
+            let mut problem = Self::check_link(linked).err();
+            if problem.is_none() && !linked.hash.is_empty() {
+                if sample > 0 && file_index % every == 0 {
+                    problem = check_hash(linked).err();
+                }
+                file_index += 1;
+            }
+            if let Some(problem) = problem {
+                discrepancies.push(Discrepancy {
+                    path: linked.dest.clone(),
+                    problem,
+                });
+            }
+        }
+        discrepancies
+    }
+
+    fn check_link(linked: &Linked) -> Result<(), String> {
+        let dest_metadata = fs::symlink_metadata(&linked.dest).map_err(|e| e.to_string())?;
+        match linked.mode {
+            LinkMode::Symlink => {
+                if !dest_metadata.is_symlink() {
+                    return Err("is not a symlink".to_owned());
+                }
+                let resolved =
+                    fs::canonicalize(&linked.dest).map_err(|e| format!("doesn't resolve: {e}"))?;
+                if resolved != canonical(&linked.src)? {
+                    return Err(format!(
+                        "points at {resolved:?} instead of {:?}",
+                        linked.src
+                    ));
+                }
+            }
+            LinkMode::Hardlink => {
+                if !same_file(&linked.src, &dest_metadata)? {
+                    return Err(format!("is not hardlinked to {:?}", linked.src));
+                }
+            }
+            LinkMode::Reflink | LinkMode::Dedupe => {
+                if !dest_metadata.is_file() {
+                    return Err("is not a regular file".to_owned());
+                }
+                if dest_metadata.len() != linked.size {
+                    return Err(format!(
+                        "is {} bytes instead of {}",
+                        dest_metadata.len(),
+                        linked.size
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_hash(linked: &Linked) -> Result<(), String> {
+    let hash = hashing::compute_file_hash(&linked.dest).map_err(|e| e.to_string())?;
+    if hash != linked.hash {
+        return Err(format!("hashes to {hash} instead of {}", linked.hash));
+    }
+    Ok(())
+}
+
+fn canonical(path: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(path).map_err(|e| format!("source {path:?} is gone: {e}"))
+}
+
+#[cfg(unix)]
+fn same_file(src: &Path, dest_metadata: &fs::Metadata) -> Result<bool, String> {
+    use std::os::unix::fs::MetadataExt;
+    let src_metadata = fs::metadata(src).map_err(|e| format!("source {src:?} is gone: {e}"))?;
+    Ok((src_metadata.dev(), src_metadata.ino()) == (dest_metadata.dev(), dest_metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn same_file(_src: &Path, dest_metadata: &fs::Metadata) -> Result<bool, String> {
+    Ok(dest_metadata.is_file())
+}
+
+/// Error for a run whose replaced targets didn't all verify.
+pub fn failed(discrepancies: &[Discrepancy]) -> io::Error {
+    io::Error::other(format!(
+        "{} replaced targets failed verification",
+        discrepancies.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+
+    fn matching(src: &Path, dest: &Path) -> MatchingFile {
+        MatchingFile::new(
+            src.to_path_buf(),
+            dest.to_path_buf(),
+            7,
+            hashing::compute_file_hash(src).unwrap(),
+            MatchReason::SourceScan,
+        )
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source");
+        let other = dir.path().join("other");
+        fs::write(&src, "content").unwrap();
+        fs::write(&other, "CONTENT").unwrap();
+        let good = dir.path().join("good");
+        let wrong = dir.path().join("wrong");
+        let plain = dir.path().join("plain");
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&src, &good).unwrap();
+            std::os::unix::fs::symlink(&other, &wrong).unwrap();
+        }
+        fs::write(&plain, "content").unwrap();
+
+        let replaced = Applied::Replaced {
+            saved_bytes: 7,
+            mode: LinkMode::Symlink,
+        };
+        let mut verifier = Verifier::new();
+        for dest in [&good, &wrong, &plain] {
+            verifier.file(&matching(&src, dest), replaced);
+        }
+        verifier.file(
+            &matching(&src, &dir.path().join("skipped")),
+            Applied::Skipped,
+        );
+        assert_eq!(verifier.count(), 3);
+
+        let paths = |discrepancies: Vec<Discrepancy>| {
+            discrepancies
+                .into_iter()
+                .map(|d| d.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(verifier.run(0)), [wrong.clone(), plain.clone()]);
+
+        // Content changed behind the link is only caught when hashing
+        fs::write(&src, "changed").unwrap();
+        assert_eq!(paths(verifier.run(0)), [wrong.clone(), plain.clone()]);
+        assert_eq!(paths(verifier.run(3)), [good, wrong, plain]);
+    }
+}
@@ -0,0 +1,232 @@
+//! Creating links on Windows, where symlinks need developer mode or an elevated prompt. Without
+//! either, files fall back to hard links and directories to junctions.
+
+use std::ffi::c_void;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::{self as windows_fs, OpenOptionsExt};
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use std::path::{Component, Path};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::LinkMode;
+
+/// Returned when creating a symlink without developer mode or administrator rights
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+const FSCTL_SET_REPARSE_POINT: u32 = 0x0009_00A4;
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+/// Largest reparse point, its header included
+const MAXIMUM_REPARSE_DATA_BUFFER_SIZE: usize = 16 * 1024;
+const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+/// Needed to open a directory
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn DeviceIoControl(
+        device: RawHandle,
+        control_code: u32,
+        in_buffer: *const c_void,
+        in_buffer_size: u32,
+        out_buffer: *mut c_void,
+        out_buffer_size: u32,
+        bytes_returned: *mut u32,
+        overlapped: *mut c_void,
+    ) -> i32;
+}
+
+/// Set once creating a symlink was refused, so the rest of the run goes straight to the fallback.
+static SYMLINKS_REFUSED: AtomicBool = AtomicBool::new(false);
+
+fn symlink_or_fallback<T>(
+    symlink: impl FnOnce() -> io::Result<T>,
+    fallback: impl FnOnce() -> io::Result<T>,
+    fallback_name: &str,
+) -> io::Result<T> {
+    if !SYMLINKS_REFUSED.load(Ordering::Relaxed) {
+        match symlink() {
+            Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => {
+                tracing::warn!(
+                    "Creating symlinks needs developer mode or administrator rights, using \
+                     {fallback_name} instead"
+                );
+                SYMLINKS_REFUSED.store(true, Ordering::Relaxed);
+            }
+            result => return result,
+        }
+    }
+    fallback()
+}
+
+/// Links `link` to the file `src`, pointing at it through `target`. Falls back to a hard link
+/// when symlinks aren't allowed, as long as both are on the same volume. Returns the kind of
+/// link made.
+pub(super) fn symlink_file(target: &Path, src: &Path, link: &Path) -> io::Result<LinkMode> {
+    symlink_or_fallback(
+        || windows_fs::symlink_file(target, link).map(|()| LinkMode::Symlink),
+        || {
+            if !same_volume(src, link)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "Can't symlink {link:?} without developer mode or administrator rights, \
+                         and {src:?} is on another volume so it can't be hard linked"
+                    ),
+                ));
+            }
+            fs::hard_link(src, link)?;
+            Ok(LinkMode::Hardlink)
+        },
+        "hard links",
+    )
+}
+
+/// Links `link` to the directory `src`, pointing at it through `target`. Falls back to a
+/// junction when symlinks aren't allowed.
+pub(super) fn symlink_dir(target: &Path, src: &Path, link: &Path) -> io::Result<()> {
+    symlink_or_fallback(
+        || windows_fs::symlink_dir(target, link),
+        || junction(src, link),
+        "junctions",
+    )
+}
+
+/// Creates a junction, which unlike a symlink needs no privileges but must point at an
+/// absolute path on a local volume. Made as an empty directory turned into a mount point
+/// reparse point.
+fn junction(src: &Path, link: &Path) -> io::Result<()> {
+    let src = std::path::absolute(src)?;
+    let src = src.as_os_str().encode_wide().collect::<Vec<_>>();
+    // The NT form of the path the junction resolves to, and the one shown for it
+    const VERBATIM: [u16; 4] = [b'\\' as u16, b'\\' as u16, b'?' as u16, b'\\' as u16];
+    let print_name = src.strip_prefix(&VERBATIM[..]).unwrap_or(&src);
+    let substitute_name: Vec<u16> = r"\??\"
+        .encode_utf16()
+        .chain(print_name.iter().copied())
+        .collect();
+
+    // REPARSE_DATA_BUFFER with its MountPointReparseBuffer, both names null terminated
+    let path_buffer = (substitute_name.len() + 1 + print_name.len() + 1) * 2;
+    let data_length = 8 + path_buffer;
+    if 8 + data_length > MAXIMUM_REPARSE_DATA_BUFFER_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidFilename,
+            format!("{link:?} can't be a junction, its target's path is too long"),
+        ));
+    }
+    let mut buffer = Vec::with_capacity(8 + data_length);
+    buffer.extend(IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    for field in [
+        data_length,
+        0,
+        // Substitute name offset and length, then print name offset and length, in bytes
+        0,
+        substitute_name.len() * 2,
+        (substitute_name.len() + 1) * 2,
+        print_name.len() * 2,
+    ] {
+        buffer.extend((field as u16).to_le_bytes());
+    }
+    for unit in substitute_name
+        .iter()
+        .chain(&[0])
+        .chain(print_name)
+        .chain(&[0])
+    {
+        buffer.extend(unit.to_le_bytes());
+    }
+
+    fs::create_dir(link)?;
+    let result = OpenOptions::new()
+        .write(true)
+        .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS)
+        .open(link)
+        .and_then(|directory| {
+            let mut returned = 0;
+            // SAFETY: the handle is open for the call, and the input buffer outlives it with
+            // its length given
+            let done = unsafe {
+                DeviceIoControl(
+                    directory.as_raw_handle(),
+                    FSCTL_SET_REPARSE_POINT,
+                    buffer.as_ptr().cast(),
+                    buffer.len() as u32,
+                    ptr::null_mut(),
+                    0,
+                    &mut returned,
+                    ptr::null_mut(),
+                )
+            };
+            if done == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    if let Err(e) = result {
+        let _ = fs::remove_dir(link);
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Creating junction {link:?} failed: {e}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` are on the same drive or share, as hard links can't cross volumes.
+fn same_volume(a: &Path, b: &Path) -> io::Result<bool> {
+    let prefix = |path: &Path| -> io::Result<_> {
+        let path = std::path::absolute(path)?;
+        Ok(match path.components().next() {
+            Some(Component::Prefix(prefix)) => Some(prefix.as_os_str().to_ascii_uppercase()),
+            _ => None,
+        })
+    };
+    Ok(prefix(a)? == prefix(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_volume() {
+        assert!(same_volume(Path::new(r"C:\a\file"), Path::new(r"c:\b\file")).unwrap());
+        assert!(!same_volume(Path::new(r"C:\a\file"), Path::new(r"D:\a\file")).unwrap());
+        assert!(
+            !same_volume(Path::new(r"C:\a\file"), Path::new(r"\\server\share\a\file")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_symlink_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source");
+        let link = dir.path().join("link");
+        fs::write(&src, "content").unwrap();
+
+        // A symlink with developer mode or a hard link without, either way it reads the same
+        let mode = symlink_file(&src, &src, &link).unwrap();
+        assert_eq!(
+            fs::symlink_metadata(&link).unwrap().is_symlink(),
+            mode == LinkMode::Symlink
+        );
+        assert_eq!(fs::read_to_string(&link).unwrap(), "content");
+    }
+
+    #[test]
+    fn test_junction() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source");
+        let link = dir.path().join("link");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("file"), "content").unwrap();
+
+        junction(&src, &link).unwrap();
+        assert_eq!(fs::read_to_string(link.join("file")).unwrap(), "content");
+        // Removing the junction leaves the directory it points at alone
+        fs::remove_dir(&link).unwrap();
+        assert!(src.join("file").exists());
+    }
+}
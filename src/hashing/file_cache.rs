@@ -7,13 +7,19 @@ use std::{
 };
 
 use super::Hash;
-use super::HashCache;
+use super::{CacheStats, HashCache};
 
 type HashesHashmap = HashMap<PathBuf, (Hash, SystemTime)>;
 
+/// The cache every run uses unless told otherwise, from the command line or the service.
+pub fn default_path() -> PathBuf {
+    crate::project_dirs().cache_dir().join("hashes.cache")
+}
+
 pub struct HashingFileCache {
     path: PathBuf,
     hashes: HashesHashmap,
+    stats: CacheStats,
 }
 
 impl HashingFileCache {
@@ -21,19 +27,26 @@ impl HashingFileCache {
         if !path.exists() {
             return Ok(Self {
                 path,
-                hashes: HashMap::new()
+                hashes: HashMap::new(),
+                stats: CacheStats::default(),
             });
-        } 
+        }
 
         Ok(Self {
             hashes: HashingFileCache::deseralise_hashes(&fs::read_to_string(&path)?)?,
             path,
+            stats: CacheStats::default(),
         })
     }
 
+    /// Reads the cache at `path` without keeping it, returning how many hashes it holds.
+    pub fn check(path: &Path) -> io::Result<usize> {
+        Ok(HashingFileCache::deseralise_hashes(&fs::read_to_string(path)?)?.len())
+    }
+
     fn serialise_hashes(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(&self.hashes)
-            .inspect_err(|e| log::error!("Failed to serialise hashes from cache: {}", e))
+            .inspect_err(|e| tracing::error!("Failed to serialise hashes from cache: {}", e))
     }
 
     fn deseralise_hashes(s: &str) -> Result<HashesHashmap, serde_json::Error> {
@@ -50,29 +63,15 @@ impl HashingFileCache {
         last_modified: &SystemTime,
     ) -> io::Result<Hash> {
         let hash = super::compute_file_hash(path)?;
-        self.cache_hash(path, &hash, &last_modified);
-        return Ok(hash);
+        self.stats.hashed(path);
+        self.cache_hash(path, &hash, last_modified);
+        Ok(hash)
     }
 }
 
 impl Drop for HashingFileCache {
     fn drop(&mut self) {
-        let file = fs::File::create(&self.path);
-        if let Err(e) = file {
-            log::error!(
-                "Failed to write hash cache file to {}: {}",
-                self.path.display(),
-                e
-            );
-            return;
-        }
-        let mut file = file.unwrap();
-
-        if let Ok(serialised_hashes) = self.serialise_hashes() {
-            if let Err(e) = file.write_all(serialised_hashes.as_bytes()) {
-                log::error!("Unable to write cached hashes to: {:?}: {e}", self.path);
-            }
-        }
+        self.flush();
     }
 }
 
@@ -84,25 +83,47 @@ impl HashCache for HashingFileCache {
     fn cache_hash(&mut self, path: &Path, hash: &str, last_modified: &std::time::SystemTime) {
         self.hashes
             .entry(path.to_path_buf())
-            .insert_entry((hash.to_string(), last_modified.clone()));
+            .insert_entry((hash.to_string(), *last_modified));
     }
 
     fn hash_file(&mut self, path: &Path) -> io::Result<String> {
         if let Some((hash_cache, last_modified_cache)) = self.retrieve_hash(path) {
             let last_modified = HashingFileCache::get_file_last_modified(path)?;
             if last_modified > last_modified_cache {
-                log::debug!("Cache: Found outdated cache hash value for {:?}", path);
-                return self.compute_and_cache_hash(path, &last_modified);
+                tracing::debug!("Cache: Found outdated cache hash value for {:?}", path);
+                self.compute_and_cache_hash(path, &last_modified)
             } else {
-                log::debug!("Cache: Found cached hash value for {:?}", path);
-                return Ok(hash_cache);
+                tracing::debug!("Cache: Found cached hash value for {:?}", path);
+                self.stats.hits += 1;
+                Ok(hash_cache)
             }
         } else {
-            log::debug!("Cache: No cached hash value for {:?}", path);
+            tracing::debug!("Cache: No cached hash value for {:?}", path);
             let last_modified = HashingFileCache::get_file_last_modified(path)?;
-            let hash = super::compute_file_hash(path)?;
-            self.cache_hash(path, &hash, &last_modified);
-            return Ok(hash);
+            self.compute_and_cache_hash(path, &last_modified)
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn flush(&mut self) {
+        let file = fs::File::create(&self.path);
+        if let Err(e) = file {
+            tracing::error!(
+                "Failed to write hash cache file to {}: {}",
+                self.path.display(),
+                e
+            );
+            return;
+        }
+        let mut file = file.unwrap();
+
+        if let Ok(serialised_hashes) = self.serialise_hashes()
+            && let Err(e) = file.write_all(serialised_hashes.as_bytes())
+        {
+            tracing::error!("Unable to write cached hashes to: {:?}: {e}", self.path);
         }
     }
 }
@@ -0,0 +1,148 @@
+pub mod file_cache;
+pub mod no_cache;
+
+use sha2::Digest as _;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read as _},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub type Hash = String;
+
+/// The only hashing algorithm, named for output.
+pub const ALGORITHM: &str = "sha256";
+/// How much of a file is read at a time when hashing it in full, unless set otherwise.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 << 10;
+/// Smallest buffer size that can be set, below which every read is a syscall for nothing.
+pub const MIN_BUFFER_SIZE: usize = 4 << 10;
+/// Largest buffer size that can be set, as every hashing thread allocates one.
+pub const MAX_BUFFER_SIZE: usize = 64 << 20;
+
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_BUFFER_SIZE);
+
+/// Sets how much of a file is read at a time when hashing it in full, for the whole process.
+/// Clamped to between `MIN_BUFFER_SIZE` and `MAX_BUFFER_SIZE`.
+pub fn set_buffer_size(size: usize) {
+    BUFFER_SIZE.store(
+        size.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn compute_file_hash(path: &Path) -> io::Result<Hash> {
+    tracing::debug!("Hashing: {path:?}");
+    hash_with_buffer(path, BUFFER_SIZE.load(Ordering::Relaxed))
+}
+
+/// Hash of a whole file, read `buffer_size` bytes at a time.
+pub fn hash_with_buffer(path: &Path, buffer_size: usize) -> io::Result<Hash> {
+    let mut input = File::open(path)?;
+
+    let digest = {
+        let mut hasher = sha2::Sha256::new();
+        let mut buffer = vec![0; buffer_size];
+        loop {
+            let count = input.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+        hasher.finalize()
+    };
+    Ok(format!("{:X}", digest))
+}
+
+/// Hash of at most the first `len` bytes of a file. Used to cheaply rule out files before
+/// hashing their full content.
+pub fn compute_prefix_hash(path: &Path, len: u64) -> io::Result<Hash> {
+    let input = File::open(path)?;
+    let mut reader = BufReader::new(input).take(len);
+
+    let mut hasher = sha2::Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:X}", hasher.finalize()))
+}
+
+pub trait HashCache {
+    fn retrieve_hash(&self, path: &Path) -> Option<(String, std::time::SystemTime)>;
+    fn cache_hash(&mut self, path: &Path, hash: &str, last_modified: &std::time::SystemTime);
+    fn hash_file(&mut self, path: &Path) -> io::Result<String>;
+    /// Writes out the cached hashes, for caches that outlive a single run.
+    fn flush(&mut self) {}
+    /// Lookups and hashing done so far.
+    fn stats(&self) -> CacheStats;
+}
+
+/// How many files were looked up in a cache and how much had to be read to hash the misses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub hashed_bytes: u64,
+}
+
+impl CacheStats {
+    /// What happened after `earlier` was taken from the same cache.
+    pub fn since(&self, earlier: &CacheStats) -> CacheStats {
+        CacheStats {
+            hits: self.hits - earlier.hits,
+            misses: self.misses - earlier.misses,
+            hashed_bytes: self.hashed_bytes - earlier.hashed_bytes,
+        }
+    }
+
+    /// Share of lookups answered from the cache, if there were any.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+
+    /// Counts a miss for `path`, which was just hashed in full.
+    fn hashed(&mut self, path: &Path) {
+        self.misses += 1;
+        self.hashed_bytes += std::fs::metadata(path).map_or(0, |m| m.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_hash_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.txt");
+        let mut file = std::fs::File::create(&path).unwrap();
+
+        write!(&mut file, "test").unwrap();
+
+        assert_eq!(
+            compute_file_hash(&path).unwrap(),
+            "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08"
+        );
+
+        // Sizes out of range are clamped, hashing the same
+        set_buffer_size(0);
+        assert_eq!(BUFFER_SIZE.load(Ordering::Relaxed), MIN_BUFFER_SIZE);
+        set_buffer_size(usize::MAX);
+        assert_eq!(BUFFER_SIZE.load(Ordering::Relaxed), MAX_BUFFER_SIZE);
+        set_buffer_size(DEFAULT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn test_prefix_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.txt");
+        std::fs::write(&path, "testing").unwrap();
+
+        assert_eq!(
+            compute_prefix_hash(&path, 4).unwrap(),
+            "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08"
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use std::{io, path::Path};
+
+use crate::hashing::{CacheStats, HashCache, compute_file_hash};
+
+#[derive(Default)]
+pub struct HashingNoCache {
+    stats: CacheStats,
+}
+
+impl HashingNoCache {
+    pub fn new() -> Self {
+        Self {
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl HashCache for HashingNoCache {
+    fn retrieve_hash(&self, _path: &Path) -> Option<(String, std::time::SystemTime)> {
+        None
+    }
+    fn cache_hash(&mut self, _path: &Path, _hash: &str, _last_modified: &std::time::SystemTime) {}
+
+    fn hash_file(&mut self, path: &Path) -> io::Result<String> {
+        let hash = compute_file_hash(path)?;
+        self.stats.hashed(path);
+        Ok(hash)
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
@@ -0,0 +1,16 @@
+//! The engine shared by the `atorrlinker-undup` command line and the `atorrlinker` service:
+//! finding files, hashing them through the hash cache, matching duplicates and replacing them.
+
+pub mod actions;
+pub mod hashing;
+pub mod lock;
+pub mod matching;
+pub mod notify;
+pub mod template;
+
+/// Where the command line and the service keep their state, so that they share one hash
+/// cache, journal and audit log.
+pub fn project_dirs() -> directories::ProjectDirs {
+    directories::ProjectDirs::from("local", "jimbo", "untorr_undup")
+        .expect("Could not find the project directories")
+}
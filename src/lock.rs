@@ -0,0 +1,147 @@
+use sha2::Digest as _;
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Most target roots locked one by one, beyond which their common ancestor is locked instead
+/// so a long target list can't run out of file descriptors.
+const MAX_ROOTS: usize = 64;
+
+/// Advisory locks keeping two runs from changing the same target tree at once. Each target
+/// root is locked exclusively and its ancestors shared, so runs on `/media/tv` and
+/// `/media/movies` can go ahead together while either conflicts with a run on `/media`.
+#[derive(Debug)]
+pub struct RunLock {
+    _files: Vec<File>,
+}
+
+impl RunLock {
+    /// Locks `targets`, keeping the lock files in `lock_dir`. Fails straight away if another run
+    /// holds a conflicting lock.
+    pub fn acquire(lock_dir: &Path, targets: &[impl AsRef<Path>]) -> io::Result<Self> {
+        fs::create_dir_all(lock_dir)?;
+        let mut exclusive = BTreeSet::new();
+        let mut file_dirs = Vec::new();
+        for target in targets {
+            let target = fs::canonicalize(target.as_ref())?;
+            if target.is_dir() {
+                exclusive.insert(target);
+            } else {
+                file_dirs.push(target.parent().map(Path::to_path_buf).unwrap_or(target));
+            }
+        }
+        // Files from a target list are locked through the directory they are all in
+        exclusive.extend(common_ancestor(&file_dirs));
+        // Nested targets are covered by the lock on the outer one
+        let mut exclusive: Vec<PathBuf> = exclusive
+            .iter()
+            .filter(|dir| !dir.ancestors().skip(1).any(|a| exclusive.contains(a)))
+            .cloned()
+            .collect();
+        if exclusive.len() > MAX_ROOTS {
+            exclusive = common_ancestor(&exclusive).into_iter().collect();
+        }
+        let shared: BTreeSet<&Path> = exclusive
+            .iter()
+            .flat_map(|dir| dir.ancestors().skip(1))
+            .collect();
+
+        let mut files = Vec::new();
+        for dir in &exclusive {
+            let file = open_lock_file(lock_dir, dir)?;
+            check(file.try_lock(), dir)?;
+            files.push(file);
+        }
+        for dir in shared {
+            let file = open_lock_file(lock_dir, dir)?;
+            check(file.try_lock_shared(), dir)?;
+            files.push(file);
+        }
+        Ok(Self { _files: files })
+    }
+}
+
+/// The deepest directory all of `dirs` are in, if there are any.
+fn common_ancestor(dirs: &[PathBuf]) -> Option<PathBuf> {
+    let (first, rest) = dirs.split_first()?;
+    let mut common = first.clone();
+    for dir in rest {
+        while !dir.starts_with(&common) {
+            common.pop();
+        }
+    }
+    Some(common)
+}
+
+fn open_lock_file(lock_dir: &Path, dir: &Path) -> io::Result<File> {
+    let name = hex(&sha2::Sha256::digest(dir.as_os_str().as_encoded_bytes()));
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_dir.join(format!("{name}.lock")))
+}
+
+fn check(result: Result<(), TryLockError>, dir: &Path) -> io::Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("Another run is already working on {dir:?}"),
+        )),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_dir = dir.path().join("locks");
+        let media = dir.path().join("media");
+        let tv = media.join("tv");
+        let movies = media.join("movies");
+        for path in [&tv, &movies] {
+            fs::create_dir_all(path).unwrap();
+        }
+        fs::write(tv.join("episode.mkv"), "episode").unwrap();
+
+        let tv_lock = RunLock::acquire(&lock_dir, &[&tv]).unwrap();
+        // Siblings are independent
+        let movies_lock = RunLock::acquire(&lock_dir, &[&movies]).unwrap();
+        let err = RunLock::acquire(&lock_dir, &[tv.join("episode.mkv")]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        let err = RunLock::acquire(&lock_dir, &[&media]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop((tv_lock, movies_lock));
+        // Nested targets in one run don't conflict with each other
+        RunLock::acquire(&lock_dir, &[&media, &tv]).unwrap();
+
+        // Files all over a tree are locked through one directory rather than each of theirs
+        let seasons: Vec<PathBuf> = (0..100).map(|i| tv.join(format!("season {i}"))).collect();
+        for season in &seasons {
+            fs::create_dir(season).unwrap();
+            fs::write(season.join("episode.mkv"), "episode").unwrap();
+        }
+        let episodes: Vec<PathBuf> = seasons.iter().map(|s| s.join("episode.mkv")).collect();
+        let tv_depth = fs::canonicalize(&tv).unwrap().ancestors().count();
+        let episodes_lock = RunLock::acquire(&lock_dir, &episodes).unwrap();
+        assert_eq!(episodes_lock._files.len(), tv_depth);
+        let err = RunLock::acquire(&lock_dir, &[&seasons[0]]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        drop(episodes_lock);
+        let seasons_lock = RunLock::acquire(&lock_dir, &seasons).unwrap();
+        assert_eq!(seasons_lock._files.len(), tv_depth);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{DiscoveredFiles, MatchOptions, find::find_files};
+use crate::hashing::{Hash, HashCache};
+
+/// A file found in one of the compared trees.
+#[derive(Debug)]
+struct Entry {
+    /// Path below the root of its tree
+    relative: PathBuf,
+    content_path: PathBuf,
+    size: u64,
+    /// Only hashed when the other tree has a file of the same size, as it can't match otherwise
+    hash: Option<Hash>,
+}
+
+/// Files with the same content on both sides, by their path below each root.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Identical {
+    pub hash: Hash,
+    pub size: u64,
+    pub a: Vec<PathBuf>,
+    pub b: Vec<PathBuf>,
+}
+
+/// A path on both sides whose content differs.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Changed {
+    pub path: PathBuf,
+    pub a_size: u64,
+    pub b_size: u64,
+}
+
+/// How two trees compare by content.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct TreeDiff {
+    pub identical: Vec<Identical>,
+    /// Content only found in the first tree, apart from changed paths
+    pub only_in_a: Vec<PathBuf>,
+    /// Content only found in the second tree, apart from changed paths
+    pub only_in_b: Vec<PathBuf>,
+    pub changed: Vec<Changed>,
+}
+
+/// Scans and hashes the trees at `a` and `b`, without changing anything, to find what content
+/// they share, what each has alone and which paths they both have with different content.
+pub fn diff_trees(
+    a: &Path,
+    b: &Path,
+    hasher: &mut dyn HashCache,
+    options: &MatchOptions,
+) -> io::Result<TreeDiff> {
+    let mut a_entries = scan(a, hasher, options)?;
+    let mut b_entries = scan(b, hasher, options)?;
+
+    let a_sizes: HashSet<u64> = a_entries.iter().map(|entry| entry.size).collect();
+    let b_sizes: HashSet<u64> = b_entries.iter().map(|entry| entry.size).collect();
+    let to_hash = |entries: &[Entry], other: &HashSet<u64>| {
+        entries
+            .iter()
+            .filter(|entry| other.contains(&entry.size))
+            .fold((0, 0), |(files, bytes), entry| {
+                (files + 1, bytes + entry.size)
+            })
+    };
+    let (a_files, a_bytes) = to_hash(&a_entries, &b_sizes);
+    let (b_files, b_bytes) = to_hash(&b_entries, &a_sizes);
+    options
+        .progress
+        .start_phase("Hashing", a_files + b_files, a_bytes + b_bytes);
+    for (entries, other) in [(&mut a_entries, &b_sizes), (&mut b_entries, &a_sizes)] {
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| other.contains(&entry.size))
+        {
+            entry.hash = Some(hasher.hash_file(&entry.content_path)?);
+            options.progress.hashed(&entry.content_path, entry.size);
+        }
+    }
+    options.progress.finish();
+
+    let by_hash = |entries: &[Entry]| {
+        let mut by_hash: BTreeMap<Hash, Vec<PathBuf>> = BTreeMap::new();
+        for entry in entries {
+            if let Some(hash) = &entry.hash {
+                by_hash
+                    .entry(hash.clone())
+                    .or_default()
+                    .push(entry.relative.clone());
+            }
+        }
+        by_hash
+    };
+    let a_hashes = by_hash(&a_entries);
+    let b_hashes = by_hash(&b_entries);
+
+    let mut diff = TreeDiff::default();
+    let sizes: HashMap<&Hash, u64> = a_entries
+        .iter()
+        .filter_map(|entry| Some((entry.hash.as_ref()?, entry.size)))
+        .collect();
+    for (hash, a_paths) in &a_hashes {
+        if let Some(b_paths) = b_hashes.get(hash) {
+            diff.identical.push(Identical {
+                hash: hash.clone(),
+                size: sizes[hash],
+                a: a_paths.clone(),
+                b: b_paths.clone(),
+            });
+        }
+    }
+
+    let b_by_path: HashMap<&Path, &Entry> = b_entries
+        .iter()
+        .map(|entry| (entry.relative.as_path(), entry))
+        .collect();
+    let mut changed_paths = HashSet::new();
+    for entry in &a_entries {
+        if let Some(other) = b_by_path.get(entry.relative.as_path())
+            && (entry.size != other.size || entry.hash != other.hash)
+        {
+            changed_paths.insert(entry.relative.clone());
+            diff.changed.push(Changed {
+                path: entry.relative.clone(),
+                a_size: entry.size,
+                b_size: other.size,
+            });
+        }
+    }
+
+    let only = |entries: &[Entry], other: &BTreeMap<Hash, Vec<PathBuf>>| {
+        let mut only: Vec<PathBuf> = entries
+            .iter()
+            .filter(|entry| {
+                !entry
+                    .hash
+                    .as_ref()
+                    .is_some_and(|hash| other.contains_key(hash))
+            })
+            .filter(|entry| !changed_paths.contains(&entry.relative))
+            .map(|entry| entry.relative.clone())
+            .collect();
+        only.sort();
+        only
+    };
+    diff.only_in_a = only(&a_entries, &b_hashes);
+    diff.only_in_b = only(&b_entries, &a_hashes);
+    diff.changed.sort_by(|x, y| x.path.cmp(&y.path));
+    Ok(diff)
+}
+
+/// Every file under `root` with content to compare, leaving out broken symlinks.
+fn scan(root: &Path, hasher: &mut dyn HashCache, options: &MatchOptions) -> io::Result<Vec<Entry>> {
+    let _span = tracing::info_span!("scan", root = ?root).entered();
+    let mut discovered = DiscoveredFiles::default();
+    find_files(&mut discovered, root, hasher, options)?;
+    Ok(discovered
+        .unhashed
+        .into_iter()
+        .map(|f| Entry {
+            relative: f
+                .file
+                .src_path()
+                .strip_prefix(root)
+                .unwrap_or(f.file.src_path())
+                .to_path_buf(),
+            content_path: f.content_path,
+            size: f.size,
+            hash: None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::no_cache::HashingNoCache;
+    use std::fs;
+
+    #[test]
+    fn test_diff_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        for path in [&a, &b] {
+            fs::create_dir_all(path.join("show")).unwrap();
+        }
+        fs::write(a.join("show/e01.mkv"), "episode one").unwrap();
+        fs::write(b.join("e01 copy.mkv"), "episode one").unwrap();
+        fs::write(a.join("notes.txt"), "version 1").unwrap();
+        fs::write(b.join("notes.txt"), "version 2").unwrap();
+        fs::write(a.join("only a.mkv"), "a alone").unwrap();
+        fs::write(b.join("show/only b.mkv"), "b alone, longer").unwrap();
+
+        let diff =
+            diff_trees(&a, &b, &mut HashingNoCache::new(), &MatchOptions::default()).unwrap();
+        assert_eq!(diff.identical.len(), 1);
+        assert_eq!(diff.identical[0].a, [PathBuf::from("show/e01.mkv")]);
+        assert_eq!(diff.identical[0].b, [PathBuf::from("e01 copy.mkv")]);
+        assert_eq!(diff.identical[0].size, 11);
+        assert_eq!(
+            diff.changed,
+            [Changed {
+                path: PathBuf::from("notes.txt"),
+                a_size: 9,
+                b_size: 9,
+            }]
+        );
+        assert_eq!(diff.only_in_a, [PathBuf::from("only a.mkv")]);
+        assert_eq!(diff.only_in_b, [PathBuf::from("show/only b.mkv")]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{MatchReason, MatchingFile};
+
+/// The kind of link existing links get converted into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConvertTo {
+    /// Symlinks to a file on the same device become hardlinks
+    Hardlink,
+    /// Hardlinks sharing an inode with a source file become symlinks to it
+    Symlink,
+}
+
+/// Every regular file and symlink below `dir`, without following directory symlinks.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn walk_all(roots: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for root in roots {
+        let root = root.as_ref();
+        if fs::symlink_metadata(root)?.is_dir() {
+            walk(root, &mut files)?;
+        } else {
+            files.push(root.to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Finds the existing links under `target_dir` that can be converted, paired with the file they
+/// should link to instead. Turning hardlinks into symlinks needs `source_dir` to tell which of
+/// the linked paths is the source.
+#[cfg(unix)]
+pub fn find_convertible_links(
+    source_dir: &[impl AsRef<Path>],
+    target_dir: &[impl AsRef<Path>],
+    to: ConvertTo,
+) -> io::Result<Vec<MatchingFile>> {
+    use std::{collections::HashMap, os::unix::fs::MetadataExt};
+
+    let mut sources = HashMap::new();
+    if to == ConvertTo::Symlink {
+        for path in walk_all(source_dir)? {
+            let metadata = fs::symlink_metadata(&path)?;
+            if metadata.is_file() {
+                sources
+                    .entry((metadata.dev(), metadata.ino()))
+                    .or_insert(path);
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    for path in walk_all(target_dir)? {
+        let metadata = fs::symlink_metadata(&path)?;
+        let source = match to {
+            ConvertTo::Hardlink if metadata.is_symlink() => {
+                let Ok(resolved) = fs::canonicalize(&path) else {
+                    tracing::warn!("Skipping broken symlink {path:?}");
+                    continue;
+                };
+                let resolved_metadata = fs::metadata(&resolved)?;
+                if !resolved_metadata.is_file() {
+                    continue;
+                }
+                if resolved_metadata.dev() != metadata.dev() {
+                    tracing::info!("Skipping {path:?}: {resolved:?} is on another device");
+                    continue;
+                }
+                resolved
+            }
+            ConvertTo::Symlink if metadata.is_file() && metadata.nlink() > 1 => {
+                match sources.get(&(metadata.dev(), metadata.ino())) {
+                    Some(source) => source.clone(),
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let size = fs::metadata(&source)?.len();
+        links.push(MatchingFile::new(
+            source,
+            path,
+            size,
+            String::new(),
+            MatchReason::ExistingLink,
+        ));
+    }
+    Ok(links)
+}
+
+#[cfg(not(unix))]
+pub fn find_convertible_links(
+    _source_dir: &[impl AsRef<Path>],
+    _target_dir: &[impl AsRef<Path>],
+    _to: ConvertTo,
+) -> io::Result<Vec<MatchingFile>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Converting links is only supported on Unix",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_convertible_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_dir = dir.path().join("source");
+        let target_dir = dir.path().join("target");
+        fs::create_dir(&source_dir).unwrap();
+        fs::create_dir(&target_dir).unwrap();
+        let source = source_dir.join("episode.mkv");
+        fs::write(&source, "episode").unwrap();
+        fs::write(target_dir.join("unrelated.mkv"), "unrelated").unwrap();
+        std::os::unix::fs::symlink(&source, target_dir.join("symlinked.mkv")).unwrap();
+        fs::hard_link(&source, target_dir.join("hardlinked.mkv")).unwrap();
+
+        let to_hardlink =
+            find_convertible_links(&[&source_dir], &[&target_dir], ConvertTo::Hardlink).unwrap();
+        assert_eq!(to_hardlink.len(), 1);
+        assert_eq!(to_hardlink[0].dest_path(), target_dir.join("symlinked.mkv"));
+        assert_eq!(
+            to_hardlink[0].src_path(),
+            fs::canonicalize(&source).unwrap()
+        );
+
+        let to_symlink =
+            find_convertible_links(&[&source_dir], &[&target_dir], ConvertTo::Symlink).unwrap();
+        assert_eq!(to_symlink.len(), 1);
+        assert_eq!(to_symlink[0].dest_path(), target_dir.join("hardlinked.mkv"));
+        assert_eq!(to_symlink[0].src_path(), source);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::exclude::Excludes;
+use crate::actions::LinkMode;
+
+/// Name of the file overriding options for the directory it's in and everything below.
+pub const FILE_NAME: &str = ".atorr.toml";
+
+/// The contents of a `.atorr.toml`, e.g.
+///
+/// ```toml
+/// # Never touch anything in here
+/// skip = true
+/// # rsync style patterns, relative to this directory
+/// exclude = ["*.nfo", "Sample/"]
+/// link_mode = ["hardlink", "symlink"]
+/// ```
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirConfigFile {
+    #[serde(default)]
+    skip: bool,
+    #[serde(default)]
+    exclude: Vec<String>,
+    link_mode: Option<Vec<LinkMode>>,
+}
+
+/// Options overridden for one subtree.
+#[derive(Debug)]
+struct DirConfig {
+    /// Leave the whole subtree alone, in scans and when replacing
+    skip: bool,
+    /// Matched against paths below the directory of the config
+    excludes: Excludes,
+    /// Replaces the modes given on the command line for targets in the subtree
+    link_modes: Option<Vec<LinkMode>>,
+}
+
+/// The `.atorr.toml` files found so far, read once each. Clones share what was read.
+#[derive(Debug, Default, Clone)]
+pub struct DirConfigs {
+    /// The scanned paths, above which configs aren't looked for. Every ancestor when empty
+    roots: Arc<Vec<PathBuf>>,
+    /// By directory, `None` for directories without one
+    read: Arc<Mutex<HashMap<PathBuf, Option<Arc<DirConfig>>>>>,
+}
+
+impl DirConfigs {
+    /// Configs from the directories in and below `roots`, the paths being scanned.
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self {
+            roots: Arc::new(roots),
+            ..Default::default()
+        }
+    }
+
+    fn load(&self, dir: &Path) -> io::Result<Option<Arc<DirConfig>>> {
+        if let Some(config) = self.read.lock().expect("Config lock poisoned").get(dir) {
+            return Ok(config.clone());
+        }
+        let path = dir.join(FILE_NAME);
+        let config = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let file: DirConfigFile = toml::from_str(&contents).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {path:?}: {e}"))
+                })?;
+                tracing::debug!("Using the overrides in {path:?}");
+                Some(Arc::new(DirConfig {
+                    skip: file.skip,
+                    excludes: Excludes::new(&file.exclude)?,
+                    link_modes: file.link_mode.filter(|modes| !modes.is_empty()),
+                }))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.read
+            .lock()
+            .expect("Config lock poisoned")
+            .insert(dir.to_path_buf(), config.clone());
+        Ok(config)
+    }
+
+    /// The configs applying to the contents of `dir`, nearest first, up to the root it's in.
+    /// None outside of the roots.
+    fn chain<'a>(&self, dir: &'a Path) -> io::Result<Vec<(&'a Path, Arc<DirConfig>)>> {
+        let mut chain = Vec::new();
+        for dir in dir.ancestors() {
+            if let Some(config) = self.load(dir)? {
+                chain.push((dir, config));
+            }
+            if self.roots.iter().any(|root| root == dir) {
+                return Ok(chain);
+            }
+        }
+        if !self.roots.is_empty() {
+            chain.clear();
+        }
+        Ok(chain)
+    }
+
+    /// Whether `dir` is in a subtree to be left alone.
+    pub fn skipped(&self, dir: &Path) -> io::Result<bool> {
+        Ok(self.chain(dir)?.iter().any(|(_, config)| config.skip))
+    }
+
+    /// Whether `path` is excluded by the config of a directory above it.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> io::Result<bool> {
+        let Some(parent) = path.parent() else {
+            return Ok(false);
+        };
+        Ok(self.chain(parent)?.iter().any(|(dir, config)| {
+            path.strip_prefix(dir)
+                .is_ok_and(|relative| config.excludes.is_excluded(relative, is_dir))
+        }))
+    }
+
+    /// The link modes chosen for the subtree `path` is in, if any.
+    pub fn link_modes(&self, path: &Path) -> io::Result<Option<Vec<LinkMode>>> {
+        let Some(parent) = path.parent() else {
+            return Ok(None);
+        };
+        Ok(self
+            .chain(parent)?
+            .into_iter()
+            .find_map(|(_, config)| config.link_modes.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (show, season, extras) = (
+            dir.path().join("show"),
+            dir.path().join("show/season 1"),
+            dir.path().join("show/extras"),
+        );
+        fs::create_dir_all(&season).unwrap();
+        fs::create_dir_all(&extras).unwrap();
+        fs::write(
+            show.join(FILE_NAME),
+            "exclude = [\"*.nfo\"]\nlink_mode = [\"hardlink\", \"symlink\"]\n",
+        )
+        .unwrap();
+        fs::write(season.join(FILE_NAME), "exclude = [\"/sample.mkv\"]\n").unwrap();
+        fs::write(extras.join(FILE_NAME), "skip = true\n").unwrap();
+
+        let configs = DirConfigs::default();
+        assert!(configs.is_excluded(&season.join("e01.nfo"), false).unwrap());
+        assert!(
+            configs
+                .is_excluded(&season.join("sample.mkv"), false)
+                .unwrap()
+        );
+        assert!(
+            !configs
+                .is_excluded(&show.join("sample.mkv"), false)
+                .unwrap()
+        );
+        assert!(
+            !configs
+                .is_excluded(&dir.path().join("e01.nfo"), false)
+                .unwrap()
+        );
+
+        assert!(configs.skipped(&extras).unwrap());
+        assert!(!configs.skipped(&season).unwrap());
+
+        assert_eq!(
+            configs.link_modes(&season.join("e01.mkv")).unwrap(),
+            Some(vec![LinkMode::Hardlink, LinkMode::Symlink])
+        );
+        assert_eq!(
+            configs.link_modes(&dir.path().join("e01.mkv")).unwrap(),
+            None
+        );
+
+        fs::write(dir.path().join(FILE_NAME), "skip = \"yes\"\n").unwrap();
+        assert!(DirConfigs::default().skipped(&show).is_err());
+        // Nothing above the scanned paths is read
+        let configs = DirConfigs::new(vec![season.clone()]);
+        assert!(!configs.skipped(&season).unwrap());
+        assert!(
+            configs
+                .is_excluded(&season.join("sample.mkv"), false)
+                .unwrap()
+        );
+        assert!(!configs.is_excluded(&season.join("e01.nfo"), false).unwrap());
+        assert_eq!(configs.link_modes(&season.join("e01.mkv")).unwrap(), None);
+    }
+}
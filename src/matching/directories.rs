@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::MatchingFile;
+
+/// A target directory whose every file matches the file at the same relative path in a source
+/// directory, so it can be replaced by a single directory symlink.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DirectoryMatch {
+    /// The source directory to link to
+    pub src_dir: PathBuf,
+    /// The target directory to be replaced
+    pub dest_dir: PathBuf,
+    /// Total size of the files in the directory
+    pub size: u64,
+}
+
+/// Number of non-directory entries anywhere below `dir`, without following symlinks.
+fn count_files(dir: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            count += count_files(&entry.path())?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Every (target directory, source directory) pair below `target_roots` that has `matching`
+/// at the same relative path in both.
+fn directory_pairs<'a>(
+    matching: &'a MatchingFile,
+    target_roots: &[PathBuf],
+) -> Vec<(&'a Path, &'a Path)> {
+    let mut pairs = Vec::new();
+    let mut dest = matching.dest_path();
+    let mut src = matching.src_path();
+    // Files under different names would be renamed by linking their directory
+    if dest.file_name() != src.file_name() {
+        return pairs;
+    }
+    while let (Some(dest_dir), Some(src_dir)) = (dest.parent(), src.parent()) {
+        if !target_roots
+            .iter()
+            .any(|root| dest_dir.starts_with(root) && dest_dir != root)
+        {
+            break;
+        }
+        pairs.push((dest_dir, src_dir));
+
+        // Going higher requires the directory names to line up as well
+        if dest_dir.file_name() != src_dir.file_name() {
+            break;
+        }
+        dest = dest_dir;
+        src = src_dir;
+    }
+    pairs
+}
+
+/// Finds the largest target directories whose content is fully matched by a single source
+/// directory, each file by the one at the same relative path, with nothing left over on either
+/// side. Returns the directory matches along with the file matches that aren't covered by them.
+pub fn collapse_directories(
+    matches: Vec<MatchingFile>,
+    target_roots: &[impl AsRef<Path>],
+) -> io::Result<(Vec<DirectoryMatch>, Vec<MatchingFile>)> {
+    let target_roots: Vec<PathBuf> = target_roots
+        .iter()
+        .map(|root| root.as_ref().to_path_buf())
+        .collect();
+
+    // The relative paths matched in each pair, and their total size
+    let mut support: HashMap<(&Path, &Path), (HashSet<&Path>, u64)> = HashMap::new();
+    for matching in &matches {
+        for pair in directory_pairs(matching, &target_roots) {
+            let Ok(relative) = matching.dest_path().strip_prefix(pair.0) else {
+                continue;
+            };
+            let (relatives, size) = support.entry(pair).or_default();
+            // Another source for the same target file adds nothing to the directory
+            if relatives.insert(relative) {
+                *size += matching.size();
+            }
+        }
+    }
+
+    let mut candidates: Vec<_> = support.into_iter().collect();
+    // Shallowest directories first so the largest subtree wins
+    candidates.sort_by_key(|((dest_dir, _), _)| (dest_dir.components().count(), *dest_dir));
+
+    let mut directories: Vec<DirectoryMatch> = Vec::new();
+    for ((dest_dir, src_dir), (relatives, size)) in candidates {
+        let count = relatives.len();
+        if directories
+            .iter()
+            .any(|d| dest_dir.starts_with(&d.dest_dir))
+        {
+            continue;
+        }
+        if count_files(dest_dir)? == count && count_files(src_dir)? == count {
+            directories.push(DirectoryMatch {
+                src_dir: src_dir.to_path_buf(),
+                dest_dir: dest_dir.to_path_buf(),
+                size,
+            });
+        }
+    }
+
+    let remaining = matches
+        .iter()
+        .filter(|m| {
+            !directories
+                .iter()
+                .any(|d| m.dest_path().starts_with(&d.dest_dir))
+        })
+        .cloned()
+        .collect();
+
+    Ok((directories, remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::no_cache::HashingNoCache;
+    use crate::matching::{MatchOptions, matcher::HashMatcher, stream_matching_files};
+
+    fn create_test_file(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_collapse_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        // Fully identical subtree under a different name
+        create_test_file(&source_dir.join("Show (2020)/e1.mkv"), "e1");
+        create_test_file(&source_dir.join("Show (2020)/extras/e2.mkv"), "e2");
+        create_test_file(&target_dir.join("Show.2020/e1.mkv"), "e1");
+        create_test_file(&target_dir.join("Show.2020/extras/e2.mkv"), "e2");
+
+        // Target directory with an extra unmatched file
+        create_test_file(&source_dir.join("Movie/movie.mkv"), "movie");
+        create_test_file(&target_dir.join("Movie/movie.mkv"), "movie");
+        create_test_file(&target_dir.join("Movie/notes.txt"), "notes");
+
+        let matches: Vec<MatchingFile> = stream_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut HashingNoCache::new(),
+            Box::new(HashMatcher::new()),
+            &MatchOptions::default(),
+        )
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap();
+        assert_eq!(matches.len(), 3);
+
+        let (directories, remaining) = collapse_directories(matches, &[&target_dir]).unwrap();
+
+        assert_eq!(directories.len(), 1);
+        assert_eq!(directories[0].dest_dir, target_dir.join("Show.2020"));
+        assert_eq!(directories[0].src_dir, source_dir.join("Show (2020)"));
+        assert_eq!(directories[0].size, 4);
+
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].dest_path(), target_dir.join("Movie/movie.mkv"));
+
+        // As many matches as files on both sides, but under different names
+        create_test_file(&source_dir.join("Other/e01.mkv"), "e01");
+        create_test_file(&source_dir.join("Other/trailer.mkv"), "trailer");
+        create_test_file(&target_dir.join("Other/e01.mkv"), "e01");
+        create_test_file(&target_dir.join("Other/e01 copy.mkv"), "e01");
+        let matches: Vec<MatchingFile> = stream_matching_files(
+            &[source_dir.join("Other")],
+            &[target_dir.join("Other")],
+            &mut HashingNoCache::new(),
+            Box::new(HashMatcher::new()),
+            &MatchOptions::default(),
+        )
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap();
+        assert_eq!(matches.len(), 2);
+        let (directories, remaining) = collapse_directories(matches, &[&target_dir]).unwrap();
+        assert!(directories.is_empty());
+        assert_eq!(remaining.len(), 2);
+    }
+}
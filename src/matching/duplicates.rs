@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{DiscoveredFiles, FileType, MatchOptions, find::find_files};
+use crate::hashing::{Hash, HashCache};
+
+/// Files sharing the same content.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct DuplicateGroup {
+    pub hash: Hash,
+    /// Size of each file
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+    /// Bytes freed by keeping a single copy. Paths already hardlinked together count once.
+    pub reclaimable: u64,
+}
+
+/// Finds the regular files under `paths` with the same content, biggest saving first. Only
+/// files sharing their size with another are hashed.
+pub fn find_duplicates(
+    paths: &[impl AsRef<Path>],
+    hasher: &mut dyn HashCache,
+    options: &MatchOptions,
+) -> io::Result<Vec<DuplicateGroup>> {
+    let mut discovered = DiscoveredFiles::default();
+    for path in paths {
+        let path = path.as_ref();
+        let _span = tracing::info_span!("scan", path = ?path).entered();
+        find_files(&mut discovered, path, hasher, options)?;
+    }
+
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for f in discovered.unhashed {
+        if let FileType::File(path) = f.file {
+            by_size.entry(f.size).or_default().push(path);
+        }
+    }
+    by_size.retain(|_, paths| paths.len() > 1);
+    let (files, bytes) = by_size
+        .iter()
+        .fold((0, 0), |(files, bytes), (size, paths)| {
+            (
+                files + paths.len() as u64,
+                bytes + size * paths.len() as u64,
+            )
+        });
+    options.progress.start_phase("Hashing", files, bytes);
+
+    let mut by_hash: BTreeMap<(u64, Hash), Vec<PathBuf>> = BTreeMap::new();
+    for (size, paths) in by_size {
+        for path in paths {
+            let hash = hasher.hash_file(&path)?;
+            options.progress.hashed(&path, size);
+            by_hash.entry((size, hash)).or_default().push(path);
+        }
+    }
+    options.progress.finish();
+
+    let mut groups = Vec::new();
+    for ((size, hash), mut paths) in by_hash {
+        if paths.len() < 2 {
+            continue;
+        }
+        paths.sort();
+        let copies = distinct_files(&paths)?;
+        groups.push(DuplicateGroup {
+            hash,
+            size,
+            reclaimable: size * (copies as u64 - 1),
+            paths,
+        });
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable));
+    Ok(groups)
+}
+
+/// How many separate files `paths` are, counting hardlinks to the same data once.
+#[cfg(unix)]
+fn distinct_files(paths: &[PathBuf]) -> io::Result<usize> {
+    use std::os::unix::fs::MetadataExt as _;
+    let mut seen = std::collections::HashSet::new();
+    for path in paths {
+        let metadata = std::fs::metadata(path)?;
+        seen.insert((metadata.dev(), metadata.ino()));
+    }
+    Ok(seen.len())
+}
+
+#[cfg(not(unix))]
+fn distinct_files(paths: &[PathBuf]) -> io::Result<usize> {
+    Ok(paths.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::no_cache::HashingNoCache;
+    use std::fs;
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("film.mkv"), "a long film").unwrap();
+        fs::write(b.join("film copy.mkv"), "a long film").unwrap();
+        fs::write(b.join("film again.mkv"), "a long film").unwrap();
+        fs::write(a.join("e01.mkv"), "episode").unwrap();
+        fs::write(b.join("e01.mkv"), "episode").unwrap();
+        // Same size, different content
+        fs::write(a.join("e02.mkv"), "EPISODE").unwrap();
+        fs::write(a.join("unique.mkv"), "unique").unwrap();
+        #[cfg(unix)]
+        fs::hard_link(a.join("e01.mkv"), a.join("e01 link.mkv")).unwrap();
+
+        let groups = find_duplicates(
+            &[&a, &b],
+            &mut HashingNoCache::new(),
+            &MatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].size, 11);
+        assert_eq!(groups[0].paths.len(), 3);
+        assert_eq!(groups[0].reclaimable, 22);
+        assert!(groups[1].paths.contains(&b.join("e01.mkv")));
+        // The hardlink takes no extra space
+        assert_eq!(groups[1].reclaimable, 7);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+/// One rsync style exclude pattern.
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: GlobMatcher,
+    /// Given with a trailing `/`, so only matching directories
+    dir_only: bool,
+    /// Containing a `/`, so matched against the path below the scanned directory rather than
+    /// just the file name
+    whole_path: bool,
+}
+
+/// Paths left out of scanning, following rsync's exclude rules: a pattern without a `/`
+/// matches a file or directory name anywhere, one with a `/` matches the end of the path below
+/// the scanned directory, or all of it when it starts with `/`. A trailing `/` only matches
+/// directories, `*` stops at a `/` and `**` doesn't.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    patterns: Vec<Pattern>,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> io::Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let (pattern, dir_only) = match pattern.strip_suffix('/') {
+                    Some(pattern) => (pattern, true),
+                    None => (pattern.as_str(), false),
+                };
+                let (glob, whole_path) = match pattern.strip_prefix('/') {
+                    Some(anchored) => (anchored.to_owned(), true),
+                    None if pattern.contains('/') => (format!("**/{pattern}"), true),
+                    None => (pattern.to_owned(), false),
+                };
+                let glob = GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+                    .compile_matcher();
+                Ok(Pattern {
+                    glob,
+                    dir_only,
+                    whole_path,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Whether `relative`, a path below the scanned directory, is excluded.
+    pub(super) fn is_excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        self.patterns.iter().any(|pattern| {
+            if pattern.dir_only && !is_dir {
+                return false;
+            }
+            if pattern.whole_path {
+                pattern.glob.is_match(relative)
+            } else {
+                relative
+                    .file_name()
+                    .is_some_and(|name| pattern.glob.is_match(name))
+            }
+        })
+    }
+}
+
+/// Reads exclude patterns from `path`, one per line like rsync's `--exclude-from`. Blank lines
+/// and lines starting with `#` or `;` are comments.
+pub fn read_exclude_file(path: &Path) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Reading excludes {path:?}: {e}")))?;
+    Ok(contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.starts_with(['#', ';']))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Every exclude pattern given directly and read from `files`.
+pub fn collect_patterns(patterns: &[String], files: &[PathBuf]) -> io::Result<Vec<String>> {
+    let mut all = patterns.to_vec();
+    for file in files {
+        all.extend(read_exclude_file(file)?);
+    }
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excludes() {
+        let excludes = Excludes::new(&[
+            "*.nfo".to_owned(),
+            "Sample/".to_owned(),
+            "/extras".to_owned(),
+            "season 1/*.srt".to_owned(),
+        ])
+        .unwrap();
+        let excluded = |path: &str, is_dir| excludes.is_excluded(Path::new(path), is_dir);
+
+        assert!(excluded("show/episode.nfo", false));
+        assert!(!excluded("show/episode.mkv", false));
+        // Directories only
+        assert!(excluded("show/Sample", true));
+        assert!(!excluded("show/Sample", false));
+        // Anchored at the scanned directory
+        assert!(excluded("extras", true));
+        assert!(!excluded("show/extras", true));
+        // Matching the end of the path, without crossing directories
+        assert!(excluded("show/season 1/e01.srt", false));
+        assert!(!excluded("show/season 1/subs/e01.srt", false));
+
+        assert!(Excludes::new(&["[".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_read_exclude_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("excludes");
+        fs::write(&path, "# Metadata\n*.nfo\r\n\n; Samples\nSample/\n").unwrap();
+        assert_eq!(
+            collect_patterns(&["*.txt".to_owned()], &[path]).unwrap(),
+            ["*.txt", "*.nfo", "Sample/"]
+        );
+    }
+}
@@ -0,0 +1,830 @@
+use crate::hashing::Hash;
+use std::path::{Path, PathBuf};
+
+use crate::hashing::HashCache;
+
+use super::{MatchOptions, dir_config};
+
+#[derive(Debug)]
+pub enum FileType {
+    File(PathBuf),
+    Symlink {
+        source: PathBuf,
+        target: PathBuf,
+    }, // Directory,
+    /// Symlink whose target no longer exists. The hash is the one previously recorded for the
+    /// target.
+    BrokenSymlink {
+        source: PathBuf,
+        target: PathBuf,
+    },
+}
+
+impl FileType {
+    pub fn src_path(&self) -> &Path {
+        match self {
+            Self::File(path) => path,
+            Self::Symlink { source, target: _ } => source,
+            Self::BrokenSymlink { source, target: _ } => source,
+        }
+    }
+}
+
+/// A file found during traversal that hasn't been hashed yet.
+#[derive(Debug)]
+pub struct UnhashedFile {
+    pub file: FileType,
+    /// The path whose content gets hashed. For symlinks this is the resolved link target.
+    pub content_path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Default)]
+pub struct DiscoveredFiles {
+    pub files: std::collections::HashMap<Hash, Vec<FileType>>,
+    /// Files found by [`find_files`] that are still waiting to be hashed
+    pub unhashed: Vec<UnhashedFile>,
+    /// Device id of the data behind each discovered path. For symlinks this is the device of
+    /// the file being pointed to.
+    pub devices: std::collections::HashMap<PathBuf, u64>,
+    /// Identities of every directory already traversed, so the same directory exposed through
+    /// a bind mount or a loop is only scanned once.
+    visited_dirs: std::collections::HashSet<DirIdentity>,
+}
+
+impl DiscoveredFiles {
+    fn add_hash(&mut self, hash: Hash, path: FileType) {
+        self.record_device(&path);
+        self.files.entry(hash).or_default().push(path);
+    }
+
+    fn add_unhashed(&mut self, file: FileType, content_path: PathBuf) -> std::io::Result<()> {
+        self.record_device(&file);
+        let size = std::fs::metadata(&content_path)?.len();
+        self.unhashed.push(UnhashedFile {
+            file,
+            content_path,
+            size,
+        });
+        Ok(())
+    }
+
+    /// Number of files found, hashed or not.
+    pub fn count(&self) -> usize {
+        self.unhashed.len() + self.files.values().map(Vec::len).sum::<usize>()
+    }
+
+    fn record_device(&mut self, path: &FileType) {
+        if let Some(device) = device_id(path.src_path()) {
+            self.devices.insert(path.src_path().to_path_buf(), device);
+        }
+    }
+
+    /// Hashes a file previously found by [`find_files`] and records it under its hash.
+    pub fn hash_unhashed(
+        &mut self,
+        unhashed: UnhashedFile,
+        hasher: &mut dyn HashCache,
+    ) -> std::io::Result<()> {
+        let hash = hasher.hash_file(&unhashed.content_path)?;
+        self.files.entry(hash).or_default().push(unhashed.file);
+        Ok(())
+    }
+
+    /// Records `dir` as visited. Returns false if it has already been traversed.
+    fn mark_visited(&mut self, dir: &Path) -> std::io::Result<bool> {
+        Ok(self.visited_dirs.insert(dir_identity(dir)?))
+    }
+
+    /// Device id recorded for `path`, if the platform supports it.
+    pub fn device_of(&self, path: &Path) -> Option<u64> {
+        self.devices.get(path).copied()
+    }
+}
+
+/// Device id of the filesystem containing `path`, following symlinks.
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+    std::fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Device id from metadata already read, if the platform supports it.
+#[cfg(unix)]
+fn metadata_device(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn metadata_device(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+type DirIdentity = (u64, u64);
+#[cfg(not(unix))]
+type DirIdentity = PathBuf;
+
+/// Identifies a directory regardless of the path used to reach it.
+#[cfg(unix)]
+fn dir_identity(dir: &Path) -> std::io::Result<DirIdentity> {
+    use std::os::unix::fs::MetadataExt as _;
+    let metadata = std::fs::metadata(dir)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_identity(dir: &Path) -> std::io::Result<DirIdentity> {
+    std::fs::canonicalize(dir)
+}
+
+/// Resolves a link target relative to the directory containing the link.
+fn resolve_link_target(link: &Path, target: &Path) -> PathBuf {
+    match link.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target.to_path_buf(),
+    }
+}
+
+/// Dotfiles and dot-directories such as `.git` or `.Trash`
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".")
+}
+
+/// Traverse through any subdirectories and find any files that exist then hash them.
+/// Records any symlinks found
+#[cfg(test)]
+pub fn find_and_hash_files(
+    disc_files: &mut DiscoveredFiles,
+    dir: &Path,
+    hasher: &mut dyn HashCache,
+    options: &MatchOptions,
+) -> std::io::Result<()> {
+    find_files(disc_files, dir, hasher, options)?;
+    for unhashed in std::mem::take(&mut disc_files.unhashed) {
+        disc_files.hash_unhashed(unhashed, hasher)?;
+    }
+    Ok(())
+}
+
+/// Traverse through any subdirectories and record any files that exist without hashing them,
+/// apart from broken symlinks which can only be identified by their previously cached hash.
+pub fn find_files(
+    disc_files: &mut DiscoveredFiles,
+    dir: &Path,
+    hasher: &mut dyn HashCache,
+    options: &MatchOptions,
+) -> std::io::Result<()> {
+    let mut queue = std::collections::VecDeque::<PathBuf>::from(vec![dir.to_path_buf()]);
+
+    if !dir.symlink_metadata()?.is_dir() {
+        return disc_files.add_unhashed(FileType::File(dir.to_path_buf()), dir.to_path_buf());
+    }
+
+    let root = dir;
+    let root_device = device_id(root);
+    while let Some(dir) = queue.pop_back() {
+        if !disc_files.mark_visited(&dir)? {
+            tracing::debug!("Skipping already visited directory {dir:?}");
+            continue;
+        }
+        if options.dir_configs.skipped(&dir)? {
+            tracing::debug!(
+                "Skipping {dir:?} as configured in {}",
+                dir_config::FILE_NAME
+            );
+            continue;
+        }
+
+        let mut files = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if options.skip_hidden && is_hidden(&entry.file_name()) {
+                tracing::debug!("Skipping hidden entry {:?}", entry.path());
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if options.excludes.is_excluded(relative, metadata.is_dir())
+                || options.dir_configs.is_excluded(&path, metadata.is_dir())?
+                || entry.file_name() == dir_config::FILE_NAME
+            {
+                tracing::debug!("Skipping excluded entry {path:?}");
+                continue;
+            }
+
+            match metadata {
+                ft if ft.is_dir() => {
+                    if options.one_file_system && metadata_device(&ft) != root_device {
+                        tracing::debug!("Skipping {path:?} on another filesystem");
+                        continue;
+                    }
+                    queue.push_back(entry.path());
+                    continue;
+                }
+                ft if ft.is_file() => {
+                    disc_files.add_unhashed(FileType::File(entry.path()), entry.path())?;
+                }
+                ft if ft.is_symlink() => {
+                    let target = std::fs::read_link(entry.path())?;
+                    let resolved = resolve_link_target(&entry.path(), &target);
+                    if resolved.exists() {
+                        // Hash through the link target so the hash is recorded against it in the
+                        // cache, allowing the link to be repaired if the target goes missing
+                        disc_files.add_unhashed(
+                            FileType::Symlink {
+                                source: entry.path(),
+                                target,
+                            },
+                            resolved,
+                        )?;
+                    } else if let Some((hash, _)) = hasher.retrieve_hash(&resolved) {
+                        disc_files.add_hash(
+                            hash,
+                            FileType::BrokenSymlink {
+                                source: entry.path(),
+                                target: resolved,
+                            },
+                        );
+                    } else {
+                        tracing::warn!(
+                            "Broken symlink {:?} -> {:?} has no previously recorded hash",
+                            entry.path(),
+                            target
+                        );
+                    }
+                }
+                _ => {
+                    tracing::error!("Entry is not directory, file or symlink");
+                    continue;
+                }
+            }
+            files += 1;
+        }
+        options.progress.scanned_directory(files);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    mod find_and_hash_files {
+        use crate::hashing::no_cache::HashingNoCache;
+        use crate::matching::MatchOptions;
+
+        use super::*;
+
+        #[test]
+        fn test_empty_directory() {
+            let temp_dir = tempdir().unwrap();
+            let mut result = DiscoveredFiles::default();
+            let mut hasher = HashingNoCache::new();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            assert!(result.files.is_empty());
+        }
+
+        #[test]
+        fn test_single_file() {
+            let temp_dir = tempdir().unwrap();
+            let file_path = temp_dir.path().join("test.txt");
+
+            let mut file = File::create(&file_path).unwrap();
+            file.write_all(b"Hello, World!").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(result.files.len(), 1);
+
+            let (hash, file_types) = result.files.iter().next().unwrap();
+            assert_eq!(file_types.len(), 1);
+
+            // Verify the hash is correct for "Hello, World!"
+            let expected_hash = "DFFD6021BB2BD5B0AF676290809EC3A53191DD81C7F70A4B28688A362182986F";
+            assert_eq!(hash, expected_hash);
+
+            if let FileType::File(path) = &file_types[0] {
+                assert_eq!(path, &file_path);
+            } else {
+                panic!("Expected FileType::File");
+            }
+        }
+
+        #[test]
+        fn test_nested_directories() {
+            let temp_dir = tempdir().unwrap();
+
+            // Create nested directory structure
+            let sub_dir = temp_dir.path().join("subdir");
+            fs::create_dir(&sub_dir).unwrap();
+
+            let file1 = temp_dir.path().join("file1.txt");
+            let file2 = sub_dir.join("file2.txt");
+
+            let mut f1 = File::create(&file1).unwrap();
+            f1.write_all(b"File 1 content").unwrap();
+
+            let mut f2 = File::create(&file2).unwrap();
+            f2.write_all(b"File 2 content").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            // Should have 2 entries in the map (different hashes for different content)
+            assert_eq!(result.files.len(), 2);
+
+            // Each hash should have exactly one file
+            for (_, file_types) in result.files.iter() {
+                assert_eq!(file_types.len(), 1);
+            }
+        }
+
+        #[test]
+        fn test_duplicate_files_same_hash() {
+            let temp_dir = tempdir().unwrap();
+
+            let file1 = temp_dir.path().join("file1.txt");
+            let file2 = temp_dir.path().join("file2.txt");
+
+            let content = b"Identical content";
+
+            let mut f1 = File::create(&file1).unwrap();
+            f1.write_all(content).unwrap();
+
+            let mut f2 = File::create(&file2).unwrap();
+            f2.write_all(content).unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            // Should have only one hash entry (both files have same content)
+            assert_eq!(result.files.len(), 1);
+
+            let (_, file_types) = result.files.iter().next().unwrap();
+            // But that hash should have two files associated with it
+            assert_eq!(file_types.len(), 2);
+
+            let paths: Vec<&PathBuf> = file_types
+                .iter()
+                .filter_map(|ft| {
+                    if let FileType::File(path) = ft {
+                        Some(path)
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            assert_eq!(paths.len(), 2);
+            assert!(paths.contains(&&file1));
+            assert!(paths.contains(&&file2));
+        }
+
+        #[test]
+        fn test_skip_hidden() {
+            let temp_dir = tempdir().unwrap();
+
+            let hidden_dir = temp_dir.path().join(".git");
+            fs::create_dir(&hidden_dir).unwrap();
+            File::create(hidden_dir.join("config")).unwrap();
+            File::create(temp_dir.path().join(".hidden")).unwrap();
+
+            let visible = temp_dir.path().join("visible.txt");
+            let mut file = File::create(&visible).unwrap();
+            file.write_all(b"visible").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            let options = MatchOptions {
+                skip_hidden: true,
+                ..Default::default()
+            };
+            find_and_hash_files(&mut result, temp_dir.path(), &mut hasher, &options).unwrap();
+
+            assert_eq!(result.files.len(), 1);
+            let (_, file_types) = result.files.iter().next().unwrap();
+            assert_eq!(file_types.len(), 1);
+            assert_eq!(file_types[0].src_path(), visible);
+        }
+
+        #[test]
+        fn test_excludes() {
+            let temp_dir = tempdir().unwrap();
+
+            let sample_dir = temp_dir.path().join("Sample");
+            fs::create_dir(&sample_dir).unwrap();
+            fs::write(sample_dir.join("sample.mkv"), "sample").unwrap();
+            fs::write(temp_dir.path().join("movie.nfo"), "info").unwrap();
+            let movie = temp_dir.path().join("movie.mkv");
+            fs::write(&movie, "movie").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            let options = MatchOptions {
+                excludes: crate::matching::exclude::Excludes::new(&[
+                    "Sample/".to_owned(),
+                    "*.nfo".to_owned(),
+                ])
+                .unwrap(),
+                ..Default::default()
+            };
+            find_and_hash_files(&mut result, temp_dir.path(), &mut hasher, &options).unwrap();
+
+            let paths: Vec<_> = result
+                .files
+                .values()
+                .flatten()
+                .map(|f| f.src_path())
+                .collect();
+            assert_eq!(paths, [movie.as_path()]);
+        }
+
+        #[test]
+        fn test_same_directory_scanned_once() {
+            let temp_dir = tempdir().unwrap();
+            let sub_dir = temp_dir.path().join("subdir");
+            fs::create_dir(&sub_dir).unwrap();
+            let mut file = File::create(sub_dir.join("file.txt")).unwrap();
+            file.write_all(b"File content").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            let options = MatchOptions::default();
+            find_and_hash_files(&mut result, temp_dir.path(), &mut hasher, &options).unwrap();
+            // Reach the same subdirectory through a different path
+            find_and_hash_files(
+                &mut result,
+                &sub_dir.join("..").join("subdir"),
+                &mut hasher,
+                &options,
+            )
+            .unwrap();
+
+            assert_eq!(result.files.len(), 1);
+            let (_, file_types) = result.files.iter().next().unwrap();
+            assert_eq!(file_types.len(), 1);
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_records_device_ids() {
+            let temp_dir = tempdir().unwrap();
+            let file_path = temp_dir.path().join("file.txt");
+            File::create(&file_path).unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            use std::os::unix::fs::MetadataExt as _;
+            let expected = fs::metadata(temp_dir.path()).unwrap().dev();
+            assert_eq!(result.device_of(&file_path), Some(expected));
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        fn test_one_file_system() {
+            let temp_dir = tempdir().unwrap();
+            let movie = temp_dir.path().join("movie.mkv");
+            fs::write(&movie, "movie").unwrap();
+            let mount = temp_dir.path().join("snapshot");
+            fs::create_dir(&mount).unwrap();
+            // Mounting takes root
+            let mounted = std::process::Command::new("mount")
+                .args(["-t", "tmpfs", "tmpfs"])
+                .arg(&mount)
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if !mounted {
+                eprintln!("Skipping, a filesystem can't be mounted here");
+                return;
+            }
+            fs::write(mount.join("movie.mkv"), "movie").unwrap();
+
+            let scan = |one_file_system| {
+                let mut result = DiscoveredFiles::default();
+                let options = MatchOptions {
+                    one_file_system,
+                    ..Default::default()
+                };
+                find_and_hash_files(
+                    &mut result,
+                    temp_dir.path(),
+                    &mut HashingNoCache::new(),
+                    &options,
+                )
+                .map(|()| result)
+            };
+            let found = scan(true).map(|result| {
+                let mut paths: Vec<_> = result
+                    .files
+                    .values()
+                    .flatten()
+                    .map(|f| f.src_path().to_path_buf())
+                    .collect();
+                paths.sort();
+                paths
+            });
+            let without = scan(false).map(|result| result.files.values().flatten().count());
+            std::process::Command::new("umount")
+                .arg(&mount)
+                .status()
+                .unwrap();
+
+            assert_eq!(found.unwrap(), [movie]);
+            assert_eq!(without.unwrap(), 2);
+        }
+
+        #[test]
+        fn test_symlink_hashing() {
+            let temp_dir = tempdir().unwrap();
+
+            // Create a target file
+            let target_file = temp_dir.path().join("target.txt");
+            let mut file = File::create(&target_file).unwrap();
+            file.write_all(b"Target content").unwrap();
+
+            // Create a symlink
+            let symlink_path = temp_dir.path().join("link.txt");
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target_file, &symlink_path).unwrap();
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_file(&target_file, &symlink_path).unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            // Retrieve the first hash result
+            let files_entry = result.files.iter().next().unwrap();
+
+            // Should have 1 entry because both files should share the same hash
+            assert_eq!(result.files.len(), 1);
+            // Should have 2 entries: one for the file, one for the symlink
+            // Note: The symlink will be hashed (its content is the path it points to)
+            assert_eq!(files_entry.1.len(), 2);
+
+            // Find the symlink entry
+            let symlink = files_entry
+                .1
+                .iter()
+                .find(|f| matches!(f, FileType::Symlink { .. }))
+                .expect("There should be at least 1 symlink");
+
+            // The symlink should link back to the original file
+            if let FileType::Symlink { source: _, target } = symlink {
+                assert_eq!(target, &target_file);
+            } else {
+                panic!("Is not a symlink somehow!");
+            }
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_broken_symlink_uses_cached_hash() {
+            use crate::hashing::file_cache::HashingFileCache;
+
+            let temp_dir = tempdir().unwrap();
+            let scan_dir = temp_dir.path().join("scan");
+            fs::create_dir(&scan_dir).unwrap();
+
+            let target_file = temp_dir.path().join("target.txt");
+            let mut file = File::create(&target_file).unwrap();
+            file.write_all(b"Target content").unwrap();
+            let symlink_path = scan_dir.join("link.txt");
+            std::os::unix::fs::symlink(&target_file, &symlink_path).unwrap();
+
+            let mut hasher = HashingFileCache::new(temp_dir.path().join("hashes.cache")).unwrap();
+            let options = MatchOptions::default();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(&mut result, &scan_dir, &mut hasher, &options).unwrap();
+            let (expected_hash, _) = result.files.iter().next().unwrap();
+
+            fs::remove_file(&target_file).unwrap();
+            let mut broken_result = DiscoveredFiles::default();
+            find_and_hash_files(&mut broken_result, &scan_dir, &mut hasher, &options).unwrap();
+
+            let (hash, file_types) = broken_result.files.iter().next().unwrap();
+            assert_eq!(hash, expected_hash);
+            assert!(matches!(
+                &file_types[0],
+                FileType::BrokenSymlink { source, target } if source == &symlink_path && target == &target_file
+            ));
+        }
+
+        #[test]
+        fn test_file_as_input() {
+            let temp_dir = tempdir().unwrap();
+            let file_path = temp_dir.path().join("single_file.txt");
+
+            let mut file = File::create(&file_path).unwrap();
+            file.write_all(b"Single file content").unwrap();
+
+            // Call find_and_hash_files directly on the file path
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                &file_path,
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(result.files.len(), 1);
+
+            let (_hash, file_types) = result.files.iter().next().unwrap();
+            assert_eq!(file_types.len(), 1);
+
+            if let FileType::File(path) = &file_types[0] {
+                assert_eq!(path, &file_path);
+            } else {
+                panic!("Expected FileType::File");
+            }
+        }
+
+        #[test]
+        fn test_mixed_content_with_duplicates() {
+            let temp_dir = tempdir().unwrap();
+
+            // Create files with same content
+            let file1 = temp_dir.path().join("file1.txt");
+            let file2 = temp_dir.path().join("file2.txt");
+
+            let common_content = b"Common content";
+            let mut f1 = File::create(&file1).unwrap();
+            f1.write_all(common_content).unwrap();
+            let mut f2 = File::create(&file2).unwrap();
+            f2.write_all(common_content).unwrap();
+
+            // Create file with different content
+            let file3 = temp_dir.path().join("file3.txt");
+            let mut f3 = File::create(&file3).unwrap();
+            f3.write_all(b"Different content").unwrap();
+
+            // Create subdirectory with another file
+            let sub_dir = temp_dir.path().join("subdir");
+            fs::create_dir(&sub_dir).unwrap();
+            let file4 = sub_dir.join("file4.txt");
+            let mut f4 = File::create(&file4).unwrap();
+            f4.write_all(common_content).unwrap(); // Same content again
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            // Should have 2 unique hashes: one for the common content, one for different content
+            assert_eq!(result.files.len(), 2);
+
+            // Find the common content hash (should have 3 files)
+            let common_hash_entry = result
+                .files
+                .iter()
+                .find(|(_, file_types)| file_types.len() == 3)
+                .expect("Should find hash with 3 files");
+
+            let (_, common_files) = common_hash_entry;
+            assert_eq!(common_files.len(), 3);
+
+            // Find the unique content hash (should have 1 file)
+            let unique_hash_entry = result
+                .files
+                .iter()
+                .find(|(_, file_types)| file_types.len() == 1)
+                .expect("Should find hash with 1 file");
+
+            let (_, unique_files) = unique_hash_entry;
+            assert_eq!(unique_files.len(), 1);
+        }
+
+        #[test]
+        fn test_empty_file() {
+            let temp_dir = tempdir().unwrap();
+            let file_path = temp_dir.path().join("empty.txt");
+
+            File::create(&file_path).unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(result.files.len(), 1);
+
+            let (hash, file_types) = result.files.iter().next().unwrap();
+            // SHA-256 hash of empty string
+            let expected_hash = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+            assert_eq!(hash, expected_hash);
+
+            assert_eq!(file_types.len(), 1);
+            if let FileType::File(path) = &file_types[0] {
+                assert_eq!(path, &file_path);
+            } else {
+                panic!("Expected FileType::File");
+            }
+        }
+
+        #[test]
+        fn test_directory_entries_ignored_in_map() {
+            let temp_dir = tempdir().unwrap();
+
+            // Create a directory (should not appear in the files map)
+            let sub_dir = temp_dir.path().join("subdir");
+            fs::create_dir(&sub_dir).unwrap();
+
+            // Create a file in the directory
+            let file_path = sub_dir.join("file.txt");
+            let mut file = File::create(&file_path).unwrap();
+            file.write_all(b"File content").unwrap();
+
+            let mut hasher = HashingNoCache::new();
+            let mut result = DiscoveredFiles::default();
+            find_and_hash_files(
+                &mut result,
+                temp_dir.path(),
+                &mut hasher,
+                &MatchOptions::default(),
+            )
+            .unwrap();
+
+            // Should only have the file, not the directory
+            assert_eq!(result.files.len(), 1);
+
+            let (_, file_types) = result.files.iter().next().unwrap();
+            assert_eq!(file_types.len(), 1);
+
+            // Verify it's a file, not a directory
+            if let FileType::File(path) = &file_types[0] {
+                assert_eq!(path, &file_path);
+            } else {
+                panic!("Expected FileType::File");
+            }
+        }
+    }
+}
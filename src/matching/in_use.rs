@@ -0,0 +1,85 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+/// Files held open by running processes, gathered once from `/proc` so that a download still
+/// being written or a video mid-transcode isn't replaced underneath its writer.
+pub(super) struct OpenFiles {
+    paths: HashSet<PathBuf>,
+}
+
+impl OpenFiles {
+    /// Records every file open by a process we're allowed to inspect. Without `/proc` only
+    /// advisory locks are detected.
+    pub(super) fn scan() -> Self {
+        let mut paths = HashSet::new();
+        let Ok(processes) = fs::read_dir("/proc") else {
+            tracing::warn!("/proc is unavailable, only locked files will be detected as in use");
+            return Self { paths };
+        };
+        for process in processes.flatten() {
+            // Processes can exit mid-scan and others' descriptors may be unreadable
+            let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+                continue;
+            };
+            for fd in fds.flatten() {
+                if let Ok(target) = fs::read_link(fd.path())
+                    && target.is_absolute()
+                {
+                    paths.insert(target);
+                }
+            }
+        }
+        Self { paths }
+    }
+
+    /// Whether `path` is open in another process or holds an advisory lock.
+    pub(super) fn contains(&self, path: &Path) -> bool {
+        let open = fs::canonicalize(path).is_ok_and(|path| self.paths.contains(&path));
+        open || is_locked(path)
+    }
+}
+
+fn is_locked(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    matches!(file.try_lock(), Err(fs::TryLockError::WouldBlock))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_open_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let open_path = dir.path().join("open");
+        let closed_path = dir.path().join("closed");
+        fs::write(&closed_path, "closed").unwrap();
+        let _open = File::create(&open_path).unwrap();
+
+        let open_files = OpenFiles::scan();
+
+        assert!(open_files.contains(&open_path));
+        assert!(!open_files.contains(&closed_path));
+    }
+
+    #[test]
+    fn test_locked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locked");
+        fs::write(&path, "locked").unwrap();
+        let open_files = OpenFiles {
+            paths: HashSet::new(),
+        };
+        assert!(!open_files.contains(&path));
+
+        let file = File::open(&path).unwrap();
+        file.lock().unwrap();
+        assert!(open_files.contains(&path));
+    }
+}
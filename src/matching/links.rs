@@ -0,0 +1,188 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{convert::walk_all, normalize::normalize_path, resolves_within};
+use crate::actions::verify::Discrepancy;
+use crate::hashing::{self, HashCache};
+
+/// What checking the symlinks under the target paths found.
+#[derive(Debug, Default)]
+pub struct LinkCheck {
+    /// Symlinks looked at
+    pub checked: usize,
+    /// Symlinks to files whose content was compared with their cached hash
+    pub rehashed: usize,
+    pub problems: Vec<Discrepancy>,
+}
+
+/// Checks every symlink under `target_paths` resolves to something inside `source_paths`.
+/// With a `cache`, files linked to are also hashed again and compared with the hash cached for
+/// them, when there is one, to catch content changed behind the links.
+pub fn check_links(
+    source_paths: &[impl AsRef<Path>],
+    target_paths: &[impl AsRef<Path>],
+    cache: Option<&dyn HashCache>,
+) -> io::Result<LinkCheck> {
+    let source_roots: Vec<PathBuf> = source_paths
+        .iter()
+        .map(|dir| fs::canonicalize(dir).map(|dir| normalize_path(&dir)))
+        .collect::<io::Result<_>>()?;
+    let mut check = LinkCheck::default();
+    for path in walk_all(target_paths)? {
+        if !fs::symlink_metadata(&path)?.is_symlink() {
+            continue;
+        }
+        check.checked += 1;
+        let problem = match fs::canonicalize(&path) {
+            Err(_) => Some(format!(
+                "is broken, pointing at {:?}",
+                fs::read_link(&path)?
+            )),
+            Ok(resolved) if !resolves_within(&resolved, &source_roots) => {
+                Some(format!("points at {resolved:?} outside the source paths"))
+            }
+            Ok(resolved) => match cache.and_then(|cache| cache.retrieve_hash(&resolved)) {
+                Some((cached, _)) if resolved.is_file() => {
+                    check.rehashed += 1;
+                    let hash = hashing::compute_file_hash(&resolved)?;
+                    (hash != cached).then(|| {
+                        format!("points at {resolved:?} which hashes to {hash} instead of {cached}")
+                    })
+                }
+                _ => None,
+            },
+        };
+        if let Some(problem) = problem {
+            check.problems.push(Discrepancy { path, problem });
+        }
+    }
+    Ok(check)
+}
+
+/// Finds the symlinks under `target_paths` whose destination inside one of `source_paths` no
+/// longer exists. Links into source paths that are missing themselves, or whose destination's
+/// nearest remaining directory is empty, are left out: those are more likely an unmounted
+/// drive than removed files.
+pub fn find_dangling_links(
+    source_paths: &[impl AsRef<Path>],
+    target_paths: &[impl AsRef<Path>],
+) -> io::Result<Vec<PathBuf>> {
+    let mut source_roots = Vec::new();
+    for dir in source_paths {
+        let dir = dir.as_ref();
+        match fs::canonicalize(dir) {
+            Ok(root) if !is_empty_dir(&root)? => source_roots.push(normalize_path(&root)),
+            _ => tracing::warn!(
+                "{dir:?} is missing or empty, perhaps unmounted, so links into it are kept"
+            ),
+        }
+    }
+    let mut dangling = Vec::new();
+    for path in walk_all(target_paths)? {
+        if !fs::symlink_metadata(&path)?.is_symlink() || fs::exists(&path)? {
+            continue;
+        }
+        let destination = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(fs::read_link(&path)?);
+        let mut remaining = destination.as_path();
+        while !fs::exists(remaining)? {
+            let Some(parent) = remaining.parent() else {
+                break;
+            };
+            remaining = parent;
+        }
+        if !resolves_within(remaining, &source_roots) {
+            tracing::debug!("Keeping {path:?}, pointing outside the source paths");
+        } else if is_empty_dir(remaining)? {
+            tracing::warn!(
+                "Keeping {path:?}, pointing into {remaining:?} which is empty, perhaps unmounted"
+            );
+        } else {
+            dangling.push(path);
+        }
+    }
+    Ok(dangling)
+}
+
+fn is_empty_dir(path: &Path) -> io::Result<bool> {
+    Ok(path.is_dir() && fs::read_dir(path)?.next().is_none())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::hashing::file_cache::HashingFileCache;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_check_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = fs::canonicalize(dir.path()).unwrap();
+        let (source_dir, target_dir) = (dir.join("source"), dir.join("target"));
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        let source = source_dir.join("episode.mkv");
+        let outside = dir.join("elsewhere.mkv");
+        fs::write(&source, "episode").unwrap();
+        fs::write(&outside, "episode").unwrap();
+        fs::write(target_dir.join("poster.jpg"), "poster").unwrap();
+        symlink(&source, target_dir.join("good.mkv")).unwrap();
+        symlink(&outside, target_dir.join("outside.mkv")).unwrap();
+        symlink(source_dir.join("gone.mkv"), target_dir.join("broken.mkv")).unwrap();
+
+        let paths = |check: &LinkCheck| -> Vec<PathBuf> {
+            check.problems.iter().map(|d| d.path.clone()).collect()
+        };
+        let check = check_links(&[&source_dir], &[&target_dir], None).unwrap();
+        assert_eq!(check.checked, 3);
+        assert_eq!(
+            paths(&check),
+            [
+                target_dir.join("broken.mkv"),
+                target_dir.join("outside.mkv")
+            ]
+        );
+        assert_eq!(
+            find_dangling_links(&[&source_dir], &[&target_dir]).unwrap(),
+            [target_dir.join("broken.mkv")]
+        );
+        // Nothing is pruned outside the source paths given, or in what looks unmounted
+        assert!(
+            find_dangling_links(&[dir.join("elsewhere")], &[&target_dir])
+                .unwrap()
+                .is_empty()
+        );
+        let mount = dir.join("mount");
+        fs::create_dir(&mount).unwrap();
+        symlink(mount.join("Show/e01.mkv"), target_dir.join("unmounted.mkv")).unwrap();
+        assert!(
+            find_dangling_links(&[&mount], &[&target_dir])
+                .unwrap()
+                .is_empty()
+        );
+        fs::remove_file(target_dir.join("unmounted.mkv")).unwrap();
+        fs::create_dir(source_dir.join("disk2")).unwrap();
+        symlink(
+            source_dir.join("disk2/Show/e01.mkv"),
+            target_dir.join("unmounted.mkv"),
+        )
+        .unwrap();
+        assert_eq!(
+            find_dangling_links(&[&source_dir], &[&target_dir]).unwrap(),
+            [target_dir.join("broken.mkv")]
+        );
+        fs::remove_file(target_dir.join("unmounted.mkv")).unwrap();
+
+        // Content changed since it was hashed
+        let mut cache = HashingFileCache::new(dir.join("hashes.cache")).unwrap();
+        cache.cache_hash(&source, "ABC", &std::time::SystemTime::now());
+        let check = check_links(&[&source_dir], &[&target_dir], Some(&cache)).unwrap();
+        assert_eq!(check.rehashed, 1);
+        assert_eq!(check.problems.len(), 3);
+        assert!(check.problems[1].problem.contains("instead of ABC"));
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use super::{DiscoveredFiles, normalize::normalize_name};
+use crate::hashing::Hash;
+
+/// Strategy used to pair a target file with the source files it duplicates.
+pub trait Matcher {
+    /// Called once with every discovered source file before any target is matched.
+    fn prepare(&mut self, _sources: &DiscoveredFiles) {}
+
+    /// Whether matches depend on file content. When false, files aren't ruled out by the hash
+    /// of their first bytes before matching.
+    fn compares_content(&self) -> bool {
+        true
+    }
+
+    /// Source files that `target` could be replaced by, in order of preference.
+    fn find_sources(&self, target: &Path, hash: &Hash, sources: &DiscoveredFiles) -> Vec<PathBuf>;
+}
+
+/// Pairs files with identical content hashes.
+#[derive(Default)]
+pub struct HashMatcher {}
+
+impl HashMatcher {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Matcher for HashMatcher {
+    fn find_sources(&self, _target: &Path, hash: &Hash, sources: &DiscoveredFiles) -> Vec<PathBuf> {
+        sources
+            .files
+            .get(hash)
+            .map(|files| files.iter().map(|f| f.src_path().to_path_buf()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Pairs files with identical content hashes that also have the same file name, for libraries
+/// that keep the names of the files they were given.
+#[derive(Default)]
+pub struct NameSizeMatcher {}
+
+impl NameSizeMatcher {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Matcher for NameSizeMatcher {
+    fn find_sources(&self, target: &Path, hash: &Hash, sources: &DiscoveredFiles) -> Vec<PathBuf> {
+        let Some(name) = target.file_name().map(normalize_name) else {
+            return Vec::new();
+        };
+        HashMatcher::new()
+            .find_sources(target, hash, sources)
+            .into_iter()
+            .filter(|source| source.file_name().map(normalize_name).as_ref() == Some(&name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::hashing::no_cache::HashingNoCache;
+    use crate::matching::{MatchOptions, find::find_and_hash_files};
+
+    #[test]
+    fn test_name_size_matcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("episode.mkv"), "abcd").unwrap();
+        fs::write(source.join("other.mkv"), "abcd").unwrap();
+        fs::write(source.join("Caf\u{e9}.mkv"), "abcd").unwrap();
+
+        let mut sources = DiscoveredFiles::default();
+        find_and_hash_files(
+            &mut sources,
+            &source,
+            &mut HashingNoCache::new(),
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        let matcher = NameSizeMatcher::new();
+        let hash = sources.files.keys().next().unwrap().clone();
+
+        // Same content under the same name matches, but not under another one
+        let target = dir.path().join("episode.mkv");
+        fs::write(&target, "abcd").unwrap();
+        assert_eq!(
+            matcher.find_sources(&target, &hash, &sources),
+            vec![source.join("episode.mkv")]
+        );
+
+        // Same name and size but different content does not
+        fs::write(&target, "wxyz").unwrap();
+        let other = Hash::from("wxyz");
+        assert!(matcher.find_sources(&target, &other, &sources).is_empty());
+
+        // A name decomposed by macOS matches its composed form
+        let target = dir.path().join("Cafe\u{301}.mkv");
+        fs::write(&target, "abcd").unwrap();
+        assert_eq!(
+            matcher.find_sources(&target, &hash, &sources),
+            vec![source.join("Caf\u{e9}.mkv")]
+        );
+    }
+}
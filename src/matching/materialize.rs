@@ -0,0 +1,98 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::convert::walk_all;
+
+/// A symlink under a target pointing outside of it.
+#[derive(Debug, PartialEq)]
+pub struct ExternalLink {
+    pub link: PathBuf,
+    /// What the link resolves to, a file or a directory
+    pub resolved: PathBuf,
+    /// Bytes in the regular files making up `resolved`
+    pub size: u64,
+}
+
+/// Finds the symlinks under `target_paths` that reach outside of the target they're in, and so
+/// stop working once it's moved away from them. Broken symlinks are skipped.
+pub fn find_external_links(target_paths: &[impl AsRef<Path>]) -> io::Result<Vec<ExternalLink>> {
+    let mut links = Vec::new();
+    for root in target_paths {
+        let canonical_root = fs::canonicalize(root.as_ref())?;
+        for path in walk_all(&[root])? {
+            if !fs::symlink_metadata(&path)?.is_symlink() {
+                continue;
+            }
+            let Ok(resolved) = fs::canonicalize(&path) else {
+                tracing::warn!("Skipping broken symlink {path:?}");
+                continue;
+            };
+            if !resolved.starts_with(&canonical_root) {
+                links.push(ExternalLink {
+                    link: path,
+                    size: size(&resolved)?,
+                    resolved,
+                });
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Bytes in the regular files under `path`, or of `path` itself if it's a file.
+fn size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for file in walk_all(&[path])? {
+        let metadata = fs::symlink_metadata(&file)?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_find_external_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = fs::canonicalize(dir.path()).unwrap();
+        let source_dir = dir.join("source");
+        let target_dir = dir.join("target");
+        fs::create_dir_all(source_dir.join("season")).unwrap();
+        fs::create_dir(&target_dir).unwrap();
+        fs::write(source_dir.join("episode.mkv"), "episode").unwrap();
+        fs::write(source_dir.join("season/e01.mkv"), "e01").unwrap();
+        fs::write(target_dir.join("poster.jpg"), "poster").unwrap();
+        symlink(
+            source_dir.join("episode.mkv"),
+            target_dir.join("episode.mkv"),
+        )
+        .unwrap();
+        symlink(source_dir.join("season"), target_dir.join("season")).unwrap();
+        // Still fine once the target is moved
+        symlink("poster.jpg", target_dir.join("folder.jpg")).unwrap();
+        symlink(source_dir.join("gone.mkv"), target_dir.join("broken.mkv")).unwrap();
+
+        assert_eq!(
+            find_external_links(&[&target_dir]).unwrap(),
+            [
+                ExternalLink {
+                    link: target_dir.join("episode.mkv"),
+                    resolved: source_dir.join("episode.mkv"),
+                    size: 7,
+                },
+                ExternalLink {
+                    link: target_dir.join("season"),
+                    resolved: source_dir.join("season"),
+                    size: 3,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1519 @@
+pub mod compare;
+pub mod convert;
+pub mod dir_config;
+pub mod directories;
+pub mod duplicates;
+pub mod exclude;
+mod find;
+mod in_use;
+pub mod links;
+pub mod matcher;
+pub mod materialize;
+pub mod mounts;
+mod normalize;
+mod partial;
+pub mod pipeline;
+pub mod progress;
+pub mod similar;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use find::find_files;
+pub use find::{DiscoveredFiles, FileType};
+use matcher::Matcher;
+
+use crate::actions::LinkMode;
+use crate::hashing::{Hash, HashCache};
+
+/// How the source of a match was decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    /// Another target file with the same hash is already a symlink to the source
+    ExistingSymlink,
+    /// The source was found while scanning the source paths
+    SourceScan,
+    /// The target already links to the source, only the kind of link changes
+    ExistingLink,
+}
+
+/// Size and modification time of a file when it was matched, to notice it changing before the
+/// match is acted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FileState {
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+impl FileState {
+    pub fn of(metadata: &fs::Metadata) -> io::Result<Self> {
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MatchingFile {
+    /// The path of the actual file
+    src_path: PathBuf,
+    /// The path of the file to be replaced with a symlink
+    dest_path: PathBuf,
+    /// Size of the file in bytes
+    size: u64,
+    hash: Hash,
+    reason: MatchReason,
+    /// State of the source (followed) and of the destination itself when matched
+    #[serde(skip)]
+    states: Option<(FileState, FileState)>,
+}
+
+impl MatchingFile {
+    pub fn new(
+        src_path: PathBuf,
+        dest_path: PathBuf,
+        size: u64,
+        hash: Hash,
+        reason: MatchReason,
+    ) -> Self {
+        Self {
+            src_path,
+            dest_path,
+            size,
+            hash,
+            reason,
+            states: None,
+        }
+    }
+
+    /// Records the state of the source and destination, which are checked again before acting.
+    pub fn with_states(mut self) -> io::Result<Self> {
+        self.states = Some((
+            FileState::of(&fs::metadata(&self.src_path)?)?,
+            FileState::of(&fs::symlink_metadata(&self.dest_path)?)?,
+        ));
+        Ok(self)
+    }
+
+    /// Restores the states recorded by [`Self::with_states`] in an earlier run.
+    pub fn with_recorded_states(mut self, states: Option<(FileState, FileState)>) -> Self {
+        self.states = states;
+        self
+    }
+
+    /// Source and destination state recorded when matched, if any.
+    pub fn states(&self) -> Option<(FileState, FileState)> {
+        self.states
+    }
+
+    /// The path of the actual file
+    pub fn src_path(&self) -> &Path {
+        &self.src_path
+    }
+
+    /// The path of the file to be replaced with a link
+    pub fn dest_path(&self) -> &Path {
+        &self.dest_path
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn reason(&self) -> MatchReason {
+        self.reason
+    }
+}
+
+/// Options controlling which files are allowed to be matched together.
+#[derive(Debug, Default, Clone)]
+pub struct MatchOptions {
+    /// Only pair files that live on the same device. Required when hardlinking or reflinking.
+    /// Subtrees overriding the link modes decide for themselves.
+    pub same_device: bool,
+    /// Ignore dotfiles and dot-directories in both source and target scans.
+    pub skip_hidden: bool,
+    /// Don't descend into directories on another filesystem than the path being scanned.
+    pub one_file_system: bool,
+    /// Paths ignored in both source and target scans.
+    pub excludes: exclude::Excludes,
+    /// Overrides for subtrees, read from the `.atorr.toml` files found while scanning.
+    pub dir_configs: dir_config::DirConfigs,
+    /// Only replace target files that haven't been modified for at least this long.
+    pub older_than: Option<Duration>,
+    /// Re-point target symlinks whose destination no longer exists at a source file with the
+    /// hash previously recorded for that destination.
+    pub repair_broken_symlinks: bool,
+    /// Re-point target symlinks that resolve outside every source directory at the matching
+    /// source copy.
+    pub rewrite_external_symlinks: bool,
+    /// Re-point target symlinks that resolve to a different copy than the preferred source at
+    /// the preferred one.
+    pub retarget_symlinks: bool,
+    /// Look for unmatched target files that are a byte-prefix of a larger source file.
+    pub detect_partial: bool,
+    /// Leave files open in another process or locked alone, whether as target or source.
+    pub skip_in_use: bool,
+    /// Link targets to sources on network filesystems or removable drives, though the links
+    /// break whenever those aren't mounted. Otherwise such matches are only reported.
+    pub allow_cross_device: bool,
+    /// Flush the hashes computed so far to the cache this often while hashing, so an
+    /// interrupted run doesn't have to hash them again.
+    pub checkpoint_every: Option<Duration>,
+    /// Told how far scanning and hashing have got.
+    pub progress: progress::Progress,
+}
+
+/// Target files in one directory that had no source counterpart and will keep taking up space.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnmatchedDirectory {
+    pub dir: PathBuf,
+    /// Each unmatched file and its size, in path order
+    pub files: Vec<(PathBuf, u64)>,
+    pub size: u64,
+}
+
+/// A target file whose content is the start of a larger source file, most likely an incomplete
+/// download of the same release.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ResumableDuplicate {
+    pub target: PathBuf,
+    pub source: PathBuf,
+    pub target_size: u64,
+    pub source_size: u64,
+}
+
+/// A match left alone because its source is on a filesystem that can go away, taking every link
+/// to it with it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CrossDeviceMatch {
+    pub target: PathBuf,
+    pub source: PathBuf,
+    pub detachable: mounts::Detachable,
+}
+
+/// A target and source sharing a hash despite differing in size, pointing at a stale cache, a
+/// hash collision or a file changing mid-scan. These are never replaced without confirmation.
+#[derive(Debug, Clone)]
+pub struct QuarantinedMatch {
+    pub matching: MatchingFile,
+    pub source_size: u64,
+}
+
+/// How many files were scanned and matched, and how long each phase took.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchStats {
+    pub source_files: usize,
+    pub target_files: usize,
+    /// Matches handed out so far
+    pub matches: usize,
+    pub scan_time: Duration,
+    pub hash_time: Duration,
+    /// Time spent pairing targets with sources so far, after hashing
+    pub match_time: Duration,
+}
+
+/// What happened when matching a single target file.
+enum Outcome {
+    Matched(MatchingFile),
+    /// No source was found for the file
+    Unmatched,
+    /// The file was excluded by the options
+    Skipped,
+    /// The file shares a hash with its source but not its size
+    Quarantined(QuarantinedMatch),
+}
+
+/// Refuses source and target paths that contain one another, as matching could otherwise link
+/// a file to itself or replace a source file.
+fn check_overlap(
+    source_dir: &[impl AsRef<Path>],
+    target_dir: &[impl AsRef<Path>],
+) -> io::Result<()> {
+    for source in source_dir {
+        let Ok(source) = fs::canonicalize(source) else {
+            continue;
+        };
+        let source = normalize::normalize_path(&source);
+        for target in target_dir {
+            let Ok(target) = fs::canonicalize(target) else {
+                continue;
+            };
+            let target = normalize::normalize_path(&target);
+            if source.starts_with(&target) || target.starts_with(&source) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Source path {source:?} and target path {target:?} overlap"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` resolves to somewhere inside one of the (canonicalised and normalised) `roots`.
+fn resolves_within(path: &Path, roots: &[PathBuf]) -> bool {
+    fs::canonicalize(path).is_ok_and(|path| {
+        let path = normalize::normalize_path(&path);
+        roots.iter().any(|root| path.starts_with(root))
+    })
+}
+
+/// Whether `path` was last modified at least `age` ago.
+fn is_older_than(path: &Path, age: Duration) -> io::Result<bool> {
+    let modified = fs::symlink_metadata(path)?.modified()?;
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|elapsed| elapsed >= age))
+}
+
+/// Hash files in source and target directories and find matches between them.
+/// Target directory will contain files that will be deleted and symlinked to the target dirs.
+/// Every file is scanned and hashed before this returns, as ruling files out by size and by the
+/// hash of their first bytes needs all of them. Only the pairing of each target with its sources
+/// is then done lazily, in order of destination path, so callers can act on the first matches
+/// without waiting for every target to be paired.
+pub fn stream_matching_files(
+    source_dir: &[impl AsRef<Path>],
+    target_dir: &[impl AsRef<Path>],
+    hasher: &mut dyn HashCache,
+    mut matcher: Box<dyn Matcher>,
+    options: &MatchOptions,
+) -> io::Result<MatchingFiles> {
+    check_overlap(source_dir, target_dir)?;
+
+    let mut source_hashes = DiscoveredFiles::default();
+    let mut target_hashes = DiscoveredFiles::default();
+    let mut match_stats = MatchStats::default();
+    let started = Instant::now();
+
+    for dir in source_dir {
+        let dir = dir.as_ref();
+        let _span = tracing::info_span!("scan", source = ?dir).entered();
+        find_files(&mut source_hashes, dir, hasher, options)
+            .inspect_err(|e| tracing::error!("IO error in {dir:?}: {e}"))?;
+    }
+    for dir in target_dir {
+        let dir = dir.as_ref();
+        let _span = tracing::info_span!("scan", target = ?dir).entered();
+        find_files(&mut target_hashes, dir, hasher, options)
+            .inspect_err(|e| tracing::error!("IO error in {dir:?}: {e}"))?;
+    }
+    match_stats.source_files = source_hashes.count();
+    match_stats.target_files = target_hashes.count();
+    match_stats.scan_time = started.elapsed();
+
+    // Every regular source file, including those ruled out before being hashed
+    let mut source_files: Vec<PathBuf> = source_hashes
+        .unhashed
+        .iter()
+        .map(|f| &f.file)
+        .chain(source_hashes.files.values().flatten())
+        .filter(|f| matches!(f, FileType::File(_)))
+        .map(|f| f.src_path().to_path_buf())
+        .collect();
+    source_files.sort();
+
+    let started = Instant::now();
+    let (stats, eliminated) = pipeline::run(
+        &mut source_hashes,
+        &mut target_hashes,
+        hasher,
+        matcher.compares_content(),
+        &options.progress,
+        options.checkpoint_every,
+    )?;
+    options.progress.finish();
+    match_stats.hash_time = started.elapsed();
+
+    // Sort everything so the chosen sources and the order of matches don't depend on hashmap
+    // or directory iteration order
+    for files in source_hashes
+        .files
+        .values_mut()
+        .chain(target_hashes.files.values_mut())
+    {
+        files.sort_by(|a, b| a.src_path().cmp(b.src_path()));
+    }
+    let mut queue: Vec<(PathBuf, Option<Hash>)> = target_hashes
+        .files
+        .iter()
+        .flat_map(|(hash, files)| {
+            files
+                .iter()
+                .map(|f| (f.src_path().to_path_buf(), Some(hash.clone())))
+        })
+        .chain(
+            eliminated
+                .iter()
+                .filter(|f| matches!(f, FileType::File(_)))
+                .map(|f| (f.src_path().to_path_buf(), None)),
+        )
+        .collect();
+    queue.sort();
+    // The same file reached through differently normalised paths is only matched once
+    let mut seen = HashSet::new();
+    queue.retain(|(path, _)| seen.insert(normalize::normalize_path(path)));
+
+    matcher.prepare(&source_hashes);
+
+    let source_roots = source_dir
+        .iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .map(|dir| normalize::normalize_path(&dir))
+        .collect();
+
+    Ok(MatchingFiles {
+        source_hashes,
+        target_hashes,
+        source_files,
+        source_roots,
+        matcher,
+        options: options.clone(),
+        stats,
+        match_stats,
+        open_files: options.skip_in_use.then(in_use::OpenFiles::scan),
+        queue: queue.into_iter(),
+        resumed: Vec::new().into_iter(),
+        resumable: Vec::new(),
+        unmatched: Vec::new(),
+        mounts: mounts::Mounts::default(),
+        cross_device: Vec::new(),
+        quarantined: Vec::new(),
+    })
+}
+
+/// Iterator over the matches between hashed source and target files.
+pub struct MatchingFiles {
+    source_hashes: DiscoveredFiles,
+    target_hashes: DiscoveredFiles,
+    /// Every regular source file, whether or not it was hashed
+    source_files: Vec<PathBuf>,
+    source_roots: Vec<PathBuf>,
+    matcher: Box<dyn Matcher>,
+    options: MatchOptions,
+    stats: pipeline::PipelineStats,
+    match_stats: MatchStats,
+    /// Files open in other processes, when skipping files in use
+    open_files: Option<in_use::OpenFiles>,
+    /// Every target path still to be matched and its hash, in order. Files ruled out before
+    /// being hashed have no hash.
+    queue: std::vec::IntoIter<(PathBuf, Option<Hash>)>,
+    /// Matches left over from an interrupted run, handed out before the queue
+    resumed: std::vec::IntoIter<MatchingFile>,
+    resumable: Vec<ResumableDuplicate>,
+    /// Target files for which no source was found so far
+    unmatched: Vec<PathBuf>,
+    mounts: mounts::Mounts,
+    cross_device: Vec<CrossDeviceMatch>,
+    quarantined: Vec<QuarantinedMatch>,
+}
+
+impl MatchingFiles {
+    /// Picks up the `remaining` matches of an interrupted run without scanning or hashing
+    /// anything again.
+    pub fn resume(
+        remaining: Vec<MatchingFile>,
+        matcher: Box<dyn Matcher>,
+        options: &MatchOptions,
+    ) -> Self {
+        Self {
+            source_hashes: DiscoveredFiles::default(),
+            target_hashes: DiscoveredFiles::default(),
+            source_files: Vec::new(),
+            source_roots: Vec::new(),
+            matcher,
+            options: options.clone(),
+            stats: pipeline::PipelineStats::default(),
+            match_stats: MatchStats::default(),
+            open_files: None,
+            queue: Vec::new().into_iter(),
+            resumed: remaining.into_iter(),
+            resumable: Vec::new(),
+            unmatched: Vec::new(),
+            mounts: mounts::Mounts::default(),
+            cross_device: Vec::new(),
+            quarantined: Vec::new(),
+        }
+    }
+
+    fn is_external_symlink(&self, f: &FileType) -> bool {
+        matches!(f, FileType::Symlink { .. }) && !resolves_within(f.src_path(), &self.source_roots)
+    }
+
+    /// Whether the symlink at `path` resolves somewhere other than the preferred source.
+    fn points_elsewhere(&self, hash: &Hash, path: &Path) -> bool {
+        let sources = self.matcher.find_sources(path, hash, &self.source_hashes);
+        let Some(preferred) = sources.first() else {
+            return false;
+        };
+        match (fs::canonicalize(path), fs::canonicalize(preferred)) {
+            (Ok(resolved), Ok(preferred)) => resolved != preferred,
+            _ => false,
+        }
+    }
+
+    /// Possible sources for the target file at `path`, along with their device and how they
+    /// were found.
+    fn candidates(&self, hash: &Hash, path: &Path) -> Vec<(PathBuf, Option<u64>, MatchReason)> {
+        let files = &self.target_hashes.files[hash];
+        let source_candidates: Vec<_> = self
+            .matcher
+            .find_sources(path, hash, &self.source_hashes)
+            .into_iter()
+            .map(|source| {
+                let device = self.source_hashes.device_of(&source);
+                (source, device, MatchReason::SourceScan)
+            })
+            .collect();
+
+        if (self.options.rewrite_external_symlinks || self.options.retarget_symlinks)
+            && !source_candidates.is_empty()
+        {
+            // Prefer the managed source copy so all links converge on it
+            source_candidates
+        }
+        // Find first symlink and use as source if exists
+        else if let Some(FileType::Symlink {
+            source: sym_source,
+            target: sym_target,
+        }) = files
+            .iter()
+            .find(|p| matches!(**p, FileType::Symlink { .. }))
+        {
+            vec![(
+                sym_target.clone(),
+                self.target_hashes.device_of(sym_source),
+                MatchReason::ExistingSymlink,
+            )]
+        }
+        // Find source in source directories
+        else {
+            source_candidates
+        }
+    }
+
+    /// Pair the target file at `path` with a source.
+    fn match_file(&self, hash: &Hash, path: &Path) -> io::Result<Outcome> {
+        let options = &self.options;
+        let f = self.target_hashes.files[hash]
+            .iter()
+            .find(|f| f.src_path() == path)
+            .expect("Queued target file should have been discovered");
+
+        // Check for non-linked file
+        let replaceable = match f {
+            FileType::File(_) => true,
+            FileType::BrokenSymlink { source, target } => {
+                tracing::info!("Found broken symlink {source:?} -> {target:?}");
+                options.repair_broken_symlinks
+            }
+            FileType::Symlink { .. } if self.is_external_symlink(f) => {
+                tracing::info!("Symlink {path:?} points outside the source paths");
+                options.rewrite_external_symlinks
+            }
+            FileType::Symlink { .. } if options.retarget_symlinks => {
+                let elsewhere = self.points_elsewhere(hash, path);
+                if elsewhere {
+                    tracing::info!("Symlink {path:?} doesn't point at the preferred source");
+                }
+                elsewhere
+            }
+            FileType::Symlink { .. } => false,
+        };
+        if !replaceable {
+            return Ok(Outcome::Skipped);
+        }
+
+        let candidates = self.candidates(hash, path);
+        if candidates.is_empty() {
+            tracing::info!("Couldn't find file to symlink to for {path:?}");
+            return Ok(if matches!(f, FileType::File(_)) {
+                Outcome::Unmatched
+            } else {
+                Outcome::Skipped
+            });
+        }
+
+        if let Some(age) = options.older_than
+            && !is_older_than(path, age)?
+        {
+            tracing::info!("Skipping {path:?}: modified too recently");
+            return Ok(Outcome::Skipped);
+        }
+
+        let dest_device = self.target_hashes.device_of(path);
+        // Link modes overridden for the subtree decide for themselves whether they need one
+        let same_device = match options.dir_configs.link_modes(path)? {
+            Some(modes) => !modes.contains(&LinkMode::Symlink),
+            None => options.same_device,
+        };
+        let source = candidates
+            .iter()
+            .find(|(_, device, _)| !same_device || *device == dest_device);
+
+        let Some((source_path, _, reason)) = source else {
+            tracing::info!("Skipping {path:?}: no matching source on the same device");
+            return Ok(Outcome::Skipped);
+        };
+
+        if let Some(open_files) = &self.open_files
+            && (open_files.contains(path) || open_files.contains(source_path))
+        {
+            tracing::info!("Skipping {path:?}: it or {source_path:?} is in use");
+            return Ok(Outcome::Skipped);
+        }
+
+        let source_size = match fs::metadata(source_path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                tracing::warn!("Skipping {path:?}: its source {source_path:?} is unreadable: {e}");
+                return Ok(Outcome::Skipped);
+            }
+        };
+        let size = fs::metadata(path).map_or(source_size, |m| m.len());
+
+        let matching = MatchingFile::new(
+            source_path.clone(),
+            path.to_path_buf(),
+            size,
+            hash.clone(),
+            *reason,
+        )
+        .with_states()?;
+        // Broken symlinks have no size of their own to compare
+        if size != source_size && matches!(f, FileType::File(_)) {
+            tracing::warn!(
+                "Quarantining {path:?}: it shares hash {hash} with {source_path:?} but is \
+                 {size} bytes instead of {source_size}"
+            );
+            return Ok(Outcome::Quarantined(QuarantinedMatch {
+                matching,
+                source_size,
+            }));
+        }
+        Ok(Outcome::Matched(matching))
+    }
+
+    /// Finds a source file that starts with the entire content of the target at `path`.
+    fn find_resumable(&self, path: &Path) -> io::Result<Option<ResumableDuplicate>> {
+        let target_size = fs::metadata(path)?.len();
+        if target_size == 0 {
+            return Ok(None);
+        }
+
+        for source in &self.source_files {
+            let Ok(source_size) = fs::metadata(source).map(|m| m.len()) else {
+                continue;
+            };
+            if source_size > target_size && partial::is_prefix_of(path, source)? {
+                return Ok(Some(ResumableDuplicate {
+                    target: path.to_path_buf(),
+                    source: source.to_path_buf(),
+                    target_size,
+                    source_size,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Compares the unmatched targets seen so far against every source and reports those that
+    /// share at least `threshold` (0 to 1) of their content with one.
+    pub fn near_duplicates(&self, threshold: f64) -> io::Result<Vec<similar::NearDuplicate>> {
+        let sources: Vec<&Path> = self.source_files.iter().map(PathBuf::as_path).collect();
+        let targets: Vec<&Path> = self.unmatched.iter().map(PathBuf::as_path).collect();
+
+        similar::find_near_duplicates(
+            &sources,
+            &targets,
+            threshold,
+            &similar::ChunkParams::default(),
+        )
+    }
+
+    /// The unmatched targets seen so far, grouped by their directory.
+    pub fn unmatched_report(&self) -> io::Result<Vec<UnmatchedDirectory>> {
+        let mut dirs: BTreeMap<PathBuf, UnmatchedDirectory> = BTreeMap::new();
+        for path in &self.unmatched {
+            let size = fs::symlink_metadata(path)?.len();
+            let dir = path.parent().unwrap_or(path).to_path_buf();
+            let entry = dirs
+                .entry(dir.clone())
+                .or_insert_with(|| UnmatchedDirectory {
+                    dir,
+                    files: Vec::new(),
+                    size: 0,
+                });
+            entry.files.push((path.clone(), size));
+            entry.size += size;
+        }
+        Ok(dirs.into_values().collect())
+    }
+
+    /// How many target files made it through each phase of matching.
+    pub fn pipeline_stats(&self) -> pipeline::PipelineStats {
+        self.stats
+    }
+
+    /// Files scanned and matched so far, and the time taken by each phase.
+    pub fn match_stats(&self) -> MatchStats {
+        self.match_stats
+    }
+
+    /// Targets found to be partial copies of a source so far. Only populated when
+    /// [`MatchOptions::detect_partial`] is set.
+    pub fn resumable_duplicates(&self) -> &[ResumableDuplicate] {
+        &self.resumable
+    }
+
+    /// Matches so far whose source is on a network filesystem or removable drive, left alone
+    /// unless [`MatchOptions::allow_cross_device`] is set.
+    pub fn cross_device_matches(&self) -> &[CrossDeviceMatch] {
+        &self.cross_device
+    }
+
+    /// Matches so far that share a hash but not a size, held back from being replaced.
+    pub fn quarantined(&self) -> &[QuarantinedMatch] {
+        &self.quarantined
+    }
+
+    /// Every source path with the content `hash`, sorted.
+    pub fn sources_with_hash(&self, hash: &str) -> Vec<PathBuf> {
+        self.source_hashes
+            .files
+            .get(hash)
+            .into_iter()
+            .flatten()
+            .map(|f| f.src_path().to_path_buf())
+            .collect()
+    }
+}
+
+impl Iterator for MatchingFiles {
+    type Item = io::Result<MatchingFile>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        let next = self.next_match();
+        self.match_stats.match_time += started.elapsed();
+        if let Some(Ok(matching)) = &next {
+            self.match_stats.matches += 1;
+            self.options.progress.matched(matching);
+        }
+        next
+    }
+}
+
+impl MatchingFiles {
+    fn next_match(&mut self) -> Option<io::Result<MatchingFile>> {
+        if let Some(matching) = self.resumed.next() {
+            return Some(Ok(matching));
+        }
+        loop {
+            let (path, hash) = self.queue.next()?;
+            let _span = tracing::debug_span!("match", target = ?path).entered();
+            let outcome = match &hash {
+                Some(hash) => self.match_file(hash, &path),
+                None => Ok(Outcome::Unmatched),
+            };
+            match outcome {
+                Ok(Outcome::Matched(matching)) => {
+                    if !self.options.allow_cross_device
+                        && let Some(detachable) = self
+                            .mounts
+                            .detachable_link(matching.src_path(), matching.dest_path())
+                    {
+                        tracing::info!(
+                            "Skipping {path:?}: {:?} is on {detachable}",
+                            matching.src_path()
+                        );
+                        self.cross_device.push(CrossDeviceMatch {
+                            target: path,
+                            source: matching.src_path().to_path_buf(),
+                            detachable,
+                        });
+                        continue;
+                    }
+                    return Some(Ok(matching));
+                }
+                Ok(Outcome::Unmatched) => {
+                    if self.options.detect_partial {
+                        match self.find_resumable(&path) {
+                            Ok(Some(resumable)) => {
+                                tracing::info!(
+                                    "{path:?} is a partial copy of {:?}",
+                                    resumable.source
+                                );
+                                self.resumable.push(resumable);
+                            }
+                            Ok(None) => {}
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                    self.unmatched.push(path);
+                }
+                Ok(Outcome::Skipped) => continue,
+                Ok(Outcome::Quarantined(quarantined)) => self.quarantined.push(quarantined),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashing::no_cache::HashingNoCache;
+
+    use super::*;
+    use std::{fs, io::Write as _};
+    use tempfile::TempDir;
+
+    // Helper function to collect every match
+    fn find_matching_files(
+        source_dir: &[impl AsRef<Path>],
+        target_dir: &[impl AsRef<Path>],
+        hasher: &mut dyn HashCache,
+        options: &MatchOptions,
+    ) -> io::Result<Vec<MatchingFile>> {
+        let matcher = Box::new(matcher::HashMatcher::new());
+        stream_matching_files(source_dir, target_dir, hasher, matcher, options)?.collect()
+    }
+
+    // Helper function to create test files with content
+    fn create_test_file(path: &Path, content: &str) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(path)?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    // Helper function to create a symlink (Unix-like systems)
+    #[cfg(unix)]
+    fn create_symlink(original: &Path, link: &Path) -> io::Result<()> {
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::os::unix::fs::symlink(original, link)
+    }
+
+    // For Windows, you'd need a different implementation
+    #[cfg(windows)]
+    fn create_symlink(original: &Path, link: &Path) -> io::Result<()> {
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::os::windows::fs::symlink_file(original, link)
+    }
+
+    #[test]
+    fn test_find_matching_files_basic_matching() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Create identical files in both directories
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&source_dir.join("file2.txt"), "content2").unwrap();
+        create_test_file(&target_dir.join("file2.txt"), "content2").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 2);
+        assert!(
+            matches.iter().any(
+                |m| m.src_path().ends_with("file1.txt") && m.dest_path().ends_with("file1.txt")
+            )
+        );
+        assert!(
+            matches.iter().any(
+                |m| m.src_path().ends_with("file2.txt") && m.dest_path().ends_with("file2.txt")
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_matching_files_different_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Create files with different content (different hashes)
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "different_content").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        // Files with different content should not match
+        assert_eq!(matches.len(), 0);
+    }
+
+    #[test]
+    fn test_find_matching_files_multiple_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir1 = temp_dir.path().join("source1");
+        let source_dir2 = temp_dir.path().join("source2");
+        let target_dir1 = temp_dir.path().join("target1");
+        let target_dir2 = temp_dir.path().join("target2");
+
+        fs::create_dir_all(&source_dir1).unwrap();
+        fs::create_dir_all(&source_dir2).unwrap();
+        fs::create_dir_all(&target_dir1).unwrap();
+        fs::create_dir_all(&target_dir2).unwrap();
+
+        create_test_file(&source_dir1.join("file1.txt"), "content1").unwrap();
+        create_test_file(&source_dir2.join("file2.txt"), "content2").unwrap();
+        create_test_file(&target_dir1.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir2.join("file2.txt"), "content2").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir1, &source_dir2],
+            &[&target_dir1, &target_dir2],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)] // Symlink test is platform-specific
+    fn test_find_matching_files_with_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Create source file
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+
+        // Create symlink in target directory
+        create_symlink(&source_dir.join("file1.txt"), &target_dir.join("file1.txt")).unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        // Should skip the symlink-only case
+        assert_eq!(matches.len(), 0);
+    }
+
+    #[test]
+    fn test_find_matching_files_nonexistent_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let nonexistent_dir = temp_dir.path().join("nonexistent");
+
+        let mut hasher = HashingNoCache::new();
+        let result = find_matching_files(
+            &[&nonexistent_dir],
+            &[&nonexistent_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_find_matching_files_empty_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty_dir1 = temp_dir.path().join("empty1");
+        let empty_dir2 = temp_dir.path().join("empty2");
+
+        fs::create_dir_all(&empty_dir1).unwrap();
+        fs::create_dir_all(&empty_dir2).unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&empty_dir1],
+            &[&empty_dir2],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 0);
+    }
+
+    #[test]
+    fn test_find_matching_files_subdirectories() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(source_dir.join("subdir")).unwrap();
+        fs::create_dir_all(target_dir.join("subdir")).unwrap();
+
+        create_test_file(&source_dir.join("subdir/file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("subdir/file1.txt"), "content1").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].src_path().ends_with("subdir/file1.txt"));
+        assert!(matches[0].dest_path().ends_with("subdir/file1.txt"));
+    }
+
+    #[test]
+    fn test_find_matching_files_partial_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Only some files match
+        create_test_file(&source_dir.join("match1.txt"), "content1").unwrap();
+        create_test_file(&source_dir.join("match2.txt"), "content2").unwrap();
+        create_test_file(&source_dir.join("nomatch.txt"), "source_content").unwrap();
+
+        create_test_file(&target_dir.join("match1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("match2.txt"), "content2").unwrap();
+        create_test_file(&target_dir.join("nomatch.txt"), "target_content").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(
+            |m| m.dest_path().ends_with("match1.txt") || m.dest_path().ends_with("match2.txt")
+        ));
+    }
+
+    #[test]
+    fn test_find_matching_files_duplicate_hashes() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // Create files with same content (same hash) but different names
+        create_test_file(&source_dir.join("file1.txt"), "same_content").unwrap();
+        create_test_file(&source_dir.join("file2.txt"), "same_content").unwrap();
+        create_test_file(&target_dir.join("target_file.txt"), "same_content").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        // Should match based on hash, regardless of filename
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].dest_path().ends_with("target_file.txt"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_matching_files_same_device() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+
+        // Both directories live in the same temporary directory so share a device
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions {
+            same_device: true,
+            ..Default::default()
+        };
+        let matches =
+            find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        assert_eq!(matches.len(), 1);
+
+        // A source on another filesystem, when there's a tmpfs to put it on
+        let Ok(other_dir) = tempfile::tempdir_in("/dev/shm") else {
+            eprintln!("Skipping the other device, there's no /dev/shm");
+            return;
+        };
+        let other_source = other_dir.path().join("source");
+        create_test_file(&other_source.join("file1.txt"), "content1").unwrap();
+        let device =
+            |path: &Path| std::os::unix::fs::MetadataExt::dev(&fs::metadata(path).unwrap());
+        if device(other_dir.path()) == device(temp_dir.path()) {
+            eprintln!("Skipping the other device, /dev/shm is on the same one");
+            return;
+        }
+        let matches =
+            find_matching_files(&[&other_source], &[&target_dir], &mut hasher, &options).unwrap();
+        assert!(matches.is_empty());
+        let options = MatchOptions {
+            allow_cross_device: true,
+            ..Default::default()
+        };
+        let matches =
+            find_matching_files(&[&other_source], &[&target_dir], &mut hasher, &options).unwrap();
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn test_progress() {
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<(u64, Vec<String>, u64)>);
+        impl progress::ProgressCallbacks for Recorder {
+            fn scanned_directory(&self, files: u64) {
+                self.0.lock().unwrap().0 += files;
+            }
+            fn start_phase(&self, name: &str, files: u64, bytes: u64) {
+                let mut recorded = self.0.lock().unwrap();
+                recorded
+                    .1
+                    .push(format!("{name}: {files} files, {bytes} bytes"));
+                recorded.2 = 0;
+            }
+            fn hashed(&self, _path: &Path, bytes: u64) {
+                self.0.lock().unwrap().2 += bytes;
+            }
+            fn finish(&self) {}
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("show/file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("other.txt"), "other").unwrap();
+
+        let recorder = std::sync::Arc::new(Recorder::default());
+        let options = MatchOptions {
+            progress: progress::Progress::new(recorder.clone()),
+            ..Default::default()
+        };
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut HashingNoCache::new(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+
+        let (scanned, phases, hashed) = &*recorder.0.lock().unwrap();
+        assert_eq!(*scanned, 3);
+        // other.txt is ruled out by its size
+        assert_eq!(
+            *phases,
+            vec![
+                "Comparing starts: 2 files, 16 bytes",
+                "Hashing: 2 files, 16 bytes"
+            ]
+        );
+        assert_eq!(*hashed, 16);
+    }
+
+    #[test]
+    fn test_stream_matching_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        for (name, content) in [
+            ("b.txt", "content2"),
+            ("a.txt", "content1"),
+            ("c.txt", "content3"),
+        ] {
+            create_test_file(&source_dir.join(name), content).unwrap();
+            create_test_file(&target_dir.join(name), content).unwrap();
+        }
+
+        let mut hasher = HashingNoCache::new();
+        let mut matching_files = stream_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            Box::new(matcher::HashMatcher::new()),
+            &MatchOptions::default(),
+        )
+        .unwrap();
+        // In order of destination, each target paired only once it's asked for
+        let first = matching_files.next().unwrap().unwrap();
+        assert_eq!(first.dest_path(), target_dir.join("a.txt"));
+        fs::remove_file(source_dir.join("c.txt")).unwrap();
+        let rest: Vec<_> = matching_files
+            .map(|matching| matching.unwrap().dest_path().to_path_buf())
+            .collect();
+
+        assert_eq!(rest, [target_dir.join("b.txt")]);
+    }
+
+    #[test]
+    fn test_quarantine_size_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&source_dir.join("file2.txt"), "content2").unwrap();
+        create_test_file(&target_dir.join("file2.txt"), "content2").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matcher = Box::new(matcher::HashMatcher::new());
+        let mut matching_files = stream_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            matcher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+        // Grows after being hashed
+        fs::OpenOptions::new()
+            .append(true)
+            .open(target_dir.join("file1.txt"))
+            .unwrap()
+            .write_all(b" and more")
+            .unwrap();
+        let matches: Vec<_> = matching_files.by_ref().map(Result::unwrap).collect();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].dest_path(), target_dir.join("file2.txt"));
+        let quarantined = matching_files.quarantined();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(
+            quarantined[0].matching.dest_path(),
+            target_dir.join("file1.txt")
+        );
+        assert_eq!(quarantined[0].matching.size(), 17);
+        assert_eq!(quarantined[0].source_size, 8);
+    }
+
+    #[test]
+    fn test_find_matching_files_older_than() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("old.txt"), "old").unwrap();
+        create_test_file(&target_dir.join("old.txt"), "old").unwrap();
+        create_test_file(&source_dir.join("new.txt"), "new").unwrap();
+        create_test_file(&target_dir.join("new.txt"), "new").unwrap();
+
+        let month_ago = SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 30);
+        fs::File::options()
+            .write(true)
+            .open(target_dir.join("old.txt"))
+            .unwrap()
+            .set_modified(month_ago)
+            .unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions {
+            older_than: Some(Duration::from_secs(60 * 60 * 24)),
+            ..Default::default()
+        };
+        let matches =
+            find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].dest_path().ends_with("old.txt"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_matching_files_repair_broken_symlinks() {
+        use crate::hashing::file_cache::HashingFileCache;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        let old_location = temp_dir.path().join("old/file1.txt");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&old_location, "content1").unwrap();
+        create_symlink(&old_location, &target_dir.join("file1.txt")).unwrap();
+
+        let mut hasher = HashingFileCache::new(temp_dir.path().join("hashes.cache")).unwrap();
+        let options = MatchOptions {
+            repair_broken_symlinks: true,
+            ..Default::default()
+        };
+        // Record the hash of the symlink target in the cache
+        find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        fs::remove_file(&old_location).unwrap();
+        let matches =
+            find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].src_path(), source_dir.join("file1.txt"));
+        assert_eq!(matches[0].dest_path(), target_dir.join("file1.txt"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_matching_files_rewrite_external_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        let external_file = temp_dir.path().join("external/file1.txt");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&external_file, "content1").unwrap();
+        create_symlink(&external_file, &target_dir.join("file1.txt")).unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 0);
+
+        let options = MatchOptions {
+            rewrite_external_symlinks: true,
+            ..Default::default()
+        };
+        let matches =
+            find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].src_path(), source_dir.join("file1.txt"));
+        assert_eq!(matches[0].dest_path(), target_dir.join("file1.txt"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_find_matching_files_retarget_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("a/file1.txt"), "content1").unwrap();
+        create_test_file(&source_dir.join("b/file1.txt"), "content1").unwrap();
+        create_symlink(
+            &source_dir.join("b/file1.txt"),
+            &target_dir.join("other.txt"),
+        )
+        .unwrap();
+        create_symlink(
+            &source_dir.join("a/file1.txt"),
+            &target_dir.join("file1.txt"),
+        )
+        .unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions {
+            retarget_symlinks: true,
+            ..Default::default()
+        };
+        let matches =
+            find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options).unwrap();
+
+        // Only the link to the non-preferred copy is rewritten
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].src_path(), source_dir.join("a/file1.txt"));
+        assert_eq!(matches[0].dest_path(), target_dir.join("other.txt"));
+    }
+
+    #[test]
+    fn test_find_matching_files_overlapping_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = source_dir.join("target");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions::default();
+        let result = find_matching_files(&[&source_dir], &[&target_dir], &mut hasher, &options);
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+        let result = find_matching_files(&[&target_dir], &[&source_dir], &mut hasher, &options);
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_find_matching_files_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].size(), "content1".len() as u64);
+        assert_eq!(
+            matches[0].hash(),
+            crate::hashing::compute_file_hash(&source_dir.join("file1.txt")).unwrap()
+        );
+        assert_eq!(matches[0].reason(), MatchReason::SourceScan);
+    }
+
+    #[test]
+    fn test_find_matching_files_sorted_by_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        for (name, content) in [("d", "1"), ("b", "2"), ("a", "1"), ("c", "3"), ("e", "2")] {
+            create_test_file(&target_dir.join(name), content).unwrap();
+        }
+        for content in ["1", "2", "3"] {
+            create_test_file(&source_dir.join(content), content).unwrap();
+        }
+
+        let mut hasher = HashingNoCache::new();
+        let matches = find_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        let dest_paths: Vec<&Path> = matches.iter().map(|m| m.dest_path()).collect();
+        let expected: Vec<PathBuf> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| target_dir.join(name))
+            .collect();
+        assert_eq!(dest_paths, expected);
+    }
+
+    #[test]
+    fn test_find_matching_files_detect_partial() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("release.mkv"), "complete release").unwrap();
+        create_test_file(&target_dir.join("release.mkv"), "complete").unwrap();
+        create_test_file(&target_dir.join("other.mkv"), "release").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions {
+            detect_partial: true,
+            ..Default::default()
+        };
+        let mut matching_files = stream_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            Box::new(matcher::HashMatcher::new()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(matching_files.by_ref().count(), 0);
+
+        let resumable = matching_files.resumable_duplicates();
+        assert_eq!(resumable.len(), 1);
+        assert_eq!(resumable[0].target, target_dir.join("release.mkv"));
+        assert_eq!(resumable[0].source, source_dir.join("release.mkv"));
+        assert_eq!(resumable[0].target_size, 8);
+        assert_eq!(resumable[0].source_size, 16);
+    }
+
+    #[test]
+    fn test_unmatched_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+
+        create_test_file(&source_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("file1.txt"), "content1").unwrap();
+        create_test_file(&target_dir.join("a/extra.txt"), "extra").unwrap();
+        create_test_file(&target_dir.join("a/more.txt"), "more").unwrap();
+        create_test_file(&target_dir.join("b/other.txt"), "other content").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let mut matching_files = stream_matching_files(
+            &[&source_dir],
+            &[&target_dir],
+            &mut hasher,
+            Box::new(matcher::HashMatcher::new()),
+            &MatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(matching_files.by_ref().count(), 1);
+
+        let report = matching_files.unmatched_report().unwrap();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].dir, target_dir.join("a"));
+        assert_eq!(
+            report[0].files,
+            vec![
+                (target_dir.join("a/extra.txt"), 5),
+                (target_dir.join("a/more.txt"), 4)
+            ]
+        );
+        assert_eq!(report[0].size, 9);
+        assert_eq!(report[1].dir, target_dir.join("b"));
+        assert_eq!(report[1].size, 13);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, path::Path};
+
+/// Filesystem types served over the network, which disappear with the connection
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.s3fs",
+];
+
+/// Why links into a filesystem may break while the system is running.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Detachable {
+    /// A network filesystem, of this type
+    Network(String),
+    /// A removable drive, such as one on USB
+    Removable,
+}
+
+impl std::fmt::Display for Detachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Detachable::Network(fs_type) => write!(f, "a network filesystem ({fs_type})"),
+            Detachable::Removable => write!(f, "a removable drive"),
+        }
+    }
+}
+
+/// Remembers which devices are network or removable mounts, looked up once per device.
+#[derive(Debug, Default)]
+pub(super) struct Mounts {
+    devices: HashMap<u64, Option<Detachable>>,
+}
+
+impl Mounts {
+    /// Whether a symlink at `dest` pointing at `src` leaves `dest`'s filesystem for one that
+    /// can go away, and if so why.
+    pub(super) fn detachable_link(&mut self, src: &Path, dest: &Path) -> Option<Detachable> {
+        let (src_device, dest_device) = (device_id(src)?, device_id(dest.parent()?)?);
+        if src_device == dest_device {
+            return None;
+        }
+        self.devices
+            .entry(src_device)
+            .or_insert_with(|| detachable(src, src_device))
+            .clone()
+    }
+}
+
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+    std::fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn detachable(_path: &Path, device: u64) -> Option<Detachable> {
+    let (major, minor) = (libc::major(device), libc::minor(device));
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    if let Some(fs_type) = filesystem_type(&mountinfo, major, minor)
+        && NETWORK_FILESYSTEMS.contains(&fs_type)
+    {
+        return Some(Detachable::Network(fs_type.to_owned()));
+    }
+    is_removable(major, minor).then_some(Detachable::Removable)
+}
+
+/// The type of the filesystem mounted from the device `major:minor`, from the contents of
+/// `/proc/self/mountinfo`.
+#[cfg(target_os = "linux")]
+fn filesystem_type(mountinfo: &str, major: u32, minor: u32) -> Option<&str> {
+    let device = format!("{major}:{minor}");
+    mountinfo.lines().find_map(|line| {
+        let mut fields = line.split(' ');
+        if fields.nth(2)? != device {
+            return None;
+        }
+        // Optional fields end with a lone "-", followed by the type
+        fields.skip_while(|field| *field != "-").nth(1)
+    })
+}
+
+/// Whether the block device `major:minor`, or the disk it is a partition of, is removable or
+/// attached over USB.
+#[cfg(target_os = "linux")]
+fn is_removable(major: u32, minor: u32) -> bool {
+    let Ok(sys_path) = std::fs::canonicalize(format!("/sys/dev/block/{major}:{minor}")) else {
+        return false;
+    };
+    let removable = |dir: &Path| {
+        std::fs::read_to_string(dir.join("removable")).is_ok_and(|flag| flag.trim() == "1")
+    };
+    sys_path.to_string_lossy().contains("/usb")
+        || removable(&sys_path)
+        || sys_path.parent().is_some_and(removable)
+}
+
+#[cfg(target_os = "macos")]
+fn detachable(path: &Path, _device: u64) -> Option<Detachable> {
+    use std::os::unix::ffi::OsStrExt as _;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statfs is plain data for which all zeroes is valid
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL terminated and `stat` is valid for writes
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    if stat.f_flags & libc::MNT_LOCAL as u32 == 0 {
+        // SAFETY: the kernel NUL terminates f_fstypename
+        let fs_type = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+        return Some(Detachable::Network(fs_type.to_string_lossy().into_owned()));
+    }
+    // External drives are mounted under /Volumes
+    path.to_bytes()
+        .starts_with(b"/Volumes/")
+        .then_some(Detachable::Removable)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn detachable(_path: &Path, _device: u64) -> Option<Detachable> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_filesystem_type() {
+        let mountinfo = "\
+28 1 254:0 / / rw,relatime - ext4 /dev/vda rw
+61 28 0:53 / /mnt/nas rw,relatime shared:30 master:2 - nfs4 nas:/media rw,vers=4.2
+";
+        assert_eq!(filesystem_type(mountinfo, 254, 0), Some("ext4"));
+        assert_eq!(filesystem_type(mountinfo, 0, 53), Some("nfs4"));
+        assert_eq!(filesystem_type(mountinfo, 8, 1), None);
+    }
+
+    #[test]
+    fn test_same_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("source.mkv");
+        std::fs::write(&src, "episode").unwrap();
+        let mut mounts = Mounts::default();
+        assert_eq!(
+            mounts.detachable_link(&src, &dir.path().join("target.mkv")),
+            None
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Converts a file name to Unicode NFC. macOS stores names decomposed (NFD) while Linux keeps
+/// whatever bytes it is given, so the same name copied between them can differ byte for byte.
+/// Names that aren't valid UTF-8 are returned unchanged.
+pub(super) fn normalize_name(name: &OsStr) -> OsString {
+    match name.to_str() {
+        Some(name) => name.nfc().collect::<String>().into(),
+        None => name.to_os_string(),
+    }
+}
+
+/// Converts every component of `path` to Unicode NFC, for comparing paths from different systems.
+pub(super) fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            Component::Normal(name) => normalize_name(name),
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        let decomposed = Path::new("/music/Cafe\u{301}/Beyonce\u{301}.flac");
+        let composed = Path::new("/music/Caf\u{e9}/Beyonc\u{e9}.flac");
+
+        assert_ne!(decomposed, composed);
+        assert_eq!(normalize_path(decomposed), composed);
+        assert_eq!(normalize_path(composed), composed);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read as _},
+    path::Path,
+};
+
+/// Whether the whole content of `prefix` appears at the start of `file`.
+pub(super) fn is_prefix_of(prefix: &Path, file: &Path) -> io::Result<bool> {
+    let mut prefix = BufReader::new(File::open(prefix)?);
+    let mut file = BufReader::new(File::open(file)?);
+
+    let mut prefix_buffer = [0; 1024];
+    let mut file_buffer = [0; 1024];
+    loop {
+        let count = prefix.read(&mut prefix_buffer)?;
+        if count == 0 {
+            return Ok(true);
+        }
+        if file.read_exact(&mut file_buffer[..count]).is_err() {
+            return Ok(false);
+        }
+        if prefix_buffer[..count] != file_buffer[..count] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_is_prefix_of() {
+        let dir = tempfile::tempdir().unwrap();
+        let full = dir.path().join("full");
+        let partial = dir.path().join("partial");
+        let different = dir.path().join("different");
+        fs::write(&full, "hello world").unwrap();
+        fs::write(&partial, "hello").unwrap();
+        fs::write(&different, "help").unwrap();
+
+        assert!(is_prefix_of(&partial, &full).unwrap());
+        assert!(!is_prefix_of(&different, &full).unwrap());
+        assert!(!is_prefix_of(&full, &partial).unwrap());
+    }
+}
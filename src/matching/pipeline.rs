@@ -0,0 +1,317 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash as StdHash,
+    io,
+    time::{Duration, Instant},
+};
+
+use super::find::{DiscoveredFiles, FileType, UnhashedFile};
+use super::progress::Progress;
+use crate::hashing::{self, HashCache};
+
+/// Number of leading bytes hashed in the prefix phase
+const PREFIX_LEN: u64 = 64 * 1024;
+
+/// How many target files went into a phase and how many were still candidates after it.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct PhaseStats {
+    pub candidates: usize,
+    pub remaining: usize,
+}
+
+/// Statistics for each phase of narrowing target files down to genuine duplicates.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct PipelineStats {
+    /// Targets sharing a size with another file
+    pub size: PhaseStats,
+    /// Targets sharing the hash of their first bytes with another file
+    pub prefix: PhaseStats,
+    /// Targets sharing their full hash with another file
+    pub full_hash: PhaseStats,
+    /// Bytes read to hash the start of files
+    pub prefix_bytes: u64,
+}
+
+/// Whether a target can be paired with anything sharing `key`. Target files pair with sources
+/// and target symlinks, target symlinks pair with sources and target files.
+struct Keys<K> {
+    sources: HashSet<K>,
+    target_files: HashSet<K>,
+    target_links: HashSet<K>,
+}
+
+impl<K: Eq + StdHash> Keys<K> {
+    fn new(sources: &[(UnhashedFile, K)], targets: &[(UnhashedFile, K)]) -> Self
+    where
+        K: Clone,
+    {
+        let mut keys = Keys {
+            sources: sources.iter().map(|(_, key)| key.clone()).collect(),
+            target_files: HashSet::new(),
+            target_links: HashSet::new(),
+        };
+        for (f, key) in targets {
+            if matches!(f.file, FileType::Symlink { .. }) {
+                keys.target_links.insert(key.clone());
+            } else {
+                keys.target_files.insert(key.clone());
+            }
+        }
+        keys
+    }
+
+    fn target_has_partner(&self, f: &UnhashedFile, key: &K) -> bool {
+        self.sources.contains(key)
+            || if matches!(f.file, FileType::Symlink { .. }) {
+                self.target_files.contains(key)
+            } else {
+                self.target_links.contains(key)
+            }
+    }
+}
+
+/// Drops every file without a partner sharing the same key. Eliminated targets are appended to
+/// `eliminated`. Sources are all kept when `keep_sources` is set.
+fn narrow<K: Eq + StdHash + Clone>(
+    sources: Vec<(UnhashedFile, K)>,
+    targets: Vec<(UnhashedFile, K)>,
+    keep_sources: bool,
+    eliminated: &mut Vec<FileType>,
+) -> (Vec<UnhashedFile>, Vec<UnhashedFile>) {
+    let keys = Keys::new(&sources, &targets);
+
+    let mut remaining_targets = Vec::new();
+    let mut target_keys = HashSet::new();
+    for (f, key) in targets {
+        if keys.target_has_partner(&f, &key) {
+            target_keys.insert(key);
+            remaining_targets.push(f);
+        } else {
+            eliminated.push(f.file);
+        }
+    }
+
+    let remaining_sources = sources
+        .into_iter()
+        .filter(|(_, key)| keep_sources || target_keys.contains(key))
+        .map(|(f, _)| f)
+        .collect();
+
+    (remaining_sources, remaining_targets)
+}
+
+fn with_prefix_hash(
+    files: Vec<UnhashedFile>,
+    progress: &Progress,
+) -> io::Result<Vec<(UnhashedFile, (u64, String))>> {
+    files
+        .into_iter()
+        .map(|f| {
+            let prefix = hashing::compute_prefix_hash(&f.content_path, PREFIX_LEN)?;
+            let size = f.size;
+            progress.hashed(f.file.src_path(), size.min(PREFIX_LEN));
+            Ok((f, (size, prefix)))
+        })
+        .collect()
+}
+
+/// Reports the start of a hashing phase over `sources` and `targets`, reading `bytes` of each.
+fn start_phase(
+    progress: &Progress,
+    name: &str,
+    sources: &[UnhashedFile],
+    targets: &[UnhashedFile],
+    bytes: impl Fn(&UnhashedFile) -> u64,
+) {
+    let files = sources.iter().chain(targets);
+    progress.start_phase(
+        name,
+        (sources.len() + targets.len()) as u64,
+        files.map(bytes).sum(),
+    );
+}
+
+/// Hashes the unhashed files of `sources` and `targets`, first ruling out files by size and
+/// then by the hash of their first bytes so only genuine candidates get fully hashed.
+/// The prefix phase is skipped when `compare_content` is false.
+///
+/// Returns the statistics of each phase and the target files ruled out before full hashing.
+/// The hashes computed so far are flushed to the cache every `checkpoint_every`, so they
+/// survive the run being interrupted.
+pub(super) fn run(
+    sources: &mut DiscoveredFiles,
+    targets: &mut DiscoveredFiles,
+    hasher: &mut dyn HashCache,
+    compare_content: bool,
+    progress: &Progress,
+    checkpoint_every: Option<Duration>,
+) -> io::Result<(PipelineStats, Vec<FileType>)> {
+    let _span = tracing::info_span!("hash").entered();
+    let mut stats = PipelineStats::default();
+    let mut eliminated = Vec::new();
+
+    // Broken symlinks are only known by their cached hash, so any source may be their match
+    let keep_sources = !targets.files.is_empty();
+    let source_files = std::mem::take(&mut sources.unhashed);
+    let target_files = std::mem::take(&mut targets.unhashed);
+
+    // Size
+    stats.size.candidates = target_files.len();
+    let keyed = |files: Vec<UnhashedFile>| {
+        files
+            .into_iter()
+            .map(|f| {
+                let size = f.size;
+                (f, size)
+            })
+            .collect::<Vec<_>>()
+    };
+    let (source_files, target_files) = narrow(
+        keyed(source_files),
+        keyed(target_files),
+        keep_sources,
+        &mut eliminated,
+    );
+    stats.size.remaining = target_files.len();
+    tracing::debug!("Size phase left {} candidates", stats.size.remaining);
+
+    // Prefix hash
+    stats.prefix.candidates = target_files.len();
+    let (source_files, target_files) = if compare_content {
+        start_phase(
+            progress,
+            "Comparing starts",
+            &source_files,
+            &target_files,
+            |f| f.size.min(PREFIX_LEN),
+        );
+        stats.prefix_bytes = source_files
+            .iter()
+            .chain(&target_files)
+            .map(|f| f.size.min(PREFIX_LEN))
+            .sum();
+        narrow(
+            with_prefix_hash(source_files, progress)?,
+            with_prefix_hash(target_files, progress)?,
+            keep_sources,
+            &mut eliminated,
+        )
+    } else {
+        (source_files, target_files)
+    };
+    stats.prefix.remaining = target_files.len();
+    tracing::debug!("Prefix phase left {} candidates", stats.prefix.remaining);
+
+    // Full hash
+    stats.full_hash.candidates = target_files.len();
+    start_phase(progress, "Hashing", &source_files, &target_files, |f| {
+        f.size
+    });
+    let mut checkpointed = Instant::now();
+    let mut checkpoint = |hasher: &mut dyn HashCache| {
+        if checkpoint_every.is_some_and(|every| checkpointed.elapsed() >= every) {
+            tracing::debug!("Checkpointing hashes");
+            hasher.flush();
+            checkpointed = Instant::now();
+        }
+    };
+    for f in source_files {
+        let (path, size) = (f.file.src_path().to_path_buf(), f.size);
+        sources.hash_unhashed(f, hasher)?;
+        progress.hashed(&path, size);
+        checkpoint(hasher);
+    }
+    for f in target_files {
+        let (path, size) = (f.file.src_path().to_path_buf(), f.size);
+        targets.hash_unhashed(f, hasher)?;
+        progress.hashed(&path, size);
+        checkpoint(hasher);
+    }
+    let mut kinds: HashMap<&String, (bool, bool)> = HashMap::new();
+    for (hash, files) in &targets.files {
+        let entry = kinds.entry(hash).or_default();
+        for f in files {
+            match f {
+                FileType::Symlink { .. } => entry.1 = true,
+                _ => entry.0 = true,
+            }
+        }
+    }
+    stats.full_hash.remaining = targets
+        .files
+        .iter()
+        .map(|(hash, files)| {
+            let in_sources = sources.files.contains_key(hash);
+            let (has_files, has_links) = kinds[hash];
+            files
+                .iter()
+                .filter(|f| {
+                    in_sources
+                        || match f {
+                            FileType::Symlink { .. } => has_files,
+                            _ => has_links,
+                        }
+                })
+                .count()
+        })
+        .sum();
+    tracing::debug!(
+        "Full hash phase left {} candidates",
+        stats.full_hash.remaining
+    );
+
+    Ok((stats, eliminated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::no_cache::HashingNoCache;
+    use crate::matching::{MatchOptions, find::find_files};
+    use std::fs;
+
+    #[test]
+    fn test_pipeline_phases() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_dir = dir.path().join("source");
+        let target_dir = dir.path().join("target");
+        fs::create_dir(&source_dir).unwrap();
+        fs::create_dir(&target_dir).unwrap();
+
+        let prefix = "x".repeat(PREFIX_LEN as usize);
+        fs::write(source_dir.join("same"), "same content").unwrap();
+        fs::write(source_dir.join("late_difference"), format!("{prefix}a")).unwrap();
+        fs::write(target_dir.join("same"), "same content").unwrap();
+        // Same size as "same" but different start
+        fs::write(target_dir.join("same_size"), "other conten").unwrap();
+        // Same size and prefix, different ending
+        fs::write(target_dir.join("late_difference"), format!("{prefix}b")).unwrap();
+        fs::write(target_dir.join("unique_size"), "unique").unwrap();
+
+        let mut hasher = HashingNoCache::new();
+        let options = MatchOptions::default();
+        let mut sources = DiscoveredFiles::default();
+        let mut targets = DiscoveredFiles::default();
+        find_files(&mut sources, &source_dir, &mut hasher, &options).unwrap();
+        find_files(&mut targets, &target_dir, &mut hasher, &options).unwrap();
+
+        let (stats, eliminated) = run(
+            &mut sources,
+            &mut targets,
+            &mut hasher,
+            true,
+            &Progress::default(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(stats.size.candidates, 4);
+        assert_eq!(stats.size.remaining, 3);
+        assert_eq!(stats.prefix.remaining, 2);
+        assert_eq!(stats.full_hash.candidates, 2);
+        assert_eq!(stats.full_hash.remaining, 1);
+        assert_eq!(eliminated.len(), 2);
+        // Only the files that made it through to the last phase are hashed
+        assert_eq!(targets.files.values().flatten().count(), 2);
+    }
+}
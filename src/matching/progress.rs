@@ -0,0 +1,72 @@
+use std::{io, path::Path, sync::Arc};
+
+use super::MatchingFile;
+use crate::actions::audit::AuditEntry;
+
+/// Callbacks told how far a run has got, e.g. to draw progress bars.
+pub trait ProgressCallbacks: Send + Sync {
+    /// A directory with `files` files in it was walked.
+    fn scanned_directory(&self, files: u64);
+    /// A hashing phase is starting, hashing `files` files of `bytes` in total.
+    fn start_phase(&self, name: &str, files: u64, bytes: u64);
+    /// The file at `path` was hashed in the current phase, having `bytes` read from it.
+    fn hashed(&self, path: &Path, bytes: u64);
+    /// Scanning and hashing are over.
+    fn finish(&self);
+    /// A match was found.
+    fn matched(&self, _matching: &MatchingFile) {}
+    /// Replacing a target was attempted, with the same result as recorded in the audit log.
+    fn applied(&self, _entry: &AuditEntry, _result: Result<&str, &io::Error>) {}
+}
+
+/// Where to report progress while matching, if anywhere.
+#[derive(Clone, Default)]
+pub struct Progress(Option<Arc<dyn ProgressCallbacks>>);
+
+impl Progress {
+    pub fn new(callbacks: Arc<dyn ProgressCallbacks>) -> Self {
+        Self(Some(callbacks))
+    }
+
+    pub(super) fn scanned_directory(&self, files: u64) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.scanned_directory(files);
+        }
+    }
+
+    pub(super) fn start_phase(&self, name: &str, files: u64, bytes: u64) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.start_phase(name, files, bytes);
+        }
+    }
+
+    pub(super) fn hashed(&self, path: &Path, bytes: u64) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.hashed(path, bytes);
+        }
+    }
+
+    pub(super) fn finish(&self) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.finish();
+        }
+    }
+
+    pub(super) fn matched(&self, matching: &MatchingFile) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.matched(matching);
+        }
+    }
+
+    pub fn applied(&self, entry: &AuditEntry, result: Result<&str, &io::Error>) {
+        if let Some(callbacks) = &self.0 {
+            callbacks.applied(entry, result);
+        }
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Progress").field(&self.0.is_some()).finish()
+    }
+}
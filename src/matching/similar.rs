@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read as _},
+    path::{Path, PathBuf},
+};
+
+use sha2::Digest as _;
+
+/// Two files sharing a large proportion of their content without being identical.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NearDuplicate {
+    pub target: PathBuf,
+    pub source: PathBuf,
+    /// Fraction of the larger file's bytes that are shared with the other file, from 0 to 1
+    pub similarity: f64,
+}
+
+/// Content-defined chunking parameters. Chunk boundaries depend on the content rather than the
+/// offset, so an insertion or removal only changes the chunks around it.
+pub(super) struct ChunkParams {
+    pub min_size: usize,
+    pub max_size: usize,
+    /// A boundary is placed where the rolling hash has all these bits unset
+    pub mask: u64,
+}
+
+impl Default for ChunkParams {
+    fn default() -> Self {
+        Self {
+            min_size: 256 * 1024,
+            max_size: 4 * 1024 * 1024,
+            // Roughly 1MiB chunks on average
+            mask: (1 << 20) - 1,
+        }
+    }
+}
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64 so the table is fixed between runs
+    let mut table = [0; 256];
+    let mut state: u64 = 0x9E3779B97F4A7C15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+type ChunkHash = [u8; 32];
+
+/// Splits the file at `path` into content-defined chunks, returning each chunk's hash and size.
+pub(super) fn chunk_file(path: &Path, params: &ChunkParams) -> io::Result<Vec<(ChunkHash, u64)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::with_capacity(params.min_size);
+    let mut rolling: u64 = 0;
+
+    let mut finish_chunk = |chunk: &mut Vec<u8>| {
+        chunks.push((sha2::Sha256::digest(&chunk).into(), chunk.len() as u64));
+        chunk.clear();
+    };
+
+    for byte in reader.bytes() {
+        let byte = byte?;
+        chunk.push(byte);
+        rolling = (rolling << 1).wrapping_add(GEAR[byte as usize]);
+        if (chunk.len() >= params.min_size && rolling & params.mask == 0)
+            || chunk.len() >= params.max_size
+        {
+            finish_chunk(&mut chunk);
+            rolling = 0;
+        }
+    }
+    if !chunk.is_empty() {
+        finish_chunk(&mut chunk);
+    }
+    Ok(chunks)
+}
+
+/// Compares every target against every source by their chunks and reports the pairs with at
+/// least `threshold` similarity.
+pub(super) fn find_near_duplicates(
+    sources: &[&Path],
+    targets: &[&Path],
+    threshold: f64,
+    params: &ChunkParams,
+) -> io::Result<Vec<NearDuplicate>> {
+    let mut source_sizes = Vec::with_capacity(sources.len());
+    let mut chunk_index: HashMap<ChunkHash, Vec<usize>> = HashMap::new();
+    for (i, source) in sources.iter().enumerate() {
+        let chunks = chunk_file(source, params)?;
+        source_sizes.push(chunks.iter().map(|(_, size)| size).sum::<u64>());
+        for (hash, _) in chunks {
+            let indices = chunk_index.entry(hash).or_default();
+            if indices.last() != Some(&i) {
+                indices.push(i);
+            }
+        }
+    }
+
+    let mut near_duplicates = Vec::new();
+    for target in targets {
+        let chunks = chunk_file(target, params)?;
+        let target_size: u64 = chunks.iter().map(|(_, size)| size).sum();
+
+        let mut shared: HashMap<usize, u64> = HashMap::new();
+        for (hash, size) in &chunks {
+            for &source in chunk_index.get(hash).into_iter().flatten() {
+                *shared.entry(source).or_default() += size;
+            }
+        }
+
+        let mut found: Vec<_> = shared
+            .into_iter()
+            .map(|(source, shared)| {
+                let larger = target_size.max(source_sizes[source]).max(1);
+                (source, shared.min(larger) as f64 / larger as f64)
+            })
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .collect();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        near_duplicates.extend(found.into_iter().map(|(source, similarity)| NearDuplicate {
+            target: target.to_path_buf(),
+            source: sources[source].to_path_buf(),
+            similarity,
+        }));
+    }
+    Ok(near_duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn test_params() -> ChunkParams {
+        ChunkParams {
+            min_size: 64,
+            max_size: 1024,
+            mask: (1 << 8) - 1,
+        }
+    }
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_near_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = pseudo_random_bytes(64 * 1024, 1);
+
+        let source = dir.path().join("source.mkv");
+        fs::write(&source, &content).unwrap();
+        let unrelated = dir.path().join("unrelated.mkv");
+        fs::write(&unrelated, pseudo_random_bytes(64 * 1024, 2)).unwrap();
+
+        // Same content with a small section replaced in the middle
+        let mut modified = content.clone();
+        modified.splice(30_000..30_100, pseudo_random_bytes(500, 3));
+        let target = dir.path().join("target.mkv");
+        fs::write(&target, &modified).unwrap();
+
+        let near_duplicates =
+            find_near_duplicates(&[&source, &unrelated], &[&target], 0.9, &test_params()).unwrap();
+
+        assert_eq!(near_duplicates.len(), 1);
+        assert_eq!(near_duplicates[0].source, source);
+        assert!(near_duplicates[0].similarity < 1.0);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    io::{self, Write as _},
+    process::Stdio,
+    time::Duration,
+};
+
+use crate::actions::{hooks::shell, summary::RunSummary};
+
+/// How long posting to the webhook may take before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a completion notification says about the run.
+#[derive(Debug, serde::Serialize)]
+pub struct RunOutcome<'a> {
+    /// `completed` or `failed`
+    pub status: &'static str,
+    /// One line for the notification itself
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<&'a RunSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<'a> RunOutcome<'a> {
+    pub fn of(result: &'a io::Result<RunSummary>) -> Self {
+        match result {
+            Ok(summary) => Self {
+                status: "completed",
+                message: format!("atorrlinker run completed: {summary}"),
+                summary: Some(summary),
+                error: None,
+            },
+            Err(e) => Self {
+                status: "failed",
+                message: format!("atorrlinker run failed: {e}"),
+                summary: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Tells a webhook and/or a command how each run went, so unattended runs can be followed.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    /// Gets the outcome POSTed as JSON
+    pub url: Option<String>,
+    /// Run by the shell with the outcome as JSON on stdin, and its status and message in
+    /// `ATORRLINKER_STATUS` and `ATORRLINKER_MESSAGE`
+    pub command: Option<String>,
+}
+
+impl Notifier {
+    /// Sends `outcome` everywhere configured. Failures are only logged, as the run is over.
+    pub fn notify(&self, outcome: &RunOutcome) {
+        let json = serde_json::to_string(outcome).expect("Outcomes serialize to JSON");
+        if let Some(url) = &self.url
+            && let Err(e) = post(url, &json)
+        {
+            tracing::warn!("Couldn't post the run outcome to {url}: {e}");
+        }
+        if let Some(command) = &self.command
+            && let Err(e) = run(command, outcome, &json)
+        {
+            tracing::warn!("Notify command {command:?} failed: {e}");
+        }
+    }
+}
+
+/// POSTs `json` to `url`, failing on an error status. Redirects fail too rather than being
+/// followed, so nothing gets posted anywhere but `url`.
+pub fn post(url: &str, json: &str) -> io::Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .max_redirects(0)
+        .build()
+        .into();
+    let response = agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(json)
+        .map_err(io::Error::other)?;
+    if response.status().is_redirection() {
+        return Err(io::Error::other(format!(
+            "redirected with {}",
+            response.status()
+        )));
+    }
+    Ok(())
+}
+
+fn run(command: &str, outcome: &RunOutcome, json: &str) -> io::Result<()> {
+    let mut child = shell(command)
+        .env("ATORRLINKER_STATUS", outcome.status)
+        .env("ATORRLINKER_MESSAGE", &outcome.message)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may not read it
+        let _ = stdin.write_all(json.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("exited with {status}")));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::{fs, io::Read as _, net::TcpListener};
+
+    #[test]
+    fn test_notify() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"status\"") {
+                let len = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let notifier = Notifier {
+            url: Some(url),
+            command: Some(format!(
+                "echo \"$ATORRLINKER_STATUS $(cat)\" > {}",
+                out.display()
+            )),
+        };
+        let result = Err(io::Error::other("disk full"));
+        notifier.notify(&RunOutcome::of(&result));
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook "));
+        assert!(request.contains("\"error\":\"disk full\""));
+        let written = fs::read_to_string(&out).unwrap();
+        assert!(written.starts_with("failed {\"status\":\"failed\""));
+    }
+
+    #[test]
+    fn test_post_redirect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !request.ends_with(b"{}") {
+                let len = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 307 Temporary Redirect\r\nLocation: http://169.254.169.254/\r\n\
+                      Content-Length: 0\r\n\r\n",
+                )
+                .unwrap();
+        });
+
+        let e = post(&url, "{}").unwrap_err();
+        assert!(e.to_string().contains("307"));
+        server.join().unwrap();
+    }
+}
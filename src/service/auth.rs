@@ -0,0 +1,392 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use base64::Engine as _;
+use rocket::{
+    Request,
+    http::{Header, Method, Status},
+    request::{FromRequest, Outcome},
+};
+
+use crate::{jobs::JobRequest, limits::RateLimiter};
+
+/// A user of the API, as configured: either just a password, or a table also giving tokens
+/// and the directories the user is restricted to, e.g.
+/// `alice = { password = "…", tokens = ["…"], roots = ["/srv/alice"] }`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+pub enum User {
+    Password(String),
+    Account {
+        #[serde(default)]
+        password: Option<String>,
+        /// Accepted as `Authorization: Bearer <token>`, or as the password of this user
+        #[serde(default)]
+        tokens: Vec<String>,
+        /// The directories the user may deduplicate and browse, with everything under them.
+        /// Anywhere unless given
+        #[serde(default)]
+        roots: Option<Vec<PathBuf>>,
+    },
+}
+
+impl User {
+    /// None also for an empty password, which would let anyone in by the name alone.
+    fn password(&self) -> Option<&str> {
+        match self {
+            User::Password(password) => Some(password.as_str()),
+            User::Account { password, .. } => password.as_deref(),
+        }
+        .filter(|password| !password.is_empty())
+    }
+
+    fn tokens(&self) -> &[String] {
+        match self {
+            User::Password(_) => &[],
+            User::Account { tokens, .. } => tokens,
+        }
+    }
+
+    fn authorized(&self, name: &str) -> Authorized {
+        Authorized {
+            user: Some(name.to_owned()),
+            roots: match self {
+                User::Password(_) => None,
+                User::Account { roots, .. } => roots.clone(),
+            },
+        }
+    }
+}
+
+/// Who may use the API, as configured.
+#[derive(Debug, Default)]
+pub struct Credentials {
+    /// Accepted as `Authorization: Bearer <token>`, with access to everything
+    tokens: Vec<String>,
+    /// Accepted as HTTP basic authentication, or by their own tokens
+    users: BTreeMap<String, User>,
+}
+
+impl Credentials {
+    /// Refuses an empty configuration, which would leave the API open to anyone who can reach
+    /// it.
+    pub fn new(tokens: Vec<String>, users: BTreeMap<String, User>) -> Result<Self, String> {
+        let tokens: Vec<_> = tokens
+            .into_iter()
+            .filter(|token| !token.is_empty())
+            .collect();
+        let mut users = users;
+        for user in users.values_mut() {
+            if let User::Account { tokens, .. } = user {
+                tokens.retain(|token| !token.is_empty());
+            }
+        }
+        if tokens.is_empty() && users.is_empty() {
+            return Err(
+                "No API tokens or users are configured. Set `tokens` or `users` in service.toml \
+                 or Rocket.toml, or ROCKET_TOKENS, e.g. ROCKET_TOKENS='[\"<random secret>\"]'"
+                    .to_owned(),
+            );
+        }
+        Ok(Self { tokens, users })
+    }
+
+    /// Who the value of an `Authorization` header authenticates, if anyone.
+    fn accept(&self, authorization: &str) -> Option<Authorized> {
+        let (scheme, value) = authorization.trim().split_once(' ')?;
+        let value = value.trim();
+        let service_token = |secret| self.tokens.iter().any(|token| same(token, secret));
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if service_token(value) {
+                return Some(Authorized::default());
+            }
+            return self
+                .users
+                .iter()
+                .find(|(_, user)| user.tokens().iter().any(|token| same(token, value)))
+                .map(|(name, user)| user.authorized(name));
+        }
+        if scheme.eq_ignore_ascii_case("basic")
+            && let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(value)
+            && let Ok(decoded) = String::from_utf8(decoded)
+            && let Some((name, password)) = decoded.split_once(':')
+        {
+            if let Some(user) = self.users.get(name)
+                && (user
+                    .password()
+                    .is_some_and(|expected| same(expected, password))
+                    || user.tokens().iter().any(|token| same(token, password)))
+            {
+                return Some(user.authorized(name));
+            }
+            // Browsers only prompt for basic authentication, so the tokens of the service
+            // work as anyone's password
+            if service_token(password) {
+                return Some(Authorized::default());
+            }
+        }
+        None
+    }
+}
+
+/// Compares secrets in time independent of where they differ.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |differences, (a, b)| differences | (a ^ b))
+            == 0
+}
+
+/// Whether a request from a page at `origin`, as browsers give it, is to the service itself at
+/// `host`.
+fn same_origin(origin: &str, host: Option<&str>) -> bool {
+    let authority = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"));
+    authority.is_some_and(|authority| Some(authority) == host)
+}
+
+/// Guards a route, failing the request with 401 unless it carries valid credentials. Every
+/// route takes one, as the service can replace and delete files. Clients making too many
+/// requests get 429 first, whether their credentials are valid or not.
+///
+/// Browsers send the basic credentials they were given for the dashboard along with requests
+/// other sites make to the service, so requests that change anything are refused with 403 when
+/// they come from a page elsewhere.
+///
+/// Routes check the paths a request would work on against it, and show only the jobs and
+/// profiles whose paths it allows.
+#[derive(Debug, Clone, Default)]
+pub struct Authorized {
+    /// None for a token of the service
+    pub user: Option<String>,
+    /// The directories the user is restricted to, with everything under them, or None for
+    /// anywhere
+    pub roots: Option<Vec<PathBuf>>,
+}
+
+impl Authorized {
+    /// Whether `path` is in one of the roots, also once symlinks are resolved.
+    pub fn allows(&self, path: &Path) -> bool {
+        let Some(roots) = &self.roots else {
+            return true;
+        };
+        if path.components().any(|c| c == Component::ParentDir) {
+            return false;
+        }
+        let resolved = path.canonicalize().ok();
+        roots.iter().any(|root| {
+            path.starts_with(root)
+                && resolved.as_ref().is_none_or(|resolved| {
+                    resolved.starts_with(root.canonicalize().as_deref().unwrap_or(root))
+                })
+        })
+    }
+
+    /// Whether all the paths of `request` are allowed.
+    pub fn allows_request(&self, request: &JobRequest) -> bool {
+        request
+            .source_paths
+            .iter()
+            .chain(&request.target_paths)
+            .all(|path| self.allows(path))
+    }
+
+    /// Fails with 403 unless all the paths of `request` are allowed.
+    pub fn check(&self, request: &JobRequest) -> Result<(), (Status, String)> {
+        if self.allows_request(request) {
+            return Ok(());
+        }
+        Err((
+            Status::Forbidden,
+            format!(
+                "{} may only use paths under {:?}",
+                self.user.as_deref().unwrap_or("This token"),
+                self.roots.as_deref().unwrap_or_default()
+            ),
+        ))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let credentials = request
+            .rocket()
+            .state::<Credentials>()
+            .expect("Credentials are managed");
+        let limiter = request
+            .rocket()
+            .state::<RateLimiter>()
+            .expect("The rate limiter is managed");
+        if let Some(client) = request.client_ip()
+            && !limiter.allow(client)
+        {
+            tracing::warn!("Too many requests from {client}");
+            return Outcome::Error((Status::TooManyRequests, "Too many requests"));
+        }
+        if !matches!(
+            request.method(),
+            Method::Get | Method::Head | Method::Options
+        ) && let Some(origin) = request.headers().get_one("Origin")
+            && !same_origin(origin, request.headers().get_one("Host"))
+        {
+            tracing::warn!("Refused a request to {} from {origin}", request.uri());
+            return Outcome::Error((Status::Forbidden, "Cross-site request"));
+        }
+        match request
+            .headers()
+            .get_one("Authorization")
+            .map(|authorization| credentials.accept(authorization))
+        {
+            Some(Some(authorized)) => Outcome::Success(authorized),
+            Some(None) => {
+                tracing::warn!(
+                    "Rejected invalid credentials from {:?}",
+                    request.client_ip()
+                );
+                Outcome::Error((Status::Unauthorized, "Invalid credentials"))
+            }
+            None => Outcome::Error((Status::Unauthorized, "Missing credentials")),
+        }
+    }
+}
+
+/// Asks for credentials, for requests failing authentication.
+#[derive(rocket::Responder)]
+#[response(status = 401)]
+pub struct Challenge {
+    message: &'static str,
+    challenge: Header<'static>,
+}
+
+#[rocket::catch(401)]
+pub fn unauthorized() -> Challenge {
+    Challenge {
+        message: "Authentication required",
+        challenge: Header::new(
+            "WWW-Authenticate",
+            "Bearer realm=\"atorrlinker\", Basic realm=\"atorrlinker\"",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept() {
+        let credentials = Credentials::new(
+            vec!["s3cret".to_owned()],
+            BTreeMap::from([
+                ("alice".to_owned(), User::Password("hunter2".to_owned())),
+                (
+                    "bob".to_owned(),
+                    User::Account {
+                        password: None,
+                        tokens: vec!["b0b".to_owned(), String::new()],
+                        roots: Some(vec!["/srv/bob".into()]),
+                    },
+                ),
+            ]),
+        )
+        .unwrap();
+        let user = |authorization| {
+            credentials
+                .accept(authorization)
+                .map(|authorized| authorized.user)
+        };
+        assert_eq!(user("Bearer s3cret"), Some(None));
+        assert_eq!(user("bearer  s3cret "), Some(None));
+        assert_eq!(user("Bearer s3cre"), None);
+        assert_eq!(user("s3cret"), None);
+        assert_eq!(user("Bearer "), None);
+        // alice:hunter2
+        assert_eq!(
+            user("Basic YWxpY2U6aHVudGVyMg=="),
+            Some(Some("alice".to_owned()))
+        );
+        // alice:hunter3
+        assert_eq!(user("Basic YWxpY2U6aHVudGVyMw=="), None);
+        assert_eq!(user("Basic !!"), None);
+        // anyone:s3cret
+        assert_eq!(user("Basic YW55b25lOnMzY3JldA=="), Some(None));
+        // bob:b0b
+        assert_eq!(user("Basic Ym9iOmIwYg=="), Some(Some("bob".to_owned())));
+
+        let bob = credentials.accept("Bearer b0b").unwrap();
+        assert!(bob.allows(Path::new("/srv/bob/tv")));
+        assert!(!bob.allows(Path::new("/srv/bobby")));
+        assert!(!bob.allows(Path::new("/srv/bob/../alice")));
+        assert!(Authorized::default().allows(Path::new("/srv/alice")));
+
+        assert!(Credentials::new(vec![String::new()], BTreeMap::new()).is_err());
+
+        let credentials = Credentials::new(
+            Vec::new(),
+            BTreeMap::from([
+                ("alice".to_owned(), User::Password(String::new())),
+                (
+                    "bob".to_owned(),
+                    User::Account {
+                        password: Some(String::new()),
+                        tokens: Vec::new(),
+                        roots: None,
+                    },
+                ),
+            ]),
+        )
+        .unwrap();
+        // alice:
+        assert!(credentials.accept("Basic YWxpY2U6").is_none());
+        // bob:
+        assert!(credentials.accept("Basic Ym9iOg==").is_none());
+    }
+
+    #[test]
+    fn test_check() {
+        let bob = Authorized {
+            user: Some("bob".to_owned()),
+            roots: Some(vec!["/srv/bob".into()]),
+        };
+        let request = |source: &str, target: &str| -> JobRequest {
+            serde_json::from_value(serde_json::json!({
+                "source_paths": [source],
+                "target_paths": [target],
+            }))
+            .unwrap()
+        };
+
+        let inside = request("/srv/bob/dl", "/srv/bob/tv");
+        assert!(bob.allows_request(&inside));
+        assert!(bob.check(&inside).is_ok());
+        for outside in [
+            request("/srv/bob/dl", "/srv/alice/tv"),
+            request("/srv/alice/dl", "/srv/bob/tv"),
+            request("/srv/bob/dl", "/srv/bob/../alice/tv"),
+        ] {
+            assert!(!bob.allows_request(&outside));
+            let (status, message) = bob.check(&outside).unwrap_err();
+            assert_eq!(status, Status::Forbidden);
+            assert_eq!(message, "bob may only use paths under [\"/srv/bob\"]");
+        }
+        assert!(Authorized::default().check(&request("/dl", "/tv")).is_ok());
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let host = Some("seedbox:8000");
+        assert!(same_origin("http://seedbox:8000", host));
+        assert!(same_origin("https://seedbox:8000", host));
+        assert!(!same_origin("https://evil.example", host));
+        assert!(!same_origin("http://seedbox:8000.evil.example", host));
+        assert!(!same_origin("null", host));
+        assert!(!same_origin("http://seedbox:8000", None));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use rocket::{State, http::Status, serde::json::Json};
+
+use crate::{auth::Authorized, jobs::internal_error};
+
+/// The directories that can be browsed, with everything under them.
+pub struct Roots(pub Vec<PathBuf>);
+
+/// A directory and the directories in it, or the roots when browsing starts.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Listing {
+    /// None for the roots
+    #[schema(value_type = Option<String>)]
+    pub path: Option<PathBuf>,
+    /// Where to go up to, none at the roots or a root
+    #[schema(value_type = Option<String>)]
+    pub parent: Option<PathBuf>,
+    pub directories: Vec<Directory>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Directory {
+    pub name: String,
+    #[schema(value_type = String)]
+    pub path: PathBuf,
+}
+
+/// Lists the directories in `path`, or `roots` without one. Paths resolving outside the roots,
+/// such as through `..` or a symlink, are refused with `PermissionDenied`.
+pub fn list(roots: &[PathBuf], path: Option<&Path>) -> io::Result<Listing> {
+    let Some(path) = path else {
+        return Ok(Listing {
+            path: None,
+            parent: None,
+            directories: roots
+                .iter()
+                .map(|root| Directory {
+                    name: root.display().to_string(),
+                    path: root.clone(),
+                })
+                .collect(),
+        });
+    };
+    let outside = || {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{path:?} is outside of the directories that can be browsed"),
+        )
+    };
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(outside());
+    }
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        // Whether paths outside of the roots exist is none of the client's business
+        Err(e) if roots.iter().any(|root| path.starts_with(root)) => return Err(e),
+        Err(_) => return Err(outside()),
+    };
+    let root = roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .find(|root| resolved.starts_with(root))
+        .ok_or_else(outside)?;
+    let mut directories = Vec::new();
+    for entry in std::fs::read_dir(&resolved)? {
+        let entry = entry?;
+        // Symlinks are left out, as they may lead outside of the roots
+        if entry.file_type()?.is_dir() {
+            directories.push(Directory {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                path: entry.path(),
+            });
+        }
+    }
+    directories.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Listing {
+        parent: (resolved != root)
+            .then(|| resolved.parent().map(Path::to_path_buf))
+            .flatten(),
+        path: Some(resolved),
+        directories,
+    })
+}
+
+/// Lists the directories in `path` for picking source and target paths, only ever under the
+/// configured `roots`, or those of the user. Without `path`, lists the roots.
+#[utoipa::path(
+    get,
+    path = "/fs",
+    operation_id = "list_directories",
+    tag = "files",
+    params(("path" = Option<String>, Query, description = "An absolute path")),
+    responses(
+        (status = 200, body = Listing),
+        (status = 403, description = "The path is outside of the roots"),
+        (status = 404),
+        (status = 422, description = "The path isn't absolute"),
+    )
+)]
+#[rocket::get("/fs?<path>")]
+pub fn browse(
+    auth: Authorized,
+    roots: &State<Roots>,
+    path: Option<&str>,
+) -> Result<Json<Listing>, (Status, String)> {
+    let path = path.map(Path::new);
+    if let Some(path) = path
+        && !path.is_absolute()
+    {
+        return Err((
+            Status::UnprocessableEntity,
+            format!("{path:?} isn't an absolute path"),
+        ));
+    }
+    list(auth.roots.as_deref().unwrap_or(&roots.0), path)
+        .map(Json)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => (Status::Forbidden, e.to_string()),
+            io::ErrorKind::NotFound => (Status::NotFound, e.to_string()),
+            _ => internal_error(e),
+        })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("media");
+        fs::create_dir_all(root.join("tv/Show")).unwrap();
+        fs::create_dir(root.join("movies")).unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        fs::create_dir(dir.path().join("private")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("private"), root.join("escape")).unwrap();
+        let roots = [root.clone()];
+
+        let names = |listing: Listing| -> Vec<String> {
+            listing.directories.into_iter().map(|d| d.name).collect()
+        };
+        assert_eq!(
+            names(list(&roots, None).unwrap()),
+            [root.display().to_string()]
+        );
+        let listing = list(&roots, Some(&root)).unwrap();
+        assert_eq!(listing.parent, None);
+        assert_eq!(names(listing), ["movies", "tv"]);
+        let listing = list(&roots, Some(&root.join("tv"))).unwrap();
+        assert_eq!(listing.parent, Some(root.canonicalize().unwrap()));
+        assert_eq!(names(listing), ["Show"]);
+
+        for outside in [
+            dir.path().to_path_buf(),
+            root.join(".."),
+            root.join("escape"),
+        ] {
+            let e = list(&roots, Some(&outside)).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::PermissionDenied, "{outside:?}");
+        }
+        assert_eq!(
+            list(&roots, Some(&root.join("nope"))).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            list(&roots, Some(&dir.path().join("nope")))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+}
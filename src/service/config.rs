@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use rocket::figment::{
+    Figment, Profile,
+    providers::{Env, Format as _, Toml},
+};
+
+use crate::{auth::User, jobs::CallbackHosts, qbittorrent::QBittorrent};
+
+/// Where the settings are read from, each overriding the ones before:
+///
+/// - `service.toml` in the configuration directory, e.g. `~/.config/untorr_undup/` on Linux
+/// - `Rocket.toml`, or the file `ROCKET_CONFIG` names
+/// - `ROCKET_` environment variables, e.g. `ROCKET_ADDRESS=0.0.0.0`
+///
+/// Both files take the settings of each Rocket profile under its name, usually `[default]`.
+/// Besides those of [`Config`], Rocket's own set where the service listens: `address` (only
+/// `127.0.0.1` unless given), `port` (8000 unless given), and `tls.certs` and `tls.key` for
+/// the PEM files serving HTTPS.
+pub fn figment() -> Figment {
+    let file = atorrlinker::project_dirs()
+        .config_dir()
+        .join("service.toml");
+    layered(
+        &file,
+        Path::new(&Env::var_or("ROCKET_CONFIG", "Rocket.toml")),
+    )
+}
+
+/// The settings of `service_file`, overridden by those of `rocket_file` and the environment.
+fn layered(service_file: &Path, rocket_file: &Path) -> Figment {
+    Figment::from(rocket::Config::default())
+        .merge(Toml::file(service_file).nested())
+        .merge(Toml::file(rocket_file).nested())
+        .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+        .select(Profile::from_env_or(
+            "ROCKET_PROFILE",
+            rocket::Config::DEFAULT_PROFILE,
+        ))
+}
+
+/// The service's own settings, read along with Rocket's from the places [`figment`] lists,
+/// e.g. `ROCKET_DATABASE=/var/lib/atorrlinker/jobs.sqlite3`.
+#[derive(Debug, serde::Deserialize)]
+pub struct Config {
+    /// The SQLite database keeping the history of jobs, the profiles and their schedules
+    #[serde(default = "default_database")]
+    pub database: PathBuf,
+    /// Where the plans of dry runs are saved, and the parts of them approved for applying
+    #[serde(default = "default_plans")]
+    pub plans: PathBuf,
+    /// Bearer tokens accepted by the API, also as the password of any user through basic
+    /// authentication
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// Users by name, each with a password accepted through HTTP basic authentication, or
+    /// also tokens and the directories they are restricted to
+    #[serde(default)]
+    pub users: BTreeMap<String, User>,
+    /// The directories the dashboard may browse to pick paths from, with everything under
+    /// them. None unless configured
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    /// How many jobs run at once, the others waiting in the queue
+    #[serde(default = "default_max_jobs")]
+    pub max_jobs: NonZeroUsize,
+    /// How many requests each client may make in a minute, all at once or spread out, or 0
+    /// for no limit
+    #[serde(default = "default_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// The hosts jobs may post their callbacks to, e.g. `["ntfy.sh", "jellyfin.lan:8096"]`:
+    /// `host` on any port, or `host:port`. None unless configured
+    #[serde(default)]
+    pub callback_hosts: CallbackHosts,
+    /// The profiles `POST /hooks/qbittorrent` runs finished torrents with
+    #[serde(default)]
+    pub qbittorrent: QBittorrent,
+}
+
+fn default_database() -> PathBuf {
+    atorrlinker::project_dirs()
+        .data_dir()
+        .join("service.sqlite3")
+}
+
+fn default_plans() -> PathBuf {
+    atorrlinker::project_dirs().data_dir().join("plans")
+}
+
+fn default_max_jobs() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+fn default_requests_per_minute() -> u32 {
+    300
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, net::Ipv4Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_layered() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service_file, rocket_file) = (
+            dir.path().join("service.toml"),
+            dir.path().join("Rocket.toml"),
+        );
+        fs::write(
+            &service_file,
+            r#"
+            [default]
+            address = "0.0.0.0"
+            port = 8443
+            tokens = ["s3cret"]
+            max_jobs = 2
+            tls.certs = "/etc/atorrlinker/cert.pem"
+            tls.key = "/etc/atorrlinker/key.pem"
+            "#,
+        )
+        .unwrap();
+        // Overrides the service's own file
+        fs::write(&rocket_file, "[default]\nport = 9443\n").unwrap();
+
+        let figment = layered(&service_file, &rocket_file);
+        let listening: rocket::Config = figment.extract().unwrap();
+        assert_eq!(listening.address, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(listening.port, 9443);
+        assert!(listening.tls_enabled());
+        let config: Config = figment.extract().unwrap();
+        assert_eq!(config.tokens, ["s3cret"]);
+        assert_eq!(config.max_jobs.get(), 2);
+        assert_eq!(config.requests_per_minute, 300);
+
+        // Only on localhost, over plain HTTP, unless configured
+        let figment = layered(
+            &dir.path().join("missing.toml"),
+            &dir.path().join("missing.toml"),
+        );
+        let listening: rocket::Config = figment.extract().unwrap();
+        assert_eq!(listening.address, Ipv4Addr::LOCALHOST);
+        assert_eq!(listening.port, 8000);
+        assert!(!listening.tls_enabled());
+    }
+}
@@ -0,0 +1,12 @@
+use rocket::response::content::RawHtml;
+
+use crate::auth::Authorized;
+
+/// A single page driving the API from a browser: runs are previewed with a dry run, whose plan
+/// can then be applied.
+const PAGE: &str = include_str!("dashboard.html");
+
+#[rocket::get("/")]
+pub fn index(_auth: Authorized) -> RawHtml<&'static str> {
+    RawHtml(PAGE)
+}
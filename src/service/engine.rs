@@ -0,0 +1,126 @@
+use std::{
+    ffi::OsString,
+    io::{self, BufRead as _, BufReader, Read as _},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// How many of the last lines the engine logged are kept to explain a failure.
+const LOG_TAIL: usize = 10;
+
+/// The `atorrlinker-undup` binary installed next to the service, which does the work.
+pub fn locate() -> io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    Ok(exe.with_file_name(format!("atorrlinker-undup{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// A progress event the engine reports while running, one JSON line each on its stderr.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    Scanned {
+        directories: u64,
+        files: u64,
+    },
+    /// The files about to be hashed, first just their start, then in full
+    Phase {
+        name: String,
+        files: u64,
+        bytes: u64,
+    },
+    Hashed {
+        path: PathBuf,
+        bytes: u64,
+    },
+    Matching,
+    Matched {
+        target: PathBuf,
+        source: PathBuf,
+        size: u64,
+    },
+    Applied {
+        target: PathBuf,
+        result: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// Runs `engine` with `args`, such as those of a job, passing on its progress events as they
+/// come, and returns its JSON report.
+pub fn run(
+    engine: &Path,
+    args: &[OsString],
+    mut on_event: impl FnMut(Event),
+) -> io::Result<serde_json::Value> {
+    let mut child = Command::new(engine)
+        .args(args)
+        .args(["--progress-format", "jsonl"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("Stdout is piped");
+    // Read at the same time as stderr, so neither fills up and blocks the engine
+    let report = std::thread::spawn(move || {
+        let mut report = Vec::new();
+        stdout.read_to_end(&mut report).map(|_| report)
+    });
+
+    let mut log = Vec::new();
+    for line in BufReader::new(child.stderr.take().expect("Stderr is piped")).lines() {
+        let line = line?;
+        match serde_json::from_str(&line) {
+            Ok(event) => on_event(event),
+            Err(_) => {
+                log.push(line);
+                if log.len() > LOG_TAIL {
+                    log.remove(0);
+                }
+            }
+        }
+    }
+    let status = child.wait()?;
+    let report = report.join().expect("Reading the report panicked")?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "The job failed with {status}: {}",
+            log.join("\n").trim()
+        )));
+    }
+    serde_json::from_slice(&report).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected output from {engine:?}: {e}"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let event = |line: &str| serde_json::from_str::<Event>(line).unwrap();
+        assert_eq!(
+            event(r#"{"event":"scanned","directories":2,"files":6}"#),
+            Event::Scanned {
+                directories: 2,
+                files: 6
+            }
+        );
+        assert_eq!(event(r#"{"event":"matching"}"#), Event::Matching);
+        assert_eq!(
+            event(
+                r#"{"event":"applied","action":"symlink","target":"/tv/a.mkv","source":"/dl/a.mkv","hash":"ABC","result":"replaced"}"#
+            ),
+            Event::Applied {
+                target: "/tv/a.mkv".into(),
+                result: "replaced".to_owned(),
+                error: None
+            }
+        );
+        assert!(serde_json::from_str::<Event>("WARN something").is_err());
+    }
+}
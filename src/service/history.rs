@@ -0,0 +1,210 @@
+use std::{io, path::Path, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension as _, Row, params, types::Type};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::jobs::Job;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY,
+    state TEXT NOT NULL,
+    request TEXT NOT NULL,
+    progress TEXT NOT NULL,
+    submitted_at TEXT NOT NULL,
+    started_at TEXT,
+    finished_at TEXT,
+    summary TEXT,
+    error TEXT
+)";
+
+const COLUMNS: &str =
+    "id, state, request, progress, submitted_at, started_at, finished_at, summary, error";
+
+/// Every job the service ran, kept in SQLite so they can be reviewed after a restart. The
+/// parameters, progress and summary of a job are stored as JSON, its times as RFC 3339.
+pub struct History {
+    db: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path).map_err(io::Error::other)?;
+        db.execute(SCHEMA, []).map_err(io::Error::other)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    fn db(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.db.lock().expect("History lock poisoned")
+    }
+
+    /// Records `job` as it is now, replacing what was stored for it before.
+    pub fn save(&self, job: &Job) -> io::Result<()> {
+        let state = serde_json::to_value(job.state)?;
+        self.db()
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO jobs ({COLUMNS}) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+                ),
+                params![
+                    job.id,
+                    state.as_str(),
+                    to_json(&job.request)?,
+                    to_json(&job.progress)?,
+                    job.submitted_at.to_rfc3339(),
+                    job.started_at.map(|time| time.to_rfc3339()),
+                    job.finished_at.map(|time| time.to_rfc3339()),
+                    job.summary.as_ref().map(to_json).transpose()?,
+                    job.error,
+                ],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: u64) -> io::Result<Option<Job>> {
+        self.db()
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM jobs WHERE id = ?1"),
+                [id],
+                from_row,
+            )
+            .optional()
+            .map_err(io::Error::other)
+    }
+
+    /// The last `limit` jobs before the job `before`, or of all, newest first.
+    pub fn list(&self, limit: u64, before: Option<u64>) -> io::Result<Vec<Job>> {
+        let db = self.db();
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT {COLUMNS} FROM jobs WHERE ?2 IS NULL OR id < ?2 ORDER BY id DESC LIMIT ?1"
+            ))
+            .map_err(io::Error::other)?;
+        statement
+            .query_map(params![limit, before], from_row)
+            .and_then(Iterator::collect)
+            .map_err(io::Error::other)
+    }
+
+    /// The highest job ID used so far, so new jobs carry on from it.
+    pub fn last_id(&self) -> io::Result<u64> {
+        self.db()
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM jobs", [], |row| {
+                row.get(0)
+            })
+            .map_err(io::Error::other)
+    }
+
+    /// Marks the jobs that never finished as failed, as the service stopping ended them.
+    pub fn interrupt_unfinished(&self) -> io::Result<usize> {
+        self.db()
+            .execute(
+                "UPDATE jobs SET state = 'failed', finished_at = ?1, \
+                 error = 'Interrupted by the service stopping' \
+                 WHERE state NOT IN ('done', 'failed')",
+                [Utc::now().to_rfc3339()],
+            )
+            .map_err(io::Error::other)
+    }
+}
+
+fn to_json(value: &impl Serialize) -> io::Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+fn invalid(index: usize, e: impl std::error::Error + Send + Sync + 'static) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+}
+
+fn json<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<Option<T>> {
+    row.get::<_, Option<String>>(index)?
+        .map(|text| serde_json::from_str(&text).map_err(|e| invalid(index, e)))
+        .transpose()
+}
+
+fn time(row: &Row, index: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    row.get::<_, Option<String>>(index)?
+        .map(|text| {
+            DateTime::parse_from_rfc3339(&text)
+                .map(|time| time.to_utc())
+                .map_err(|e| invalid(index, e))
+        })
+        .transpose()
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<Job> {
+    let state: String = row.get(1)?;
+    Ok(Job {
+        id: row.get(0)?,
+        state: serde_json::from_value(serde_json::Value::String(state))
+            .map_err(|e| invalid(1, e))?,
+        request: json(row, 2)?.ok_or(rusqlite::Error::InvalidColumnType(
+            2,
+            "request".to_owned(),
+            Type::Null,
+        ))?,
+        progress: json(row, 3)?.unwrap_or_default(),
+        submitted_at: time(row, 4)?.unwrap_or_default(),
+        started_at: time(row, 5)?,
+        finished_at: time(row, 6)?,
+        summary: json(row, 7)?,
+        error: row.get(8)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobRequest, JobState, Progress};
+
+    #[test]
+    fn test_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.last_id().unwrap(), 0);
+
+        let request: JobRequest =
+            serde_json::from_str(r#"{"source_paths": ["/dl"], "target_paths": ["/tv"]}"#).unwrap();
+        let mut job = Job {
+            id: 1,
+            state: JobState::Done,
+            request,
+            progress: Progress {
+                matches: 3,
+                ..Progress::default()
+            },
+            submitted_at: Utc::now(),
+            started_at: Some(Utc::now()),
+            finished_at: Some(Utc::now()),
+            summary: Some(serde_json::json!({"summary": {"files_linked": 3}})),
+            error: None,
+        };
+        history.save(&job).unwrap();
+        job.id = 2;
+        job.state = JobState::Hashing;
+        job.summary = None;
+        history.save(&job).unwrap();
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.last_id().unwrap(), 2);
+        assert_eq!(history.interrupt_unfinished().unwrap(), 1);
+        let jobs = history.list(10, None).unwrap();
+        assert_eq!(jobs.iter().map(|job| job.id).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(jobs[0].state, JobState::Failed);
+        assert!(jobs[0].error.is_some());
+        let done = history.get(1).unwrap().unwrap();
+        assert_eq!(done.state, JobState::Done);
+        assert_eq!(done.progress.matches, 3);
+        assert_eq!(done.request.target_paths, [Path::new("/tv")]);
+        assert_eq!(done.summary.unwrap()["summary"]["files_linked"], 3);
+        assert_eq!(history.list(1, None).unwrap().len(), 1);
+        assert_eq!(history.list(10, Some(2)).unwrap()[0].id, 1);
+        assert!(history.get(3).unwrap().is_none());
+    }
+}
@@ -0,0 +1,1041 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    io,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use rocket::{
+    Shutdown, State,
+    http::Status,
+    response::{
+        status::Accepted,
+        stream::{Event as SseEvent, EventStream},
+    },
+    serde::json::Json,
+    tokio::sync::{Semaphore, broadcast},
+};
+
+use atorrlinker::{
+    actions::{LinkMode, saved_plan::SavedPlan},
+    notify,
+};
+use clap::ValueEnum as _;
+
+use crate::{
+    auth::Authorized,
+    engine::{self, Event},
+    history::History,
+};
+
+/// What `POST /jobs` takes: the paths to deduplicate and the options of the run.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct JobRequest {
+    #[schema(value_type = Vec<String>)]
+    pub source_paths: Vec<PathBuf>,
+    #[schema(value_type = Vec<String>)]
+    pub target_paths: Vec<PathBuf>,
+    /// Only report what would be replaced
+    #[serde(default)]
+    pub dry_run: bool,
+    /// How to replace duplicates, tried in the order given. Defaults to symlinks
+    #[serde(default)]
+    #[schema(value_type = Vec<String>, example = json!(["reflink", "hardlink"]))]
+    pub link_mode: Vec<LinkMode>,
+    /// rsync style patterns of paths to leave out
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub skip_hidden: bool,
+    #[serde(default)]
+    pub one_file_system: bool,
+    /// Only replace targets unmodified for this long, e.g. `7d`
+    #[serde(default)]
+    pub older_than: Option<String>,
+    /// URLs to POST the job to as JSON once it is done or failed, on the `callback_hosts`
+    /// configured
+    #[serde(default)]
+    pub callbacks: Vec<String>,
+    /// Applies the plan of a dry run instead of scanning. Only set by `POST /plans/<id>/apply`,
+    /// which checks the dry run is the client's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<PlanSelection>,
+}
+
+/// A plan saved by a dry run, and which of its entries to apply.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PlanSelection {
+    /// The ID of the dry run
+    pub id: u64,
+    /// Indices of the entries listed at `/plans/<id>`, all of them unless given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entries: Option<Vec<usize>>,
+}
+
+/// The hosts jobs may post their callbacks to, as configured: `host` on any port, or
+/// `host:port`. Callbacks elsewhere are refused, so clients can't have the service send
+/// requests into the network it runs in.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(transparent)]
+pub struct CallbackHosts(pub Vec<String>);
+
+impl CallbackHosts {
+    /// Fails unless `url` is an http:// or https:// URL to one of the hosts.
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        let uri = url
+            .parse::<ureq::http::Uri>()
+            .ok()
+            .filter(|uri| matches!(uri.scheme_str(), Some("http" | "https")))
+            .ok_or_else(|| format!("{url:?} isn't an http:// or https:// URL"))?;
+        let host = uri.host().unwrap_or_default();
+        let port = uri
+            .port_u16()
+            .unwrap_or(if uri.scheme_str() == Some("https") {
+                443
+            } else {
+                80
+            });
+        let allowed = self.0.iter().any(|allowed| {
+            match allowed
+                .rsplit_once(':')
+                .and_then(|(name, p)| Some((name, p.parse::<u16>().ok()?)))
+            {
+                Some((name, p)) => name.eq_ignore_ascii_case(host) && p == port,
+                None => allowed.eq_ignore_ascii_case(host),
+            }
+        });
+        if !allowed {
+            return Err(format!(
+                "Callbacks may only go to the callback_hosts configured, not {host:?}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl JobRequest {
+    pub fn validate(&self, callback_hosts: &CallbackHosts) -> Result<(), String> {
+        if self.source_paths.is_empty() || self.target_paths.is_empty() {
+            return Err("Both source_paths and target_paths are needed".to_owned());
+        }
+        // The working directory of the service means nothing to its clients
+        if let Some(path) = self
+            .source_paths
+            .iter()
+            .chain(&self.target_paths)
+            .find(|path| !path.is_absolute())
+        {
+            return Err(format!("{path:?} isn't an absolute path"));
+        }
+        for url in &self.callbacks {
+            callback_hosts.check(url)?;
+        }
+        if self.plan.is_some() {
+            return Err("Plans are applied through POST /plans/<id>/apply".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Arguments to the engine for running the job, reporting as JSON on stdout. Progress is
+    /// left to the caller to ask for.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["--output-format", "json", "--yes"]
+            .map(OsString::from)
+            .into();
+        for path in &self.source_paths {
+            args.extend(["--source-paths".into(), path.into()]);
+        }
+        for path in &self.target_paths {
+            args.extend(["--target-paths".into(), path.into()]);
+        }
+        if self.dry_run {
+            args.push("--dry-run".into());
+        }
+        if !self.link_mode.is_empty() {
+            let modes: Vec<_> = self
+                .link_mode
+                .iter()
+                .filter_map(|mode| mode.to_possible_value())
+                .map(|mode| mode.get_name().to_owned())
+                .collect();
+            args.extend(["--link-mode".into(), modes.join(",").into()]);
+        }
+        // Values are joined to their options, so that one starting with `-` isn't taken for an
+        // option of its own
+        for pattern in &self.exclude {
+            let mut arg = OsString::from("--exclude=");
+            arg.push(pattern);
+            args.push(arg);
+        }
+        if self.skip_hidden {
+            args.push("--skip-hidden".into());
+        }
+        if self.one_file_system {
+            args.push("--one-file-system".into());
+        }
+        if let Some(older_than) = &self.older_than {
+            let mut arg = OsString::from("--older-than=");
+            arg.push(older_than);
+            args.push(arg);
+        }
+        args
+    }
+}
+
+/// Where a job is at.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a worker, or for the jobs replacing files in the same targets to finish
+    Queued,
+    Scanning,
+    Hashing,
+    /// Matching the hashed files and replacing the duplicates found, which go hand in hand
+    Applying,
+    Done,
+    Failed,
+}
+
+/// Counters of what a job got through so far.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct Progress {
+    pub directories: u64,
+    pub files: u64,
+    /// The hashing pass going on, comparing the start of files or hashing them in full
+    pub phase: Option<String>,
+    /// Files and bytes to hash in this pass
+    pub files_to_hash: u64,
+    pub bytes_to_hash: u64,
+    pub files_hashed: u64,
+    pub bytes_hashed: u64,
+    pub matches: u64,
+    pub applied: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// A job submitted to the service.
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct Job {
+    pub id: u64,
+    pub state: JobState,
+    pub request: JobRequest,
+    pub progress: Progress,
+    pub submitted_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The totals reported by the engine once done, and the actions proposed by a dry run
+    pub summary: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, JobState::Done | JobState::Failed)
+    }
+
+    fn record(&mut self, event: Event) {
+        let progress = &mut self.progress;
+        match event {
+            Event::Scanned { directories, files } => {
+                self.state = JobState::Scanning;
+                progress.directories = directories;
+                progress.files = files;
+            }
+            Event::Phase { name, files, bytes } => {
+                self.state = JobState::Hashing;
+                progress.phase = Some(name);
+                progress.files_to_hash = files;
+                progress.bytes_to_hash = bytes;
+                progress.files_hashed = 0;
+                progress.bytes_hashed = 0;
+            }
+            Event::Hashed { bytes, .. } => {
+                progress.files_hashed += 1;
+                progress.bytes_hashed += bytes;
+            }
+            Event::Matching => self.state = JobState::Applying,
+            Event::Matched { .. } => progress.matches += 1,
+            Event::Applied { result, .. } => match result.as_str() {
+                "failed" => progress.failed += 1,
+                "skipped" => progress.skipped += 1,
+                _ => progress.applied += 1,
+            },
+        }
+    }
+
+    fn finish(&mut self, result: io::Result<serde_json::Value>) {
+        self.finished_at = Some(Utc::now());
+        match result {
+            Ok(mut report) => {
+                self.state = JobState::Done;
+                // What was done to each file can be had from the journal and audit log, while
+                // the actions of a dry run are the plan to review
+                if !self.request.dry_run
+                    && let Some(report) = report.as_object_mut()
+                {
+                    report.remove("actions");
+                }
+                self.summary = Some(report);
+            }
+            Err(e) => {
+                tracing::error!("Job {} failed: {e}", self.id);
+                self.state = JobState::Failed;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// How many updates a slow `/jobs/<id>/events` client may fall behind before missing some.
+const UPDATES_BUFFERED: usize = 1024;
+
+/// A change to a job, streamed to whoever follows it.
+#[derive(Debug, Clone)]
+pub enum Update {
+    Progress(Event),
+    /// The whole job, whenever its state changes
+    Job(Box<Job>),
+}
+
+/// How many jobs `GET /jobs` lists unless asked otherwise.
+const DEFAULT_LIMIT: u64 = 20;
+
+/// The jobs submitted so far, run in the background in the order they came, up to a number
+/// of them at once. Each is saved to the history whenever its state changes.
+pub struct Jobs {
+    /// The jobs submitted since the service started
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    /// The targets of the jobs replacing files right now. Another job on the same files waits
+    /// for them, rather than failing to lock them.
+    replacing: Mutex<BTreeMap<u64, Vec<PathBuf>>>,
+    replaced: Condvar,
+    engine: PathBuf,
+    /// Where each job keeps its plan, the one saved by a dry run or the part of one applied
+    plans: PathBuf,
+    history: History,
+    next_id: AtomicU64,
+    queue: mpsc::Sender<u64>,
+    /// Previews run outside of the queue, as many at once as there are workers
+    previews: Arc<Semaphore>,
+    /// Checked again when calling back, for jobs queued before they changed
+    callback_hosts: CallbackHosts,
+    updates: broadcast::Sender<(u64, Update)>,
+}
+
+impl Jobs {
+    /// Starts `workers` running the queued jobs with `engine`. Jobs the history has as
+    /// unfinished were cut short by the service stopping, and are marked as failed.
+    pub fn start(
+        engine: PathBuf,
+        history: History,
+        plans: PathBuf,
+        workers: NonZeroUsize,
+        callback_hosts: CallbackHosts,
+    ) -> io::Result<Arc<Self>> {
+        std::fs::create_dir_all(&plans)?;
+        let interrupted = history.interrupt_unfinished()?;
+        if interrupted > 0 {
+            tracing::warn!("{interrupted} jobs were interrupted by the service stopping");
+        }
+        let (queue, queued) = mpsc::channel();
+        let jobs = Arc::new(Self {
+            jobs: Mutex::default(),
+            replacing: Mutex::default(),
+            replaced: Condvar::new(),
+            engine,
+            plans,
+            next_id: AtomicU64::new(history.last_id()? + 1),
+            history,
+            queue,
+            previews: Arc::new(Semaphore::new(workers.get())),
+            callback_hosts,
+            updates: broadcast::channel(UPDATES_BUFFERED).0,
+        });
+        let queued = Arc::new(Mutex::new(queued));
+        for _ in 0..workers.get() {
+            let worker = Arc::clone(&jobs);
+            let queued = Arc::clone(&queued);
+            std::thread::spawn(move || {
+                loop {
+                    let next = queued.lock().expect("Queue lock poisoned").recv();
+                    let Ok(id) = next else { return };
+                    worker.run(id);
+                }
+            });
+        }
+        Ok(jobs)
+    }
+
+    /// Queues `request`, returning the job as submitted.
+    pub fn submit(&self, request: JobRequest) -> io::Result<Job> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            id,
+            state: JobState::Queued,
+            request,
+            progress: Progress::default(),
+            submitted_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            summary: None,
+            error: None,
+        };
+        self.history.save(&job)?;
+        self.lock().insert(id, job.clone());
+        self.queue.send(id).expect("The job worker stopped");
+        Ok(job)
+    }
+
+    /// Scans and matches `request` as a dry run outside of the queue, once fewer previews than
+    /// workers are running, returning the report of the engine with the actions it would take.
+    pub async fn preview(
+        self: Arc<Self>,
+        mut request: JobRequest,
+    ) -> io::Result<serde_json::Value> {
+        // Held until the engine is done, even if the client stops waiting for it
+        let running = Arc::clone(&self.previews)
+            .acquire_owned()
+            .await
+            .map_err(io::Error::other)?;
+        request.dry_run = true;
+        rocket::tokio::task::spawn_blocking(move || {
+            let _running = running;
+            engine::run(&self.engine, &request.args(), |_| {})
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    /// The hosts the jobs may call back.
+    pub fn callback_hosts(&self) -> &CallbackHosts {
+        &self.callback_hosts
+    }
+
+    /// The plan of the job `id`, there once it ran.
+    pub fn plan_path(&self, id: u64) -> PathBuf {
+        self.plans.join(format!("{id}.json"))
+    }
+
+    /// The job with `id`, from before the service started too.
+    pub fn get(&self, id: u64) -> io::Result<Option<Job>> {
+        match self.lock().get(&id) {
+            Some(job) => Ok(Some(job.clone())),
+            None => self.history.get(id),
+        }
+    }
+
+    /// The last `limit` jobs that are `visible`, newest first.
+    pub fn list(&self, limit: u64, visible: impl Fn(&Job) -> bool) -> io::Result<Vec<Job>> {
+        let mut listed = Vec::new();
+        let mut before = None;
+        while (listed.len() as u64) < limit {
+            let page = self.history.list(limit, before)?;
+            let Some(last) = page.last() else { break };
+            before = Some(last.id);
+            // The progress of running jobs is only kept in memory
+            let jobs = self.lock();
+            listed.extend(
+                page.into_iter()
+                    .map(|job| jobs.get(&job.id).cloned().unwrap_or(job))
+                    .filter(&visible),
+            );
+        }
+        listed.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+        Ok(listed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Job>> {
+        self.jobs.lock().expect("Jobs lock poisoned")
+    }
+
+    /// Follows the changes to every job from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<(u64, Update)> {
+        self.updates.subscribe()
+    }
+
+    /// Changes a job, letting followers know when its state changed.
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.lock().get_mut(&id) {
+            let state = job.state;
+            f(job);
+            if job.state != state {
+                if let Err(e) = self.history.save(job) {
+                    tracing::error!("Couldn't save job {id} to the history: {e}");
+                }
+                // Nobody may be following
+                let _ = self.updates.send((id, Update::Job(Box::new(job.clone()))));
+            }
+        }
+    }
+
+    /// Arguments to the engine for the job `id`. A dry run saves its plan, while applying a
+    /// plan first saves the entries picked from it as the plan of this job.
+    fn args(&self, id: u64, request: &JobRequest) -> io::Result<Vec<OsString>> {
+        let mut args = request.args();
+        let plan = self.plan_path(id);
+        if request.dry_run {
+            args.extend(["plan".into(), "--out".into(), plan.into()]);
+        } else if let Some(selection) = &request.plan {
+            let mut picked = SavedPlan::load(&self.plan_path(selection.id))?;
+            if let Some(entries) = &selection.entries {
+                picked = picked.select(entries)?;
+            }
+            picked.save(&plan)?;
+            args.extend(["apply".into(), plan.into()]);
+        }
+        Ok(args)
+    }
+
+    /// The targets the job of `request` may replace files in, which for applying a plan are
+    /// those of the plan.
+    fn targets(&self, request: &JobRequest) -> Vec<PathBuf> {
+        let mut targets = request.target_paths.clone();
+        if let Some(selection) = &request.plan {
+            match SavedPlan::load(&self.plan_path(selection.id)) {
+                Ok(plan) => targets.extend(plan.targets),
+                // The job fails on it anyway
+                Err(e) => tracing::warn!("Couldn't read the plan of job {}: {e}", selection.id),
+            }
+        }
+        targets
+    }
+
+    /// Waits until no other job replaces files in the targets of `request`, then claims them
+    /// for the job `id`. Dry runs change nothing, so they go ahead straight away.
+    fn claim_targets(&self, id: u64, request: &JobRequest) {
+        if request.dry_run {
+            return;
+        }
+        let claimed = self.targets(request);
+        let overlap = |targets: &Vec<PathBuf>| {
+            targets
+                .iter()
+                .any(|a| claimed.iter().any(|b| a.starts_with(b) || b.starts_with(a)))
+        };
+        let mut replacing = self.replacing.lock().expect("Targets lock poisoned");
+        if replacing.values().any(overlap) {
+            tracing::info!("Job {id} waits for the jobs replacing files in its targets");
+        }
+        while replacing.values().any(overlap) {
+            replacing = self
+                .replaced
+                .wait(replacing)
+                .expect("Targets lock poisoned");
+        }
+        replacing.insert(id, claimed);
+    }
+
+    fn release_targets(&self, id: u64) {
+        let mut replacing = self.replacing.lock().expect("Targets lock poisoned");
+        if replacing.remove(&id).is_some() {
+            self.replaced.notify_all();
+        }
+    }
+
+    fn run(&self, id: u64) {
+        let Some(request) = self.lock().get(&id).map(|job| job.request.clone()) else {
+            return;
+        };
+        self.claim_targets(id, &request);
+        tracing::info!("Starting job {id} on {:?}", request.target_paths);
+        self.update(id, |job| {
+            job.state = JobState::Scanning;
+            job.started_at = Some(Utc::now());
+        });
+        let result = self.args(id, &request).and_then(|args| {
+            engine::run(&self.engine, &args, |event| {
+                let _ = self.updates.send((id, Update::Progress(event.clone())));
+                self.update(id, |job| job.record(event));
+            })
+        });
+        self.release_targets(id);
+        self.update(id, |job| job.finish(result));
+        if !request.callbacks.is_empty()
+            && let Some(job) = self.lock().get(&id).cloned()
+        {
+            // Slow callbacks mustn't hold up the queue
+            let callback_hosts = self.callback_hosts.clone();
+            std::thread::spawn(move || call_back(&job, &callback_hosts));
+        }
+    }
+}
+
+/// POSTs the finished `job` to its callbacks. Failures are only logged, as the job is over.
+fn call_back(job: &Job, callback_hosts: &CallbackHosts) {
+    let json = serde_json::to_string(job).expect("Jobs serialize to JSON");
+    for url in &job.request.callbacks {
+        if let Err(e) = callback_hosts.check(url) {
+            tracing::warn!("Not posting job {} to {url}: {e}", job.id);
+            continue;
+        }
+        match notify::post(url, &json) {
+            Ok(()) => tracing::info!("Posted job {} to {url}", job.id),
+            Err(e) => tracing::warn!("Couldn't post job {} to {url}: {e}", job.id),
+        }
+    }
+}
+
+/// Queues a deduplication job, responding with it to be followed at `/jobs/<id>`.
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    operation_id = "submit_job",
+    tag = "jobs",
+    responses(
+        (status = 202, body = Job),
+        (status = 403, description = "A path is outside of the roots of the user"),
+        (status = 422, description = "Invalid paths or options"),
+    )
+)]
+#[rocket::post("/jobs", format = "json", data = "<request>")]
+pub fn submit(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    callback_hosts: &State<CallbackHosts>,
+    request: Json<JobRequest>,
+) -> Result<Accepted<Json<Job>>, (Status, String)> {
+    let request = request.into_inner();
+    request
+        .validate(callback_hosts)
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    auth.check(&request)?;
+    let job = jobs.submit(request).map_err(internal_error)?;
+    Ok(Accepted(Json(job)))
+}
+
+/// Answers with what a run would replace, as `actions` with their paths, sizes and the bytes
+/// each saves, and the `projected_saved_bytes` in total. Nothing is changed, queued or kept in
+/// the history.
+#[utoipa::path(
+    post,
+    path = "/preview",
+    operation_id = "preview_job",
+    tag = "jobs",
+    responses(
+        (status = 200, body = Object, description = "The plan of a dry run"),
+        (status = 403, description = "A path is outside of the roots of the user"),
+        (status = 422, description = "Invalid paths or options"),
+    )
+)]
+#[rocket::post("/preview", format = "json", data = "<request>")]
+pub async fn preview(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    callback_hosts: &State<CallbackHosts>,
+    request: Json<JobRequest>,
+) -> Result<Json<serde_json::Value>, (Status, String)> {
+    let request = request.into_inner();
+    request
+        .validate(callback_hosts)
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    auth.check(&request)?;
+    Arc::clone(jobs)
+        .preview(request)
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// The last jobs, newest first, including those from before the service restarted.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    operation_id = "list_jobs",
+    tag = "jobs",
+    params(("limit" = Option<u64>, Query, description = "How many jobs to list, 20 by default")),
+    responses((status = 200, body = Vec<Job>))
+)]
+#[rocket::get("/jobs?<limit>")]
+pub fn list(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    limit: Option<u64>,
+) -> Result<Json<Vec<Job>>, (Status, String)> {
+    jobs.list(limit.unwrap_or(DEFAULT_LIMIT), |job| {
+        auth.allows_request(&job.request)
+    })
+    .map(Json)
+    .map_err(internal_error)
+}
+
+/// Where a job is at, with its summary once done.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    operation_id = "get_job",
+    tag = "jobs",
+    params(("id" = u64, Path)),
+    responses((status = 200, body = Job), (status = 404))
+)]
+#[rocket::get("/jobs/<id>")]
+pub fn status(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    id: u64,
+) -> Result<Option<Json<Job>>, (Status, String)> {
+    Ok(jobs
+        .get(id)
+        .map_err(internal_error)?
+        .filter(|job| auth.allows_request(&job.request))
+        .map(Json))
+}
+
+pub fn internal_error(e: io::Error) -> (Status, String) {
+    tracing::error!("{e}");
+    (Status::InternalServerError, e.to_string())
+}
+
+/// Streams the progress of a job as server-sent events: a `job` event with the whole job at
+/// first and whenever its state changes, and a `progress` event for each engine event in
+/// between. The stream ends with the job.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/events",
+    operation_id = "job_events",
+    tag = "jobs",
+    params(("id" = u64, Path)),
+    responses((status = 200, content_type = "text/event-stream"), (status = 404))
+)]
+#[rocket::get("/jobs/<id>/events")]
+pub fn events(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    id: u64,
+    mut shutdown: Shutdown,
+) -> Result<Option<EventStream![]>, (Status, String)> {
+    // Subscribed first so nothing happening after the job is read is missed
+    let mut updates = jobs.subscribe();
+    let jobs = Arc::clone(jobs);
+    let Some(job) = jobs
+        .get(id)
+        .map_err(internal_error)?
+        .filter(|job| auth.allows_request(&job.request))
+    else {
+        return Ok(None);
+    };
+    Ok(Some(EventStream! {
+        let finished = job.is_finished();
+        yield SseEvent::json(&job).event("job");
+        if finished {
+            return;
+        }
+        loop {
+            let update = rocket::tokio::select! {
+                update = updates.recv() => update,
+                _ = &mut shutdown => break,
+            };
+            match update {
+                Ok((update_id, Update::Progress(event))) if update_id == id => {
+                    yield SseEvent::json(&event).event("progress");
+                }
+                Ok((update_id, Update::Job(job))) if update_id == id => {
+                    yield SseEvent::json(&job).event("job");
+                    if job.is_finished() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                // Catch up with the job as it is now
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    let Ok(Some(job)) = jobs.get(id) else { break };
+                    yield SseEvent::json(&job).event("job");
+                    if job.is_finished() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_request() {
+        let hosts = CallbackHosts::default();
+        let request: JobRequest = serde_json::from_str(
+            r#"{
+                "source_paths": ["/data/torrents"],
+                "target_paths": ["/data/tv", "/data/movies"],
+                "dry_run": true,
+                "link_mode": ["hardlink", "symlink"],
+                "exclude": ["*.nfo", "-sample*"],
+                "older_than": "7d"
+            }"#,
+        )
+        .unwrap();
+        assert!(request.validate(&hosts).is_ok());
+        let args: Vec<_> = request
+            .args()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args[3..],
+            [
+                "--source-paths",
+                "/data/torrents",
+                "--target-paths",
+                "/data/tv",
+                "--target-paths",
+                "/data/movies",
+                "--dry-run",
+                "--link-mode",
+                "hardlink,symlink",
+                "--exclude=*.nfo",
+                "--exclude=-sample*",
+                "--older-than=7d"
+            ]
+        );
+
+        let relative = JobRequest {
+            target_paths: vec!["tv".into()],
+            ..request.clone()
+        };
+        assert!(relative.validate(&hosts).is_err());
+        let callback = JobRequest {
+            callbacks: vec!["ftp://example.com/hook".to_owned()],
+            ..request.clone()
+        };
+        assert!(callback.validate(&hosts).is_err());
+        // Plans may belong to other users
+        let plan = JobRequest {
+            dry_run: false,
+            plan: Some(PlanSelection {
+                id: 1,
+                entries: None,
+            }),
+            ..request
+        };
+        assert!(plan.validate(&hosts).is_err());
+        assert!(
+            serde_json::from_str::<JobRequest>(r#"{"source_paths": [], "paths": []}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_callback_hosts() {
+        let hosts = CallbackHosts(vec!["ntfy.sh".to_owned(), "jellyfin.lan:8096".to_owned()]);
+        for url in [
+            "https://ntfy.sh/atorrlinker",
+            "http://NTFY.sh:8080/atorrlinker",
+            "http://jellyfin.lan:8096/hook",
+        ] {
+            assert!(hosts.check(url).is_ok(), "{url}");
+        }
+        for url in [
+            "http://jellyfin.lan/hook",
+            "https://jellyfin.lan:8097/hook",
+            "http://localhost:8000/jobs",
+            "http://127.0.0.1/",
+            "http://[::1]:8000/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://192.168.1.1/",
+            "http://ntfy.sh.evil.example/",
+            "http://ntfy.sh@evil.example/",
+            "ftp://ntfy.sh/",
+            "ntfy.sh",
+            "not a url",
+        ] {
+            assert!(hosts.check(url).is_err(), "{url}");
+        }
+        // Nothing is allowed unless configured
+        assert!(
+            CallbackHosts::default()
+                .check("https://ntfy.sh/atorrlinker")
+                .is_err()
+        );
+
+        let request: JobRequest = serde_json::from_str(
+            r#"{
+                "source_paths": ["/data/torrents"],
+                "target_paths": ["/data/tv"],
+                "callbacks": ["https://ntfy.sh/atorrlinker", "http://localhost:8000/jobs"]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.validate(&hosts).unwrap_err(),
+            "Callbacks may only go to the callback_hosts configured, not \"localhost\""
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        use rocket::{http::Header, local::blocking::Client};
+
+        use crate::auth::{Credentials, User};
+
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = Jobs::start(
+            dir.path().join("missing-engine"),
+            History::open(&dir.path().join("history.sqlite3")).unwrap(),
+            dir.path().join("plans"),
+            NonZeroUsize::MIN,
+            CallbackHosts::default(),
+        )
+        .unwrap();
+        let request: JobRequest =
+            serde_json::from_str(r#"{"source_paths": ["/dl"], "target_paths": ["/tv"]}"#).unwrap();
+        // Running, without a worker on it
+        jobs.lock().insert(
+            1,
+            Job {
+                id: 1,
+                state: JobState::Hashing,
+                request,
+                progress: Progress::default(),
+                submitted_at: Utc::now(),
+                started_at: Some(Utc::now()),
+                finished_at: None,
+                summary: None,
+                error: None,
+            },
+        );
+        let credentials = Credentials::new(
+            vec!["s3cret".to_owned()],
+            BTreeMap::from([(
+                "bob".to_owned(),
+                User::Account {
+                    password: Some("hunter2".to_owned()),
+                    tokens: vec!["b0b".to_owned()],
+                    roots: Some(vec!["/srv/bob".into()]),
+                },
+            )]),
+        )
+        .unwrap();
+        let client = Client::untracked(
+            rocket::build()
+                .manage(credentials)
+                .manage(crate::limits::RateLimiter::new(0))
+                .manage(Arc::clone(&jobs))
+                .mount("/", rocket::routes![events]),
+        )
+        .unwrap();
+        let follow = |token: &str| {
+            client
+                .get("/jobs/1/events")
+                .header(Header::new("Authorization", format!("Bearer {token}")))
+                .dispatch()
+        };
+        let events = |body: String| -> Vec<String> {
+            body.lines()
+                .filter_map(|line| line.strip_prefix("event:"))
+                .map(str::to_owned)
+                .collect()
+        };
+
+        let response = follow("s3cret");
+        assert_eq!(response.status(), Status::Ok);
+        // Another job's progress is left out
+        jobs.updates
+            .send((2, Update::Progress(Event::Matching)))
+            .unwrap();
+        jobs.updates
+            .send((1, Update::Progress(Event::Matching)))
+            .unwrap();
+        jobs.update(1, |job| job.finish(Ok(serde_json::json!({}))));
+        let body = response.into_string().unwrap();
+        assert_eq!(events(body.clone()), ["job", "progress", "job"]);
+        assert!(body.contains(r#""event":"matching""#));
+        assert!(body.contains(r#""state":"done""#));
+
+        // A finished job is sent once, ending the stream
+        assert_eq!(events(follow("s3cret").into_string().unwrap()), ["job"]);
+        assert_eq!(follow("b0b").status(), Status::NotFound);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_preview() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        use rocket::{
+            http::{ContentType, Header},
+            local::blocking::Client,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        // Reports the arguments it was run with
+        let engine = dir.path().join("engine");
+        std::fs::write(&engine, "#!/bin/sh\nprintf '{\"args\": \"%s\"}' \"$*\"\n").unwrap();
+        std::fs::set_permissions(&engine, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let jobs = Jobs::start(
+            engine,
+            History::open(&dir.path().join("history.sqlite3")).unwrap(),
+            dir.path().join("plans"),
+            NonZeroUsize::MIN,
+            CallbackHosts::default(),
+        )
+        .unwrap();
+        let credentials = crate::auth::Credentials::new(
+            vec!["s3cret".to_owned()],
+            BTreeMap::from([(
+                "bob".to_owned(),
+                crate::auth::User::Account {
+                    password: None,
+                    tokens: vec!["b0b".to_owned()],
+                    roots: Some(vec!["/srv/bob".into()]),
+                },
+            )]),
+        )
+        .unwrap();
+        let client = Client::untracked(
+            rocket::build()
+                .manage(credentials)
+                .manage(crate::limits::RateLimiter::new(0))
+                .manage(CallbackHosts::default())
+                .manage(Arc::clone(&jobs))
+                .mount("/", rocket::routes![preview]),
+        )
+        .unwrap();
+        let preview = |token: &str, body: &str| {
+            client
+                .post("/preview")
+                .header(ContentType::JSON)
+                .header(Header::new("Authorization", format!("Bearer {token}")))
+                .body(body)
+                .dispatch()
+        };
+
+        let response = preview(
+            "s3cret",
+            r#"{"source_paths": ["/dl"], "target_paths": ["/tv"], "dry_run": false}"#,
+        );
+        assert_eq!(response.status(), Status::Ok);
+        let report: serde_json::Value = response.into_json().unwrap();
+        let args = report["args"].as_str().unwrap();
+        assert!(args.contains("--source-paths /dl --target-paths /tv --dry-run"));
+        // Nothing is queued or kept
+        assert!(jobs.list(10, |_| true).unwrap().is_empty());
+        assert!(
+            std::fs::read_dir(dir.path().join("plans"))
+                .unwrap()
+                .next()
+                .is_none()
+        );
+
+        let relative = r#"{"source_paths": ["dl"], "target_paths": ["/tv"]}"#;
+        assert_eq!(
+            preview("s3cret", relative).status(),
+            Status::UnprocessableEntity
+        );
+        let elsewhere = r#"{"source_paths": ["/dl"], "target_paths": ["/srv/bob/tv"]}"#;
+        assert_eq!(preview("b0b", elsewhere).status(), Status::Forbidden);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rocket::{Request, http::Header};
+
+/// How many clients are tracked before those that are back to a full allowance are forgotten.
+const CLIENTS_TRACKED: usize = 1024;
+
+/// Limits how many requests each client makes, by IP address. Each may make `per_minute` in a
+/// burst, then one every `60 / per_minute` seconds.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_minute: u32,
+    /// Requests each client has left, and when that was worked out
+    clients: Mutex<HashMap<IpAddr, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    /// No limit with `per_minute` as 0.
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            clients: Mutex::default(),
+        }
+    }
+
+    /// Whether `client` may make another request now, counting it if so.
+    pub fn allow(&self, client: IpAddr) -> bool {
+        self.allow_at(client, Instant::now())
+    }
+
+    fn allow_at(&self, client: IpAddr, now: Instant) -> bool {
+        if self.per_minute == 0 {
+            return true;
+        }
+        let burst = f64::from(self.per_minute);
+        let refill = |(left, since): (f64, Instant)| {
+            let earned = now.saturating_duration_since(since).as_secs_f64() * burst / 60.0;
+            (burst.min(left + earned), now)
+        };
+        let mut clients = self.clients.lock().expect("Rate limiter lock poisoned");
+        if clients.len() >= CLIENTS_TRACKED {
+            clients.retain(|_, allowance| refill(*allowance).0 < burst);
+        }
+        let allowance = clients.entry(client).or_insert((burst, now));
+        let (left, since) = refill(*allowance);
+        if left < 1.0 {
+            return false;
+        }
+        *allowance = (left - 1.0, since);
+        true
+    }
+
+    /// How long until a client that ran out may make a request again.
+    pub fn retry_after(&self) -> Duration {
+        Duration::from_secs(60)
+            .checked_div(self.per_minute)
+            .unwrap_or_default()
+    }
+}
+
+/// Tells a client that made too many requests when to try again.
+#[derive(rocket::Responder)]
+#[response(status = 429)]
+pub struct TooManyRequests {
+    message: &'static str,
+    retry_after: Header<'static>,
+}
+
+#[rocket::catch(429)]
+pub fn too_many_requests(request: &Request) -> TooManyRequests {
+    let retry_after = request
+        .rocket()
+        .state::<RateLimiter>()
+        .map_or(Duration::ZERO, RateLimiter::retry_after);
+    TooManyRequests {
+        message: "Too many requests",
+        retry_after: Header::new("Retry-After", retry_after.as_secs().max(1).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(3);
+        let (alice, bob) = ([10, 0, 0, 1].into(), [10, 0, 0, 2].into());
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.allow_at(alice, start));
+        }
+        assert!(!limiter.allow_at(alice, start));
+        assert!(limiter.allow_at(bob, start));
+        // One more every 20 seconds
+        assert!(!limiter.allow_at(alice, start + Duration::from_secs(19)));
+        assert!(limiter.allow_at(alice, start + Duration::from_secs(20)));
+        assert!(!limiter.allow_at(alice, start + Duration::from_secs(21)));
+        assert_eq!(limiter.retry_after(), Duration::from_secs(20));
+
+        let unlimited = RateLimiter::new(0);
+        assert!((0..100).all(|_| unlimited.allow_at(alice, start)));
+    }
+}
@@ -0,0 +1,98 @@
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{browse, jobs, plans, profiles, qbittorrent, scheduler};
+
+/// The OpenAPI document of the service, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "atorrlinker"),
+    paths(
+        jobs::submit,
+        jobs::preview,
+        jobs::list,
+        jobs::status,
+        jobs::events,
+        plans::get,
+        plans::apply,
+        profiles::list,
+        profiles::get,
+        profiles::put,
+        profiles::delete,
+        profiles::launch,
+        qbittorrent::completed,
+        scheduler::list,
+        scheduler::get,
+        scheduler::put,
+        scheduler::delete,
+        browse::browse,
+    ),
+    modifiers(&Credentials),
+    security(("basic" = []), ("bearer" = [])),
+    tags(
+        (name = "jobs", description = "Deduplication runs"),
+        (name = "plans", description = "Reviewing dry runs and applying them"),
+        (name = "profiles", description = "Saved jobs, and when they run"),
+        (name = "files", description = "Picking paths"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The ways of authenticating, as `Authorized` takes them.
+struct Credentials;
+
+impl Modify for Credentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Swagger UI at `/docs`, exploring the document served at `/openapi.json`. Neither needs
+/// credentials, trying out the endpoints does.
+pub fn routes() -> Vec<rocket::Route> {
+    SwaggerUi::new("/docs/<_..>")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi() {
+        let openapi = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = openapi["paths"].as_object().unwrap();
+        assert!(paths["/jobs"]["post"].is_object());
+        assert!(paths["/plans/{id}/apply"]["post"].is_object());
+        assert!(paths["/profiles/{name}"]["put"].is_object());
+        // Clients are generated with a method for each operation
+        let mut operations: Vec<_> = paths
+            .values()
+            .flat_map(|path| path.as_object().unwrap().values())
+            .map(|operation| operation["operationId"].as_str().unwrap())
+            .collect();
+        operations.sort_unstable();
+        let count = operations.len();
+        operations.dedup();
+        assert_eq!(operations.len(), count);
+        // Every schema referred to is there
+        let schemas = openapi["components"]["schemas"].as_object().unwrap();
+        let document = openapi.to_string();
+        for reference in document.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "{name}");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::{io, path::PathBuf, sync::Arc};
+
+use atorrlinker::actions::saved_plan::SavedPlan;
+use rocket::{State, http::Status, response::status::Accepted, serde::json::Json};
+
+use crate::{
+    auth::Authorized,
+    jobs::{Job, JobRequest, Jobs, PlanSelection, internal_error},
+};
+
+/// A plan listed for review, with its entries numbered to pick which to apply.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Plan {
+    /// The ID of the job that saved it
+    pub id: u64,
+    #[schema(value_type = Vec<String>)]
+    pub sources: Vec<PathBuf>,
+    #[schema(value_type = Vec<String>)]
+    pub targets: Vec<PathBuf>,
+    pub entries: Vec<Entry>,
+}
+
+/// A target file to replace by a source with the same content.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Entry {
+    pub id: usize,
+    #[schema(value_type = String)]
+    pub target: PathBuf,
+    #[schema(value_type = String)]
+    pub source: PathBuf,
+    pub size: u64,
+    pub hash: String,
+}
+
+/// The plan of the job `id`, if there is one.
+fn load(jobs: &Jobs, id: u64) -> Result<Option<SavedPlan>, (Status, String)> {
+    match SavedPlan::load(&jobs.plan_path(id)) {
+        Ok(plan) => Ok(Some(plan)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// The plan saved by a dry run, or the part of one applied by a job.
+#[utoipa::path(
+    get,
+    path = "/plans/{id}",
+    operation_id = "get_plan",
+    tag = "plans",
+    params(("id" = u64, Path, description = "The ID of the job")),
+    responses((status = 200, body = Plan), (status = 404))
+)]
+#[rocket::get("/plans/<id>")]
+pub fn get(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    id: u64,
+) -> Result<Option<Json<Plan>>, (Status, String)> {
+    let job = jobs.get(id).map_err(internal_error)?;
+    if !job.is_some_and(|job| auth.allows_request(&job.request)) {
+        return Ok(None);
+    }
+    let Some(plan) = load(jobs, id)? else {
+        return Ok(None);
+    };
+    let entries = plan
+        .matches()
+        .enumerate()
+        .map(|(entry, matching)| Entry {
+            id: entry,
+            target: matching.dest_path().to_path_buf(),
+            source: matching.src_path().to_path_buf(),
+            size: matching.size(),
+            hash: matching.hash().to_owned(),
+        })
+        .collect();
+    Ok(Some(Json(Plan {
+        id,
+        sources: plan.sources,
+        targets: plan.targets,
+        entries,
+    })))
+}
+
+/// What `POST /plans/<id>/apply` takes.
+#[derive(Debug, Default, serde::Deserialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Approval {
+    /// The IDs of the entries to apply, all of them unless given
+    pub entries: Option<Vec<usize>>,
+}
+
+/// Queues a job replacing the files of the approved entries of a plan. Entries whose files
+/// changed since the plan was made are skipped.
+#[utoipa::path(
+    post,
+    path = "/plans/{id}/apply",
+    operation_id = "apply_plan",
+    tag = "plans",
+    params(("id" = u64, Path, description = "The ID of the dry run")),
+    request_body(content = Option<Approval>),
+    responses(
+        (status = 202, body = Job),
+        (status = 404),
+        (status = 422, description = "An entry isn't in the plan"),
+    )
+)]
+#[rocket::post("/plans/<id>/apply", format = "json", data = "<approval>")]
+pub fn apply(
+    auth: Authorized,
+    jobs: &State<Arc<Jobs>>,
+    id: u64,
+    approval: Option<Json<Approval>>,
+) -> Result<Option<Accepted<Json<Job>>>, (Status, String)> {
+    let job = jobs
+        .get(id)
+        .map_err(internal_error)?
+        .filter(|job| auth.allows_request(&job.request));
+    let (Some(job), Some(plan)) = (job, load(jobs, id)?) else {
+        return Ok(None);
+    };
+    let entries = approval.and_then(|approval| approval.into_inner().entries);
+    if let Some(entries) = &entries {
+        plan.select(entries)
+            .map_err(|e| (Status::UnprocessableEntity, e.to_string()))?;
+    }
+    let request = JobRequest {
+        dry_run: false,
+        plan: Some(PlanSelection { id, entries }),
+        ..job.request
+    };
+    let job = jobs.submit(request).map_err(internal_error)?;
+    Ok(Some(Accepted(Json(job))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::Path};
+
+    use atorrlinker::matching::{MatchReason, MatchingFile};
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        history::History,
+        jobs::{JobState, Progress},
+    };
+
+    #[test]
+    fn test_plans() {
+        let dir = tempfile::tempdir().unwrap();
+        let request: JobRequest = serde_json::from_str(
+            r#"{"source_paths": ["/srv/alice/dl"], "target_paths": ["/srv/alice/tv"], "dry_run": true}"#,
+        )
+        .unwrap();
+        let history = History::open(&dir.path().join("history.sqlite3")).unwrap();
+        history
+            .save(&Job {
+                id: 1,
+                state: JobState::Done,
+                request,
+                progress: Progress::default(),
+                submitted_at: Utc::now(),
+                started_at: Some(Utc::now()),
+                finished_at: Some(Utc::now()),
+                summary: None,
+                error: None,
+            })
+            .unwrap();
+        let jobs = Jobs::start(
+            dir.path().join("missing-engine"),
+            history,
+            dir.path().join("plans"),
+            NonZeroUsize::MIN,
+            Default::default(),
+        )
+        .unwrap();
+        let matches: Vec<_> = ["e01.mkv", "e02.mkv", "e03.mkv"]
+            .map(|name| {
+                MatchingFile::new(
+                    Path::new("/srv/alice/dl").join(name),
+                    Path::new("/srv/alice/tv").join(name),
+                    7,
+                    "ABC".to_owned(),
+                    MatchReason::SourceScan,
+                )
+            })
+            .into();
+        SavedPlan::new(
+            &["/srv/alice/dl".into()],
+            &["/srv/alice/tv".into()],
+            &matches,
+        )
+        .save(&jobs.plan_path(1))
+        .unwrap();
+        let alice = Authorized {
+            user: Some("alice".to_owned()),
+            roots: Some(vec!["/srv/alice".into()]),
+        };
+        let bob = Authorized {
+            user: Some("bob".to_owned()),
+            roots: Some(vec!["/srv/bob".into()]),
+        };
+
+        let plan = get(alice.clone(), (&jobs).into(), 1).unwrap().unwrap();
+        let entries: Vec<_> = plan
+            .entries
+            .iter()
+            .map(|entry| (entry.id, entry.target.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0, "/srv/alice/tv/e01.mkv".into()),
+                (1, "/srv/alice/tv/e02.mkv".into()),
+                (2, "/srv/alice/tv/e03.mkv".into()),
+            ]
+        );
+        // The plan of a job elsewhere is as good as missing
+        assert!(get(bob.clone(), (&jobs).into(), 1).unwrap().is_none());
+        assert!(get(alice.clone(), (&jobs).into(), 2).unwrap().is_none());
+
+        let approve = |auth: &Authorized, entries| {
+            apply(
+                auth.clone(),
+                (&jobs).into(),
+                1,
+                Some(Json(Approval { entries })),
+            )
+        };
+        assert!(approve(&bob, None).unwrap().is_none());
+        let (status, message) = approve(&alice, Some(vec![0, 3])).unwrap_err();
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(message, "The plan has no entry 3");
+        let Accepted(Json(job)) = approve(&alice, Some(vec![2, 0])).unwrap().unwrap();
+        assert!(!job.request.dry_run);
+        let selection = job.request.plan.unwrap();
+        assert_eq!((selection.id, selection.entries), (1, Some(vec![2, 0])));
+    }
+}
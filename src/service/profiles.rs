@@ -0,0 +1,364 @@
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use rocket::{
+    State,
+    http::Status,
+    response::status::{Accepted, NoContent},
+    serde::json::Json,
+};
+use rusqlite::{Connection, OptionalExtension as _, Row, params};
+
+use crate::{
+    auth::Authorized,
+    jobs::{CallbackHosts, Job, JobRequest, Jobs, internal_error},
+    scheduler::Scheduler,
+};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS profiles (
+    name TEXT PRIMARY KEY,
+    request TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+)";
+
+/// A named set of paths and options to launch jobs with.
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct Profile {
+    pub name: String,
+    pub request: JobRequest,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The profiles, stored next to the job history.
+pub struct Profiles {
+    db: Mutex<Connection>,
+}
+
+impl Profiles {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path).map_err(io::Error::other)?;
+        db.execute(SCHEMA, []).map_err(io::Error::other)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    fn db(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.db.lock().expect("Profiles lock poisoned")
+    }
+
+    pub fn list(&self) -> io::Result<Vec<Profile>> {
+        let db = self.db();
+        let mut statement = db
+            .prepare("SELECT name, request, updated_at FROM profiles ORDER BY name")
+            .map_err(io::Error::other)?;
+        statement
+            .query_map([], from_row)
+            .and_then(Iterator::collect)
+            .map_err(io::Error::other)
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<Option<Profile>> {
+        self.db()
+            .query_row(
+                "SELECT name, request, updated_at FROM profiles WHERE name = ?1",
+                [name],
+                from_row,
+            )
+            .optional()
+            .map_err(io::Error::other)
+    }
+
+    /// Creates the profile `name`, or replaces it.
+    pub fn put(&self, name: &str, request: JobRequest) -> io::Result<Profile> {
+        let profile = Profile {
+            name: name.to_owned(),
+            request,
+            updated_at: Utc::now(),
+        };
+        self.db()
+            .execute(
+                "INSERT OR REPLACE INTO profiles (name, request, updated_at) VALUES (?1, ?2, ?3)",
+                params![
+                    profile.name,
+                    serde_json::to_string(&profile.request)?,
+                    profile.updated_at.to_rfc3339(),
+                ],
+            )
+            .map_err(io::Error::other)?;
+        Ok(profile)
+    }
+
+    /// Whether there was a profile `name` to delete.
+    pub fn delete(&self, name: &str) -> io::Result<bool> {
+        self.db()
+            .execute("DELETE FROM profiles WHERE name = ?1", [name])
+            .map(|deleted| deleted > 0)
+            .map_err(io::Error::other)
+    }
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<Profile> {
+    let invalid = |index, e: Box<dyn std::error::Error + Send + Sync>| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e)
+    };
+    let request: String = row.get(1)?;
+    let updated_at: String = row.get(2)?;
+    Ok(Profile {
+        name: row.get(0)?,
+        request: serde_json::from_str(&request).map_err(|e| invalid(1, e.into()))?,
+        updated_at: DateTime::parse_from_rfc3339(&updated_at)
+            .map_err(|e| invalid(2, e.into()))?
+            .to_utc(),
+    })
+}
+
+/// Profile names go in URLs, so they're kept to letters, digits, `-`, `_` and `.`.
+fn check_name(name: &str) -> Result<(), (Status, String)> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err((
+            Status::UnprocessableEntity,
+            format!("Invalid profile name {name:?}: use up to 64 letters, digits, -, _ or ."),
+        ));
+    }
+    Ok(())
+}
+
+/// The profile `name`, unless `auth` doesn't allow all of its paths.
+pub fn allowed(
+    profiles: &Profiles,
+    auth: &Authorized,
+    name: &str,
+) -> Result<Option<Profile>, (Status, String)> {
+    Ok(profiles
+        .get(name)
+        .map_err(internal_error)?
+        .filter(|profile| auth.allows_request(&profile.request)))
+}
+
+/// Options for launching a job from a profile.
+#[derive(Debug, Default, serde::Deserialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Launch {
+    /// Overrides the `dry_run` of the profile
+    pub dry_run: Option<bool>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/profiles",
+    operation_id = "list_profiles",
+    tag = "profiles",
+    responses((status = 200, body = Vec<Profile>))
+)]
+#[rocket::get("/profiles")]
+pub fn list(
+    auth: Authorized,
+    profiles: &State<Arc<Profiles>>,
+) -> Result<Json<Vec<Profile>>, (Status, String)> {
+    let mut listed = profiles.list().map_err(internal_error)?;
+    listed.retain(|profile| auth.allows_request(&profile.request));
+    Ok(Json(listed))
+}
+
+#[utoipa::path(
+    get,
+    path = "/profiles/{name}",
+    operation_id = "get_profile",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    responses((status = 200, body = Profile), (status = 404))
+)]
+#[rocket::get("/profiles/<name>")]
+pub fn get(
+    auth: Authorized,
+    profiles: &State<Arc<Profiles>>,
+    name: &str,
+) -> Result<Option<Json<Profile>>, (Status, String)> {
+    Ok(allowed(profiles, &auth, name)?.map(Json))
+}
+
+/// Creates or replaces a profile, taking the same paths and options as `POST /jobs`. Users
+/// restricted to some directories can only replace profiles within them.
+#[utoipa::path(
+    put,
+    path = "/profiles/{name}",
+    operation_id = "put_profile",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    request_body = JobRequest,
+    responses(
+        (status = 200, body = Profile),
+        (status = 403, description = "A path is outside of the roots of the user"),
+        (status = 422, description = "Invalid name, paths or options"),
+    )
+)]
+#[rocket::put("/profiles/<name>", format = "json", data = "<request>")]
+pub fn put(
+    auth: Authorized,
+    profiles: &State<Arc<Profiles>>,
+    callback_hosts: &State<CallbackHosts>,
+    name: &str,
+    request: Json<JobRequest>,
+) -> Result<Json<Profile>, (Status, String)> {
+    check_name(name)?;
+    let request = request.into_inner();
+    request
+        .validate(callback_hosts)
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    auth.check(&request)?;
+    if let Some(existing) = profiles.get(name).map_err(internal_error)? {
+        auth.check(&existing.request)?;
+    }
+    profiles
+        .put(name, request)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Deletes a profile along with its schedule.
+#[utoipa::path(
+    delete,
+    path = "/profiles/{name}",
+    operation_id = "delete_profile",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    responses((status = 204), (status = 404))
+)]
+#[rocket::delete("/profiles/<name>")]
+pub fn delete(
+    auth: Authorized,
+    profiles: &State<Arc<Profiles>>,
+    scheduler: &State<Arc<Scheduler>>,
+    name: &str,
+) -> Result<Option<NoContent>, (Status, String)> {
+    if allowed(profiles, &auth, name)?.is_none() {
+        return Ok(None);
+    }
+    scheduler.schedules().delete(name).map_err(internal_error)?;
+    let deleted = profiles.delete(name).map_err(internal_error)?;
+    Ok(deleted.then_some(NoContent))
+}
+
+/// Queues a job with the paths and options of a profile.
+#[utoipa::path(
+    post,
+    path = "/profiles/{name}/jobs",
+    operation_id = "launch_profile",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    request_body(content = Option<Launch>),
+    responses((status = 202, body = Job), (status = 404))
+)]
+#[rocket::post("/profiles/<name>/jobs", format = "json", data = "<launch>")]
+pub fn launch(
+    auth: Authorized,
+    profiles: &State<Arc<Profiles>>,
+    jobs: &State<Arc<Jobs>>,
+    callback_hosts: &State<CallbackHosts>,
+    name: &str,
+    launch: Option<Json<Launch>>,
+) -> Result<Option<Accepted<Json<Job>>>, (Status, String)> {
+    let Some(profile) = allowed(profiles, &auth, name)? else {
+        return Ok(None);
+    };
+    let mut request = profile.request;
+    if let Some(dry_run) = launch.and_then(|launch| launch.dry_run) {
+        request.dry_run = dry_run;
+    }
+    request
+        .validate(callback_hosts)
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    let job = jobs.submit(request).map_err(internal_error)?;
+    Ok(Some(Accepted(Json(job))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let profiles = Profiles::open(&dir.path().join("service.sqlite3")).unwrap();
+        let request: JobRequest = serde_json::from_str(
+            r#"{"source_paths": ["/dl"], "target_paths": ["/tv"], "link_mode": ["hardlink"]}"#,
+        )
+        .unwrap();
+        profiles.put("tv", request.clone()).unwrap();
+        profiles.put("movies", request).unwrap();
+
+        let names: Vec<_> = profiles
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect();
+        assert_eq!(names, ["movies", "tv"]);
+        let tv = profiles.get("tv").unwrap().unwrap();
+        assert_eq!(tv.request.target_paths, [Path::new("/tv")]);
+
+        assert!(profiles.delete("tv").unwrap());
+        assert!(!profiles.delete("tv").unwrap());
+        assert!(profiles.get("tv").unwrap().is_none());
+
+        assert!(check_name("tv-4k").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("a b").is_err());
+    }
+
+    #[test]
+    fn test_put() {
+        let dir = tempfile::tempdir().unwrap();
+        let profiles = Arc::new(Profiles::open(&dir.path().join("service.sqlite3")).unwrap());
+        let user = |name: &str| Authorized {
+            user: Some(name.to_owned()),
+            roots: Some(vec![Path::new("/srv").join(name)]),
+        };
+        let request = |user: &str| -> Json<JobRequest> {
+            Json(
+                serde_json::from_value(serde_json::json!({
+                    "source_paths": [format!("/srv/{user}/dl")],
+                    "target_paths": [format!("/srv/{user}/tv")],
+                }))
+                .unwrap(),
+            )
+        };
+        let targets = |name| profiles.get(name).unwrap().unwrap().request.target_paths;
+        let callback_hosts = CallbackHosts::default();
+        let put_as = |auth, name, request| {
+            put(
+                auth,
+                (&profiles).into(),
+                (&callback_hosts).into(),
+                name,
+                request,
+            )
+        };
+
+        put_as(user("alice"), "tv", request("alice")).unwrap();
+        // Nobody else's paths, and nobody else's profile either, even with their own paths
+        let (status, _) = put_as(user("bob"), "tv", request("alice")).unwrap_err();
+        assert_eq!(status, Status::Forbidden);
+        let (status, _) = put_as(user("bob"), "tv", request("bob")).unwrap_err();
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(targets("tv"), [Path::new("/srv/alice/tv")]);
+        assert!(allowed(&profiles, &user("bob"), "tv").unwrap().is_none());
+
+        put_as(user("bob"), "bob-tv", request("bob")).unwrap();
+        put_as(user("alice"), "tv", request("alice")).unwrap();
+        // A token of the service may replace anyone's
+        put_as(Authorized::default(), "tv", request("bob")).unwrap();
+        assert_eq!(targets("tv"), [Path::new("/srv/bob/tv")]);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use rocket::{
+    State,
+    form::{Form, FromForm},
+    http::Status,
+    response::status::Accepted,
+    serde::json::Json,
+};
+
+use crate::{
+    auth::Authorized,
+    jobs::{CallbackHosts, Job, JobRequest, Jobs, internal_error},
+    profiles::Profiles,
+};
+
+/// Which profile the torrents qBittorrent completes are linked into the library with, e.g.
+///
+/// ```toml
+/// [default.qbittorrent]
+/// profile = "library"
+/// categories = { tv = "tv", movies = "movies" }
+/// ```
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QBittorrent {
+    /// For the torrents of categories not listed, and those without one
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Profiles by category
+    #[serde(default)]
+    pub categories: BTreeMap<String, String>,
+}
+
+impl QBittorrent {
+    /// The profile for torrents of `category`, if any.
+    pub fn profile(&self, category: Option<&str>) -> Option<&str> {
+        category
+            .filter(|category| !category.is_empty())
+            .and_then(|category| self.categories.get(category))
+            .or(self.profile.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// What qBittorrent's "Run external program on torrent finished" passes along, as a form,
+/// e.g. `curl -u <user>:<token> -d name=%N -d save_path=%D -d category=%L -d content_path=%F
+/// http://localhost:8000/hooks/qbittorrent`.
+#[derive(Debug, FromForm, utoipa::ToSchema)]
+pub struct Completion {
+    /// `%N`
+    pub name: String,
+    /// `%D`
+    pub save_path: String,
+    /// `%L`, empty without one
+    pub category: Option<String>,
+    /// `%F`, the file of a single file torrent or the directory of the others. The name in
+    /// the save path unless given
+    pub content_path: Option<String>,
+}
+
+impl Completion {
+    /// The files of the torrent, refusing paths that aren't absolute or lead out of the save
+    /// path.
+    pub fn payload(&self) -> Result<PathBuf, String> {
+        let save_path = Path::new(&self.save_path);
+        if !save_path.is_absolute() {
+            return Err(format!("{save_path:?} isn't an absolute path"));
+        }
+        let payload = match &self.content_path {
+            Some(content_path) if !content_path.is_empty() => PathBuf::from(content_path),
+            _ => save_path.join(&self.name),
+        };
+        if payload.components().any(|c| c == Component::ParentDir)
+            || !payload.starts_with(save_path)
+            || payload == save_path
+        {
+            return Err(format!("{payload:?} isn't in the save path {save_path:?}"));
+        }
+        Ok(payload)
+    }
+}
+
+/// Queues a job replacing the duplicates of a torrent qBittorrent finished, with the paths
+/// and options of the profile configured for its category but only its files as sources.
+#[utoipa::path(
+    post,
+    path = "/hooks/qbittorrent",
+    operation_id = "qbittorrent_completed",
+    tag = "jobs",
+    request_body(content = Completion, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 202, body = Job),
+        (status = 403, description = "A path is outside of the roots of the user"),
+        (status = 422, description = "Invalid paths, or no profile for the category"),
+    )
+)]
+#[rocket::post("/hooks/qbittorrent", format = "form", data = "<completion>")]
+pub fn completed(
+    auth: Authorized,
+    qbittorrent: &State<QBittorrent>,
+    profiles: &State<Arc<Profiles>>,
+    jobs: &State<Arc<Jobs>>,
+    callback_hosts: &State<CallbackHosts>,
+    completion: Form<Completion>,
+) -> Result<Accepted<Json<Job>>, (Status, String)> {
+    let payload = completion
+        .payload()
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    let category = completion.category.as_deref();
+    let Some(name) = qbittorrent.profile(category) else {
+        let torrents = match category.filter(|category| !category.is_empty()) {
+            Some(category) => format!("the category {category:?}"),
+            None => "torrents without a category".to_owned(),
+        };
+        return Err((
+            Status::UnprocessableEntity,
+            format!("No profile is configured for {torrents}"),
+        ));
+    };
+    let Some(profile) = profiles.get(name).map_err(internal_error)? else {
+        tracing::error!("The profile {name} configured for qBittorrent doesn't exist");
+        return Err((
+            Status::InternalServerError,
+            format!("The profile {name} configured for qBittorrent doesn't exist"),
+        ));
+    };
+    let request = JobRequest {
+        source_paths: vec![payload],
+        ..profile.request
+    };
+    request
+        .validate(callback_hosts)
+        .map_err(|e| (Status::UnprocessableEntity, e))?;
+    auth.check(&request)?;
+    let job = jobs.submit(request).map_err(internal_error)?;
+    tracing::info!(
+        "Queued job {} for the torrent {} with the profile {name}",
+        job.id,
+        completion.name
+    );
+    Ok(Accepted(Json(job)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion() {
+        let completion = |name: &str, content_path: Option<&str>| Completion {
+            name: name.to_owned(),
+            save_path: "/downloads".to_owned(),
+            category: None,
+            content_path: content_path.map(str::to_owned),
+        };
+        assert_eq!(
+            completion("Show S01", None).payload().unwrap(),
+            Path::new("/downloads/Show S01")
+        );
+        assert_eq!(
+            completion("Show S01", Some("/downloads/Show.S01")).payload(),
+            Ok(PathBuf::from("/downloads/Show.S01"))
+        );
+        assert!(completion("..", None).payload().is_err());
+        assert!(completion("", None).payload().is_err());
+        assert!(completion("Show", Some("/etc")).payload().is_err());
+
+        let qbittorrent = QBittorrent {
+            profile: Some("library".to_owned()),
+            categories: [("tv".to_owned(), "tv".to_owned())].into(),
+        };
+        assert_eq!(qbittorrent.profile(Some("tv")), Some("tv"));
+        assert_eq!(qbittorrent.profile(Some("music")), Some("library"));
+        assert_eq!(qbittorrent.profile(Some("")), Some("library"));
+        assert_eq!(QBittorrent::default().profile(Some("tv")), None);
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, Utc};
+use rocket::{State, http::Status, response::status::NoContent, serde::json::Json};
+use rusqlite::{Connection, OptionalExtension as _, Row, params};
+
+use crate::{
+    auth::Authorized,
+    jobs::{Jobs, internal_error},
+    profiles::{self, Profiles},
+};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS schedules (
+    profile TEXT PRIMARY KEY,
+    cron TEXT NOT NULL,
+    last_job INTEGER,
+    last_run_at TEXT,
+    skipped INTEGER NOT NULL DEFAULT 0
+)";
+
+const COLUMNS: &str = "profile, cron, last_job, last_run_at, skipped";
+
+/// How long the scheduler sleeps when nothing is scheduled, unless a schedule is set.
+const IDLE: Duration = Duration::from_secs(3600);
+
+/// Parses a cron expression. The usual five fields (minute, hour, day of month, month, day of
+/// week) run on the minute, six or seven give the seconds and year too.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {expression}"),
+        _ => expression.to_owned(),
+    };
+    cron::Schedule::from_str(&expression).map_err(|e| format!("Invalid cron expression: {e}"))
+}
+
+/// When a profile is run, and how its last runs went.
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct Schedule {
+    pub profile: String,
+    /// A cron expression, in the local time of the service
+    pub cron: String,
+    pub next_run: Option<DateTime<Local>>,
+    /// The job of the last run, to be followed at `/jobs/<id>`
+    pub last_job: Option<u64>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// How many runs were skipped because the previous one was still going
+    pub skipped: u64,
+}
+
+/// The schedules of the profiles, stored next to them.
+pub struct Schedules {
+    db: Mutex<Connection>,
+}
+
+impl Schedules {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path).map_err(io::Error::other)?;
+        db.execute(SCHEMA, []).map_err(io::Error::other)?;
+        Ok(Self { db: Mutex::new(db) })
+    }
+
+    fn db(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.db.lock().expect("Schedules lock poisoned")
+    }
+
+    pub fn list(&self) -> io::Result<Vec<Schedule>> {
+        let db = self.db();
+        let mut statement = db
+            .prepare(&format!("SELECT {COLUMNS} FROM schedules ORDER BY profile"))
+            .map_err(io::Error::other)?;
+        statement
+            .query_map([], from_row)
+            .and_then(Iterator::collect)
+            .map_err(io::Error::other)
+    }
+
+    pub fn get(&self, profile: &str) -> io::Result<Option<Schedule>> {
+        self.db()
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM schedules WHERE profile = ?1"),
+                [profile],
+                from_row,
+            )
+            .optional()
+            .map_err(io::Error::other)
+    }
+
+    /// Schedules `profile` to run on `cron`, keeping the record of its past runs.
+    pub fn put(&self, profile: &str, cron: &str) -> io::Result<()> {
+        self.db()
+            .execute(
+                "INSERT INTO schedules (profile, cron) VALUES (?1, ?2) \
+                 ON CONFLICT (profile) DO UPDATE SET cron = excluded.cron",
+                [profile, cron],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// Whether `profile` had a schedule to delete.
+    pub fn delete(&self, profile: &str) -> io::Result<bool> {
+        self.db()
+            .execute("DELETE FROM schedules WHERE profile = ?1", [profile])
+            .map(|deleted| deleted > 0)
+            .map_err(io::Error::other)
+    }
+
+    /// Records that a scheduled run of `profile` queued the job `id`.
+    pub fn ran(&self, profile: &str, id: u64) -> io::Result<()> {
+        self.db()
+            .execute(
+                "UPDATE schedules SET last_job = ?2, last_run_at = ?3 WHERE profile = ?1",
+                params![profile, id, Utc::now().to_rfc3339()],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// Records that a scheduled run of `profile` was skipped.
+    pub fn skipped(&self, profile: &str) -> io::Result<()> {
+        self.db()
+            .execute(
+                "UPDATE schedules SET skipped = skipped + 1 WHERE profile = ?1",
+                [profile],
+            )
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<Schedule> {
+    let invalid = |index, e: Box<dyn std::error::Error + Send + Sync>| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e)
+    };
+    let cron: String = row.get(1)?;
+    let last_run_at: Option<String> = row.get(3)?;
+    Ok(Schedule {
+        profile: row.get(0)?,
+        next_run: parse_cron(&cron)
+            .map_err(|e| invalid(1, e.into()))?
+            .after(&Local::now())
+            .next(),
+        cron,
+        last_job: row.get(2)?,
+        last_run_at: last_run_at
+            .map(|time| DateTime::parse_from_rfc3339(&time).map(|time| time.to_utc()))
+            .transpose()
+            .map_err(|e| invalid(3, e.into()))?,
+        skipped: row.get(4)?,
+    })
+}
+
+/// Runs the profiles on their schedules in the background, queuing a job for each run unless
+/// the job of the previous one is still queued or running.
+pub struct Scheduler {
+    schedules: Schedules,
+    profiles: Arc<Profiles>,
+    jobs: Arc<Jobs>,
+    /// Wakes the scheduler up to see to a changed schedule
+    wake: mpsc::Sender<()>,
+}
+
+impl Scheduler {
+    pub fn start(schedules: Schedules, profiles: Arc<Profiles>, jobs: Arc<Jobs>) -> Arc<Self> {
+        let (wake, woken) = mpsc::channel();
+        let scheduler = Arc::new(Self {
+            schedules,
+            profiles,
+            jobs,
+            wake,
+        });
+        let worker = Arc::clone(&scheduler);
+        std::thread::spawn(move || worker.run(woken));
+        scheduler
+    }
+
+    pub fn schedules(&self) -> &Schedules {
+        &self.schedules
+    }
+
+    pub fn put(&self, profile: &str, cron: &str) -> io::Result<()> {
+        self.schedules.put(profile, cron)?;
+        // The scheduler stops with the service
+        let _ = self.wake.send(());
+        Ok(())
+    }
+
+    /// Runs the schedules that came due since it last looked, then sleeps until the next one
+    /// is.
+    fn run(&self, woken: mpsc::Receiver<()>) {
+        let mut checked = Local::now();
+        loop {
+            let now = Local::now();
+            let schedules = self.schedules.list().unwrap_or_else(|e| {
+                tracing::error!("Couldn't read the schedules: {e}");
+                Vec::new()
+            });
+            for schedule in &schedules {
+                let Ok(cron) = parse_cron(&schedule.cron) else {
+                    continue;
+                };
+                if cron.after(&checked).next().is_some_and(|next| next <= now)
+                    && let Err(e) = self.launch(schedule)
+                {
+                    tracing::error!("Couldn't run the profile {}: {e}", schedule.profile);
+                }
+            }
+            checked = now;
+            let wait = schedules
+                .iter()
+                .filter_map(|schedule| schedule.next_run)
+                .min()
+                .map_or(IDLE, |next| (next - now).to_std().unwrap_or_default());
+            if let Err(mpsc::RecvTimeoutError::Disconnected) = woken.recv_timeout(wait) {
+                return;
+            }
+        }
+    }
+
+    fn launch(&self, schedule: &Schedule) -> io::Result<()> {
+        if let Some(id) = schedule.last_job
+            && self.jobs.get(id)?.is_some_and(|job| !job.is_finished())
+        {
+            tracing::warn!(
+                "Skipping the scheduled run of the profile {}, as job {id} is still going",
+                schedule.profile
+            );
+            return self.schedules.skipped(&schedule.profile);
+        }
+        let Some(profile) = self.profiles.get(&schedule.profile)? else {
+            tracing::warn!("The scheduled profile {} is gone", schedule.profile);
+            return Ok(());
+        };
+        profile
+            .request
+            .validate(self.jobs.callback_hosts())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let job = self.jobs.submit(profile.request)?;
+        tracing::info!("Queued job {} for the profile {}", job.id, profile.name);
+        self.schedules.ran(&profile.name, job.id)
+    }
+}
+
+/// What `PUT /profiles/<name>/schedule` takes.
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRequest {
+    /// e.g. `0 3 * * *` for every night at 3am
+    pub cron: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/schedules",
+    operation_id = "list_schedules",
+    tag = "profiles",
+    responses((status = 200, body = Vec<Schedule>))
+)]
+#[rocket::get("/schedules")]
+pub fn list(
+    auth: Authorized,
+    scheduler: &State<Arc<Scheduler>>,
+    profiles: &State<Arc<Profiles>>,
+) -> Result<Json<Vec<Schedule>>, (Status, String)> {
+    let mut listed = Vec::new();
+    for schedule in scheduler.schedules().list().map_err(internal_error)? {
+        if profiles::allowed(profiles, &auth, &schedule.profile)?.is_some() {
+            listed.push(schedule);
+        }
+    }
+    Ok(Json(listed))
+}
+
+#[utoipa::path(
+    get,
+    path = "/profiles/{name}/schedule",
+    operation_id = "get_schedule",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    responses((status = 200, body = Schedule), (status = 404))
+)]
+#[rocket::get("/profiles/<name>/schedule")]
+pub fn get(
+    auth: Authorized,
+    scheduler: &State<Arc<Scheduler>>,
+    profiles: &State<Arc<Profiles>>,
+    name: &str,
+) -> Result<Option<Json<Schedule>>, (Status, String)> {
+    if profiles::allowed(profiles, &auth, name)?.is_none() {
+        return Ok(None);
+    }
+    Ok(scheduler
+        .schedules()
+        .get(name)
+        .map_err(internal_error)?
+        .map(Json))
+}
+
+/// Schedules a profile to run on a cron expression, or changes when it runs.
+#[utoipa::path(
+    put,
+    path = "/profiles/{name}/schedule",
+    operation_id = "put_schedule",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    request_body = ScheduleRequest,
+    responses(
+        (status = 200, body = Schedule),
+        (status = 404),
+        (status = 422, description = "Invalid cron expression"),
+    )
+)]
+#[rocket::put("/profiles/<name>/schedule", format = "json", data = "<request>")]
+pub fn put(
+    auth: Authorized,
+    scheduler: &State<Arc<Scheduler>>,
+    profiles: &State<Arc<Profiles>>,
+    name: &str,
+    request: Json<ScheduleRequest>,
+) -> Result<Option<Json<Schedule>>, (Status, String)> {
+    parse_cron(&request.cron).map_err(|e| (Status::UnprocessableEntity, e))?;
+    if profiles::allowed(profiles, &auth, name)?.is_none() {
+        return Ok(None);
+    }
+    scheduler
+        .put(name, request.cron.trim())
+        .map_err(internal_error)?;
+    Ok(scheduler
+        .schedules()
+        .get(name)
+        .map_err(internal_error)?
+        .map(Json))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/profiles/{name}/schedule",
+    operation_id = "delete_schedule",
+    tag = "profiles",
+    params(("name" = String, Path)),
+    responses((status = 204), (status = 404))
+)]
+#[rocket::delete("/profiles/<name>/schedule")]
+pub fn delete(
+    auth: Authorized,
+    scheduler: &State<Arc<Scheduler>>,
+    profiles: &State<Arc<Profiles>>,
+    name: &str,
+) -> Result<Option<NoContent>, (Status, String)> {
+    // Schedules outside of any profile are left to those allowed everywhere
+    if auth.roots.is_some() && profiles::allowed(profiles, &auth, name)?.is_none() {
+        return Ok(None);
+    }
+    let deleted = scheduler.schedules().delete(name).map_err(internal_error)?;
+    Ok(deleted.then_some(NoContent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedules() {
+        let dir = tempfile::tempdir().unwrap();
+        let schedules = Schedules::open(&dir.path().join("service.sqlite3")).unwrap();
+        schedules.put("tv", "0 3 * * *").unwrap();
+        schedules.ran("tv", 7).unwrap();
+        schedules.skipped("tv").unwrap();
+        // Changing when it runs keeps the record of its runs
+        schedules.put("tv", "30 4 * * *").unwrap();
+
+        let tv = schedules.get("tv").unwrap().unwrap();
+        assert_eq!(tv.cron, "30 4 * * *");
+        assert_eq!(tv.last_job, Some(7));
+        assert!(tv.last_run_at.is_some());
+        assert_eq!(tv.skipped, 1);
+        let next_run = tv.next_run.unwrap();
+        assert_eq!(next_run.format("%H:%M:%S").to_string(), "04:30:00");
+        assert!(next_run > Local::now());
+
+        assert_eq!(schedules.list().unwrap().len(), 1);
+        assert!(schedules.delete("tv").unwrap());
+        assert!(schedules.get("tv").unwrap().is_none());
+
+        assert!(parse_cron("0 15 10 * * *").is_ok());
+        assert!(parse_cron("every day").is_err());
+    }
+
+    #[test]
+    fn test_launch() {
+        use std::num::NonZeroUsize;
+
+        use crate::{
+            history::History,
+            jobs::{CallbackHosts, JobRequest},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("service.sqlite3");
+        let jobs = Jobs::start(
+            dir.path().join("missing-engine"),
+            History::open(&dir.path().join("history.sqlite3")).unwrap(),
+            dir.path().join("plans"),
+            NonZeroUsize::MIN,
+            CallbackHosts(vec!["localhost".to_owned()]),
+        )
+        .unwrap();
+        let profiles = Arc::new(Profiles::open(&db).unwrap());
+        let scheduler =
+            Scheduler::start(Schedules::open(&db).unwrap(), Arc::clone(&profiles), jobs);
+        let request = |callback: &str| -> JobRequest {
+            serde_json::from_str(&format!(
+                r#"{{"source_paths": ["/dl"], "target_paths": ["/tv"], "callbacks": ["{callback}"]}}"#
+            ))
+            .unwrap()
+        };
+        scheduler.put("tv", "0 3 * * *").unwrap();
+        let tv = scheduler.schedules().get("tv").unwrap().unwrap();
+
+        // Checked like a launch through the API
+        profiles
+            .put("tv", request("http://example.com/done"))
+            .unwrap();
+        let e = scheduler.launch(&tv).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            scheduler.schedules().get("tv").unwrap().unwrap().last_job,
+            None
+        );
+
+        profiles
+            .put("tv", request("http://localhost/done"))
+            .unwrap();
+        scheduler.launch(&tv).unwrap();
+        assert!(
+            scheduler
+                .schedules()
+                .get("tv")
+                .unwrap()
+                .unwrap()
+                .last_job
+                .is_some()
+        );
+    }
+}
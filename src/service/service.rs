@@ -1,3 +1,96 @@
-fn main() {
-    
+use std::sync::Arc;
+
+mod auth;
+mod browse;
+mod config;
+mod dashboard;
+mod engine;
+mod history;
+mod jobs;
+mod limits;
+mod openapi;
+mod plans;
+mod profiles;
+mod qbittorrent;
+mod scheduler;
+
+#[rocket::launch]
+fn rocket() -> _ {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+    let rocket = rocket::custom(config::figment());
+    let config: config::Config = rocket
+        .figment()
+        .extract()
+        .expect("Invalid service configuration");
+    let listening: rocket::Config = rocket
+        .figment()
+        .extract()
+        .expect("Invalid service configuration");
+    if !listening.address.is_loopback() && !listening.tls_enabled() {
+        tracing::warn!(
+            "Listening on {} without TLS, so credentials are sent in the clear",
+            listening.address
+        );
+    }
+    let credentials =
+        auth::Credentials::new(config.tokens, config.users).unwrap_or_else(|e| panic!("{e}"));
+    let engine = engine::locate().expect("Couldn't locate the service executable");
+    let history = history::History::open(&config.database).expect("Couldn't open the job history");
+    let profiles =
+        Arc::new(profiles::Profiles::open(&config.database).expect("Couldn't open the profiles"));
+    let schedules =
+        scheduler::Schedules::open(&config.database).expect("Couldn't open the schedules");
+    let jobs = jobs::Jobs::start(
+        engine,
+        history,
+        config.plans,
+        config.max_jobs,
+        config.callback_hosts.clone(),
+    )
+    .expect("Couldn't read the job history");
+    let scheduler =
+        scheduler::Scheduler::start(schedules, Arc::clone(&profiles), Arc::clone(&jobs));
+    rocket
+        .manage(credentials)
+        .manage(browse::Roots(config.roots))
+        .manage(limits::RateLimiter::new(config.requests_per_minute))
+        .manage(config.qbittorrent)
+        .manage(config.callback_hosts)
+        .manage(jobs)
+        .manage(profiles)
+        .manage(scheduler)
+        .register(
+            "/",
+            rocket::catchers![auth::unauthorized, limits::too_many_requests],
+        )
+        .mount(
+            "/",
+            rocket::routes![
+                dashboard::index,
+                browse::browse,
+                jobs::submit,
+                jobs::preview,
+                jobs::list,
+                jobs::status,
+                jobs::events,
+                plans::get,
+                plans::apply,
+                profiles::list,
+                profiles::get,
+                profiles::put,
+                profiles::delete,
+                profiles::launch,
+                qbittorrent::completed,
+                scheduler::list,
+                scheduler::get,
+                scheduler::put,
+                scheduler::delete,
+            ],
+        )
+        .mount("/", openapi::routes())
 }
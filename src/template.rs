@@ -0,0 +1,124 @@
+use std::io;
+
+/// A piece of an output line template.
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// An output line with `{field}` placeholders, e.g. `{dest}\t{src}\t{size}`. `\t`, `\n` and
+/// `\\` are unescaped so they can be given from a shell, and `{{`/`}}` stand for literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Checks every placeholder is one of `fields`, the ones the output filling it in has.
+    pub fn check(&self, fields: &[&str]) -> io::Result<()> {
+        for part in &self.parts {
+            if let Part::Field(field) = part
+                && !fields.contains(&field.as_str())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown field {{{field}}} in --format, expected one of {}",
+                        fields
+                            .iter()
+                            .map(|field| format!("{{{field}}}"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in the placeholders with `value`, which gives the value of a field by name.
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => value(field),
+            })
+            .collect()
+    }
+}
+
+/// Parses a `--format` template.
+pub fn parse_template(s: &str) -> Result<Template, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => text.push('\t'),
+                Some('n') => text.push('\n'),
+                Some('\\') => text.push('\\'),
+                Some(other) => {
+                    text.push('\\');
+                    text.push(other);
+                }
+                None => text.push('\\'),
+            },
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(format!("Unclosed {{ in {s:?}")),
+                    }
+                }
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(Part::Field(field));
+            }
+            '}' => {
+                return Err(format!(
+                    "Unmatched }} in {s:?}, write }}}} for a literal one"
+                ));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(Template { parts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let template = parse_template(r"{dest}\t{src}\t{{{size}}}").unwrap();
+        let line = template.render(|field| match field {
+            "dest" => "/tv/e01.mkv".to_owned(),
+            "src" => "/dl/e01.mkv".to_owned(),
+            _ => "7".to_owned(),
+        });
+        assert_eq!(line, "/tv/e01.mkv\t/dl/e01.mkv\t{7}");
+        assert!(template.check(&["dest", "src", "size"]).is_ok());
+        assert!(template.check(&["dest", "src"]).is_err());
+
+        assert!(parse_template("{dest").is_err());
+        assert!(parse_template("dest}").is_err());
+    }
+}
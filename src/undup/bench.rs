@@ -0,0 +1,125 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::hashing::{self, ALGORITHM};
+
+/// Buffer sizes tried by `bench`, from what reads a block at a time up to large sequential reads.
+pub const BUFFER_SIZES: &[usize] = &[4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20];
+
+/// How fast one hashing configuration got through the sample.
+#[derive(Debug, serde::Serialize)]
+pub struct Measurement {
+    pub algorithm: &'static str,
+    pub buffer_size: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+    pub mb_per_sec: f64,
+}
+
+impl Measurement {
+    fn new(buffer_size: usize, bytes: u64, elapsed: Duration) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        Self {
+            algorithm: ALGORITHM,
+            buffer_size,
+            bytes,
+            seconds,
+            mb_per_sec: if seconds > 0.0 {
+                bytes as f64 / (1 << 20) as f64 / seconds
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} with a {} KiB buffer: {:.1} MB/s ({} bytes in {:.2}s)",
+            self.algorithm,
+            self.buffer_size >> 10,
+            self.mb_per_sec,
+            self.bytes,
+            self.seconds
+        )
+    }
+}
+
+/// Regular files under `paths`, in directory order, until they add up to at least `max_bytes`.
+/// Symlinks aren't followed.
+pub fn sample(paths: &[PathBuf], max_bytes: u64) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut total = 0;
+    let mut pending: Vec<PathBuf> = paths.iter().rev().cloned().collect();
+    while let Some(path) = pending.pop() {
+        if total >= max_bytes {
+            break;
+        }
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            let mut entries = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            entries.sort();
+            pending.extend(entries.into_iter().rev());
+        } else if metadata.is_file() && metadata.len() > 0 {
+            total += metadata.len();
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Hashes `files` with each of `buffer_sizes`. They are read once beforehand so every
+/// configuration finds them equally cached, measuring the hashing rather than the disk.
+pub fn run(files: &[PathBuf], buffer_sizes: &[usize]) -> io::Result<Vec<Measurement>> {
+    for file in files {
+        io::copy(&mut fs::File::open(file)?, &mut io::sink())?;
+    }
+    buffer_sizes
+        .iter()
+        .map(|&buffer_size| {
+            let start = Instant::now();
+            let bytes = hash_all(files, buffer_size)?;
+            Ok(Measurement::new(buffer_size, bytes, start.elapsed()))
+        })
+        .collect()
+}
+
+fn hash_all(files: &[PathBuf], buffer_size: usize) -> io::Result<u64> {
+    let mut bytes = 0;
+    for file in files {
+        hashing::hash_with_buffer(file, buffer_size)?;
+        bytes += fs::metadata(file)?.len();
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("season 1")).unwrap();
+        fs::write(dir.path().join("season 1/e01.mkv"), vec![1; 3000]).unwrap();
+        fs::write(dir.path().join("season 1/e02.mkv"), vec![2; 3000]).unwrap();
+        fs::write(dir.path().join("show.nfo"), "").unwrap();
+
+        let files = sample(&[dir.path().to_path_buf()], 2000).unwrap();
+        assert_eq!(files, [dir.path().join("season 1/e01.mkv")]);
+        let files = sample(&[dir.path().to_path_buf()], u64::MAX).unwrap();
+        assert_eq!(files.len(), 2);
+
+        let measurements = run(&files, &[1 << 10, 4 << 10]).unwrap();
+        assert_eq!(measurements.len(), 2);
+        assert!(measurements.iter().all(|m| m.bytes == 6000));
+        assert_eq!(measurements[1].buffer_size, 4 << 10);
+    }
+}
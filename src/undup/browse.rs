@@ -0,0 +1,310 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, IsTerminal as _},
+    path::PathBuf,
+};
+
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use crate::matching::MatchingFile;
+
+/// Every file sharing one hash: the sources and the matched targets that would be replaced.
+#[derive(Debug)]
+struct Group {
+    hash: String,
+    size: u64,
+    sources: Vec<PathBuf>,
+    targets: Vec<MatchingFile>,
+    marked: bool,
+}
+
+impl Group {
+    /// Bytes freed by replacing every target of the group
+    fn reclaimable(&self) -> u64 {
+        self.targets.iter().map(MatchingFile::size).sum()
+    }
+}
+
+/// The matches grouped by hash, biggest saving first, with none marked to start with.
+#[derive(Debug)]
+pub struct Browser {
+    groups: Vec<Group>,
+    cursor: usize,
+    /// Lines the details of the current group are scrolled by
+    scroll: u16,
+}
+
+impl Browser {
+    /// Groups `matches` by hash, listing every source with that hash from `sources`.
+    pub fn new(
+        matches: impl IntoIterator<Item = MatchingFile>,
+        sources: impl Fn(&str) -> Vec<PathBuf>,
+    ) -> Self {
+        let mut by_hash: BTreeMap<String, Vec<MatchingFile>> = BTreeMap::new();
+        for matching in matches {
+            by_hash
+                .entry(matching.hash().to_owned())
+                .or_default()
+                .push(matching);
+        }
+        let mut groups: Vec<Group> = by_hash
+            .into_iter()
+            .map(|(hash, targets)| Group {
+                size: targets[0].size(),
+                sources: sources(&hash),
+                hash,
+                targets,
+                marked: false,
+            })
+            .collect();
+        groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable()));
+        Self {
+            groups,
+            cursor: 0,
+            scroll: 0,
+        }
+    }
+
+    /// The matches of every marked group, to hand on to be applied.
+    pub fn into_selected(self) -> Vec<MatchingFile> {
+        self.groups
+            .into_iter()
+            .filter(|group| group.marked)
+            .flat_map(|group| group.targets)
+            .collect()
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        let last = self.groups.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(offset).min(last);
+        self.scroll = 0;
+    }
+
+    fn toggle(&mut self) {
+        if let Some(group) = self.groups.get_mut(self.cursor) {
+            group.marked = !group.marked;
+        }
+    }
+
+    fn toggle_all(&mut self) {
+        let mark = !self.groups.iter().all(|group| group.marked);
+        for group in &mut self.groups {
+            group.marked = mark;
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, list, details, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(50),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (count, size) = self
+            .groups
+            .iter()
+            .filter(|group| group.marked)
+            .fold((0, 0), |(count, size), group| {
+                (count + 1, size + group.reclaimable())
+            });
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{count} of {} groups marked, {size} bytes",
+                self.groups.len()
+            )),
+            header,
+        );
+
+        let items: Vec<ListItem> = self
+            .groups
+            .iter()
+            .map(|group| {
+                ListItem::new(format!(
+                    "{} {:.12} {} x {} bytes, {} sources",
+                    if group.marked { "[x]" } else { "[ ]" },
+                    group.hash,
+                    group.targets.len(),
+                    group.size,
+                    group.sources.len()
+                ))
+            })
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.cursor));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title("Duplicate groups"))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut state,
+        );
+
+        let mut lines = Vec::new();
+        if let Some(group) = self.groups.get(self.cursor) {
+            lines.push(Line::from(format!("Hash: {}", group.hash)));
+            lines.push(Line::from(format!(
+                "Size: {} bytes each, {} bytes reclaimable",
+                group.size,
+                group.reclaimable()
+            )));
+            lines.push(Line::from("Sources:"));
+            lines.extend(
+                group
+                    .sources
+                    .iter()
+                    .map(|source| Line::from(format!("  {}", source.display()))),
+            );
+            lines.push(Line::from("Targets:"));
+            lines.extend(group.targets.iter().map(|target| {
+                Line::from(format!(
+                    "  {} ({} bytes) <- {}",
+                    target.dest_path().display(),
+                    target.size(),
+                    target.src_path().display()
+                ))
+            }));
+        }
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title("Details"))
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0)),
+            details,
+        );
+
+        frame.render_widget(
+            Line::from(
+                "space: mark  a: mark all  J/K: scroll details  enter: apply marked  q: cancel",
+            ),
+            footer,
+        );
+    }
+
+    /// Lets the user explore the groups and mark the ones to replace in a full screen terminal
+    /// UI. Returns whether to go ahead and apply the marked groups.
+    pub fn run(&mut self) -> io::Result<bool> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--browse needs a terminal",
+            ));
+        }
+        let mut terminal = ratatui::try_init().map_err(|e| {
+            ratatui::restore();
+            io::Error::new(e.kind(), format!("--browse needs a terminal: {e}"))
+        })?;
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::PageUp => self.move_by(-20),
+                KeyCode::PageDown => self.move_by(20),
+                KeyCode::Char('K') => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::Char('J') => self.scroll = self.scroll.saturating_add(1),
+                KeyCode::Char(' ') => self.toggle(),
+                KeyCode::Char('a') => self.toggle_all(),
+                KeyCode::Enter => return Ok(true),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+
+    fn matching(target: &str, hash: &str, size: u64) -> MatchingFile {
+        MatchingFile::new(
+            PathBuf::from("/source").join(hash),
+            PathBuf::from(target),
+            size,
+            hash.to_owned(),
+            MatchReason::SourceScan,
+        )
+    }
+
+    fn browser() -> Browser {
+        Browser::new(
+            [
+                matching("/tv/e01.mkv", "AAA", 10),
+                matching("/movies/film.mkv", "BBB", 30),
+                matching("/tv/copy/e01.mkv", "AAA", 10),
+            ],
+            |hash| vec![PathBuf::from("/source").join(hash)],
+        )
+    }
+
+    #[test]
+    fn test_browser() {
+        let mut browser = browser();
+        // Biggest saving first
+        assert_eq!(browser.groups[0].hash, "BBB");
+        assert_eq!(browser.groups[1].reclaimable(), 20);
+        assert!(browser.groups.iter().all(|group| !group.marked));
+
+        browser.move_by(1);
+        browser.toggle();
+        let selected: Vec<_> = browser
+            .into_selected()
+            .iter()
+            .map(|matching| matching.dest_path().to_path_buf())
+            .collect();
+        assert_eq!(
+            selected,
+            [
+                PathBuf::from("/tv/e01.mkv"),
+                PathBuf::from("/tv/copy/e01.mkv")
+            ]
+        );
+
+        let mut browser = self::browser();
+        browser.toggle_all();
+        assert_eq!(browser.into_selected().len(), 3);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut browser = browser();
+        browser.move_by(1);
+        browser.toggle();
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(60, 24)).unwrap();
+        terminal.draw(|frame| browser.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("1 of 2 groups marked, 20 bytes"));
+        assert!(screen.contains("[x] AAA 2 x 10 bytes, 1 sources"));
+        assert!(screen.contains("Hash: AAA"));
+        assert!(screen.contains("/source/AAA"));
+        assert!(screen.contains("/tv/copy/e01.mkv (10 bytes)"));
+    }
+}
@@ -1,12 +1,35 @@
-mod actions;
-mod hashing;
-mod matching;
+mod bench;
+mod browse;
+mod confirm;
+mod doctor;
+mod logging;
+mod manifest;
+mod mapping;
+mod progress;
+mod schedule;
+mod select;
+mod stats;
+mod systemd;
 
-use clap::Parser;
+use atorrlinker::{actions, hashing, lock, matching, notify, template};
+
+use clap::{CommandFactory as _, Parser};
 use directories::ProjectDirs;
-use std::{io, path::PathBuf};
+use std::{
+    io::{self, IsTerminal as _},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use crate::actions::LinkMode;
+use crate::actions::on_error::OnError;
+use crate::actions::plan::{OutputFormat, Plan};
 use crate::hashing::{HashCache, file_cache::HashingFileCache, no_cache::HashingNoCache};
+use crate::matching::MatchOptions;
+use crate::matching::convert::ConvertTo;
+use crate::matching::matcher::{HashMatcher, Matcher, NameSizeMatcher};
 
 #[derive(Clone, Debug, clap::ValueEnum)]
 enum HashingCacheOptions {
@@ -14,47 +37,2054 @@ enum HashingCacheOptions {
     File,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Clone, Debug, clap::ValueEnum)]
+enum MatcherOptions {
+    /// Files with identical content
+    Hash,
+    /// Files with identical content and the same name
+    NameSize,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+    /// Permanently delete runs in a trash directory older than a grace period
+    PurgeTrash {
+        /// Directory previously given to --trash
+        trash: PathBuf,
+        /// How long trashed files are kept (e.g. 7d)
+        #[clap(long, value_parser = parse_duration)]
+        older_than: Duration,
+    },
+    /// Convert existing symlinks in the target paths into hardlinks, or hardlinks to source files
+    /// into symlinks
+    Convert {
+        #[clap(long, value_enum)]
+        to: ConvertTo,
+        /// Source paths, needed to know which path a hardlink should point at as a symlink
+        #[clap(short, long, required_if_eq("to", "symlink"))]
+        source_paths: Vec<PathBuf>,
+        #[clap(short, long, required = true)]
+        target_paths: Vec<PathBuf>,
+        /// Create symlinks relative to their own directory instead of absolute
+        #[clap(long)]
+        relative: bool,
+        #[clap(long, short)]
+        dry_run: bool,
+    },
+    /// Replace symlinks under the target paths that point outside of them with copies of what
+    /// they point at, so the targets no longer depend on the source library
+    Materialize {
+        #[clap(required = true)]
+        target_paths: Vec<PathBuf>,
+        #[clap(long, short)]
+        dry_run: bool,
+    },
+    /// Delete symlinks under the target paths whose destination in the source paths no longer
+    /// exists, as left behind by removed sources. The removals are journalled
+    Prune {
+        /// Only links into these are removed, and none into one that's missing or empty
+        #[clap(short, long, required = true)]
+        source_paths: Vec<PathBuf>,
+        #[clap(required = true)]
+        target_paths: Vec<PathBuf>,
+        /// Only list the dangling symlinks
+        #[clap(long, short)]
+        dry_run: bool,
+    },
+    /// Compare two trees by content without changing anything: files found in both, files
+    /// only found in one, and paths found in both with different content
+    Diff {
+        dir_a: PathBuf,
+        dir_b: PathBuf,
+        /// Ignore dotfiles and dot-directories
+        #[clap(long)]
+        skip_hidden: bool,
+        /// Don't descend into other filesystems mounted under the paths
+        #[clap(long, short = 'x')]
+        one_file_system: bool,
+        /// Leave out paths matching this rsync style pattern. Can be repeated
+        #[clap(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+    },
+    /// List the groups of files with the same content across the paths, with how much space
+    /// keeping one copy of each would reclaim, without changing anything
+    Report {
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+        /// Ignore dotfiles and dot-directories
+        #[clap(long)]
+        skip_hidden: bool,
+        /// Don't descend into other filesystems mounted under the paths
+        #[clap(long, short = 'x')]
+        one_file_system: bool,
+        /// Leave out paths matching this rsync style pattern. Can be repeated
+        #[clap(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+    },
+    /// Check the symlinks under the target paths without changing anything, reporting those
+    /// that are broken or point outside the source paths
+    Verify {
+        #[clap(short, long, required = true)]
+        source_paths: Vec<PathBuf>,
+        #[clap(short, long, required = true)]
+        target_paths: Vec<PathBuf>,
+        /// Also hash the files linked to again, reporting those whose content no longer matches
+        /// the hash cached for them
+        #[clap(long)]
+        rehash: bool,
+    },
+    /// Match the source paths against the target paths like a dry run, and save the matches
+    /// found to a plan file to review and apply later
+    Plan {
+        #[clap(long, value_name = "FILE")]
+        out: PathBuf,
+    },
+    /// Apply a plan saved by `plan`. Matches whose files changed or no longer hash the same
+    /// since are skipped
+    Apply { plan: PathBuf },
+    /// Show what the runs recorded in the journal reclaimed over time, and in total
+    Stats {
+        /// Group the runs by
+        #[clap(long, value_enum, default_value = "month")]
+        by: stats::Period,
+    },
+    /// Restore journalled target files to independent copies of their content
+    Undo {
+        /// Only restore files under these paths
+        paths: Vec<PathBuf>,
+    },
+    /// Check the filesystems, permissions and state files a run would use without changing
+    /// anything, printing what needs fixing first
+    Doctor {
+        #[clap(short, long, required = true)]
+        source_paths: Vec<PathBuf>,
+        #[clap(short, long, required = true)]
+        target_paths: Vec<PathBuf>,
+        /// The link modes the run would try, in order
+        #[clap(long, value_enum, value_delimiter = ',', default_value = "symlink")]
+        link_mode: Vec<LinkMode>,
+    },
+    /// Measure how fast a sample of the given directories hashes with each buffer size, to pick
+    /// --hash-buffer for this hardware before a big run
+    Bench {
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+        /// Hash up to about this much of the directories (e.g. 500M, 2G)
+        #[clap(long, value_name = "SIZE", value_parser = parse_size, default_value = "256M")]
+        sample: u64,
+    },
+    /// Write man pages for the command and each subcommand, for packaging
+    #[command(hide = true)]
+    GenerateMan {
+        /// Directory to write the pages to
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(subcommand_negates_reqs = true)]
 struct Arguments {
-    #[clap(short, long, value_parser, required = true)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[clap(short, long, value_parser, required_unless_present = "map")]
     source_paths: Vec<PathBuf>,
-    #[clap(short, long, value_parser, required = true)]
+    /// Target paths to replace duplicates in. `-` reads more from stdin, one per line or NUL
+    /// delimited, e.g. piped from `find -print0`
+    #[clap(short, long, value_parser, required_unless_present_any = ["target_list", "map"])]
     target_paths: Vec<PathBuf>,
-    #[clap(long, value_enum, default_value_t=HashingCacheOptions::File )]
+    /// Read target paths from a file, or stdin for `-`. One path per line, or NUL delimited
+    /// as from `find -print0`
+    #[clap(long, value_name = "FILE")]
+    target_list: Option<PathBuf>,
+    /// Match some target paths only against some source paths, e.g.
+    /// target=/media/tv:source=/downloads/tv. Each mapping is run on its own, so unrelated
+    /// libraries are never matched against each other. Can be repeated
+    #[clap(
+        long,
+        value_parser = mapping::parse_mapping,
+        value_name = "target=DIR:source=DIR",
+        conflicts_with_all = ["source_paths", "target_paths", "target_list"]
+    )]
+    map: Vec<mapping::Mapping>,
+    #[clap(long, global = true, value_enum, default_value_t=HashingCacheOptions::File )]
     hashing_cache: HashingCacheOptions,
+    /// How much of a file to read at a time when hashing it (e.g. 64K, 1M), from 4K to 64M.
+    /// `bench` shows which is fastest
+    #[clap(long, global = true, value_name = "SIZE", value_parser = parse_hash_buffer)]
+    hash_buffer: Option<usize>,
+    /// How to replace duplicates. A comma separated list falls back to the next mode for files
+    /// the filesystem can't handle with the previous one, e.g. reflink,hardlink,symlink
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "symlink")]
+    link_mode: Vec<LinkMode>,
+    #[clap(long, value_enum, default_value_t=MatcherOptions::Hash)]
+    matcher: MatcherOptions,
+
+    /// Skip dotfiles and dot-directories (.git, .stfolder, .Trash, ...)
+    #[clap(long)]
+    skip_hidden: bool,
+    /// Don't descend into other filesystems mounted under the source and target paths, like
+    /// `du -x`, e.g. network mounts or bind-mounted snapshots
+    #[clap(long, short = 'x')]
+    one_file_system: bool,
+    /// Skip files and directories matching an rsync style pattern, e.g. *.nfo, Sample/ or
+    /// /extras. Can be repeated. A .atorr.toml file in a directory can add `exclude` patterns,
+    /// `skip = true` or a `link_mode` list for its subtree alone
+    #[clap(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// Read exclude patterns from a file, one per line. Lines starting with # or ; are comments
+    #[clap(long, value_name = "FILE")]
+    exclude_from: Vec<PathBuf>,
+    /// Only replace target files untouched for at least this long (e.g. 30d, 12h, 90m)
+    #[clap(long, value_parser = parse_duration)]
+    older_than: Option<Duration>,
+    /// Re-point target symlinks whose destination is gone at a source file with the same hash.
+    /// Needs the hashing cache to know what the missing file contained.
+    #[clap(long)]
+    repair_broken_symlinks: bool,
+    /// Re-point target symlinks that resolve outside every source path at the source copy
+    #[clap(long)]
+    rewrite_external_symlinks: bool,
+    /// Re-point target symlinks at the preferred source when they resolve to another copy of it
+    #[clap(long)]
+    retarget_symlinks: bool,
+    /// Replace target directories whose files all match a single source directory with one
+    /// directory symlink. Only valid with symlink mode
+    #[clap(long)]
+    link_directories: bool,
+    /// Report target files that are an incomplete download of a larger source file
+    #[clap(long)]
+    detect_partial: bool,
+    /// Report unmatched target files sharing at least this percentage of their content with a
+    /// source file. These are never linked
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100))]
+    near_duplicates: Option<u8>,
+    /// List target files without a source counterpart, grouped by directory
+    #[clap(long)]
+    report_unmatched: bool,
+    /// Leave files that are open in another process or locked alone, such as a torrent still
+    /// being downloaded
+    #[clap(long)]
+    skip_in_use: bool,
+    /// Link to sources on network filesystems or removable drives. The links break whenever
+    /// those aren't mounted, so such matches are only reported otherwise
+    #[clap(long)]
+    allow_cross_device: bool,
+    /// Don't show progress bars while scanning and hashing
+    #[clap(long, global = true)]
+    no_progress: bool,
+    /// Show progress as bars, or as JSON lines on stderr for other tools to follow: phase
+    /// changes, files hashed, matches found and replacements attempted
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = progress::ProgressFormat::Bar,
+        conflicts_with = "no_progress"
+    )]
+    progress_format: progress::ProgressFormat,
+    /// Ask about each quarantined match, sharing a hash with its source but not its size, after
+    /// the run instead of only reporting them
+    #[clap(long, conflicts_with = "dry_run")]
+    review_quarantine: bool,
+
+    /// Create symlinks relative to their own directory instead of absolute
+    #[clap(long)]
+    relative: bool,
+    /// Move replaced target files into this directory instead of deleting them, mirroring their
+    /// original paths. Clear it out later with `purge-trash`
+    #[clap(long, value_name = "DIR")]
+    trash: Option<PathBuf>,
+    /// Hash each source and target again right before replacing it, skipping any that changed
+    #[clap(long)]
+    verify_hash: bool,
+    /// Journal of replaced files used by `undo`. Defaults to one in the user's data directory
+    #[clap(long, global = true, value_name = "FILE")]
+    journal: Option<PathBuf>,
+    /// Append-only log of every change made, kept independently of the journal. Defaults to one
+    /// in the user's data directory
+    #[clap(long, global = true, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+    /// Log more: -v for what is skipped and why, -vv for debugging, -vvv for everything
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only log errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log level for one module, overriding -v/-q, e.g. matching::pipeline=debug or
+    /// hashing=off. Can be repeated or comma separated
+    #[clap(
+        long,
+        global = true,
+        value_name = "MODULE=LEVEL",
+        value_delimiter = ','
+    )]
+    log: Vec<String>,
+    /// Also log to this file, at --log-file-level whatever -v/-q say, rotating it as set by
+    /// --log-rotate
+    #[clap(long, global = true, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// Level logged to --log-file: error, warn, info, debug or trace
+    #[clap(long, global = true, value_name = "LEVEL", default_value = "info")]
+    log_file_level: tracing::level_filters::LevelFilter,
+    /// Start a new log file daily, hourly, or once it grows past a size such as 100M
+    #[clap(
+        long,
+        global = true,
+        value_name = "daily|hourly|SIZE",
+        value_parser = logging::parse_rotation,
+        default_value = "daily"
+    )]
+    log_rotate: logging::Rotation,
+    /// How many rotated log files to keep besides the current one
+    #[clap(long, global = true, value_name = "N", default_value_t = 7)]
+    log_keep: usize,
+    /// Don't color the dry run listing or the logs, even on a terminal
+    #[clap(long, global = true)]
+    no_color: bool,
+    /// Fail instead of creating, changing or deleting anything under the source paths
+    #[clap(long)]
+    protect_sources: bool,
+
+    /// If anything fails, undo every replacement made so far in this run before exiting.
+    /// Directory symlinks can't be rolled back, so this excludes --link-directories
+    #[clap(long, conflicts_with = "link_directories")]
+    transactional: bool,
+    /// Replace at most this many files or directories in this run
+    #[clap(long, value_name = "N")]
+    max_actions: Option<u64>,
+    /// Replace at most this much data in this run (e.g. 500M, 20G)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_bytes: Option<u64>,
+    /// Write the targets left over once a budget is reached here, to continue later with
+    /// --target-list
+    #[clap(long, value_name = "FILE")]
+    remaining_list: Option<PathBuf>,
+    /// Replace targets even when they were modified after the source they match
+    #[clap(long)]
+    force: bool,
+    /// Pick up where an interrupted run over the same paths left off, applying the rest of its
+    /// plan without scanning again
+    #[clap(
+        long,
+        conflicts_with_all = ["dry_run", "link_directories", "select", "browse"]
+    )]
+    resume: bool,
+    /// How often to save the hashes computed so far while hashing, so an interrupted run
+    /// doesn't have to hash them again (e.g. 30s, 5m)
+    #[clap(
+        long,
+        value_parser = parse_duration,
+        value_name = "DURATION",
+        default_value = "5m"
+    )]
+    checkpoint_interval: Duration,
+    /// Make at most this many replacements per second, to go easy on SMR drives and network
+    /// filesystems (e.g. 5 or 0.5)
+    #[clap(long, value_name = "RATE")]
+    ops_per_second: Option<f64>,
+    /// Check every replaced target afterwards resolves to its source
+    #[clap(long, conflicts_with = "dry_run")]
+    verify: bool,
+    /// Also read this many of the replaced files back through their link to check their hash
+    #[clap(long, value_name = "N", default_value_t = 0, requires = "verify")]
+    verify_sample: usize,
+    /// Shell command run before each replacement, which is skipped if the command fails. Details
+    /// are passed in ATORRLINKER_TARGET, _SOURCE, _HASH, _SIZE and _MODE environment variables
+    #[clap(long, value_name = "COMMAND")]
+    pre_hook: Option<String>,
+    /// Shell command run after each replacement, with the same environment as --pre-hook
+    #[clap(long, value_name = "COMMAND")]
+    post_hook: Option<String>,
+    /// POST the outcome of each run, with its summary, as JSON to this URL once it completes or
+    /// fails, e.g. an ntfy topic or a chat webhook
+    #[clap(long, value_name = "URL")]
+    notify_url: Option<String>,
+    /// Shell command run once each run completes or fails, given its outcome as JSON on stdin
+    /// and in ATORRLINKER_STATUS and ATORRLINKER_MESSAGE environment variables
+    #[clap(long, value_name = "COMMAND")]
+    notify_cmd: Option<String>,
+    /// Replace files on this many threads, speeding up filesystems where each change waits on
+    /// the network. Files in the same directory are still replaced one at a time
+    #[clap(long, default_value = "1", conflicts_with = "interactive")]
+    jobs: NonZeroUsize,
+    /// What to do when replacing a target fails: abort the run, skip the target, or retry it
+    /// up to N times (3 by default) before aborting. Targets needing privileges are always
+    /// skipped unless the run is transactional
+    #[clap(long, value_name = "abort|skip|retry[:N]", default_value = "abort")]
+    on_error: OnError,
+    /// Ask before each replacement: y(es), n(o), a(ll remaining) or q(uit)
+    #[clap(long, short, conflicts_with = "dry_run")]
+    interactive: bool,
+    /// Replace files without first confirming the number of files and bytes about to be
+    /// replaced. Needed whenever there's no terminal to ask on, e.g. scheduled runs
+    #[clap(long, short)]
+    yes: bool,
+    /// Review the whole plan grouped by directory in a terminal UI, picking the replacements
+    /// to apply before any is made
+    #[clap(long, conflicts_with_all = ["dry_run", "interactive"])]
+    select: bool,
+    /// Explore the duplicates grouped by hash in a terminal UI, with every source and target
+    /// path of each group, and mark the groups to replace
+    #[clap(long, conflicts_with_all = ["interactive", "select", "link_directories"])]
+    browse: bool,
 
     #[clap(long, short)]
     dry_run: bool,
+    /// Instead of replacing anything, write the commands that would do it to this shell script
+    /// for review. Implies --dry-run
+    #[clap(long, value_name = "FILE", conflicts_with = "interactive")]
+    emit_script: Option<PathBuf>,
+    /// How a dry run reports the planned actions and projected saving. JSON reports the
+    /// actions and their results, the summary and every report in one document on stdout
+    #[clap(
+        long,
+        global = true,
+        alias = "output",
+        value_enum,
+        default_value_t = OutputFormat::Text
+    )]
+    output_format: OutputFormat,
+    /// Write a line per dry run action or duplicate reported from this template instead, e.g.
+    /// '{dest}\t{src}\t{size}'. Dry runs have {dest}, {src}, {size}, {saved}, {hash}, {mode}
+    /// and {kind}, reports {path}, {hash}, {size}, {group} and {reclaimable}
+    #[clap(
+        long,
+        global = true,
+        value_parser = template::parse_template,
+        value_name = "TEMPLATE",
+        conflicts_with = "output_format"
+    )]
+    format: Option<template::Template>,
+    /// Keep running as a daemon, reconciling again this long after each run started (e.g. 6h).
+    /// Hashes are cached between runs and each run's summary is logged at -v. Run as a systemd
+    /// service with Type=notify, readiness and watchdog pings are reported, and SIGTERM stops
+    /// once the matches being applied are done
+    #[clap(
+        long,
+        value_parser = parse_interval,
+        value_name = "DURATION",
+        conflicts_with_all = ["interactive", "select", "browse", "review_quarantine"]
+    )]
+    every: Option<Duration>,
+    /// Keep running as a daemon, reconciling on a cron schedule in local time, e.g. "0 3 * * *"
+    #[clap(
+        long,
+        value_parser = schedule::parse_cron,
+        value_name = "EXPRESSION",
+        conflicts_with_all = ["every", "interactive", "select", "browse", "review_quarantine"]
+    )]
+    cron: Option<schedule::Schedule>,
 }
 
 fn main() -> io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::default().filter_or("ATORR_LOG", "warn"));
-    let args = Arguments::parse();
+    let mut args = Arguments::parse();
+    let log_file = args.log_file.clone().map(|path| logging::LogFile {
+        path,
+        level: args.log_file_level,
+        rotation: args.log_rotate,
+        keep: args.log_keep,
+    });
+    logging::init(
+        args.verbose,
+        args.quiet,
+        &args.log,
+        args.no_color,
+        log_file.as_ref(),
+    )?;
+    args.dry_run |= args.emit_script.is_some();
+    let mut plan = None;
+    match &args.command {
+        Some(Command::Plan { .. } | Command::Apply { .. }) if !args.map.is_empty() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Plans can't be used with --map",
+            ));
+        }
+        Some(Command::Plan { .. }) => args.dry_run = true,
+        Some(Command::Apply { .. })
+            if !args.source_paths.is_empty()
+                || !args.target_paths.is_empty()
+                || args.target_list.is_some() =>
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A plan is applied to the paths it was made for, so apply takes no \
+                 --source-paths, --target-paths or --target-list",
+            ));
+        }
+        Some(Command::Apply { plan: path }) => {
+            let loaded = actions::saved_plan::SavedPlan::load(path)?;
+            args.source_paths = loaded.sources.clone();
+            args.target_paths = loaded.targets.clone();
+            plan = Some(loaded);
+        }
+        Some(_) if args.emit_script.is_some() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--emit-script only scripts replacing duplicates, not the other commands",
+            ));
+        }
+        _ => {}
+    }
+    if let Some(template) = &args.format
+        && !matches!(args.command, Some(Command::Report { .. }))
+    {
+        template.check(actions::plan::TEMPLATE_FIELDS)?;
+    }
+    expand_targets(
+        &mut args.target_paths,
+        args.target_list.as_deref(),
+        manifest::read_path_list,
+    )?;
+    if matches!(args.command, Some(Command::Plan { .. }))
+        && (args.source_paths.is_empty() || args.target_paths.is_empty())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Planning needs --source-paths and --target-paths",
+        ));
+    }
 
-    let dirs: directories::ProjectDirs =
-        directories::ProjectDirs::from("local", "jimbo", "untorr_undup")
-            .expect("Could not find the project directories");
+    let dirs = atorrlinker::project_dirs();
     create_dirs(&dirs)?;
+    let journal_path = args
+        .journal
+        .clone()
+        .unwrap_or_else(|| dirs.data_dir().join("journal.jsonl"));
+    let audit_path = args
+        .audit_log
+        .clone()
+        .unwrap_or_else(|| dirs.data_dir().join("audit.jsonl"));
+    let open_audit_log = |dry_run: bool| {
+        if dry_run {
+            Ok(None)
+        } else {
+            actions::audit::AuditLog::open(&audit_path).map(Some)
+        }
+    };
+
+    if let Some(Command::Doctor {
+        source_paths,
+        target_paths,
+        link_mode,
+    }) = &args.command
+    {
+        let cache = hashing::file_cache::default_path();
+        let findings = doctor::Checkup {
+            sources: source_paths,
+            targets: target_paths,
+            modes: link_mode,
+            cache: matches!(args.hashing_cache, HashingCacheOptions::File).then_some(&*cache),
+            state_dirs: [&journal_path, &audit_path]
+                .into_iter()
+                .filter_map(|path| path.parent())
+                .collect(),
+        }
+        .run();
+        let mut report = Report::new(args.output_format == OutputFormat::Json);
+        report.section("findings", &findings, || {
+            for finding in &findings {
+                println!("{finding}");
+            }
+        });
+        report.finish()?;
+        let problems = findings
+            .iter()
+            .filter(|finding| finding.level == doctor::Level::Problem)
+            .count();
+        if problems > 0 {
+            return Err(io::Error::other(format!(
+                "Found {problems} problems to fix before a real run"
+            )));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::GenerateMan { out_dir }) = &args.command {
+        std::fs::create_dir_all(out_dir)?;
+        clap_mangen::generate_to(Arguments::command().name("atorrlinker-undup"), out_dir)?;
+        return Ok(());
+    }
+
+    if let Some(size) = args.hash_buffer {
+        hashing::set_buffer_size(size);
+    }
+
+    if let Some(Command::Bench { paths, sample }) = &args.command {
+        let files = bench::sample(paths, *sample)?;
+        if files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("No files to hash in {paths:?}"),
+            ));
+        }
+        let measurements = bench::run(&files, bench::BUFFER_SIZES)?;
+        let fastest = measurements
+            .iter()
+            .max_by(|a, b| a.mb_per_sec.total_cmp(&b.mb_per_sec))
+            .map(|fastest| fastest.buffer_size);
+        let mut report = Report::new(args.output_format == OutputFormat::Json);
+        report.section("measurements", &measurements, || {
+            for measurement in &measurements {
+                println!("{measurement}");
+            }
+        });
+        report.section("fastest_buffer_size", &fastest, || {
+            if let Some(size) = fastest {
+                println!("Fastest: --hash-buffer {}K", size >> 10);
+            }
+        });
+        report.finish()?;
+        return Ok(());
+    }
+
+    if let Some(Command::PurgeTrash { trash, older_than }) = &args.command {
+        let audit = actions::audit::AuditLog::open(&audit_path)?;
+        for removed in actions::trash::purge(trash, *older_than)? {
+            println!("Purged {removed:?}");
+            audit.record(&audit_entry("purge_trash", &removed), Ok("removed"))?;
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Convert {
+        to,
+        source_paths,
+        target_paths,
+        relative,
+        dry_run,
+    }) = &args.command
+    {
+        let mode = match to {
+            ConvertTo::Hardlink => LinkMode::Hardlink,
+            ConvertTo::Symlink => LinkMode::Symlink,
+        };
+        let _lock = if *dry_run {
+            None
+        } else {
+            Some(lock::RunLock::acquire(
+                &dirs.data_dir().join("locks"),
+                target_paths,
+            )?)
+        };
+        let links = matching::convert::find_convertible_links(source_paths, target_paths, *to)?;
+        let link_options = actions::LinkOptions {
+            mode,
+            relative: *relative,
+            journal: if *dry_run {
+                None
+            } else {
+                Some(actions::journal::Journal::open(&journal_path)?)
+            },
+            audit: open_audit_log(*dry_run)?,
+            ..Default::default()
+        };
+        let mut plan = Plan::new(OutputFormat::Text, mode)
+            .with_color(!args.no_color && io::stdout().is_terminal())
+            .with_template(args.format.clone());
+        for link in &links {
+            if *dry_run {
+                plan.file(link)?;
+            } else {
+                actions::link_file(link, &link_options)?;
+            }
+        }
+        if *dry_run {
+            plan.finish(io::stdout())?;
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Materialize {
+        target_paths,
+        dry_run,
+    }) = &args.command
+    {
+        let _lock = if *dry_run {
+            None
+        } else {
+            Some(lock::RunLock::acquire(
+                &dirs.data_dir().join("locks"),
+                target_paths,
+            )?)
+        };
+        let audit = open_audit_log(*dry_run)?;
+        let mut total = 0;
+        for link in matching::materialize::find_external_links(target_paths)? {
+            if let Some(audit) = &audit {
+                let result = actions::materialize::materialize(&link.link, &link.resolved);
+                let entry = actions::audit::AuditEntry {
+                    source: Some(&link.resolved),
+                    ..audit_entry("materialize", &link.link)
+                };
+                audit.record(&entry, result.as_ref().map(|_| "replaced"))?;
+                total += result?;
+                println!("Materialized {:?} from {:?}", link.link, link.resolved);
+            } else {
+                println!("Would materialize {:?} from {:?}", link.link, link.resolved);
+                total += link.size;
+            }
+        }
+        if *dry_run {
+            println!("{total} bytes would be copied");
+        } else {
+            println!("{total} bytes copied");
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Prune {
+        source_paths,
+        target_paths,
+        dry_run,
+    }) = &args.command
+    {
+        let _lock = if *dry_run {
+            None
+        } else {
+            Some(lock::RunLock::acquire(
+                &dirs.data_dir().join("locks"),
+                target_paths,
+            )?)
+        };
+        let audit = open_audit_log(*dry_run)?;
+        let journal = if *dry_run {
+            None
+        } else {
+            Some(actions::journal::Journal::open(&journal_path)?)
+        };
+        let dangling = matching::links::find_dangling_links(source_paths, target_paths)?;
+        for link in &dangling {
+            if let (Some(audit), Some(journal)) = (&audit, &journal) {
+                let result = journal.prune(link);
+                audit.record(
+                    &audit_entry("prune", link),
+                    result.as_ref().map(|_| "removed"),
+                )?;
+                result?;
+                println!("Removed {link:?}");
+            } else {
+                println!("Would remove {link:?}");
+            }
+        }
+        if *dry_run {
+            println!("{} dangling symlinks would be removed", dangling.len());
+        } else {
+            println!("{} dangling symlinks removed", dangling.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Stats { by }) = &args.command {
+        let summaries = actions::journal::Journal::open(&journal_path)?.summaries()?;
+        let (periods, total) = stats::history(&summaries, *by);
+        let mut report = Report::new(args.output_format == OutputFormat::Json);
+        report.section("periods", &periods, || {
+            for period in &periods {
+                println!("{period}");
+            }
+        });
+        report.section("total", &total, || println!("{total}"));
+        report.finish()?;
+        return Ok(());
+    }
+
+    if let Some(Command::Undo { paths }) = &args.command {
+        let audit = actions::audit::AuditLog::open(&audit_path)?;
+        for restored in actions::journal::Journal::open(&journal_path)?.undo(paths)? {
+            println!("Restored {restored:?}");
+            audit.record(&audit_entry("undo", &restored), Ok("restored"))?;
+        }
+        return Ok(());
+    }
 
     let mut hasher: Box<dyn HashCache> = match args.hashing_cache {
         HashingCacheOptions::NoCache => Box::new(HashingNoCache::new()),
         HashingCacheOptions::File => {
-            Box::new(HashingFileCache::new(dirs.cache_dir().join("hashes.cache")).unwrap())
+            Box::new(HashingFileCache::new(hashing::file_cache::default_path()).unwrap())
         }
     };
 
-    let matching_files =
-        matching::find_matching_files(&args.source_paths, &args.target_paths, hasher.as_mut())?;
+    if let Some(Command::Diff {
+        dir_a,
+        dir_b,
+        skip_hidden,
+        one_file_system,
+        exclude,
+    }) = &args.command
+    {
+        let options = MatchOptions {
+            skip_hidden: *skip_hidden,
+            one_file_system: *one_file_system,
+            excludes: matching::exclude::Excludes::new(exclude)?,
+            progress: progress(&args),
+            ..Default::default()
+        };
+        let diff = matching::compare::diff_trees(dir_a, dir_b, hasher.as_mut(), &options)?;
+        print_diff(&diff, args.output_format == OutputFormat::Json)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Report {
+        paths,
+        skip_hidden,
+        one_file_system,
+        exclude,
+    }) = &args.command
+    {
+        let options = MatchOptions {
+            skip_hidden: *skip_hidden,
+            one_file_system: *one_file_system,
+            excludes: matching::exclude::Excludes::new(exclude)?,
+            progress: progress(&args),
+            ..Default::default()
+        };
+        if let Some(template) = &args.format {
+            template.check(&["path", "hash", "size", "group", "reclaimable"])?;
+        }
+        let groups = matching::duplicates::find_duplicates(paths, hasher.as_mut(), &options)?;
+        if let Some(template) = &args.format {
+            for (i, group) in groups.iter().enumerate() {
+                for path in &group.paths {
+                    println!(
+                        "{}",
+                        template.render(|field| match field {
+                            "path" => path.display().to_string(),
+                            "hash" => group.hash.to_string(),
+                            "size" => group.size.to_string(),
+                            "group" => (i + 1).to_string(),
+                            _ => group.reclaimable.to_string(),
+                        })
+                    );
+                }
+            }
+            return Ok(());
+        }
+        let duplicates: usize = groups.iter().map(|group| group.paths.len() - 1).sum();
+        let reclaimable: u64 = groups.iter().map(|group| group.reclaimable).sum();
+        let mut report = Report::new(args.output_format == OutputFormat::Json);
+        report.section("groups", &groups, || {
+            for group in &groups {
+                println!(
+                    "{} files of {} bytes, {} bytes reclaimable:",
+                    group.paths.len(),
+                    group.size,
+                    group.reclaimable
+                );
+                for path in &group.paths {
+                    println!("{}", path.display());
+                }
+                println!();
+            }
+        });
+        report.section("duplicates", &duplicates, || {
+            println!(
+                "{} groups, {duplicates} duplicate files, {reclaimable} bytes reclaimable",
+                groups.len()
+            )
+        });
+        report.section("reclaimable", &reclaimable, || {});
+        report.finish()?;
+        return Ok(());
+    }
+
+    if let Some(Command::Verify {
+        source_paths,
+        target_paths,
+        rehash,
+    }) = &args.command
+    {
+        let check =
+            matching::links::check_links(source_paths, target_paths, rehash.then_some(&*hasher))?;
+        let problems: Vec<String> = check.problems.iter().map(ToString::to_string).collect();
+        let mut report = Report::new(args.output_format == OutputFormat::Json);
+        report.section("checked", &check.checked, || {
+            println!(
+                "Checked {} links, {} of them rehashed: {} problems",
+                check.checked,
+                check.rehashed,
+                problems.len()
+            )
+        });
+        report.section("rehashed", &check.rehashed, || {});
+        report.section("problems", &problems, || {
+            for problem in &problems {
+                println!("  {problem}");
+            }
+        });
+        report.finish()?;
+        if !problems.is_empty() {
+            return Err(io::Error::other(format!(
+                "{} links failed verification",
+                problems.len()
+            )));
+        }
+        return Ok(());
+    }
+
+    let schedule = args
+        .every
+        .map(schedule::Schedule::Every)
+        .or(args.cron.clone());
+    let run = |hasher: &mut dyn HashCache| {
+        if args.map.is_empty() {
+            return reconcile(
+                &args,
+                plan.as_ref(),
+                &dirs,
+                &journal_path,
+                &open_audit_log,
+                hasher,
+            );
+        }
+        let mut total = actions::summary::RunSummary::new();
+        for mapping in &args.map {
+            if systemd::stop_requested() {
+                break;
+            }
+            let _span = tracing::info_span!("map", targets = ?mapping.targets).entered();
+            if args.output_format == OutputFormat::Text {
+                println!("Mapping {:?} to {:?}", mapping.targets, mapping.sources);
+            }
+            let args = Arguments {
+                source_paths: mapping.sources.clone(),
+                target_paths: mapping.targets.clone(),
+                ..args.clone()
+            };
+            total.add(&reconcile(
+                &args,
+                None,
+                &dirs,
+                &journal_path,
+                &open_audit_log,
+                hasher,
+            )?);
+        }
+        Ok(total)
+    };
+    let notifier = notify::Notifier {
+        url: args.notify_url.clone(),
+        command: args.notify_cmd.clone(),
+    };
+    let run = |hasher: &mut dyn HashCache| {
+        let result = run(hasher);
+        notifier.notify(&notify::RunOutcome::of(&result));
+        result
+    };
+    let Some(schedule) = schedule else {
+        run(hasher.as_mut())?;
+        return Ok(());
+    };
+    if !args.yes && !args.dry_run {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Scheduled runs replace files unattended, so need --yes",
+        ));
+    }
+    systemd::handle_sigterm();
+    systemd::start_watchdog();
+    systemd::notify("READY=1");
+    loop {
+        let started = chrono::Local::now();
+        let _span = tracing::info_span!("cycle", started = %started.format(TIME_FORMAT)).entered();
+        let cycle = systemd::Cycle::start();
+        let result = run(hasher.as_mut());
+        drop(cycle);
+        match result {
+            Ok(summary) => {
+                tracing::info!("Cycle finished: {summary}");
+                systemd::notify(&format!("STATUS=Cycle finished: {summary}"));
+            }
+            Err(e) => {
+                tracing::error!("Cycle failed: {e}");
+                systemd::notify(&format!("STATUS=Cycle failed: {e}"));
+            }
+        }
+        hasher.flush();
+        if systemd::stop_requested() {
+            systemd::notify("STOPPING=1");
+            tracing::info!("Stopped by SIGTERM");
+            return Ok(());
+        }
+        let now = chrono::Local::now();
+        let Some(wait) = schedule.wait(started, now) else {
+            tracing::info!("No more runs scheduled");
+            return Ok(());
+        };
+        tracing::info!("Next cycle at {}", (now + wait).format(TIME_FORMAT));
+        std::thread::sleep(wait);
+    }
+}
+
+/// How daemon cycles are timestamped in the logs
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Matches the sources against the targets and replaces the duplicates found, once, or
+/// replaces those of `plan`.
+fn reconcile(
+    args: &Arguments,
+    plan: Option<&actions::saved_plan::SavedPlan>,
+    dirs: &directories::ProjectDirs,
+    journal_path: &Path,
+    open_audit_log: &dyn Fn(bool) -> io::Result<Option<actions::audit::AuditLog>>,
+    hasher: &mut dyn HashCache,
+) -> io::Result<actions::summary::RunSummary> {
+    // Held until the run is over
+    let _lock = if args.dry_run {
+        None
+    } else {
+        Some(lock::RunLock::acquire(
+            &dirs.data_dir().join("locks"),
+            &args.target_paths,
+        )?)
+    };
+
+    let matcher: Box<dyn Matcher> = match args.matcher {
+        MatcherOptions::Hash => Box::new(HashMatcher::new()),
+        MatcherOptions::NameSize => Box::new(NameSizeMatcher::new()),
+    };
+
+    let options = MatchOptions {
+        same_device: !args.link_mode.contains(&LinkMode::Symlink),
+        skip_hidden: args.skip_hidden,
+        one_file_system: args.one_file_system,
+        excludes: matching::exclude::Excludes::new(&matching::exclude::collect_patterns(
+            &args.exclude,
+            &args.exclude_from,
+        )?)?,
+        older_than: args.older_than,
+        repair_broken_symlinks: args.repair_broken_symlinks,
+        rewrite_external_symlinks: args.rewrite_external_symlinks,
+        retarget_symlinks: args.retarget_symlinks,
+        detect_partial: args.detect_partial,
+        skip_in_use: args.skip_in_use,
+        allow_cross_device: args.allow_cross_device,
+        checkpoint_every: Some(args.checkpoint_interval),
+        progress: progress(args),
+        dir_configs: matching::dir_config::DirConfigs::new(
+            args.source_paths
+                .iter()
+                .chain(&args.target_paths)
+                .cloned()
+                .collect(),
+        ),
+    };
+
+    // Directory symlinks aren't part of the plan, so such runs can't be resumed
+    let checkpoint_path = (!args.dry_run && !args.link_directories).then(|| {
+        actions::checkpoint::path_for(
+            &dirs.data_dir().join("checkpoints"),
+            &args.source_paths,
+            &args.target_paths,
+        )
+    });
+    let resumed = match &checkpoint_path {
+        Some(path) if args.resume => {
+            let resumed =
+                actions::checkpoint::resume(path, &args.source_paths, &args.target_paths)?;
+            if resumed.is_none() {
+                tracing::warn!("No interrupted run over these paths to resume, starting over");
+            }
+            resumed
+        }
+        Some(path) if path.exists() => {
+            tracing::warn!(
+                "Starting over, though an interrupted run over these paths can be resumed with \
+                 --resume"
+            );
+            None
+        }
+        _ => None,
+    };
+
+    let planned = plan
+        .cloned()
+        .map(actions::saved_plan::SavedPlan::into_matches);
+    let applying_plan = planned.is_some();
+
+    let cache_before = hasher.stats();
+    let (mut matching_files, checkpoint) = match (resumed, planned) {
+        (Some((checkpoint, remaining)), _) => {
+            tracing::info!("Resuming with {} matches left", remaining.len());
+            (
+                matching::MatchingFiles::resume(remaining, matcher, &options),
+                Some(checkpoint),
+            )
+        }
+        (None, Some(planned)) => {
+            tracing::info!("Applying a plan of {} matches", planned.len());
+            (
+                matching::MatchingFiles::resume(planned, matcher, &options),
+                None,
+            )
+        }
+        (None, None) => (
+            matching::stream_matching_files(
+                &args.source_paths,
+                &args.target_paths,
+                hasher,
+                matcher,
+                &options,
+            )?,
+            None,
+        ),
+    };
+    let hashed = Instant::now();
+    let stats = matching_files.pipeline_stats();
+    tracing::info!(
+        "Size: {0} candidates, {1} remaining; prefix hash: {2} candidates, {3} remaining; \
+         full hash: {4} candidates, {5} remaining",
+        stats.size.candidates,
+        stats.size.remaining,
+        stats.prefix.candidates,
+        stats.prefix.remaining,
+        stats.full_hash.candidates,
+        stats.full_hash.remaining
+    );
+    if args.link_directories && matches!(args.command, Some(Command::Plan { .. })) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--link-directories can't be saved in a plan",
+        ));
+    }
+    if args.link_directories && args.link_mode[0] != LinkMode::Symlink {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--link-directories can only be used with symlinks",
+        ));
+    }
+    let mut link_options = actions::LinkOptions {
+        mode: args.link_mode[0],
+        fallbacks: args.link_mode[1..].to_vec(),
+        relative: args.relative,
+        guard: if args.protect_sources {
+            actions::SourceGuard::new(&args.source_paths)?
+        } else {
+            actions::SourceGuard::default()
+        },
+        trash: args
+            .trash
+            .as_deref()
+            .map(actions::trash::Trash::new)
+            .transpose()?,
+        // A plan may be long out of date, so its files are hashed again
+        rehash: args.verify_hash || applying_plan,
+        journal: if args.dry_run {
+            None
+        } else {
+            Some(actions::journal::Journal::open(journal_path)?)
+        },
+        throttle: args
+            .ops_per_second
+            .map(actions::throttle::Throttle::per_second)
+            .transpose()?,
+        skip_newer_targets: !args.force,
+        hooks: actions::hooks::Hooks {
+            pre: args.pre_hook.clone(),
+            post: args.post_hook.clone(),
+        },
+        audit: open_audit_log(args.dry_run)?,
+        quiet: args.output_format == OutputFormat::Json,
+        checkpoint: None,
+        progress: options.progress.clone(),
+        dir_configs: options.dir_configs.clone(),
+    };
+
+    let browsed = if args.browse {
+        let matches = matching_files.by_ref().collect::<io::Result<Vec<_>>>()?;
+        let mut browser =
+            browse::Browser::new(matches, |hash| matching_files.sources_with_hash(hash));
+        if !browser.run()? {
+            println!("Cancelled without replacing anything");
+            return Ok(actions::summary::RunSummary::new());
+        }
+        Some(browser.into_selected())
+    } else {
+        None
+    };
+    let (directories, file_matches): (Vec<_>, Box<dyn Iterator<Item = _>>) =
+        if let Some(matches) = browsed {
+            (Vec::new(), Box::new(matches.into_iter().map(Ok)))
+        } else if args.link_directories {
+            let matches = matching_files.by_ref().collect::<io::Result<Vec<_>>>()?;
+            let (directories, remaining) =
+                matching::directories::collapse_directories(matches, &args.target_paths)?;
+            (directories, Box::new(remaining.into_iter().map(Ok)))
+        } else {
+            (Vec::new(), Box::new(matching_files.by_ref()))
+        };
+    let (directories, file_matches) = if args.select {
+        let files = file_matches.collect::<io::Result<Vec<_>>>()?;
+        let mut selection = select::Selection::new(
+            directories
+                .iter()
+                .map(|directory| select::Entry {
+                    target: directory.dest_dir.clone(),
+                    source: directory.src_dir.clone(),
+                    size: directory.size,
+                    directory: true,
+                })
+                .chain(files.iter().map(|file| select::Entry {
+                    target: file.dest_path().to_path_buf(),
+                    source: file.src_path().to_path_buf(),
+                    size: file.size(),
+                    directory: false,
+                })),
+        );
+        if !selection.run()? {
+            println!("Cancelled without replacing anything");
+            return Ok(actions::summary::RunSummary::new());
+        }
+        let selected = selection.selected();
+        let directories = directories
+            .into_iter()
+            .filter(|directory| selected.contains(&directory.dest_dir))
+            .collect();
+        let files = files
+            .into_iter()
+            .filter(move |file| selected.contains(file.dest_path()))
+            .map(Ok);
+        (directories, Box::new(files) as Box<dyn Iterator<Item = _>>)
+    } else {
+        (directories, file_matches)
+    };
+    let file_matches: Box<dyn Iterator<Item = _>> =
+        if args.dry_run || args.yes || args.interactive || args.select || args.browse {
+            file_matches
+        } else {
+            let files = file_matches.collect::<io::Result<Vec<_>>>()?;
+            let confirmed =
+                (files.is_empty() && directories.is_empty()) || confirm_run(&files, &directories)?;
+            if !confirmed {
+                if let Some(checkpoint) = checkpoint {
+                    checkpoint.finish()?;
+                }
+                println!("Cancelled without replacing anything");
+                return Ok(actions::summary::RunSummary::new());
+            }
+            Box::new(files.into_iter().map(Ok))
+        };
+    // The whole plan is saved before applying any of it, so what is left can be resumed
+    let file_matches: Box<dyn Iterator<Item = _>> = match (checkpoint, &checkpoint_path) {
+        (Some(checkpoint), _) => {
+            link_options.checkpoint = Some(checkpoint);
+            file_matches
+        }
+        (None, Some(path)) => {
+            let files = file_matches.collect::<io::Result<Vec<_>>>()?;
+            link_options.checkpoint = Some(actions::checkpoint::Checkpoint::create(
+                path,
+                &args.source_paths,
+                &args.target_paths,
+                &files,
+            )?);
+            Box::new(files.into_iter().map(Ok))
+        }
+        (None, None) => file_matches,
+    };
+    let (file_matches, saved_plan): (Box<dyn Iterator<Item = _>>, _) = match &args.command {
+        Some(Command::Plan { out }) => {
+            let files = file_matches.collect::<io::Result<Vec<_>>>()?;
+            actions::saved_plan::SavedPlan::new(&args.source_paths, &args.target_paths, &files)
+                .save(out)?;
+            let saved = (out, files.len());
+            (Box::new(files.into_iter().map(Ok)), Some(saved))
+        }
+        _ => (file_matches, None),
+    };
+
+    let mut plan = Plan::new(args.output_format, args.link_mode[0])
+        .with_color(!args.no_color && io::stdout().is_terminal())
+        .with_template(args.format.clone());
+    if let Some(path) = &args.emit_script {
+        plan = plan.with_script(actions::script::Script::new(
+            path,
+            args.link_mode[0],
+            args.relative,
+        )?);
+    }
+    let mut summary = actions::summary::RunSummary::new();
+    let mut verifier = actions::verify::Verifier::new();
+    let result = apply(
+        args,
+        &directories,
+        file_matches,
+        &link_options,
+        &mut plan,
+        &mut summary,
+        &mut verifier,
+    );
+    let since_hashing = hashed.elapsed();
+    let mut report = Report::new(args.output_format == OutputFormat::Json)
+        .with_quiet(args.dry_run && args.format.is_some());
+    if let Some((path, matches)) = saved_plan {
+        report.section("saved_plan", path, || {
+            println!("Saved a plan of {matches} matches to {path:?}, apply it with `apply`")
+        });
+    }
+    let outcome = match result {
+        Ok(outcome) if systemd::stop_requested() => {
+            tracing::warn!("Stopped before applying every match, resume with --resume");
+            outcome
+        }
+        Ok(outcome) => {
+            if let Some(checkpoint) = link_options.checkpoint.take() {
+                checkpoint.finish()?;
+            }
+            outcome
+        }
+        Err(e) => {
+            let mut journal = link_options.journal.take();
+            if args.transactional
+                && let Some(transaction) = journal.take()
+            {
+                // Nothing is left applied to resume from
+                if let Some(checkpoint) = link_options.checkpoint.take() {
+                    checkpoint.finish()?;
+                }
+                tracing::error!("Rolling back after error: {e}");
+                let restored = transaction.rollback()?;
+                if let Some(audit) = &link_options.audit {
+                    for restored in &restored {
+                        audit.record(&audit_entry("rollback", restored), Ok("restored"))?;
+                    }
+                }
+                report.section("rolled_back", &restored, || {
+                    for restored in &restored {
+                        println!("Rolled back {restored:?}");
+                    }
+                });
+                // The summary only counts what stayed replaced
+                for restored in &restored {
+                    summary.files_linked = summary.files_linked.saturating_sub(1);
+                    let size = std::fs::metadata(restored).map_or(0, |m| m.len());
+                    summary.bytes_reclaimed = summary.bytes_reclaimed.saturating_sub(size);
+                }
+                journal = Some(actions::journal::Journal::open(journal_path)?);
+            }
+            if !args.dry_run {
+                summary.finish();
+                report.section("summary", &summary, || println!("{summary}"));
+                if let Some(journal) = &journal {
+                    journal.record_summary(&summary)?;
+                }
+            }
+            report.finish()?;
+            return Err(e);
+        }
+    };
+    if !outcome.leftover.is_empty() {
+        report.section("leftover", &outcome.leftover, || {
+            println!(
+                "Budget reached with {} matches left",
+                outcome.leftover.len()
+            )
+        });
+        if let Some(path) = &args.remaining_list {
+            manifest::write_path_list(path, &outcome.leftover)?;
+            report.section("remaining_list", path, || {
+                println!("Continue with --target-list {path:?}")
+            });
+        }
+    }
+    let (needs_privileges, failed): (Vec<_>, Vec<_>) = outcome
+        .failed
+        .iter()
+        .map(|(target, e)| (target, e.to_string(), actions::needs_privileges(e)))
+        .partition(|(_, _, needs_privileges)| *needs_privileges);
+    if !needs_privileges.is_empty() {
+        report.section("needs_privileges", &needs_privileges, || {
+            println!(
+                "Needs privileges: {} targets couldn't be replaced",
+                needs_privileges.len()
+            );
+            for (target, e, _) in &needs_privileges {
+                println!("  {target:?}: {e}");
+            }
+        });
+    }
+    if !failed.is_empty() {
+        report.section("failed", &failed, || {
+            println!("Failed: {} targets were skipped after errors", failed.len());
+            for (target, e, _) in &failed {
+                println!("  {target:?}: {e}");
+            }
+        });
+    }
     if args.dry_run {
-        actions::dry_run(&matching_files);
+        if report.is_json() {
+            let (actions, saved_bytes) = plan.into_actions()?;
+            report.section("actions", &actions, || {});
+            report.section("projected_saved_bytes", &saved_bytes, || {});
+        } else {
+            plan.finish(io::stdout())?;
+        }
     } else {
-        actions::symlink_matching_files(&matching_files)?;
+        report.section("actions", &plan.into_actions()?.0, || {});
+    }
+
+    let resumable = matching_files.resumable_duplicates();
+    if !resumable.is_empty() {
+        report.section("resumable_duplicates", resumable, || {
+            for resumable in resumable {
+                println!(
+                    "Resumable duplicate {0:?} is a partial copy of {1:?} ({2}/{3} bytes)",
+                    resumable.target,
+                    resumable.source,
+                    resumable.target_size,
+                    resumable.source_size
+                );
+            }
+        });
+    }
+
+    let quarantined = matching_files.quarantined();
+    if !quarantined.is_empty() {
+        let entries: Vec<_> = quarantined
+            .iter()
+            .map(|quarantined| {
+                serde_json::json!({
+                    "target": quarantined.matching.dest_path(),
+                    "target_size": quarantined.matching.size(),
+                    "source": quarantined.matching.src_path(),
+                    "source_size": quarantined.source_size,
+                    "hash": quarantined.matching.hash(),
+                })
+            })
+            .collect();
+        report.section("quarantined", &entries, || {
+            println!(
+                "Quarantined: {} matches share a hash with their source but not its size",
+                quarantined.len()
+            );
+            for quarantined in quarantined {
+                println!(
+                    "  {0:?} ({1} bytes) and {2:?} ({3} bytes), hash {4}",
+                    quarantined.matching.dest_path(),
+                    quarantined.matching.size(),
+                    quarantined.matching.src_path(),
+                    quarantined.source_size,
+                    quarantined.matching.hash()
+                );
+            }
+            if !args.review_quarantine {
+                println!("Review them with --review-quarantine");
+            }
+        });
+        // Dry runs set by --emit-script or `plan` rather than --dry-run
+        if args.review_quarantine && !args.dry_run {
+            // Prompts go to stderr to keep them out of the report on stdout
+            let mut prompter = confirm::Prompter::new(io::stdin().lock(), io::stderr());
+            for quarantined in quarantined {
+                match prompter.confirm_file(&quarantined.matching)? {
+                    confirm::Decision::Apply => {
+                        summary.file(actions::link_file(&quarantined.matching, &link_options)?);
+                    }
+                    confirm::Decision::Skip => summary.skipped += 1,
+                    confirm::Decision::Quit => break,
+                }
+            }
+        }
+    }
+    if !args.dry_run {
+        summary.finish();
+        report.section("summary", &summary, || println!("{summary}"));
+        if let Some(journal) = &link_options.journal {
+            journal.record_summary(&summary)?;
+        }
+    }
+
+    let cross_device = matching_files.cross_device_matches();
+    if !cross_device.is_empty() {
+        report.section("cross_device", cross_device, || {
+            for cross_device in cross_device {
+                println!(
+                    "Cross-device match {0:?} left alone as {1:?} is on {2}, use \
+                     --allow-cross-device to link it anyway",
+                    cross_device.target, cross_device.source, cross_device.detachable
+                );
+            }
+        });
+    }
+
+    if args.report_unmatched {
+        let unmatched = matching_files.unmatched_report()?;
+        report.section("unmatched", &unmatched, || {
+            for directory in &unmatched {
+                println!(
+                    "Unmatched in {0:?}: {1} files, {2} bytes",
+                    directory.dir,
+                    directory.files.len(),
+                    directory.size
+                );
+                for (path, size) in &directory.files {
+                    println!("  {path:?} ({size} bytes)");
+                }
+            }
+        });
+    }
+
+    if let Some(percent) = args.near_duplicates {
+        let near_duplicates = matching_files.near_duplicates(f64::from(percent) / 100.0)?;
+        report.section("near_duplicates", &near_duplicates, || {
+            for near_duplicate in &near_duplicates {
+                println!(
+                    "Near duplicate {0:?} shares {1:.1}% with {2:?}",
+                    near_duplicate.target,
+                    near_duplicate.similarity * 100.0,
+                    near_duplicate.source
+                );
+            }
+        });
+    }
+
+    let run_stats = stats::RunStats::new(
+        matching_files.match_stats(),
+        matching_files.pipeline_stats().prefix_bytes,
+        hasher.stats().since(&cache_before),
+        matches!(args.hashing_cache, HashingCacheOptions::File),
+        (!args.dry_run).then_some(&summary),
+        since_hashing,
+    );
+    tracing::info!("{run_stats}");
+    report.section("statistics", &run_stats, || println!("{run_stats}"));
+
+    if args.verify {
+        let discrepancies = verifier.run(args.verify_sample);
+        let verification = serde_json::json!({
+            "verified": verifier.count(),
+            "discrepancies": discrepancies.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        });
+        report.section("verification", &verification, || {
+            for discrepancy in &discrepancies {
+                println!("Verification failed for {discrepancy}");
+            }
+            println!(
+                "Verified {} replaced targets, {} discrepancies",
+                verifier.count(),
+                discrepancies.len()
+            );
+        });
+        if !discrepancies.is_empty() {
+            report.finish()?;
+            return Err(actions::verify::failed(&discrepancies));
+        }
+    }
+
+    report.finish()?;
+    Ok(summary)
+}
+
+/// Where to show progress while scanning and hashing. Also counted for the systemd watchdog,
+/// when there is one.
+fn progress(args: &Arguments) -> matching::progress::Progress {
+    let callbacks: Option<Arc<dyn matching::progress::ProgressCallbacks>> =
+        match args.progress_format {
+            _ if args.no_progress => None,
+            progress::ProgressFormat::Bar => Some(Arc::new(progress::ProgressBars::new())),
+            progress::ProgressFormat::Jsonl => Some(Arc::new(progress::JsonlProgress::new())),
+        };
+    if systemd::watchdog_enabled() {
+        return matching::progress::Progress::new(Arc::new(systemd::Watched(callbacks)));
+    }
+    callbacks
+        .map(matching::progress::Progress::new)
+        .unwrap_or_default()
+}
+
+/// Prints how two trees compare, section by section.
+fn print_diff(diff: &matching::compare::TreeDiff, json: bool) -> io::Result<()> {
+    let mut report = Report::new(json);
+    report.section("identical", &diff.identical, || {
+        println!("In both trees: {} distinct contents", diff.identical.len());
+        for identical in &diff.identical {
+            println!(
+                "  {:?} = {:?} ({} bytes)",
+                identical.a, identical.b, identical.size
+            );
+        }
+    });
+    report.section("changed", &diff.changed, || {
+        println!("Changed: {} paths differ", diff.changed.len());
+        for changed in &diff.changed {
+            println!(
+                "  {:?} ({} bytes, {} bytes)",
+                changed.path, changed.a_size, changed.b_size
+            );
+        }
+    });
+    for (key, side, only) in [
+        ("only_in_a", "first", &diff.only_in_a),
+        ("only_in_b", "second", &diff.only_in_b),
+    ] {
+        report.section(key, only, || {
+            println!("Only in the {side} tree: {} files", only.len());
+            for path in only {
+                println!("  {path:?}");
+            }
+        });
+    }
+    report.finish()
+}
+
+/// The reports printed after a run, either as text straight away or collected into a single
+/// JSON document printed at the end.
+struct Report {
+    json: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Leaves the text out, so only output from a --format template is printed
+    quiet: bool,
+}
+
+impl Report {
+    fn new(json: bool) -> Self {
+        Self {
+            json: json.then(serde_json::Map::new),
+            quiet: false,
+        }
+    }
+
+    fn with_quiet(self, quiet: bool) -> Self {
+        Self { quiet, ..self }
+    }
+
+    fn is_json(&self) -> bool {
+        self.json.is_some()
+    }
+
+    /// Adds `value` under `key` to the JSON document, or prints it with `text`.
+    fn section(
+        &mut self,
+        key: &str,
+        value: &(impl serde::Serialize + ?Sized),
+        text: impl FnOnce(),
+    ) {
+        match &mut self.json {
+            Some(json) => {
+                let value = serde_json::to_value(value).expect("Reports serialize to JSON");
+                json.insert(key.to_owned(), value);
+            }
+            None if self.quiet => {}
+            None => text(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if let Some(json) = self.json {
+            serde_json::to_writer_pretty(io::stdout().lock(), &json)?;
+            println!();
+        }
+        Ok(())
+    }
+}
+
+/// Asks on the terminal to go ahead with replacing `files` and `directories`.
+fn confirm_run(
+    files: &[matching::MatchingFile],
+    directories: &[matching::directories::DirectoryMatch],
+) -> io::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Refusing to replace {} files and {} directories without confirmation, pass \
+                 --yes to run unattended",
+                files.len(),
+                directories.len()
+            ),
+        ));
+    }
+    let directory_bytes: u64 = directories.iter().map(|directory| directory.size).sum();
+    let estimate = confirm::Estimate {
+        files: files.len(),
+        directories: directories.len(),
+        bytes: files.iter().map(|file| file.size()).sum::<u64>() + directory_bytes,
+        saved_bytes: files
+            .iter()
+            .map(|file| actions::saved_bytes(file.src_path(), file.dest_path(), file.size()))
+            .sum::<u64>()
+            + directory_bytes,
+    };
+    confirm::confirm_run(io::stdin().lock(), io::stdout(), &estimate)
+}
+
+/// An audit log entry for `action` on `target` alone.
+fn audit_entry<'a>(action: &'a str, target: &'a Path) -> actions::audit::AuditEntry<'a> {
+    actions::audit::AuditEntry {
+        action,
+        target,
+        source: None,
+        hash: None,
+    }
+}
+
+/// Replaces every directory and file match, or just prints them in a dry run.
+fn apply(
+    args: &Arguments,
+    directories: &[matching::directories::DirectoryMatch],
+    file_matches: impl Iterator<Item = io::Result<matching::MatchingFile>>,
+    link_options: &actions::LinkOptions,
+    plan: &mut Plan,
+    summary: &mut actions::summary::RunSummary,
+    verifier: &mut actions::verify::Verifier,
+) -> io::Result<Outcome> {
+    let _span = tracing::info_span!("apply").entered();
+    let _applying = (!args.dry_run).then(systemd::Applying::start);
+    // Stopping for SIGTERM leaves the rest to resume from the checkpoint
+    let file_matches = file_matches.take_while(|_| !systemd::stop_requested());
+    let mut prompter = confirm::Prompter::new(io::stdin().lock(), io::stdout());
+    let mut budget = actions::budget::Budget::new(args.max_actions, args.max_bytes);
+    let mut outcome = Outcome::default();
+    for directory in directories {
+        if systemd::stop_requested() {
+            return Ok(outcome);
+        }
+        if !budget.take(directory.size) {
+            outcome.leftover.push(directory.dest_dir.clone());
+            continue;
+        }
+        if args.interactive {
+            match prompter.confirm_directory(directory)? {
+                confirm::Decision::Apply => {}
+                confirm::Decision::Skip => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                confirm::Decision::Quit => return Ok(outcome),
+            }
+        }
+        if args.dry_run {
+            plan.directory(directory)?;
+        } else {
+            let result = args
+                .on_error
+                .retry(|| actions::link_directory(directory, link_options));
+            plan.applied_directory(directory, &result);
+            match result {
+                Ok(applied) => {
+                    verifier.directory(directory, applied);
+                    summary.directory(applied);
+                }
+                Err(e) => failed(args, summary, &mut outcome.failed, &directory.dest_dir, e)?,
+            }
+        }
+    }
+    if args.jobs.get() > 1 && !args.dry_run {
+        let leftover = &mut outcome.leftover;
+        let failures = &mut outcome.failed;
+        let budgeted = file_matches.filter(|matching_file| match matching_file {
+            Ok(matching_file) if !budget.take(matching_file.size()) => {
+                leftover.push(matching_file.dest_path().to_path_buf());
+                false
+            }
+            _ => true,
+        });
+        actions::parallel::run_keyed(
+            args.jobs,
+            budgeted,
+            |matching_file| matching_file.dest_path().parent().unwrap_or(Path::new("")),
+            |matching_file| {
+                args.on_error
+                    .retry(|| actions::link_file(matching_file, link_options))
+            },
+            |matching_file, result| {
+                plan.applied_file(&matching_file, &result);
+                match result {
+                    Ok(applied) => {
+                        verifier.file(&matching_file, applied);
+                        summary.file(applied);
+                        Ok(())
+                    }
+                    Err(e) => failed(args, summary, failures, matching_file.dest_path(), e),
+                }
+            },
+        )?;
+        return Ok(outcome);
     }
+    for matching_file in file_matches {
+        let matching_file = matching_file?;
+        if !budget.take(matching_file.size()) {
+            outcome
+                .leftover
+                .push(matching_file.dest_path().to_path_buf());
+            continue;
+        }
+        if args.interactive {
+            match prompter.confirm_file(&matching_file)? {
+                confirm::Decision::Apply => {}
+                confirm::Decision::Skip => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                confirm::Decision::Quit => return Ok(outcome),
+            }
+        }
+        if args.dry_run {
+            plan.file(&matching_file)?;
+        } else {
+            let result = args
+                .on_error
+                .retry(|| actions::link_file(&matching_file, link_options));
+            plan.applied_file(&matching_file, &result);
+            match result {
+                Ok(applied) => {
+                    verifier.file(&matching_file, applied);
+                    summary.file(applied);
+                }
+                Err(e) => failed(
+                    args,
+                    summary,
+                    &mut outcome.failed,
+                    matching_file.dest_path(),
+                    e,
+                )?,
+            }
+        }
+    }
+    Ok(outcome)
+}
 
+/// What's left to report once the matches have been applied.
+#[derive(Debug, Default)]
+struct Outcome {
+    /// Targets not replaced because the budget ran out
+    leftover: Vec<PathBuf>,
+    /// Targets that couldn't be replaced without ending the run, for lack of privileges or
+    /// with --on-error skip
+    failed: Vec<(PathBuf, io::Error)>,
+}
+
+/// Counts a failure to replace `target`. A transactional run always ends; otherwise only
+/// failures that aren't for lack of privileges end it, unless errors are to be skipped.
+fn failed(
+    args: &Arguments,
+    summary: &mut actions::summary::RunSummary,
+    failures: &mut Vec<(PathBuf, io::Error)>,
+    target: &Path,
+    e: io::Error,
+) -> io::Result<()> {
+    summary.failures += 1;
+    if args.transactional || !(args.on_error == OnError::Skip || actions::needs_privileges(&e)) {
+        return Err(e);
+    }
+    tracing::warn!("Can't replace {target:?}: {e}");
+    failures.push((target.to_path_buf(), e));
+    Ok(())
+}
+
+/// Replaces a `-` among `targets` by the paths `read_list` reads from stdin, and adds those
+/// listed in `list`, which may be stdin too. Stdin can only be read once.
+fn expand_targets(
+    targets: &mut Vec<PathBuf>,
+    list: Option<&Path>,
+    read_list: impl Fn(&Path) -> io::Result<Vec<PathBuf>>,
+) -> io::Result<()> {
+    let stdin = Path::new("-");
+    let from_stdin =
+        targets.iter().filter(|p| *p == stdin).count() + usize::from(list == Some(stdin));
+    if from_stdin > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Target paths can only be read from stdin once",
+        ));
+    }
+    if let Some(i) = targets.iter().position(|p| p == stdin) {
+        let read = read_list(stdin)?;
+        targets.splice(i..=i, read);
+    }
+    if let Some(list) = list {
+        targets.extend(read_list(list)?);
+    }
     Ok(())
 }
 
 fn create_dirs(dirs: &ProjectDirs) -> io::Result<()> {
     std::fs::create_dir_all(dirs.cache_dir())?;
+    std::fs::create_dir_all(dirs.data_dir())?;
+    std::fs::create_dir_all(dirs.data_dir().join("checkpoints"))?;
     Ok(())
 }
+
+/// Parses durations such as `30d`, `12h`, `90m`, `45s` or `2w`. A bare number is taken as seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration {s:?}: expected a number followed by a unit"))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit {unit:?}: expected s, m, h, d or w"
+            ));
+        }
+    };
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Duration {s:?} is too long"))
+}
+
+/// Parses the time between scheduled runs, which can't be zero.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match parse_duration(s)? {
+        Duration::ZERO => Err(format!("Interval {s:?} must be longer than zero")),
+        interval => Ok(interval),
+    }
+}
+
+/// Parses a hash buffer size, refusing those `hashing::set_buffer_size` would clamp.
+fn parse_hash_buffer(s: &str) -> Result<usize, String> {
+    let size = parse_size(s)?;
+    usize::try_from(size)
+        .ok()
+        .filter(|size| (hashing::MIN_BUFFER_SIZE..=hashing::MAX_BUFFER_SIZE).contains(size))
+        .ok_or_else(|| format!("Hash buffer size {s:?} isn't between 4K and 64M"))
+}
+
+/// Parses sizes such as `500M`, `20G` or `1T` in binary units. A bare number is taken as bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid size {s:?}: expected a number followed by a unit"))?;
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("Invalid size unit {unit:?}: expected K, M, G or T")),
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size {s:?} is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_targets() {
+        let read_list = |path: &Path| -> io::Result<Vec<PathBuf>> {
+            Ok(if path == Path::new("-") {
+                vec!["/stdin/a".into(), "/stdin/b".into()]
+            } else {
+                vec![path.join("listed")]
+            })
+        };
+        let expand = |targets: &[&str], list: Option<&str>| {
+            let mut targets = targets.iter().map(PathBuf::from).collect();
+            expand_targets(&mut targets, list.map(Path::new), read_list).map(|()| targets)
+        };
+
+        // Read in place of the `-`
+        assert_eq!(
+            expand(&["/tv", "-", "/movies"], None).unwrap(),
+            [
+                Path::new("/tv"),
+                Path::new("/stdin/a"),
+                Path::new("/stdin/b"),
+                Path::new("/movies")
+            ]
+        );
+        assert_eq!(
+            expand(&["/tv"], Some("-")).unwrap(),
+            [
+                Path::new("/tv"),
+                Path::new("/stdin/a"),
+                Path::new("/stdin/b")
+            ]
+        );
+        assert_eq!(
+            expand(&["-"], Some("/list")).unwrap(),
+            [
+                Path::new("/stdin/a"),
+                Path::new("/stdin/b"),
+                Path::new("/list/listed")
+            ]
+        );
+        assert_eq!(expand(&["/tv"], None).unwrap(), [Path::new("/tv")]);
+        for (targets, list) in [(&["-", "-"][..], None), (&["-"], Some("-"))] {
+            let e = expand(targets, list).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let days = |days: u64| Duration::from_secs(days * 24 * 60 * 60);
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration(" 2h "), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("30d"), Ok(days(30)));
+        assert_eq!(parse_duration("2w"), Ok(days(14)));
+        assert_eq!(parse_duration("0d"), Ok(Duration::ZERO));
+
+        let overflowing = format!("{}w", u64::MAX / 2);
+        assert!(
+            parse_duration(&overflowing)
+                .unwrap_err()
+                .contains("too long")
+        );
+        assert!(parse_duration("99999999999999999999s").is_err());
+        for garbage in ["", "d", "1.5h", "-1d", "1 d", "10y", "7D"] {
+            assert!(parse_duration(garbage).is_err(), "{garbage:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("500m"), Ok(500 << 20));
+        assert_eq!(parse_size("20GB"), Ok(20 << 30));
+        assert_eq!(parse_size("1TiB"), Ok(1 << 40));
+        assert_eq!(parse_size(" 2G "), Ok(2 << 30));
+        assert_eq!(parse_size("0"), Ok(0));
+
+        let overflowing = format!("{}T", u64::MAX >> 20);
+        assert!(parse_size(&overflowing).unwrap_err().contains("too large"));
+        assert!(parse_size("99999999999999999999").is_err());
+        for garbage in ["", "G", "1.5G", "-1K", "1 G", "10P", "1KX"] {
+            assert!(parse_size(garbage).is_err(), "{garbage:?}");
+        }
+
+        assert_eq!(parse_hash_buffer("4K"), Ok(4 << 10));
+        assert_eq!(parse_hash_buffer("1M"), Ok(1 << 20));
+        assert_eq!(parse_hash_buffer("64M"), Ok(64 << 20));
+        for refused in ["0", "4095", "65M", "1T", &overflowing, "fast"] {
+            assert!(parse_hash_buffer(refused).is_err(), "{refused:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("6h"), Ok(Duration::from_secs(6 * 60 * 60)));
+        for refused in ["0", "0s", "0w"] {
+            assert!(
+                parse_interval(refused)
+                    .unwrap_err()
+                    .contains("longer than zero")
+            );
+        }
+        let overflowing = format!("{}w", u64::MAX / 2);
+        assert!(
+            parse_interval(&overflowing)
+                .unwrap_err()
+                .contains("too long")
+        );
+        assert!(parse_interval("hourly").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{self, BufRead, Write};
+
+use crate::matching::MatchingFile;
+use crate::matching::directories::DirectoryMatch;
+
+/// What to do with a single proposed replacement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Apply,
+    Skip,
+    /// Stop without applying anything further
+    Quit,
+}
+
+/// Asks the user to confirm each replacement with y(es), n(o), a(ll remaining) or q(uit).
+pub struct Prompter<R, W> {
+    input: R,
+    output: W,
+    accept_all: bool,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            accept_all: false,
+        }
+    }
+
+    pub fn confirm_file(&mut self, matching_file: &MatchingFile) -> io::Result<Decision> {
+        self.confirm(&format!(
+            "Replace {0:?}\n   with {1:?}\n   {2} bytes, hash {3}",
+            matching_file.dest_path(),
+            matching_file.src_path(),
+            matching_file.size(),
+            matching_file.hash()
+        ))
+    }
+
+    pub fn confirm_directory(&mut self, directory: &DirectoryMatch) -> io::Result<Decision> {
+        self.confirm(&format!(
+            "Replace directory {0:?}\n   with {1:?}\n   {2} bytes",
+            directory.dest_dir, directory.src_dir, directory.size
+        ))
+    }
+
+    fn confirm(&mut self, description: &str) -> io::Result<Decision> {
+        if self.accept_all {
+            return Ok(Decision::Apply);
+        }
+        writeln!(self.output, "{description}")?;
+        loop {
+            write!(self.output, "Apply? [y/n/a/q] ")?;
+            self.output.flush()?;
+            let mut answer = String::new();
+            // End of input is taken as quitting
+            if self.input.read_line(&mut answer)? == 0 {
+                return Ok(Decision::Quit);
+            }
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => return Ok(Decision::Apply),
+                "n" | "no" => return Ok(Decision::Skip),
+                "a" | "all" => {
+                    self.accept_all = true;
+                    return Ok(Decision::Apply);
+                }
+                "q" | "quit" => return Ok(Decision::Quit),
+                _ => writeln!(self.output, "Please answer y, n, a or q")?,
+            }
+        }
+    }
+}
+
+/// How much a run is about to replace.
+#[derive(Debug, Default)]
+pub struct Estimate {
+    pub files: usize,
+    pub directories: usize,
+    pub bytes: u64,
+    /// Bytes expected to be freed
+    pub saved_bytes: u64,
+}
+
+/// Asks for `yes` to be typed before a run replaces anything, saying what it's about to replace.
+/// Anything else declines.
+pub fn confirm_run(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    estimate: &Estimate,
+) -> io::Result<bool> {
+    writeln!(
+        output,
+        "About to replace {0} files and {1} directories, {2} bytes in all, freeing about {3} \
+         bytes. Replaced files are deleted.",
+        estimate.files, estimate.directories, estimate.bytes, estimate.saved_bytes
+    )?;
+    write!(output, "Type yes to continue: ")?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::MatchReason;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_prompter() {
+        let matching = MatchingFile::new(
+            PathBuf::from("/source/file"),
+            PathBuf::from("/target/file"),
+            7,
+            "ABC".to_string(),
+            MatchReason::SourceScan,
+        );
+        let mut output = Vec::new();
+        let mut prompter = Prompter::new(&b"n\nwhat\ny\na\n"[..], &mut output);
+
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Skip);
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Apply);
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Apply);
+        // Everything is accepted after answering all, without reading any more input
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Apply);
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Replace \"/target/file\"").count(), 3);
+        assert!(output.contains("7 bytes, hash ABC"));
+        assert!(output.contains("Please answer y, n, a or q"));
+    }
+
+    #[test]
+    fn test_prompter_quit() {
+        let matching = MatchingFile::new(
+            PathBuf::from("/source/file"),
+            PathBuf::from("/target/file"),
+            7,
+            String::new(),
+            MatchReason::SourceScan,
+        );
+        let mut prompter = Prompter::new(&b"q\n"[..], Vec::new());
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Quit);
+
+        let mut prompter = Prompter::new(&b""[..], Vec::new());
+        assert_eq!(prompter.confirm_file(&matching).unwrap(), Decision::Quit);
+    }
+
+    #[test]
+    fn test_confirm_run() {
+        let estimate = Estimate {
+            files: 2,
+            directories: 1,
+            bytes: 300,
+            saved_bytes: 200,
+        };
+        let mut output = Vec::new();
+        assert!(confirm_run(&b"yes\n"[..], &mut output, &estimate).unwrap());
+        assert!(String::from_utf8(output).unwrap().contains(
+            "About to replace 2 files and 1 directories, 300 bytes in all, freeing about 200 bytes"
+        ));
+        assert!(!confirm_run(&b"y\n"[..], Vec::new(), &estimate).unwrap());
+        assert!(!confirm_run(&b""[..], Vec::new(), &estimate).unwrap());
+    }
+}
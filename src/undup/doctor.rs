@@ -0,0 +1,347 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    actions::{self, LinkMode},
+    bench,
+    hashing::file_cache::HashingFileCache,
+};
+
+/// How many unwritable directories are named before just counting the rest.
+const EXAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Ok,
+    Warning,
+    Problem,
+}
+
+/// The outcome of one check, with what to do about it when it isn't ok.
+#[derive(Debug, serde::Serialize)]
+pub struct Finding {
+    pub level: Level,
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(level: Level, check: &'static str, message: String) -> Self {
+        Self {
+            level,
+            check,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Ok => "ok",
+            Level::Warning => "warning",
+            Level::Problem => "PROBLEM",
+        };
+        write!(f, "[{level}] {}: {}", self.check, self.message)
+    }
+}
+
+/// What a real run would use, to be checked before it.
+#[derive(Debug)]
+pub struct Checkup<'a> {
+    pub sources: &'a [PathBuf],
+    pub targets: &'a [PathBuf],
+    /// The link modes to try in order
+    pub modes: &'a [LinkMode],
+    /// The hash cache, unless running without one
+    pub cache: Option<&'a Path>,
+    /// Directories the journal, audit log and such are written to
+    pub state_dirs: Vec<&'a Path>,
+}
+
+impl Checkup<'_> {
+    /// Runs every check without changing anything, beyond links made and removed right away.
+    pub fn run(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let existing = |paths: &[PathBuf], findings: &mut Vec<Finding>| -> Vec<PathBuf> {
+            paths
+                .iter()
+                .filter(|path| match fs::metadata(path) {
+                    Ok(metadata) if metadata.is_dir() => true,
+                    Ok(_) => {
+                        findings.push(problem("paths", format!("{path:?} isn't a directory")));
+                        false
+                    }
+                    Err(e) => {
+                        findings.push(problem("paths", format!("Can't access {path:?}: {e}")));
+                        false
+                    }
+                })
+                .cloned()
+                .collect()
+        };
+        let sources = existing(self.sources, &mut findings);
+        let targets = existing(self.targets, &mut findings);
+
+        for source in &sources {
+            let sample = match bench::sample(std::slice::from_ref(source), 1) {
+                Ok(files) => files.into_iter().next(),
+                Err(e) => {
+                    findings.push(problem("paths", format!("Can't scan {source:?}: {e}")));
+                    continue;
+                }
+            };
+            for target in &targets {
+                findings.push(self.layout(source, target));
+                match &sample {
+                    Some(file) => findings.extend(self.links(file, target)),
+                    None => findings.push(Finding::new(
+                        Level::Warning,
+                        "links",
+                        format!("No files in {source:?} to try linking into {target:?} with"),
+                    )),
+                }
+            }
+        }
+        for target in &targets {
+            findings.push(permissions(target));
+        }
+        if let Some(cache) = self.cache {
+            findings.push(cache_health(cache));
+        }
+        let mut dirs: Vec<&Path> = self
+            .cache
+            .and_then(Path::parent)
+            .into_iter()
+            .chain(self.state_dirs.iter().copied())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs {
+            findings.push(match writable(dir) {
+                Ok(()) => Finding::new(Level::Ok, "state", format!("{dir:?} is writable")),
+                Err(e) => problem("state", format!("Can't write to {dir:?}: {e}")),
+            });
+        }
+        findings
+    }
+
+    /// Whether `source` and `target` are on one filesystem, which all but symlinks need.
+    fn layout(&self, source: &Path, target: &Path) -> Finding {
+        match (device(source), device(target)) {
+            (Some(a), Some(b)) if a != b => {
+                let level = if self.modes.contains(&LinkMode::Symlink) {
+                    Level::Ok
+                } else {
+                    Level::Problem
+                };
+                Finding::new(
+                    level,
+                    "layout",
+                    format!(
+                        "{source:?} and {target:?} are on different filesystems, so only \
+                         symlinks can link across them"
+                    ),
+                )
+            }
+            (Some(_), Some(_)) => Finding::new(
+                Level::Ok,
+                "layout",
+                format!("{source:?} and {target:?} are on the same filesystem"),
+            ),
+            _ => Finding::new(
+                Level::Warning,
+                "layout",
+                format!("Couldn't tell whether {source:?} and {target:?} share a filesystem"),
+            ),
+        }
+    }
+
+    /// Tries each mode from `file` into `target`. A mode failing is only a problem when none of
+    /// the fallbacks work either.
+    fn links(&self, file: &Path, target: &Path) -> Vec<Finding> {
+        let results: Vec<_> = self
+            .modes
+            .iter()
+            .map(|&mode| (mode, actions::probe_link(mode, file, target)))
+            .collect();
+        let any_works = results.iter().any(|(_, result)| result.is_ok());
+        results
+            .into_iter()
+            .map(|(mode, result)| match result {
+                Ok(()) => Finding::new(
+                    Level::Ok,
+                    "links",
+                    format!("{mode:?} works from {file:?} into {target:?}"),
+                ),
+                Err(e) => Finding::new(
+                    if any_works {
+                        Level::Warning
+                    } else {
+                        Level::Problem
+                    },
+                    "links",
+                    format!(
+                        "{mode:?} fails from {file:?} into {target:?}: {e}. {}",
+                        if any_works {
+                            "Another --link-mode will be used instead"
+                        } else {
+                            "Pick a --link-mode that works here, e.g. symlink"
+                        }
+                    ),
+                ),
+            })
+            .collect()
+    }
+}
+
+fn problem(check: &'static str, message: String) -> Finding {
+    Finding::new(Level::Problem, check, message)
+}
+
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Replacing a file needs write permission on the directory it's in, so every directory under
+/// `target` is checked.
+fn permissions(target: &Path) -> Finding {
+    let mut unwritable = Vec::new();
+    let mut pending = vec![target.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if !is_writable(&dir) {
+            unwritable.push(dir.clone());
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            unwritable.push(dir);
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                pending.push(entry.path());
+            }
+        }
+    }
+    unwritable.dedup();
+    if unwritable.is_empty() {
+        return Finding::new(
+            Level::Ok,
+            "permissions",
+            format!("Every directory under {target:?} can be modified"),
+        );
+    }
+    let examples: Vec<_> = unwritable.iter().take(EXAMPLES).collect();
+    problem(
+        "permissions",
+        format!(
+            "{} directories under {target:?} can't be read or modified, e.g. {examples:?}. Files \
+             in them will fail to be replaced: run as their owner or fix their permissions",
+            unwritable.len()
+        ),
+    )
+}
+
+#[cfg(unix)]
+fn is_writable(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt as _;
+    let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a valid NUL terminated string
+    unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_writable(dir: &Path) -> bool {
+    fs::metadata(dir).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// Whether a file can be created in `dir`, trying it.
+fn writable(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".atorrlinker-doctor-{}", std::process::id()));
+    fs::File::create_new(&probe)?;
+    fs::remove_file(&probe)
+}
+
+fn cache_health(cache: &Path) -> Finding {
+    if !cache.exists() {
+        return Finding::new(
+            Level::Ok,
+            "cache",
+            format!("No hash cache at {cache:?} yet, it will be created"),
+        );
+    }
+    match HashingFileCache::check(cache) {
+        Ok(hashes) => Finding::new(
+            Level::Ok,
+            "cache",
+            format!("The hash cache at {cache:?} holds {hashes} hashes"),
+        ),
+        Err(e) => problem(
+            "cache",
+            format!(
+                "The hash cache at {cache:?} can't be read: {e}. Delete it to start a new one, \
+                 or run with --hashing-cache no-cache"
+            ),
+        ),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target, state) = (
+            dir.path().join("source"),
+            dir.path().join("target"),
+            dir.path().join("state"),
+        );
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join("e01.mkv"), "episode").unwrap();
+        let cache = dir.path().join("hashes.cache");
+        fs::write(&cache, "not json").unwrap();
+
+        let sources = [source];
+        let targets = [target.clone(), dir.path().join("missing")];
+        let findings = Checkup {
+            sources: &sources,
+            targets: &targets,
+            modes: &[LinkMode::Symlink, LinkMode::Hardlink],
+            cache: Some(&cache),
+            state_dirs: vec![&state],
+        }
+        .run();
+
+        let level = |check: &str, containing: &str| {
+            findings
+                .iter()
+                .find(|finding| finding.check == check && finding.message.contains(containing))
+                .map(|finding| finding.level)
+        };
+        assert_eq!(level("paths", "missing"), Some(Level::Problem));
+        assert_eq!(level("layout", "same filesystem"), Some(Level::Ok));
+        assert_eq!(level("links", "Symlink works"), Some(Level::Ok));
+        assert_eq!(level("links", "Hardlink works"), Some(Level::Ok));
+        assert_eq!(level("permissions", "target"), Some(Level::Ok));
+        assert_eq!(level("cache", "can't be read"), Some(Level::Problem));
+        assert_eq!(level("state", "state"), Some(Level::Ok));
+        // Nothing is left behind
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&state).unwrap().count(), 0);
+    }
+}
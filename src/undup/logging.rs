@@ -0,0 +1,161 @@
+use std::{
+    io::{self, IsTerminal as _},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    EnvFilter, Layer as _, layer::SubscriberExt as _, util::SubscriberInitExt as _,
+};
+
+/// Environment variable with filter directives, used when neither -v nor -q is given
+const ENV_VAR: &str = "ATORR_LOG";
+
+/// The modules of the engine, which log under the library crate rather than this program.
+const ENGINE_MODULES: &[&str] = &["actions", "hashing", "lock", "matching", "template"];
+
+/// Filter directives for `verbose` times -v, or -q, followed by per-module overrides such as
+/// `matching::pipeline=debug`. Module paths are relative to this program or the engine.
+fn directives(verbose: u8, quiet: bool, env: Option<String>, modules: &[String]) -> String {
+    let level = match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    };
+    let mut directives = match env {
+        Some(env) if verbose == 0 && !quiet => env,
+        _ => level.to_owned(),
+    };
+    for module in modules {
+        directives.push(',');
+        let first = module.split([':', '=']).next().unwrap_or_default();
+        if module.contains('=') && ![env!("CARGO_CRATE_NAME"), "atorrlinker"].contains(&first) {
+            if ENGINE_MODULES.contains(&first) {
+                directives.push_str("atorrlinker::");
+            } else {
+                directives.push_str(concat!(env!("CARGO_CRATE_NAME"), "::"));
+            }
+        }
+        directives.push_str(module);
+    }
+    directives
+}
+
+/// When a log file is moved aside to start a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+    /// Once it grows past this many bytes
+    Size(u64),
+}
+
+/// Parses `hourly`, `daily` or a size such as `100M`.
+pub fn parse_rotation(s: &str) -> Result<Rotation, String> {
+    match s {
+        "hourly" => Ok(Rotation::Hourly),
+        "daily" => Ok(Rotation::Daily),
+        size => crate::parse_size(size)
+            .map(Rotation::Size)
+            .map_err(|e| format!("{e}, or hourly or daily")),
+    }
+}
+
+/// A file logged to on top of stderr, at its own level whatever -v/-q say.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub level: LevelFilter,
+    pub rotation: Rotation,
+    /// How many rotated files are kept, as `FILE.1` (the newest) to `FILE.N`
+    pub keep: usize,
+}
+
+impl LogFile {
+    fn open(&self) -> io::Result<BasicRollingFileAppender> {
+        let condition = match self.rotation {
+            Rotation::Hourly => RollingConditionBasic::new().hourly(),
+            Rotation::Daily => RollingConditionBasic::new().daily(),
+            Rotation::Size(size) => RollingConditionBasic::new().max_size(size),
+        };
+        // Unbuffered, so nothing is lost while a daemon sleeps or when it is killed
+        BasicRollingFileAppender::new_with_buffer_capacity(&self.path, condition, self.keep, 0)
+            .map_err(|e| io::Error::new(e.kind(), format!("Opening log file {:?}: {e}", self.path)))
+    }
+}
+
+/// Logs to stderr at the level picked by -v/-q and `modules`, or `ATORR_LOG` without either.
+/// Colored when stderr is a terminal, unless `no_color`. Also logs to `file` if given.
+pub fn init(
+    verbose: u8,
+    quiet: bool,
+    modules: &[String],
+    no_color: bool,
+    file: Option<&LogFile>,
+) -> io::Result<()> {
+    let directives = directives(verbose, quiet, std::env::var(ENV_VAR).ok(), modules);
+    let filter = EnvFilter::builder().parse(&directives).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid log filter {directives:?}: {e}"),
+        )
+    })?;
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(!no_color && io::stderr().is_terminal())
+        .with_filter(filter);
+    let file = match file {
+        Some(file) => Some(
+            tracing_subscriber::fmt::layer()
+                .with_writer(Mutex::new(file.open()?))
+                .with_ansi(false)
+                .with_filter(file.level),
+        ),
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .init();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directives() {
+        assert_eq!(directives(0, false, None, &[]), "warn");
+        assert_eq!(directives(2, false, None, &[]), "debug");
+        assert_eq!(directives(5, false, None, &[]), "trace");
+        assert_eq!(directives(0, true, None, &[]), "error");
+        // The environment is only used without -v or -q
+        assert_eq!(directives(0, false, Some("info".into()), &[]), "info");
+        assert_eq!(directives(1, false, Some("trace".into()), &[]), "info");
+
+        let modules = [
+            "matching=debug".to_owned(),
+            "hashing::file_cache=off".to_owned(),
+            "atorrlinker::actions=trace".to_owned(),
+            "progress=info".to_owned(),
+        ];
+        assert_eq!(
+            directives(0, true, None, &modules),
+            "error,atorrlinker::matching=debug,atorrlinker::hashing::file_cache=off,\
+             atorrlinker::actions=trace,atorrlinker_undup::progress=info"
+        );
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!(parse_rotation("daily"), Ok(Rotation::Daily));
+        assert_eq!(parse_rotation("hourly"), Ok(Rotation::Hourly));
+        assert_eq!(parse_rotation("100M"), Ok(Rotation::Size(100 << 20)));
+        assert!(parse_rotation("weekly").is_err());
+    }
+}
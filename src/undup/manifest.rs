@@ -0,0 +1,105 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Reads a list of paths from `path`, or from stdin when `path` is `-`. Paths are NUL delimited
+/// if the list contains any NUL bytes (as from `find -print0`) and newline delimited otherwise.
+pub fn read_path_list(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = if path == Path::new("-") {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents)?;
+        contents
+    } else {
+        fs::read(path)?
+    };
+    parse_path_list(&contents)
+}
+
+/// Writes `paths` to a file readable by [`read_path_list`], NUL delimited if any path contains a
+/// line break and newline delimited otherwise.
+pub fn write_path_list(path: &Path, paths: &[PathBuf]) -> io::Result<()> {
+    let separator = if paths
+        .iter()
+        .any(|p| p.as_os_str().as_encoded_bytes().contains(&b'\n'))
+    {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    for path in paths {
+        out.write_all(path.as_os_str().as_encoded_bytes())?;
+        out.write_all(&[separator])?;
+    }
+    out.flush()
+}
+
+fn parse_path_list(contents: &[u8]) -> io::Result<Vec<PathBuf>> {
+    let entries: Vec<&[u8]> = if contents.contains(&0) {
+        contents.split(|&b| b == 0).collect()
+    } else {
+        contents
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .collect()
+    };
+    entries
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(bytes_to_path)
+        .collect()
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    String::from_utf8(bytes.to_vec())
+        .map(PathBuf::from)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path_list() {
+        assert_eq!(
+            parse_path_list(b"/a/one.mkv\r\n/a/two words.mkv\n\n").unwrap(),
+            vec![
+                PathBuf::from("/a/one.mkv"),
+                PathBuf::from("/a/two words.mkv")
+            ]
+        );
+        assert_eq!(
+            parse_path_list(b"/a/line\nbreak.mkv\0/a/b.mkv\0").unwrap(),
+            vec![
+                PathBuf::from("/a/line\nbreak.mkv"),
+                PathBuf::from("/a/b.mkv")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_path_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("list");
+        for paths in [
+            vec![PathBuf::from("/a/one.mkv"), PathBuf::from("/a/two.mkv")],
+            vec![
+                PathBuf::from("/a/line\nbreak.mkv"),
+                PathBuf::from("/a/b.mkv"),
+            ],
+        ] {
+            write_path_list(&list, &paths).unwrap();
+            assert_eq!(read_path_list(&list).unwrap(), paths);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+/// Target paths matched only against their own source paths, independently of other mappings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub targets: Vec<PathBuf>,
+    pub sources: Vec<PathBuf>,
+}
+
+/// Parses `target=DIR:source=DIR`. Either key can be repeated, and a `:` only separates pairs
+/// when followed by a key, so paths may contain it.
+pub fn parse_mapping(s: &str) -> Result<Mapping, String> {
+    let mut mapping = Mapping {
+        targets: Vec::new(),
+        sources: Vec::new(),
+    };
+    let mut rest = s;
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("Expected target=DIR or source=DIR in {rest:?}"))?;
+        let end = [":target=", ":source="]
+            .iter()
+            .filter_map(|separator| value.find(separator))
+            .min()
+            .unwrap_or(value.len());
+        let paths = match key {
+            "target" => &mut mapping.targets,
+            "source" => &mut mapping.sources,
+            _ => return Err(format!("Unknown key {key:?}, expected target or source")),
+        };
+        if value[..end].is_empty() {
+            return Err(format!("Empty {key} path in {s:?}"));
+        }
+        paths.push(PathBuf::from(&value[..end]));
+        rest = value[end..].strip_prefix(':').unwrap_or_default();
+    }
+    if mapping.targets.is_empty() || mapping.sources.is_empty() {
+        return Err(format!("{s:?} needs both a target and a source"));
+    }
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mapping() {
+        assert_eq!(
+            parse_mapping("target=/media/tv:source=/downloads/tv"),
+            Ok(Mapping {
+                targets: vec![PathBuf::from("/media/tv")],
+                sources: vec![PathBuf::from("/downloads/tv")],
+            })
+        );
+        assert_eq!(
+            parse_mapping("source=/a:b:target=/media:source=/c"),
+            Ok(Mapping {
+                targets: vec![PathBuf::from("/media")],
+                sources: vec![PathBuf::from("/a:b"), PathBuf::from("/c")],
+            })
+        );
+        assert!(parse_mapping("target=/media/tv").is_err());
+        assert!(parse_mapping("target=/media:src=/downloads").is_err());
+        assert!(parse_mapping("target=:source=/downloads").is_err());
+        assert!(parse_mapping("/media/tv").is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{self, Write as _};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::actions::audit::AuditEntry;
+use crate::matching::{MatchingFile, progress::ProgressCallbacks};
+
+/// How progress is shown while a run goes on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Progress bars on stderr, when it is a terminal
+    #[default]
+    Bar,
+    /// One JSON event per line on stderr, for other tools to follow the run
+    Jsonl,
+}
+
+/// A progress bar on stderr for scanning and hashing. Hidden when stderr isn't a terminal.
+pub struct ProgressBars {
+    bar: ProgressBar,
+    dirs: AtomicU64,
+    files: AtomicU64,
+    /// Files to hash in the current phase, and how many of them are done
+    phase_files: AtomicU64,
+    hashed: AtomicU64,
+}
+
+impl ProgressBars {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} Scanning: {msg} ({elapsed})")
+                .expect("Valid template"),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self {
+            bar,
+            dirs: AtomicU64::new(0),
+            files: AtomicU64::new(0),
+            phase_files: AtomicU64::new(0),
+            hashed: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ProgressCallbacks for ProgressBars {
+    fn scanned_directory(&self, files: u64) {
+        let dirs = self.dirs.fetch_add(1, Ordering::Relaxed) + 1;
+        let files = self.files.fetch_add(files, Ordering::Relaxed) + files;
+        self.bar
+            .set_message(format!("{dirs} directories, {files} files"));
+    }
+
+    fn start_phase(&self, name: &str, files: u64, bytes: u64) {
+        self.hashed.store(0, Ordering::Relaxed);
+        self.bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix} {msg} [{wide_bar}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+            )
+            .expect("Valid template"),
+        );
+        self.bar.set_prefix(name.to_owned());
+        self.bar.set_length(bytes);
+        self.bar.set_position(0);
+        self.bar.reset_eta();
+        self.phase_files.store(files, Ordering::Relaxed);
+        self.bar.set_message(format!("0/{files} files"));
+    }
+
+    fn hashed(&self, _path: &Path, bytes: u64) {
+        let hashed = self.hashed.fetch_add(1, Ordering::Relaxed) + 1;
+        self.bar.set_message(format!(
+            "{hashed}/{} files",
+            self.phase_files.load(Ordering::Relaxed)
+        ));
+        self.bar.inc(bytes);
+    }
+
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// A progress event, written as one JSON line.
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    /// A directory was walked, bringing the totals so far to `directories` and `files`
+    Scanned {
+        directories: u64,
+        files: u64,
+    },
+    /// A phase started, reading `bytes` from `files` files
+    Phase {
+        name: &'a str,
+        files: u64,
+        bytes: u64,
+    },
+    Hashed {
+        path: &'a Path,
+        bytes: u64,
+    },
+    /// Scanning and hashing are over and matching goes on
+    Matching,
+    Matched {
+        target: &'a Path,
+        source: &'a Path,
+        size: u64,
+        hash: &'a str,
+    },
+    Applied {
+        #[serde(flatten)]
+        entry: &'a AuditEntry<'a>,
+        result: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// Progress as JSON lines on stderr, e.g. `{"event":"hashed","path":"/media/a.mkv","bytes":7}`.
+#[derive(Default)]
+pub struct JsonlProgress {
+    dirs: AtomicU64,
+    files: AtomicU64,
+}
+
+impl JsonlProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn emit(&self, event: &Event) {
+        let Ok(mut line) = serde_json::to_vec(event) else {
+            return;
+        };
+        line.push(b'\n');
+        // Progress is best effort, like the progress bars
+        let _ = io::stderr().lock().write_all(&line);
+    }
+}
+
+impl ProgressCallbacks for JsonlProgress {
+    fn scanned_directory(&self, files: u64) {
+        self.emit(&Event::Scanned {
+            directories: self.dirs.fetch_add(1, Ordering::Relaxed) + 1,
+            files: self.files.fetch_add(files, Ordering::Relaxed) + files,
+        });
+    }
+
+    fn start_phase(&self, name: &str, files: u64, bytes: u64) {
+        self.emit(&Event::Phase { name, files, bytes });
+    }
+
+    fn hashed(&self, path: &Path, bytes: u64) {
+        self.emit(&Event::Hashed { path, bytes });
+    }
+
+    fn finish(&self) {
+        self.emit(&Event::Matching);
+    }
+
+    fn matched(&self, matching: &MatchingFile) {
+        self.emit(&Event::Matched {
+            target: matching.dest_path(),
+            source: matching.src_path(),
+            size: matching.size(),
+            hash: matching.hash(),
+        });
+    }
+
+    fn applied(&self, entry: &AuditEntry, result: Result<&str, &io::Error>) {
+        self.emit(&Event::Applied {
+            entry,
+            result: result.unwrap_or("failed"),
+            error: result.err().map(ToString::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let line = |event: &Event| serde_json::to_string(event).unwrap();
+        assert_eq!(
+            line(&Event::Phase {
+                name: "Hashing",
+                files: 2,
+                bytes: 14
+            }),
+            r#"{"event":"phase","name":"Hashing","files":2,"bytes":14}"#
+        );
+        assert_eq!(
+            line(&Event::Applied {
+                entry: &AuditEntry {
+                    action: "symlink",
+                    target: Path::new("/media/a.mkv"),
+                    source: Some(Path::new("/downloads/a.mkv")),
+                    hash: None,
+                },
+                result: "failed",
+                error: Some("disk full".to_owned()),
+            }),
+            r#"{"event":"applied","action":"symlink","target":"/media/a.mkv","source":"/downloads/a.mkv","result":"failed","error":"disk full"}"#
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Local, TimeDelta};
+
+/// When a daemon runs the next reconciliation.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// A fixed interval from the start of one run to the start of the next
+    Every(Duration),
+    /// A cron expression, in local time
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// How long to wait from `now` for the run following one that started at `started`. Zero
+    /// when a run took longer than the interval.
+    pub fn wait(&self, started: DateTime<Local>, now: DateTime<Local>) -> Option<Duration> {
+        let next = match self {
+            Schedule::Every(interval) => started + TimeDelta::from_std(*interval).ok()?,
+            Schedule::Cron(schedule) => schedule.after(&now).next()?,
+        };
+        Some((next - now).to_std().unwrap_or_default())
+    }
+}
+
+/// Parses a cron expression. The usual five fields (minute, hour, day of month, month, day of
+/// week) run on the minute, six or seven give the seconds and year too.
+pub fn parse_cron(expression: &str) -> Result<Schedule, String> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {expression}"),
+        _ => expression.to_owned(),
+    };
+    cron::Schedule::from_str(&expression)
+        .map(|schedule| Schedule::Cron(Box::new(schedule)))
+        .map_err(|e| format!("Invalid cron expression: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 5, 14, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_every() {
+        let schedule = Schedule::Every(Duration::from_secs(3600));
+        assert_eq!(
+            schedule.wait(time(9, 0), time(9, 20)),
+            Some(Duration::from_secs(40 * 60))
+        );
+        // Overran the interval
+        assert_eq!(
+            schedule.wait(time(9, 0), time(10, 30)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_cron() {
+        let schedule = parse_cron("30 3 * * *").unwrap();
+        assert_eq!(
+            schedule.wait(time(3, 0), time(3, 10)),
+            Some(Duration::from_secs(20 * 60))
+        );
+        assert_eq!(
+            schedule.wait(time(3, 30), time(3, 31)),
+            Some(Duration::from_secs((24 * 60 - 1) * 60))
+        );
+        assert!(parse_cron("0 15 10 * * *").is_ok());
+        assert!(parse_cron("every day").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, IsTerminal as _},
+    path::{Path, PathBuf},
+};
+
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+};
+
+/// A proposed replacement the user can approve.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub target: PathBuf,
+    pub source: PathBuf,
+    pub size: u64,
+    /// Replaces a whole directory rather than one file
+    pub directory: bool,
+}
+
+#[derive(Debug)]
+struct Group {
+    dir: PathBuf,
+    entries: Vec<(Entry, bool)>,
+    expanded: bool,
+}
+
+impl Group {
+    fn all_selected(&self) -> bool {
+        self.entries.iter().all(|(_, selected)| *selected)
+    }
+
+    fn selected_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(entry, _)| entry.size)
+            .sum()
+    }
+}
+
+/// A row on screen: a group, or one of the entries of an expanded group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Group(usize),
+    Entry(usize, usize),
+}
+
+/// The plan grouped by target directory, with every entry selected to start with.
+#[derive(Debug)]
+pub struct Selection {
+    groups: Vec<Group>,
+    cursor: usize,
+}
+
+impl Selection {
+    pub fn new(entries: impl IntoIterator<Item = Entry>) -> Self {
+        let mut groups: BTreeMap<PathBuf, Vec<(Entry, bool)>> = BTreeMap::new();
+        for entry in entries {
+            let dir = entry.target.parent().unwrap_or(&entry.target).to_path_buf();
+            groups.entry(dir).or_default().push((entry, true));
+        }
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|(dir, entries)| Group {
+                    dir,
+                    entries,
+                    expanded: false,
+                })
+                .collect(),
+            cursor: 0,
+        }
+    }
+
+    /// The targets of every selected entry.
+    pub fn selected(&self) -> HashSet<PathBuf> {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.entries)
+            .filter(|(_, selected)| *selected)
+            .map(|(entry, _)| entry.target.clone())
+            .collect()
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            rows.push(Row::Group(i));
+            if group.expanded {
+                rows.extend((0..group.entries.len()).map(|j| Row::Entry(i, j)));
+            }
+        }
+        rows
+    }
+
+    fn current(&self) -> Option<Row> {
+        self.rows().get(self.cursor).copied()
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        let last = self.rows().len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(offset).min(last);
+    }
+
+    /// Toggles the entry under the cursor, or every entry of the group under it.
+    fn toggle(&mut self) {
+        match self.current() {
+            Some(Row::Group(i)) => {
+                let group = &mut self.groups[i];
+                let select = !group.all_selected();
+                for (_, selected) in &mut group.entries {
+                    *selected = select;
+                }
+            }
+            Some(Row::Entry(i, j)) => {
+                let selected = &mut self.groups[i].entries[j].1;
+                *selected = !*selected;
+            }
+            None => {}
+        }
+    }
+
+    fn toggle_all(&mut self) {
+        let select = !self.groups.iter().all(Group::all_selected);
+        for group in &mut self.groups {
+            for (_, selected) in &mut group.entries {
+                *selected = select;
+            }
+        }
+    }
+
+    /// Shows or hides the entries of the group under the cursor, keeping the cursor on it.
+    fn set_expanded(&mut self, expanded: bool) {
+        let (Some(Row::Group(i)) | Some(Row::Entry(i, _))) = self.current() else {
+            return;
+        };
+        self.groups[i].expanded = expanded;
+        self.cursor = self
+            .rows()
+            .iter()
+            .position(|row| *row == Row::Group(i))
+            .expect("Groups are always shown");
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, list, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let (count, size) = self
+            .groups
+            .iter()
+            .flat_map(|group| &group.entries)
+            .filter(|(_, selected)| *selected)
+            .fold((0, 0), |(count, size), (entry, _)| {
+                (count + 1, size + entry.size)
+            });
+        frame.render_widget(
+            Paragraph::new(format!("{count} replacements selected, {size} bytes")),
+            header,
+        );
+
+        let items: Vec<ListItem> = self
+            .rows()
+            .into_iter()
+            .map(|row| match row {
+                Row::Group(i) => {
+                    let group = &self.groups[i];
+                    let selected = group.entries.iter().filter(|(_, s)| *s).count();
+                    let mark = match selected {
+                        0 => "[ ]",
+                        n if n == group.entries.len() => "[x]",
+                        _ => "[-]",
+                    };
+                    ListItem::new(format!(
+                        "{mark} {} {} ({selected}/{}, {} bytes)",
+                        if group.expanded { "v" } else { ">" },
+                        group.dir.display(),
+                        group.entries.len(),
+                        group.selected_size()
+                    ))
+                }
+                Row::Entry(i, j) => {
+                    let (entry, selected) = &self.groups[i].entries[j];
+                    ListItem::new(format!(
+                        "    {} {}{} <- {} ({} bytes)",
+                        if *selected { "[x]" } else { "[ ]" },
+                        file_name(&entry.target),
+                        if entry.directory { "/" } else { "" },
+                        entry.source.display(),
+                        entry.size
+                    ))
+                }
+            })
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.cursor));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title("Plan"))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut state,
+        );
+
+        frame.render_widget(
+            Line::from(
+                "space: toggle  a: toggle all  \u{2192}/\u{2190}: expand/collapse  \
+                 enter: apply selected  q: cancel",
+            ),
+            footer,
+        );
+    }
+
+    /// Lets the user pick entries in a full screen terminal UI. Returns whether to go ahead and
+    /// apply the selection.
+    pub fn run(&mut self) -> io::Result<bool> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--select needs a terminal",
+            ));
+        }
+        let mut terminal = ratatui::try_init().map_err(|e| {
+            ratatui::restore();
+            io::Error::new(e.kind(), format!("--select needs a terminal: {e}"))
+        })?;
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::PageUp => self.move_by(-20),
+                KeyCode::PageDown => self.move_by(20),
+                KeyCode::Right | KeyCode::Char('l') => self.set_expanded(true),
+                KeyCode::Left | KeyCode::Char('h') => self.set_expanded(false),
+                KeyCode::Char(' ') => self.toggle(),
+                KeyCode::Char('a') => self.toggle_all(),
+                KeyCode::Enter => return Ok(true),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target: &str, size: u64) -> Entry {
+        Entry {
+            target: PathBuf::from(target),
+            source: PathBuf::from("/source").join(Path::new(target).file_name().unwrap()),
+            size,
+            directory: false,
+        }
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut selection = Selection::new([
+            entry("/tv/show/e01.mkv", 10),
+            entry("/movies/film.mkv", 30),
+            entry("/tv/show/e02.mkv", 20),
+        ]);
+        assert_eq!(selection.selected().len(), 3);
+        // Groups are sorted by directory
+        assert_eq!(selection.rows(), vec![Row::Group(0), Row::Group(1)]);
+        assert_eq!(selection.groups[1].dir, PathBuf::from("/tv/show"));
+
+        // Deselect the whole movies group
+        selection.toggle();
+        assert_eq!(selection.selected().len(), 2);
+
+        // Deselect one episode
+        selection.move_by(1);
+        selection.set_expanded(true);
+        selection.move_by(1);
+        assert_eq!(selection.current(), Some(Row::Entry(1, 0)));
+        selection.toggle();
+        assert_eq!(
+            selection.selected(),
+            HashSet::from([PathBuf::from("/tv/show/e02.mkv")])
+        );
+
+        // Collapsing moves the cursor back to the group
+        selection.set_expanded(false);
+        assert_eq!(selection.current(), Some(Row::Group(1)));
+        selection.move_by(5);
+        assert_eq!(selection.current(), Some(Row::Group(1)));
+
+        // A partly selected group is selected entirely first
+        selection.toggle();
+        assert_eq!(selection.selected().len(), 2);
+        selection.toggle_all();
+        assert_eq!(selection.selected().len(), 3);
+        selection.toggle_all();
+        assert!(selection.selected().is_empty());
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut selection = Selection::new([entry("/tv/show/e01.mkv", 10)]);
+        selection.set_expanded(true);
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(60, 6)).unwrap();
+        terminal.draw(|frame| selection.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("1 replacements selected, 10 bytes"));
+        assert!(screen.contains("[x] v /tv/show (1/1, 10 bytes)"));
+        assert!(screen.contains("[x] e01.mkv <- /source/e01.mkv"));
+    }
+}
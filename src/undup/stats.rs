@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{actions::summary::RunSummary, hashing::CacheStats, matching::MatchStats};
+
+/// How long one phase of a run took.
+#[derive(Debug, serde::Serialize)]
+pub struct PhaseTime {
+    pub phase: &'static str,
+    pub seconds: f64,
+}
+
+/// Totals for a whole run, printed at its end.
+#[derive(Debug, serde::Serialize)]
+pub struct RunStats {
+    pub source_files: usize,
+    pub target_files: usize,
+    /// Bytes read to hash files, both their start and in full
+    pub bytes_hashed: u64,
+    /// Share of full hashes found in the cache, when there is one
+    pub cache_hit_rate: Option<f64>,
+    pub matches: usize,
+    /// Replacements made, unless this was a dry run
+    pub applied: Option<u64>,
+    pub failed: Option<u64>,
+    pub phases: Vec<PhaseTime>,
+}
+
+impl RunStats {
+    /// `since_hashing` is the time from the end of hashing to the end of applying the matches,
+    /// which matching is interleaved with and taken out of. The cache hit rate is left out
+    /// unless `caching`.
+    pub fn new(
+        matched: MatchStats,
+        prefix_bytes: u64,
+        cache: CacheStats,
+        caching: bool,
+        summary: Option<&RunSummary>,
+        since_hashing: Duration,
+    ) -> Self {
+        let phases = [
+            ("scan", matched.scan_time),
+            ("hash", matched.hash_time),
+            ("match", matched.match_time),
+            ("apply", since_hashing.saturating_sub(matched.match_time)),
+        ];
+        Self {
+            source_files: matched.source_files,
+            target_files: matched.target_files,
+            bytes_hashed: prefix_bytes + cache.hashed_bytes,
+            cache_hit_rate: cache.hit_rate().filter(|_| caching),
+            matches: matched.matches,
+            applied: summary.map(|summary| summary.files_linked + summary.directories_linked),
+            failed: summary.map(|summary| summary.failures),
+            phases: phases
+                .into_iter()
+                .map(|(phase, time)| PhaseTime {
+                    phase,
+                    seconds: time.as_secs_f64(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for RunStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scanned {0} source and {1} target files, hashed {2} bytes",
+            self.source_files, self.target_files, self.bytes_hashed
+        )?;
+        if let Some(hit_rate) = self.cache_hit_rate {
+            write!(f, " ({:.1}% from cache)", hit_rate * 100.0)?;
+        }
+        write!(f, "; {} matches", self.matches)?;
+        if let (Some(applied), Some(failed)) = (self.applied, self.failed) {
+            write!(f, ", {applied} applied, {failed} failed")?;
+        }
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|phase| format!("{} {:.2}s", phase.phase, phase.seconds))
+            .collect();
+        write!(f, "\nTook {}", phases.join(", "))
+    }
+}
+
+/// How runs are grouped when looking back over them.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+/// What the runs of a period achieved together.
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct PeriodTotals {
+    /// e.g. 2025-06-01, 2025-W22 or 2025-06, by the local date runs started on
+    pub period: String,
+    pub runs: u64,
+    pub files_linked: u64,
+    pub directories_linked: u64,
+    pub bytes_reclaimed: u64,
+    pub failures: u64,
+}
+
+impl PeriodTotals {
+    fn add(&mut self, summary: &RunSummary) {
+        self.runs += 1;
+        self.files_linked += summary.files_linked;
+        self.directories_linked += summary.directories_linked;
+        self.bytes_reclaimed += summary.bytes_reclaimed;
+        self.failures += summary.failures;
+    }
+}
+
+impl std::fmt::Display for PeriodTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{0:<10} {1:>6} runs {2:>8} files {3:>6} directories {4:>16} bytes reclaimed",
+            self.period,
+            self.runs,
+            self.files_linked,
+            self.directories_linked,
+            self.bytes_reclaimed
+        )
+    }
+}
+
+/// Totals of the runs in `summaries` for each period, oldest first, along with their grand
+/// total.
+pub fn history(summaries: &[RunSummary], by: Period) -> (Vec<PeriodTotals>, PeriodTotals) {
+    let format = match by {
+        Period::Day => "%Y-%m-%d",
+        Period::Week => "%G-W%V",
+        Period::Month => "%Y-%m",
+    };
+    let mut periods: BTreeMap<String, PeriodTotals> = BTreeMap::new();
+    let mut total = PeriodTotals {
+        period: "total".to_owned(),
+        ..Default::default()
+    };
+    for summary in summaries {
+        let period = chrono::DateTime::<chrono::Local>::from(summary.started_at)
+            .format(format)
+            .to_string();
+        periods
+            .entry(period.clone())
+            .or_insert_with(|| PeriodTotals {
+                period,
+                ..Default::default()
+            })
+            .add(summary);
+        total.add(summary);
+    }
+    (periods.into_values().collect(), total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_stats() {
+        let matched = MatchStats {
+            source_files: 3,
+            target_files: 5,
+            matches: 2,
+            scan_time: Duration::from_millis(100),
+            hash_time: Duration::from_millis(2500),
+            match_time: Duration::from_millis(300),
+        };
+        let cache = CacheStats {
+            hits: 3,
+            misses: 1,
+            hashed_bytes: 1000,
+        };
+        let mut summary = RunSummary::new();
+        summary.files_linked = 1;
+        summary.failures = 1;
+        let stats = RunStats::new(
+            matched,
+            24,
+            cache,
+            true,
+            Some(&summary),
+            Duration::from_millis(800),
+        );
+        assert_eq!(
+            stats.to_string(),
+            "Scanned 3 source and 5 target files, hashed 1024 bytes (75.0% from cache); \
+             2 matches, 1 applied, 1 failed\n\
+             Took scan 0.10s, hash 2.50s, match 0.30s, apply 0.50s"
+        );
+
+        // Dry runs without a cache
+        let stats = RunStats::new(matched, 24, cache, false, None, Duration::ZERO);
+        assert_eq!(
+            stats.to_string().lines().next(),
+            Some("Scanned 3 source and 5 target files, hashed 1024 bytes; 2 matches")
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let start = std::time::SystemTime::UNIX_EPOCH + 20_000 * day;
+        let summaries: Vec<RunSummary> = [0, 1, 40]
+            .into_iter()
+            .map(|days| {
+                let mut summary = RunSummary::new();
+                summary.started_at = start + days * day;
+                summary.files_linked = 2;
+                summary.bytes_reclaimed = 100;
+                summary
+            })
+            .collect();
+
+        let (periods, total) = history(&summaries, Period::Day);
+        assert_eq!(periods.len(), 3);
+        let (periods, total_by_month) = history(&summaries, Period::Month);
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods[0].runs, 2);
+        assert_eq!(periods[0].bytes_reclaimed, 200);
+        assert_eq!(total, total_by_month);
+        assert_eq!(total.runs, 3);
+        assert_eq!(total.files_linked, 6);
+        assert_eq!(total.bytes_reclaimed, 300);
+    }
+}
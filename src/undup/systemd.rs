@@ -0,0 +1,221 @@
+use std::{
+    io,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::actions::audit::AuditEntry;
+use crate::matching::{MatchingFile, progress::ProgressCallbacks};
+
+/// Set once SIGTERM arrives while matches are being applied.
+static STOP: AtomicBool = AtomicBool::new(false);
+/// Whether SIGTERM waits for the matches being applied, rather than ending the process.
+static APPLYING: AtomicBool = AtomicBool::new(false);
+/// Whether a cycle is running, rather than waiting for the next one.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// Files scanned, hashed or applied so far, which the watchdog checks has moved.
+static PROGRESS: AtomicU64 = AtomicU64::new(0);
+
+/// Sends a state change such as `READY=1` to the service manager, when run as a systemd
+/// service with `Type=notify`. Does nothing otherwise.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET")
+        && let Err(e) = send(&socket, state)
+    {
+        tracing::warn!("Couldn't notify systemd of {state:?}: {e}");
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt as _, net::UnixDatagram};
+
+    let datagram = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt as _;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// How often the service manager expects a `WATCHDOG=1` ping, if it watches this process.
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid != std::process::id().to_string()
+    {
+        return None;
+    }
+    Some(Duration::from_micros(usec))
+}
+
+/// Whether the service manager watches this process.
+pub fn watchdog_enabled() -> bool {
+    watchdog_interval().is_some()
+}
+
+/// Pings the watchdog from a background thread at half the interval systemd expects, when
+/// it has one set up. While a cycle runs it's only pinged when the cycle made progress since
+/// the last ping, so a stuck run gets restarted. Hashing a single file must therefore take
+/// less than the interval.
+pub fn start_watchdog() {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    tracing::debug!("Pinging the systemd watchdog every {:?}", interval / 2);
+    std::thread::spawn(move || {
+        let mut last = PROGRESS.load(Ordering::Relaxed);
+        loop {
+            let progress = PROGRESS.load(Ordering::Relaxed);
+            if !RUNNING.load(Ordering::Relaxed) || progress != last {
+                notify("WATCHDOG=1");
+            } else {
+                tracing::warn!("No progress since the last systemd watchdog ping");
+            }
+            last = progress;
+            std::thread::sleep(interval / 2);
+        }
+    });
+}
+
+/// Holds back watchdog pings while alive unless progress is reported.
+pub struct Cycle(());
+
+impl Cycle {
+    pub fn start() -> Self {
+        PROGRESS.fetch_add(1, Ordering::Relaxed);
+        RUNNING.store(true, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for Cycle {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Passes progress on to the callbacks shown, if any, counting it for the watchdog.
+pub struct Watched(pub Option<Arc<dyn ProgressCallbacks>>);
+
+impl Watched {
+    fn progressed(&self) -> Option<&dyn ProgressCallbacks> {
+        PROGRESS.fetch_add(1, Ordering::Relaxed);
+        self.0.as_deref()
+    }
+}
+
+impl ProgressCallbacks for Watched {
+    fn scanned_directory(&self, files: u64) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.scanned_directory(files);
+        }
+    }
+
+    fn start_phase(&self, name: &str, files: u64, bytes: u64) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.start_phase(name, files, bytes);
+        }
+    }
+
+    fn hashed(&self, path: &Path, bytes: u64) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.hashed(path, bytes);
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.finish();
+        }
+    }
+
+    fn matched(&self, matching: &MatchingFile) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.matched(matching);
+        }
+    }
+
+    fn applied(&self, entry: &AuditEntry, result: Result<&str, &io::Error>) {
+        if let Some(callbacks) = self.progressed() {
+            callbacks.applied(entry, result);
+        }
+    }
+}
+
+/// Handles SIGTERM so matches being applied are finished first: the run then stops before
+/// the next one, leaving the rest in its checkpoint. Outside of that the process ends straight
+/// away, as there is nothing in flight.
+pub fn handle_sigterm() {
+    #[cfg(unix)]
+    {
+        extern "C" fn on_sigterm(_: libc::c_int) {
+            if APPLYING.load(Ordering::SeqCst) {
+                STOP.store(true, Ordering::SeqCst);
+            } else {
+                unsafe {
+                    libc::signal(libc::SIGTERM, libc::SIG_DFL);
+                    libc::raise(libc::SIGTERM);
+                }
+            }
+        }
+        unsafe {
+            libc::signal(
+                libc::SIGTERM,
+                on_sigterm as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// Whether SIGTERM asked to stop once the matches in flight are applied.
+pub fn stop_requested() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Defers SIGTERM while alive, for as long as matches are being applied.
+pub struct Applying(());
+
+impl Applying {
+    pub fn start() -> Self {
+        APPLYING.store(true, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Drop for Applying {
+    fn drop(&mut self) {
+        APPLYING.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_send() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}